@@ -33,6 +33,7 @@ pub const HEADER_SIZE: usize = 96;
 // ═══════════════════════════════════════════════════════════════════════════
 
 use serde::{Serialize, Deserialize};
+use std::path::Path;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -118,12 +119,47 @@ pub mod limits {
     pub const MAX_STACK_DEPTH: usize = 16;
     pub const MAX_CALL_DEPTH: usize = 4;
     pub const MAX_PREDICATE_BYTECODE: usize = 256;
+    /// Host-visible IO buffer (0x020000-0x02FFFF in the WAT memory layout)
+    pub const IO_BUFFER_SIZE: usize = 0x10000;
+    /// `<set>` pairs per node: the io.call payload (8-byte header, 16
+    /// bytes per pair) must fit the IO buffer
+    pub const MAX_NODE_DATA_PAIRS: usize = (IO_BUFFER_SIZE - 8) / 16;
 }
 
 // ═══════════════════════════════════════════════════════════════════════════
 // SPEC: Predicate VM Opcodes (must match WAT)
 // ═══════════════════════════════════════════════════════════════════════════
 
+/// Fractional digits of a `PushDec` operand: decimals are i64 counts of
+/// 10^-6, so `0.97` is encoded as 970000
+pub const DECIMAL_PLACES: u32 = 6;
+
+/// Predicate VM instruction set
+///
+/// Stack cells are 64-bit values tagged int, string or decimal. The
+/// comparisons compare numerically: when exactly one operand is a decimal
+/// the integer operand is scaled by 10^`DECIMAL_PLACES` first (saturating),
+/// so `1 == 1.0` and `0.96 < 1`. Strings compare by reference.
+///
+/// Arithmetic pops its operands (the right one on top) and pushes the
+/// result. Integers stay integers; if either operand is a decimal the
+/// other is scaled to a decimal and the result is a decimal, with `Mul`
+/// and `Div` truncating to `DECIMAL_PLACES`. Integer `Div` truncates
+/// toward zero and `Mod` takes the sign of the dividend. Results that do
+/// not fit in 64 bits saturate at `i64::MIN`/`i64::MAX` instead of
+/// wrapping. Division or remainder by zero, or a string operand, is a
+/// fault: the evaluation stops and the whole predicate (including any
+/// caller reaching it through `CallPred`) evaluates false.
+///
+/// Points in time are int cells holding Unix epoch milliseconds. `Now`
+/// and `Within` read the current time from the host once per evaluation,
+/// so every time check in one evaluation sees the same instant and tests
+/// can fix it.
+///
+/// The jumps take a 2-byte target: a byte offset from the start of the
+/// predicate's bytecode, which must be an instruction after the jump.
+/// Jumps only go forward, so every predicate still runs in at most one
+/// step per instruction.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Opcode {
@@ -133,6 +169,8 @@ pub enum Opcode {
     PushStr = 0x02,    // + 4 bytes (string pool offset)
     LoadVar = 0x03,    // + 4 bytes (var path offset)
     LoadField = 0x04,  // + 4 bytes (field name offset)
+    PushDec = 0x05,    // + 8 bytes (i64 decimal, see DECIMAL_PLACES)
+    PushI64 = 0x06,    // + 8 bytes (i64), for ints outside the i32 range
     
     // Comparison
     Eq = 0x10,
@@ -152,6 +190,7 @@ pub enum Opcode {
     Matches = 0x31,
     StartsWith = 0x32,
     EndsWith = 0x33,
+    In = 0x34,         // + 4 bytes (string pool offset of the comma-separated members)
     
     // Utility
     Len = 0x40,
@@ -160,16 +199,30 @@ pub enum Opcode {
     IsDefined = 0x43,
     IsConfirmed = 0x44,
 
+    // Arithmetic
+    Add = 0x60,
+    Sub = 0x61,
+    Mul = 0x62,
+    Div = 0x63,
+    Mod = 0x64,
+    Neg = 0x65,
+
+    // Time (epoch milliseconds)
+    Now = 0x70,        // + 8 bytes (i64 offset in ms), push now + offset
+    Within = 0x71,     // + 8 bytes (i64 duration in ms), pop t, push 1 if t is between now and now + duration
+
     // Merge/CRDT operations (Y-constraint application for conflict resolution)
     // These opcodes enable predicates to be used as merge policies
     Timestamp = 0x50,      // pop value ref, push i64 timestamp (for LWW)
     IsFlagged = 0x51,      // pop value ref, push 1 if flagged for review
     Origin = 0x52,         // pop value ref, push origin/author string offset
     VClockGt = 0x53,       // pop 2 value refs, push 1 if first vclock dominates second
-    MergeField = 0x54,     // + 1 byte (0=a, 1=b, 2=candidate), load merge context field
+    MergeField = 0x54,     // + 1 byte (0=a, 1=b, 2=candidate) + 4 bytes field path offset, load merge context field
 
     // Control
     CallPred = 0xF0,   // + 2 bytes (predicate ID)
+    JumpIfFalse = 0xF1, // + 2 bytes (target); jump if top is false, keeping it, else pop it
+    JumpIfTrue = 0xF2,  // + 2 bytes (target); jump if top is true, keeping it, else pop it
     Ret = 0xFF,
 }
 
@@ -181,6 +234,8 @@ impl Opcode {
             0x02 => Some(Self::PushStr),
             0x03 => Some(Self::LoadVar),
             0x04 => Some(Self::LoadField),
+            0x05 => Some(Self::PushDec),
+            0x06 => Some(Self::PushI64),
             0x10 => Some(Self::Eq),
             0x11 => Some(Self::Neq),
             0x12 => Some(Self::Gt),
@@ -194,6 +249,7 @@ impl Opcode {
             0x31 => Some(Self::Matches),
             0x32 => Some(Self::StartsWith),
             0x33 => Some(Self::EndsWith),
+            0x34 => Some(Self::In),
             0x40 => Some(Self::Len),
             0x41 => Some(Self::Get),
             0x42 => Some(Self::IsNull),
@@ -204,7 +260,17 @@ impl Opcode {
             0x52 => Some(Self::Origin),
             0x53 => Some(Self::VClockGt),
             0x54 => Some(Self::MergeField),
+            0x60 => Some(Self::Add),
+            0x61 => Some(Self::Sub),
+            0x62 => Some(Self::Mul),
+            0x63 => Some(Self::Div),
+            0x64 => Some(Self::Mod),
+            0x65 => Some(Self::Neg),
+            0x70 => Some(Self::Now),
+            0x71 => Some(Self::Within),
             0xF0 => Some(Self::CallPred),
+            0xF1 => Some(Self::JumpIfFalse),
+            0xF2 => Some(Self::JumpIfTrue),
             0xFF => Some(Self::Ret),
             _ => None,
         }
@@ -288,6 +354,22 @@ pub struct Location {
     pub node_id: Option<String>,
     pub edge_id: Option<String>,
     pub predicate_id: Option<String>,
+    pub span: Option<Span>,
+    /// Source file the span refers to, when it is not the input document
+    /// (e.g. an imported file)
+    pub file: Option<String>,
+}
+
+/// Source position of an element in the workflow document
+///
+/// `start`/`end` are byte offsets into the source text; `line`/`column`
+/// are 1-based and point at `start` (column counts characters, not bytes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub column: u32,
 }
 
 #[derive(Debug)]
@@ -298,6 +380,48 @@ pub enum CompileError {
     Emit(String),
     Io(std::io::Error),
     Validation { diagnostics: Vec<Diagnostic> },
+    /// An error attributed to a position in the source document
+    Spanned { error: Box<CompileError>, span: Span },
+}
+
+impl CompileError {
+    /// Attach a source span, unless the error already carries one
+    pub fn with_span(self, span: Option<Span>) -> Self {
+        match (self, span) {
+            (e @ Self::Spanned { .. }, _) | (e, None) => e,
+            (e, Some(span)) => Self::Spanned { error: Box::new(e), span },
+        }
+    }
+
+    /// Source span of the error, if known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Spanned { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+
+    /// Convert a pipeline failure into a single diagnostic
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let (code, message) = match self {
+            Self::Parse(msg) => ("PARSE", msg.clone()),
+            Self::Lower(msg) => ("LOWER", msg.clone()),
+            Self::Predicate(msg) => ("PREDICATE", msg.clone()),
+            Self::Spanned { error, span } => {
+                let mut diag = error.to_diagnostic();
+                diag.location.get_or_insert_with(Location::default).span = Some(*span);
+                return diag;
+            }
+            e => ("INTERNAL", e.to_string()),
+        };
+        Diagnostic {
+            severity: Severity::Error,
+            code: code.into(),
+            message,
+            hint: None,
+            location: None,
+        }
+    }
 }
 
 impl std::fmt::Display for CompileError {
@@ -312,6 +436,9 @@ impl std::fmt::Display for CompileError {
                 write!(f, "Validation failed: {} error(s)", 
                     diagnostics.iter().filter(|d| d.severity == Severity::Error).count())
             }
+            Self::Spanned { error, span } => {
+                write!(f, "{} (line {}, column {})", error, span.line, span.column)
+            }
         }
     }
 }
@@ -329,6 +456,8 @@ pub struct CompileOptions {
     pub optimize: bool,
     pub strict: bool,
     pub emit_audit: bool,
+    /// Values for `${name}` in attributes; recorded in the audit
+    pub profile: dsl::Profile,
 }
 
 #[derive(Debug)]
@@ -345,20 +474,66 @@ pub struct CompileResult {
 ///
 /// Pipeline stages (in order):
 /// 1. Parse XML → AST
-/// 2. Lower AST → IR
-/// 3. Compile predicates to bytecode
-/// 4. **assign_edge_indices()** ← IR invariant, must run before validation
-/// 5. Optimize (optional)
-/// 6. Validate (syntactic, semantic, pragmatic)
+/// 2. Expand fragments and sub-workflow calls
+/// 3. Lower AST → IR
+/// 4. Compile predicates to bytecode (simplified first when optimizing)
+///    and type-check them against schemas
+/// 5. **assign_edge_indices()** ← IR invariant, must run before validation
+/// 6. Optimize (optional)
+/// 7. Validate (syntactic, semantic, pragmatic)
 pub fn compile_pipeline(xml: &str, optimize: bool) -> Result<(compiler::GraphIR, dsl::ast::OmarDocument, Vec<Diagnostic>), CompileError> {
-    // Parse XML → AST
-    let ast = dsl::parse(xml).map_err(|e| CompileError::Parse(e.to_string()))?;
+    let options = CompileOptions { optimize, ..Default::default() };
+    let (ir, ast, diagnostics, _source) = run_pipeline(xml, Path::new(""), &dsl::FileResolver, &options)?;
+    Ok((ir, ast, diagnostics))
+}
+
+/// Canonical pipeline honouring all compile options
+///
+/// Parse warnings (e.g. unknown attributes) are returned alongside the
+/// validation diagnostics; in strict mode they are errors. Also returns
+/// the canonical XML of the resolved document (`dsl::writer::to_xml`),
+/// which is what the binary and audit hash: documents that differ only
+/// in formatting or front-end get the same SOURCE_HASH.
+#[allow(clippy::type_complexity)]
+fn run_pipeline(
+    xml: &str,
+    path: &Path,
+    resolver: &dyn dsl::SourceResolver,
+    options: &CompileOptions,
+) -> Result<(compiler::GraphIR, dsl::ast::OmarDocument, Vec<Diagnostic>, String), CompileError> {
+    // Parse XML → AST (imports resolved)
+    let parse_options = dsl::ParseOptions {
+        strict: options.strict,
+        profile: Some(options.profile.clone()),
+    };
+    let resolved = dsl::parse_with_imports(xml, path, resolver, &parse_options);
+    let source = dsl::writer::to_xml(&resolved.document);
+    let (ast, mut diagnostics) = (resolved.document, resolved.diagnostics);
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return Err(CompileError::Validation { diagnostics });
+    }
+
+    let (ir, diags) = check_document(&ast, options.optimize)?;
+    diagnostics.extend(diags);
+
+    Ok((ir, ast, diagnostics, source))
+}
+
+/// Pipeline stages 2-7: lower a parsed document and validate the IR
+fn check_document(ast: &dsl::ast::OmarDocument, optimize: bool) -> Result<(compiler::GraphIR, Vec<Diagnostic>), CompileError> {
+    // Expand fragments and inline call nodes so every later stage sees
+    // one flat graph
+    let ast = &compiler::expand_fragments(ast)?;
+    let ast = &compiler::inline_calls(ast)?;
 
     // Lower AST → IR
-    let mut ir = compiler::lower(&ast)?;
+    let mut ir = compiler::lower(ast)?;
+
+    // Compile predicates to bytecode, simplifying them when optimizing
+    ir = compiler::compile_predicates_with(ir, ast, optimize)?;
 
-    // Compile predicates to bytecode
-    ir = compiler::compile_predicates(ir, &ast)?;
+    // Type-check predicates against the schemas their nodes are bound to
+    let mut diagnostics = compiler::check_types(&mut ir, ast);
 
     // **CRITICAL**: Assign edge indices before validation
     // This maintains IR invariants (edge_count, edge_start must be set)
@@ -370,18 +545,73 @@ pub fn compile_pipeline(xml: &str, optimize: bool) -> Result<(compiler::GraphIR,
     }
 
     // Validate (all three layers)
-    let mut diagnostics = Vec::new();
     diagnostics.extend(compiler::check_syntactic(&ir));
     diagnostics.extend(compiler::check_semantic(&ir));
     diagnostics.extend(compiler::check_pragmatic(&ir));
 
-    Ok((ir, ast, diagnostics))
+    Ok((ir, diagnostics))
 }
 
 /// Compile workflow XML to graph.bin
+///
+/// Imports are read from disk relative to the current directory; use
+/// `compile_with_resolver` to compile a file elsewhere or from memory.
 pub fn compile(xml: &str, options: &CompileOptions) -> Result<CompileResult, CompileError> {
-    let (ir, _ast, diagnostics) = compile_pipeline(xml, options.optimize)?;
-    
+    compile_with_resolver(xml, Path::new(""), &dsl::FileResolver, options)
+}
+
+/// Compile workflow XML located at `path`, loading imports through `resolver`
+pub fn compile_with_resolver(
+    xml: &str,
+    path: &Path,
+    resolver: &dyn dsl::SourceResolver,
+    options: &CompileOptions,
+) -> Result<CompileResult, CompileError> {
+    let (ir, _ast, diagnostics, source) = run_pipeline(xml, path, resolver, options)?;
+    emit_result(ir, diagnostics, &source, options)
+}
+
+/// Compile a document that is already parsed, e.g. built by a tool or
+/// deserialized from JSON (see `dsl::ast` for the JSON shape)
+///
+/// Imports are not resolved here: pass the document through
+/// `dsl::parse_with_imports` first, or compile the file it came from.
+/// A document that still has imports fails with IMP001.
+pub fn compile_document(doc: &dsl::ast::OmarDocument, options: &CompileOptions) -> Result<CompileResult, CompileError> {
+    if !doc.imports.is_empty() {
+        let diagnostics = doc.imports.iter()
+            .map(|import| Diagnostic {
+                severity: Severity::Error,
+                code: "IMP001".into(),
+                message: format!("Import '{}' is not resolved", import.href),
+                hint: Some("Resolve imports with dsl::parse_with_imports before compile_document".into()),
+                location: None,
+            })
+            .collect();
+        return Err(CompileError::Validation { diagnostics });
+    }
+
+    // Documents built in code may leave the version empty
+    if !doc.version.is_empty() {
+        let mut diagnostics = Vec::new();
+        dsl::version::check_version(&doc.version, None, &mut diagnostics);
+        if !diagnostics.is_empty() {
+            return Err(CompileError::Validation { diagnostics });
+        }
+    }
+
+    let (ir, diagnostics) = check_document(doc, options.optimize)?;
+    emit_result(ir, diagnostics, &dsl::writer::to_xml(doc), options)
+}
+
+/// Emit the binary (and audit) for a checked IR, unless the diagnostics
+/// stop compilation
+fn emit_result(
+    ir: compiler::GraphIR,
+    diagnostics: Vec<Diagnostic>,
+    source: &str,
+    options: &CompileOptions,
+) -> Result<CompileResult, CompileError> {
     // Check for errors
     let has_errors = diagnostics.iter().any(|d| d.severity == Severity::Error);
     let has_warnings = diagnostics.iter().any(|d| d.severity == Severity::Warn);
@@ -391,11 +621,11 @@ pub fn compile(xml: &str, options: &CompileOptions) -> Result<CompileResult, Com
     }
     
     // Emit binary
-    let binary = emit::binary::emit(&ir, xml).map_err(|e| CompileError::Emit(e.to_string()))?;
+    let binary = emit::binary::emit(&ir, source).map_err(|e| CompileError::Emit(e.to_string()))?;
     
     // Generate audit
     let audit = if options.emit_audit {
-        Some(emit::audit::generate(&ir, xml, &binary, &diagnostics))
+        Some(emit::audit::generate(&ir, source, &binary, &diagnostics, &options.profile))
     } else {
         None
     };
@@ -406,41 +636,48 @@ pub fn compile(xml: &str, options: &CompileOptions) -> Result<CompileResult, Com
 /// Validate without compiling
 ///
 /// Uses the canonical pipeline but stops before binary emission.
+/// Parse errors do not stop validation: the partial document is still
+/// lowered and checked so every problem is reported in one pass.
 pub fn validate(xml: &str) -> Vec<Diagnostic> {
-    match compile_pipeline(xml, false) {
-        Ok((_ir, _ast, diagnostics)) => diagnostics,
-        Err(CompileError::Parse(msg)) => vec![Diagnostic {
-            severity: Severity::Error,
-            code: "PARSE".into(),
-            message: msg,
-            hint: None,
-            location: None,
-        }],
-        Err(CompileError::Lower(msg)) => vec![Diagnostic {
-            severity: Severity::Error,
-            code: "LOWER".into(),
-            message: msg,
-            hint: None,
-            location: None,
-        }],
-        Err(CompileError::Predicate(msg)) => vec![Diagnostic {
-            severity: Severity::Error,
-            code: "PREDICATE".into(),
-            message: msg,
-            hint: None,
-            location: None,
-        }],
-        Err(e) => vec![Diagnostic {
-            severity: Severity::Error,
-            code: "INTERNAL".into(),
-            message: e.to_string(),
-            hint: None,
-            location: None,
-        }],
+    validate_with_options(xml, &CompileOptions::default())
+}
+
+/// Validate with compile options (`strict` turns unknown elements and
+/// attributes into errors)
+pub fn validate_with_options(xml: &str, options: &CompileOptions) -> Vec<Diagnostic> {
+    validate_with_resolver(xml, Path::new(""), &dsl::FileResolver, options)
+}
+
+/// Validate workflow XML located at `path`, loading imports through `resolver`
+pub fn validate_with_resolver(
+    xml: &str,
+    path: &Path,
+    resolver: &dyn dsl::SourceResolver,
+    options: &CompileOptions,
+) -> Vec<Diagnostic> {
+    let parse_options = dsl::ParseOptions {
+        strict: options.strict,
+        profile: Some(options.profile.clone()),
+    };
+    let resolved = dsl::parse_with_imports(xml, path, resolver, &parse_options);
+    let (ast, mut diagnostics) = (resolved.document, resolved.diagnostics);
+    
+    match check_document(&ast, false) {
+        Ok((_ir, diags)) => diagnostics.extend(diags),
+        Err(CompileError::Validation { diagnostics: diags }) => diagnostics.extend(diags),
+        Err(e) => diagnostics.push(e.to_diagnostic()),
     }
+    
+    diagnostics
 }
 
 /// Inspect a compiled graph.bin
+///
+/// Besides reading the header, this verifies the predicate table (see
+/// `compiler::verify`): a binary with bytecode that could underflow or
+/// overflow the VM stack, or exceed the step or call depth limits, is
+/// rejected. Predicates are numbered from 1 by position, as the VM
+/// looks them up.
 pub fn inspect(data: &[u8]) -> Result<GraphInfo, String> {
     if data.len() < HEADER_SIZE {
         return Err("Binary too small".into());
@@ -451,13 +688,17 @@ pub fn inspect(data: &[u8]) -> Result<GraphInfo, String> {
         return Err(format!("Invalid magic: 0x{:08x}", magic));
     }
     
+    let predicate_count = u32::from_le_bytes([data[16], data[17], data[18], data[19]]);
+    let predicate_bounds = verify_predicate_table(data, predicate_count)?;
+    
     Ok(GraphInfo {
         version: format!("{}.{}", 
             u16::from_le_bytes([data[4], data[5]]),
             u16::from_le_bytes([data[6], data[7]])),
         node_count: u32::from_le_bytes([data[8], data[9], data[10], data[11]]),
         edge_count: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
-        predicate_count: u32::from_le_bytes([data[16], data[17], data[18], data[19]]),
+        predicate_count,
+        predicate_bounds,
         string_pool_size: u32::from_le_bytes([data[20], data[21], data[22], data[23]]),
         entry_count: u32::from_le_bytes([data[24], data[25], data[26], data[27]]),
         binary_size: data.len(),
@@ -470,11 +711,37 @@ pub struct GraphInfo {
     pub node_count: u32,
     pub edge_count: u32,
     pub predicate_count: u32,
+    /// Verified bounds of each predicate, in table order (id 1 first)
+    pub predicate_bounds: Vec<compiler::verify::PredicateBounds>,
     pub string_pool_size: u32,
     pub entry_count: u32,
     pub binary_size: usize,
 }
 
+/// Read and verify the `count` length-prefixed predicates of a binary
+fn verify_predicate_table(data: &[u8], count: u32) -> Result<Vec<compiler::verify::PredicateBounds>, String> {
+    if count > u16::MAX as u32 {
+        return Err(format!("Too many predicates: {}", count));
+    }
+    let at = emit::binary::header_offsets::PREDICATES_OFFSET;
+    let mut pos = u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]) as usize;
+    
+    let mut table = Vec::with_capacity(count as usize);
+    for id in 1..=count as compiler::PredicateId {
+        let len = data.get(pos..pos + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(|| format!("Predicate {} is outside the binary", id))?;
+        let bytecode = data.get(pos + 2..pos + 2 + len)
+            .ok_or_else(|| format!("Predicate {} is truncated", id))?;
+        table.push((id, bytecode));
+        pos += 2 + len;
+    }
+    
+    let bounds = compiler::verify::verify_table(&table, table.len())
+        .map_err(|(id, message)| format!("Predicate {} fails verification: {}", id, message))?;
+    Ok(table.iter().map(|(id, _)| bounds[id]).collect())
+}
+
 // ═══════════════════════════════════════════════════════════════════════════
 // HELPERS
 // ═══════════════════════════════════════════════════════════════════════════
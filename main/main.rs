@@ -1,10 +1,10 @@
 //! PXYZ CLI
 //!
-//! Compile workflow.xml → graph.bin
+//! Compile workflow.xml (or .pxyz, .json) → graph.bin
 
 use clap::{Parser, Subcommand};
 use colored::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(name = "pxyz")]
@@ -19,7 +19,7 @@ struct Cli {
 enum Command {
     /// Compile workflow.xml to graph.bin
     Compile {
-        /// Input XML file
+        /// Input workflow (.xml, .pxyz text syntax, or .json)
         #[arg(short, long)]
         input: PathBuf,
         
@@ -34,6 +34,14 @@ enum Command {
         /// Treat warnings as errors
         #[arg(long)]
         strict: bool,
+        
+        /// Profile file of `name = value` lines for `${name}` in attributes
+        #[arg(long)]
+        profile: Option<PathBuf>,
+        
+        /// Set a variable, overriding the profile (repeatable)
+        #[arg(short = 'D', long = "define", value_name = "NAME=VALUE")]
+        defines: Vec<String>,
     },
     
     /// Inspect graph.bin
@@ -49,9 +57,54 @@ enum Command {
     
     /// Validate without compiling
     Check {
-        /// Input XML file
+        /// Input workflow (.xml, .pxyz text syntax, or .json)
+        #[arg(short, long)]
+        input: PathBuf,
+        
+        /// Treat unknown elements and attributes as errors
+        #[arg(long)]
+        strict: bool,
+        
+        /// Profile file of `name = value` lines for `${name}` in attributes
+        #[arg(long)]
+        profile: Option<PathBuf>,
+        
+        /// Set a variable, overriding the profile (repeatable)
+        #[arg(short = 'D', long = "define", value_name = "NAME=VALUE")]
+        defines: Vec<String>,
+    },
+    
+    /// Rewrite workflow XML in canonical form
+    Fmt {
+        /// XML files to format
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        
+        /// Report files that are not formatted instead of rewriting them
+        #[arg(long)]
+        check: bool,
+    },
+    
+    /// Upgrade a workflow to the current DSL version
+    Migrate {
+        /// Input workflow (.xml, .pxyz text syntax, or .json)
         #[arg(short, long)]
         input: PathBuf,
+        
+        /// Output XML file
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    
+    /// Print the workflow grammar as XML Schema or JSON Schema
+    Schema {
+        /// Output format: xsd (workflow XML) or json (JSON input)
+        #[arg(long, default_value = "xsd")]
+        format: String,
+        
+        /// Write to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
     },
     
     /// Create new project
@@ -65,14 +118,25 @@ fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
     
     match cli.command {
-        Command::Compile { input, output, audit, strict } => {
-            cmd_compile(&input, &output, audit, strict)
+        Command::Compile { input, output, audit, strict, profile, defines } => {
+            let profile = load_profile(profile.as_deref(), &defines)?;
+            cmd_compile(&input, &output, audit, strict, profile)
         }
         Command::Inspect { input, format } => {
             cmd_inspect(&input, &format)
         }
-        Command::Check { input } => {
-            cmd_check(&input)
+        Command::Check { input, strict, profile, defines } => {
+            let profile = load_profile(profile.as_deref(), &defines)?;
+            cmd_check(&input, strict, profile)
+        }
+        Command::Fmt { files, check } => {
+            cmd_fmt(&files, check)
+        }
+        Command::Migrate { input, output } => {
+            cmd_migrate(&input, &output)
+        }
+        Command::Schema { format, output } => {
+            cmd_schema(&format, output.as_deref())
         }
         Command::Init { name } => {
             cmd_init(&name)
@@ -80,7 +144,35 @@ fn main() -> anyhow::Result<()> {
     }
 }
 
-fn cmd_compile(input: &PathBuf, output: &PathBuf, emit_audit: bool, strict: bool) -> anyhow::Result<()> {
+/// Profile file (if any) with `--define`s applied on top
+fn load_profile(path: Option<&Path>, defines: &[String]) -> anyhow::Result<pxyz::dsl::Profile> {
+    let report = |err: pxyz::CompileError| match err {
+        pxyz::CompileError::Validation { diagnostics } => {
+            for diag in &diagnostics {
+                print_diagnostic(diag, "", path.unwrap_or(Path::new("")));
+            }
+            anyhow::anyhow!("Invalid profile")
+        }
+        err => anyhow::anyhow!("Cannot read profile: {}", err),
+    };
+    
+    let mut profile = match path {
+        Some(path) => pxyz::dsl::Profile::load(path).map_err(report)?,
+        None => pxyz::dsl::Profile::default(),
+    };
+    for definition in defines {
+        profile.define(definition).map_err(report)?;
+    }
+    Ok(profile)
+}
+
+fn cmd_compile(
+    input: &PathBuf,
+    output: &PathBuf,
+    emit_audit: bool,
+    strict: bool,
+    profile: pxyz::dsl::Profile,
+) -> anyhow::Result<()> {
     println!("{} {}", "Compiling".cyan(), input.display());
     
     let xml = std::fs::read_to_string(input)?;
@@ -89,9 +181,10 @@ fn cmd_compile(input: &PathBuf, output: &PathBuf, emit_audit: bool, strict: bool
         optimize: true,
         strict,
         emit_audit,
+        profile,
     };
     
-    match pxyz::compile(&xml, &options) {
+    match pxyz::compile_with_resolver(&xml, input, &pxyz::dsl::FileResolver, &options) {
         Ok(result) => {
             // Write binary
             std::fs::write(output, &result.binary)?;
@@ -107,27 +200,32 @@ fn cmd_compile(input: &PathBuf, output: &PathBuf, emit_audit: bool, strict: bool
             
             // Print diagnostics
             for diag in &result.diagnostics {
-                print_diagnostic(diag);
+                print_diagnostic(diag, &xml, input);
             }
             
             Ok(())
         }
         Err(pxyz::CompileError::Validation { diagnostics }) => {
             for diag in &diagnostics {
-                print_diagnostic(diag);
+                print_diagnostic(diag, &xml, input);
             }
             let error_count = diagnostics.iter()
                 .filter(|d| d.severity == pxyz::Severity::Error)
                 .count();
             anyhow::bail!("Compilation failed with {} error(s)", error_count);
         }
-        Err(e) => anyhow::bail!("{}", e),
+        Err(e) => {
+            print_diagnostic(&e.to_diagnostic(), &xml, input);
+            anyhow::bail!("Compilation failed");
+        }
     }
 }
 
 fn cmd_inspect(input: &PathBuf, format: &str) -> anyhow::Result<()> {
     let data = std::fs::read(input)?;
     let info = pxyz::inspect(&data).map_err(|e| anyhow::anyhow!(e))?;
+    let max_stack = info.predicate_bounds.iter().map(|b| b.max_stack).max().unwrap_or(0);
+    let max_steps = info.predicate_bounds.iter().map(|b| b.max_steps).max().unwrap_or(0);
     
     match format {
         "json" => {
@@ -136,6 +234,8 @@ fn cmd_inspect(input: &PathBuf, format: &str) -> anyhow::Result<()> {
                 "nodes": info.node_count,
                 "edges": info.edge_count,
                 "predicates": info.predicate_count,
+                "max_predicate_stack": max_stack,
+                "max_predicate_steps": max_steps,
                 "strings": info.string_pool_size,
                 "entries": info.entry_count,
                 "size": info.binary_size,
@@ -159,6 +259,8 @@ fn cmd_inspect(input: &PathBuf, format: &str) -> anyhow::Result<()> {
             println!("Nodes:       {}", info.node_count);
             println!("Edges:       {}", info.edge_count);
             println!("Predicates:  {}", info.predicate_count);
+            println!("  Stack:     {} max (limit {})", max_stack, pxyz::limits::MAX_STACK_DEPTH);
+            println!("  Steps:     {} max (limit {})", max_steps, pxyz::limits::MAX_PREDICATE_STEPS);
             println!("Strings:     {} bytes", info.string_pool_size);
             println!("Entries:     {}", info.entry_count);
             println!("Total size:  {} bytes", info.binary_size);
@@ -168,14 +270,19 @@ fn cmd_inspect(input: &PathBuf, format: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn cmd_check(input: &PathBuf) -> anyhow::Result<()> {
+fn cmd_check(input: &PathBuf, strict: bool, profile: pxyz::dsl::Profile) -> anyhow::Result<()> {
     println!("{} {}", "Checking".cyan(), input.display());
     
     let xml = std::fs::read_to_string(input)?;
-    let diagnostics = pxyz::validate(&xml);
+    let options = pxyz::CompileOptions {
+        strict,
+        profile,
+        ..Default::default()
+    };
+    let diagnostics = pxyz::validate_with_resolver(&xml, input, &pxyz::dsl::FileResolver, &options);
     
     for diag in &diagnostics {
-        print_diagnostic(diag);
+        print_diagnostic(diag, &xml, input);
     }
     
     let errors = diagnostics.iter().filter(|d| d.severity == pxyz::Severity::Error).count();
@@ -194,6 +301,92 @@ fn cmd_check(input: &PathBuf) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn cmd_fmt(files: &[PathBuf], check: bool) -> anyhow::Result<()> {
+    let mut failed = 0;
+    let mut unformatted = 0;
+    
+    for path in files {
+        if path.extension().is_some_and(|ext| ext != "xml") {
+            println!("{} {}: only XML files are formatted", "✗".red(), path.display());
+            failed += 1;
+            continue;
+        }
+        
+        let xml = std::fs::read_to_string(path)?;
+        let formatted = match pxyz::dsl::writer::format(&xml) {
+            Ok(formatted) => formatted,
+            Err(pxyz::CompileError::Validation { diagnostics }) => {
+                for diag in &diagnostics {
+                    print_diagnostic(diag, &xml, path);
+                }
+                println!("{} {}: not formatted", "✗".red(), path.display());
+                failed += 1;
+                continue;
+            }
+            Err(e) => anyhow::bail!("{}: {}", path.display(), e),
+        };
+        
+        if formatted == xml {
+            continue;
+        }
+        if check {
+            println!("{} {} is not formatted", "✗".red(), path.display());
+            unformatted += 1;
+        } else {
+            std::fs::write(path, formatted)?;
+            println!("{} Formatted {}", "✓".green(), path.display());
+        }
+    }
+    
+    if failed > 0 {
+        anyhow::bail!("{} file(s) could not be formatted", failed);
+    }
+    if unformatted > 0 {
+        anyhow::bail!("{} file(s) need formatting (run pxyz fmt)", unformatted);
+    }
+    Ok(())
+}
+
+fn cmd_migrate(input: &Path, output: &Path) -> anyhow::Result<()> {
+    let source = std::fs::read_to_string(input)?;
+    
+    match pxyz::dsl::version::migrate_source(input, &source) {
+        Ok((xml, applied)) => {
+            for migration in &applied {
+                println!("  {} → {}: {}", migration.from, migration.to, migration.description);
+            }
+            std::fs::write(output, xml)?;
+            println!("{} {} (DSL {}, {} migration(s))",
+                "✓".green(), output.display(), pxyz::dsl::DSL_VERSION, applied.len());
+            Ok(())
+        }
+        Err(pxyz::CompileError::Validation { diagnostics }) => {
+            for diag in &diagnostics {
+                print_diagnostic(diag, &source, input);
+            }
+            anyhow::bail!("{} could not be migrated", input.display())
+        }
+        Err(e) => anyhow::bail!("{}: {}", input.display(), e),
+    }
+}
+
+fn cmd_schema(format: &str, output: Option<&Path>) -> anyhow::Result<()> {
+    let schema = match format {
+        "xsd" => pxyz::dsl::grammar::xsd(),
+        "json" => serde_json::to_string_pretty(&pxyz::dsl::grammar::json_schema())? + "\n",
+        _ => anyhow::bail!("Unknown schema format '{}' (expected xsd or json)", format),
+    };
+    
+    match output {
+        Some(path) => {
+            std::fs::write(path, schema)?;
+            println!("{} {}", "✓".green(), path.display());
+        }
+        None => print!("{}", schema),
+    }
+    Ok(())
+}
+
 fn cmd_init(name: &str) -> anyhow::Result<()> {
     std::fs::create_dir_all(name)?;
     
@@ -244,7 +437,7 @@ echo "Done. Deploy graph.bin + pxyz.wasm + io.js"
     Ok(())
 }
 
-fn print_diagnostic(diag: &pxyz::Diagnostic) {
+fn print_diagnostic(diag: &pxyz::Diagnostic, source: &str, path: &Path) {
     let (prefix, color) = match diag.severity {
         pxyz::Severity::Error => ("ERROR", "red"),
         pxyz::Severity::Warn => ("WARN ", "yellow"),
@@ -259,6 +452,20 @@ fn print_diagnostic(diag: &pxyz::Diagnostic) {
     
     println!("{} [{}] {}", prefix_colored, diag.code.dimmed(), diag.message);
     
+    if let Some(loc) = &diag.location {
+        if let Some(span) = loc.span {
+            // Diagnostics from imported files point into that file
+            match &loc.file {
+                Some(file) => {
+                    if let Ok(imported) = std::fs::read_to_string(file) {
+                        print_code_frame(&imported, Path::new(file), span, color);
+                    }
+                }
+                None => print_code_frame(source, path, span, color),
+            }
+        }
+    }
+    
     if let Some(hint) = &diag.hint {
         println!("      {} {}", "hint:".dimmed(), hint);
     }
@@ -273,3 +480,45 @@ fn print_diagnostic(diag: &pxyz::Diagnostic) {
         }
     }
 }
+
+/// Print the source line containing `span` with a caret underline
+///
+/// ```text
+///       --> workflow.xml:12:9
+///        |
+///     12 |         <edge from="start" to="missing"/>
+///        |         ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+/// ```
+fn print_code_frame(source: &str, path: &Path, span: pxyz::Span, color: &str) {
+    let Some(line) = source.lines().nth(span.line.saturating_sub(1) as usize) else {
+        return;
+    };
+    let line = line.trim_end_matches('\r');
+    let line_no = span.line.to_string();
+    let gutter = " ".repeat(5 + line_no.len());
+    
+    // Keep tabs so the caret lines up with the source line
+    let col = span.column.saturating_sub(1) as usize;
+    let padding: String = line.chars()
+        .take(col)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    
+    // Underline the span, clipped to the first line
+    let remaining = line.chars().count().saturating_sub(col);
+    let width = source.get(span.start..span.end)
+        .map(|s| s.lines().next().unwrap_or("").chars().count())
+        .unwrap_or(0)
+        .clamp(1, remaining.max(1));
+    let carets = "^".repeat(width);
+    let carets = match color {
+        "red" => carets.red(),
+        "yellow" => carets.yellow(),
+        _ => carets.blue(),
+    };
+    
+    println!("   {}{} {}:{}:{}", " ".repeat(line_no.len()), "-->".blue(), path.display(), span.line, span.column);
+    println!("{}{}", gutter, "|".blue());
+    println!("    {} {} {}", line_no.blue(), "|".blue(), line);
+    println!("{}{} {}{}", gutter, "|".blue(), padding, carets);
+}
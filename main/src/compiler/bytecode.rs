@@ -3,14 +3,24 @@
 //! Compiles predicate expressions from AST into bytecode for the WAT VM.
 //! The bytecode format must match what pxyz.wat expects.
 
-use crate::{Opcode, CompileError, limits};
-use crate::dsl::ast::{PredicateExpr, Value};
-use crate::compiler::ir::StringPool;
+use crate::{Opcode, CompileError, Span, DECIMAL_PLACES, limits};
+use crate::dsl::ast::{Arith, ArithOp, Comparison, PredicateExpr, Value};
+use crate::compiler::ir::{PredicateId, StringPool};
+use crate::dsl::expr::{is_unspaced_arith, unspaced_arith_message};
+use crate::dsl::time;
+use crate::dsl::writer::value_text;
+use std::collections::HashMap;
 
 /// Predicate bytecode compiler
 pub struct PredicateCompiler<'a> {
     bytecode: Vec<u8>,
     strings: &'a mut StringPool,
+    /// Source spans of the expression nodes, in pre-order
+    spans: &'a [Span],
+    /// Pre-order index of the next expression node to compile
+    expr_index: usize,
+    /// Named predicates that `<ref>` may call
+    predicates: Option<&'a HashMap<String, PredicateId>>,
 }
 
 impl<'a> PredicateCompiler<'a> {
@@ -18,12 +28,30 @@ impl<'a> PredicateCompiler<'a> {
         Self {
             bytecode: Vec::new(),
             strings,
+            spans: &[],
+            expr_index: 0,
+            predicates: None,
         }
     }
     
+    /// Attach source spans (pre-order, see `PredicateExpr::children`) so
+    /// errors point at the offending subexpression
+    pub fn with_spans(mut self, spans: &'a [Span]) -> Self {
+        self.spans = spans;
+        self
+    }
+    
+    /// Resolve `<ref predicate>` against named predicate ids; references
+    /// compile to `CallPred`
+    pub fn with_predicates(mut self, predicates: &'a HashMap<String, PredicateId>) -> Self {
+        self.predicates = Some(predicates);
+        self
+    }
+    
     /// Compile a predicate expression to bytecode
     pub fn compile(&mut self, expr: &PredicateExpr) -> Result<Vec<u8>, CompileError> {
         self.bytecode.clear();
+        self.expr_index = 0;
         self.compile_expr(expr)?;
         self.emit(Opcode::Ret as u8);
         
@@ -39,6 +67,9 @@ impl<'a> PredicateCompiler<'a> {
     }
     
     fn compile_expr(&mut self, expr: &PredicateExpr) -> Result<(), CompileError> {
+        let span = self.spans.get(self.expr_index).copied();
+        self.expr_index += 1;
+        
         match expr {
             PredicateExpr::Always => {
                 self.emit(Opcode::PushInt as u8);
@@ -51,49 +82,49 @@ impl<'a> PredicateCompiler<'a> {
             }
             
             PredicateExpr::Eq { left, right } => {
-                self.compile_var(left);
-                self.compile_value(right);
+                self.compile_var(left, span)?;
+                self.compile_value(right, span)?;
                 self.emit(Opcode::Eq as u8);
             }
             
             PredicateExpr::Neq { left, right } => {
-                self.compile_var(left);
-                self.compile_value(right);
+                self.compile_var(left, span)?;
+                self.compile_value(right, span)?;
                 self.emit(Opcode::Neq as u8);
             }
             
             PredicateExpr::Gt { left, right } => {
-                self.compile_var(left);
-                self.compile_value(right);
+                self.compile_var(left, span)?;
+                self.compile_value(right, span)?;
                 self.emit(Opcode::Gt as u8);
             }
             
             PredicateExpr::Gte { left, right } => {
-                self.compile_var(left);
-                self.compile_value(right);
+                self.compile_var(left, span)?;
+                self.compile_value(right, span)?;
                 self.emit(Opcode::Gte as u8);
             }
             
             PredicateExpr::Lt { left, right } => {
-                self.compile_var(left);
-                self.compile_value(right);
+                self.compile_var(left, span)?;
+                self.compile_value(right, span)?;
                 self.emit(Opcode::Lt as u8);
             }
             
             PredicateExpr::Lte { left, right } => {
-                self.compile_var(left);
-                self.compile_value(right);
+                self.compile_var(left, span)?;
+                self.compile_value(right, span)?;
                 self.emit(Opcode::Lte as u8);
             }
             
             PredicateExpr::Contains { left, right } => {
-                self.compile_var(left);
-                self.compile_var(right);
+                self.compile_var(left, span)?;
+                self.compile_var(right, span)?;
                 self.emit(Opcode::Contains as u8);
             }
             
             PredicateExpr::Matches { left, pattern } => {
-                self.compile_var(left);
+                self.compile_var(left, span)?;
                 self.emit(Opcode::PushStr as u8);
                 let offset = self.strings.intern(pattern);
                 self.emit_u32(offset);
@@ -101,7 +132,7 @@ impl<'a> PredicateCompiler<'a> {
             }
             
             PredicateExpr::StartsWith { left, prefix } => {
-                self.compile_var(left);
+                self.compile_var(left, span)?;
                 self.emit(Opcode::PushStr as u8);
                 let offset = self.strings.intern(prefix);
                 self.emit_u32(offset);
@@ -109,39 +140,49 @@ impl<'a> PredicateCompiler<'a> {
             }
             
             PredicateExpr::EndsWith { left, suffix } => {
-                self.compile_var(left);
+                self.compile_var(left, span)?;
                 self.emit(Opcode::PushStr as u8);
                 let offset = self.strings.intern(suffix);
                 self.emit_u32(offset);
                 self.emit(Opcode::EndsWith as u8);
             }
             
+            PredicateExpr::In { left, values } => {
+                self.compile_in(left, values, span)?;
+            }
+            
+            PredicateExpr::NotIn { left, values } => {
+                self.compile_in(left, values, span)?;
+                self.emit(Opcode::Not as u8);
+            }
+            
+            PredicateExpr::Before { left, right } | PredicateExpr::After { left, right } => {
+                // Times are epoch milliseconds, so these are Lt and Gt
+                let value = Value::time_from_str(right).ok_or_else(|| CompileError::Predicate(format!(
+                    "'{}' is not a date, a duration or a variable",
+                    right
+                )).with_span(span))?;
+                self.compile_var(left, span)?;
+                self.compile_value(&value, span)?;
+                let op = if matches!(expr, PredicateExpr::Before { .. }) { Opcode::Lt } else { Opcode::Gt };
+                self.emit(op as u8);
+            }
+            
+            PredicateExpr::Within { left, duration } => {
+                let millis = parse_duration_literal(duration, span)?;
+                self.compile_var(left, span)?;
+                self.emit(Opcode::Within as u8);
+                self.emit_i64(millis);
+            }
+            
             PredicateExpr::And { conditions } => {
-                if conditions.is_empty() {
-                    // Empty AND = true
-                    self.emit(Opcode::PushInt as u8);
-                    self.emit_i32(1);
-                } else {
-                    self.compile_expr(&conditions[0])?;
-                    for cond in conditions.iter().skip(1) {
-                        self.compile_expr(cond)?;
-                        self.emit(Opcode::And as u8);
-                    }
-                }
+                // Empty AND = true; the first false operand decides
+                self.compile_short_circuit(conditions, Opcode::JumpIfFalse, 1)?;
             }
             
             PredicateExpr::Or { conditions } => {
-                if conditions.is_empty() {
-                    // Empty OR = false
-                    self.emit(Opcode::PushInt as u8);
-                    self.emit_i32(0);
-                } else {
-                    self.compile_expr(&conditions[0])?;
-                    for cond in conditions.iter().skip(1) {
-                        self.compile_expr(cond)?;
-                        self.emit(Opcode::Or as u8);
-                    }
-                }
+                // Empty OR = false; the first true operand decides
+                self.compile_short_circuit(conditions, Opcode::JumpIfTrue, 0)?;
             }
             
             PredicateExpr::Not { condition } => {
@@ -150,46 +191,211 @@ impl<'a> PredicateCompiler<'a> {
             }
             
             PredicateExpr::Ref { predicate } => {
-                // Predicate references are resolved during lowering
-                // If we get here, it means the reference wasn't resolved
-                return Err(CompileError::Predicate(format!(
-                    "Unresolved predicate reference: {}",
-                    predicate
-                )));
+                let Some(predicates) = self.predicates else {
+                    return Err(CompileError::Predicate(format!(
+                        "Unresolved predicate reference: {}",
+                        predicate
+                    )).with_span(span));
+                };
+                let id = predicates.get(predicate).copied()
+                    .ok_or_else(|| CompileError::Predicate(format!(
+                        "Unknown predicate reference: {}",
+                        predicate
+                    )).with_span(span))?;
+                self.emit(Opcode::CallPred as u8);
+                self.emit_u16(id);
             }
             
             PredicateExpr::Fn { name, arg } => {
-                self.compile_var(arg);
-                match name.to_lowercase().as_str() {
-                    // Utility functions
-                    "length" | "len" => self.emit(Opcode::Len as u8),
-                    "defined" | "isdefined" | "is_defined" => self.emit(Opcode::IsDefined as u8),
-                    "null" | "isnull" | "is_null" => self.emit(Opcode::IsNull as u8),
-                    "confirmed" | "isconfirmed" | "is_confirmed" => self.emit(Opcode::IsConfirmed as u8),
-
-                    // Merge/CRDT functions (Y-constraint operations for conflict resolution)
-                    "timestamp" | "ts" => self.emit(Opcode::Timestamp as u8),
-                    "flagged" | "isflagged" | "is_flagged" => self.emit(Opcode::IsFlagged as u8),
-                    "origin" | "author" => self.emit(Opcode::Origin as u8),
-
-                    _ => {
-                        return Err(CompileError::Predicate(format!(
-                            "Unknown function: {}",
-                            name
-                        )));
-                    }
+                self.compile_call(name, arg, span)?;
+            }
+            
+            PredicateExpr::Compare { cmp, left, right } => {
+                self.compile_arith(left, span)?;
+                self.compile_arith(right, span)?;
+                let opcode = match cmp {
+                    Comparison::Eq => Opcode::Eq,
+                    Comparison::Neq => Opcode::Neq,
+                    Comparison::Gt => Opcode::Gt,
+                    Comparison::Gte => Opcode::Gte,
+                    Comparison::Lt => Opcode::Lt,
+                    Comparison::Lte => Opcode::Lte,
+                };
+                self.emit(opcode as u8);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Compile `left in values`: the operand, then `In` with the members
+    /// as a single comma-separated string pool entry
+    fn compile_in(&mut self, left: &str, values: &[Value], span: Option<Span>) -> Result<(), CompileError> {
+        let mut members = Vec::with_capacity(values.len());
+        for value in values {
+            match value {
+                Value::Var(var) => {
+                    return Err(CompileError::Predicate(format!(
+                        "List member '{}' is not a literal",
+                        var
+                    )).with_span(span));
+                }
+                Value::Time(text) | Value::Duration(text) => {
+                    return Err(CompileError::Predicate(format!(
+                        "List member '{}' is a date or duration; compare times with <before>, <after> or <within>",
+                        text
+                    )).with_span(span));
                 }
+                // Decimal members follow the same precision rules as literals
+                Value::Float(f) => {
+                    encode_decimal(*f).map_err(|message| CompileError::Predicate(message).with_span(span))?;
+                }
+                _ => {}
+            }
+            let member = value_text(value);
+            if member.contains(',') {
+                return Err(CompileError::Predicate(format!(
+                    "List member '{}' contains ','",
+                    member
+                )).with_span(span));
+            }
+            members.push(member);
+        }
+        
+        self.compile_var(left, span)?;
+        self.emit(Opcode::In as u8);
+        let offset = self.strings.intern(&members.join(","));
+        self.emit_u32(offset);
+        Ok(())
+    }
+    
+    /// Compile arithmetic in postfix order: operands, then the operator
+    fn compile_arith(&mut self, arith: &Arith, span: Option<Span>) -> Result<(), CompileError> {
+        match arith {
+            Arith::Operand(operand) => self.compile_operand(operand, span)?,
+            Arith::Neg(inner) => {
+                self.compile_arith_operand(inner, span)?;
+                self.emit(Opcode::Neg as u8);
+            }
+            Arith::Binary { op, left, right } => {
+                self.compile_arith_operand(left, span)?;
+                self.compile_arith_operand(right, span)?;
+                let opcode = match op {
+                    ArithOp::Add => Opcode::Add,
+                    ArithOp::Sub => Opcode::Sub,
+                    ArithOp::Mul => Opcode::Mul,
+                    ArithOp::Div => Opcode::Div,
+                    ArithOp::Mod => Opcode::Mod,
+                };
+                self.emit(opcode as u8);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Compile an operand of `+ - * / %` or negation, which must not be a
+    /// string literal (the VM would fault on it)
+    fn compile_arith_operand(&mut self, arith: &Arith, span: Option<Span>) -> Result<(), CompileError> {
+        if let Arith::Operand(operand) = arith {
+            if is_string_literal(operand) {
+                return Err(CompileError::Predicate(format!(
+                    "Arithmetic operand '{}' is not a number",
+                    operand
+                )).with_span(span));
+            }
+        }
+        self.compile_arith(arith, span)
+    }
+    
+    /// Compile a `<compare>` operand: a variable or function call, else a
+    /// date, a duration or a literal read like a comparison's right-hand
+    /// value
+    ///
+    /// A duration is a length in milliseconds here, so that
+    /// `$task.due - $task.created < P3D` compares two lengths.
+    fn compile_operand(&mut self, operand: &str, span: Option<Span>) -> Result<(), CompileError> {
+        if operand.starts_with('$') || operand_call(operand).is_some() {
+            return self.compile_var(operand, span);
+        }
+        match Value::time_from_str(operand).unwrap_or_else(|| Value::from_str_guess(operand)) {
+            Value::Duration(text) => {
+                let millis = parse_duration_literal(&text, span)?;
+                self.compile_value(&Value::Int(millis), span)
             }
+            value => self.compile_value(&value, span),
+        }
+    }
+    
+    /// Compile AND/OR operands with a `jump` after each but the last
+    ///
+    /// When an operand decides the result, the jump skips the remaining
+    /// operands and leaves it on the stack as the result; otherwise it is
+    /// popped and the next operand runs.
+    fn compile_short_circuit(&mut self, conditions: &[PredicateExpr], jump: Opcode, empty: i32) -> Result<(), CompileError> {
+        if conditions.is_empty() {
+            self.emit(Opcode::PushInt as u8);
+            self.emit_i32(empty);
+            return Ok(());
+        }
+        
+        let mut targets = Vec::new();
+        for (i, cond) in conditions.iter().enumerate() {
+            self.compile_expr(cond)?;
+            if i + 1 < conditions.len() {
+                self.emit(jump as u8);
+                targets.push(self.bytecode.len());
+                self.emit_u16(0);
+            }
+        }
+        
+        let end = u16::try_from(self.bytecode.len()).map_err(|_| CompileError::Predicate(format!(
+            "Bytecode too large: jump target {} exceeds 16 bits",
+            self.bytecode.len()
+        )))?;
+        for at in targets {
+            self.bytecode[at..at + 2].copy_from_slice(&end.to_le_bytes());
         }
         Ok(())
     }
     
-    /// Compile a variable reference or string literal
-    fn compile_var(&mut self, path: &str) {
-        if path.starts_with('$') {
+    /// Compile `name(arg)`: the argument, then the function applied to it
+    fn compile_call(&mut self, name: &str, arg: &str, span: Option<Span>) -> Result<(), CompileError> {
+        self.compile_var(arg, span)?;
+        let opcode = match name.to_lowercase().as_str() {
+            // Utility functions
+            "length" | "len" => Opcode::Len,
+            "defined" | "isdefined" | "is_defined" => Opcode::IsDefined,
+            "null" | "isnull" | "is_null" => Opcode::IsNull,
+            "confirmed" | "isconfirmed" | "is_confirmed" => Opcode::IsConfirmed,
+
+            // Merge/CRDT functions (Y-constraint operations for conflict resolution)
+            "timestamp" | "ts" => Opcode::Timestamp,
+            "flagged" | "isflagged" | "is_flagged" => Opcode::IsFlagged,
+            "origin" | "author" => Opcode::Origin,
+
+            _ => {
+                return Err(CompileError::Predicate(format!(
+                    "Unknown function: {}",
+                    name
+                )).with_span(span));
+            }
+        };
+        self.emit(opcode as u8);
+        Ok(())
+    }
+    
+    /// Compile a variable reference, function call or string literal
+    ///
+    /// An operand `name(arg)`, e.g. `left="len($input.items)"`, compares
+    /// the function's result.
+    fn compile_var(&mut self, path: &str, span: Option<Span>) -> Result<(), CompileError> {
+        if let Some((name, arg)) = operand_call(path) {
+            self.compile_call(name, arg, span)?;
+        } else if is_unspaced_arith(path) {
+            return Err(CompileError::Predicate(unspaced_arith_message(path)).with_span(span));
+        } else if let Some(var) = path.strip_prefix('$') {
             // Variable reference: $token.sub, $entity.owner_id, etc.
             self.emit(Opcode::LoadVar as u8);
-            let offset = self.strings.intern(&path[1..]); // Skip $
+            let offset = self.strings.intern(var);
             self.emit_u32(offset);
         } else {
             // String literal
@@ -197,19 +403,34 @@ impl<'a> PredicateCompiler<'a> {
             let offset = self.strings.intern(path);
             self.emit_u32(offset);
         }
+        Ok(())
     }
     
     /// Compile a value (int, string, bool, or variable)
-    fn compile_value(&mut self, value: &Value) {
+    fn compile_value(&mut self, value: &Value, span: Option<Span>) -> Result<(), CompileError> {
         match value {
             Value::Int(n) => {
-                self.emit(Opcode::PushInt as u8);
-                self.emit_i32(*n as i32);
+                // Narrowest encoding: PUSH_INT when the value fits an i32
+                if let Ok(n) = i32::try_from(*n) {
+                    self.emit(Opcode::PushInt as u8);
+                    self.emit_i32(n);
+                } else {
+                    self.emit(Opcode::PushI64 as u8);
+                    self.emit_i64(*n);
+                }
+            }
+            Value::Float(f) if f.fract() == 0.0 && f.abs() >= i64::MAX as f64 => {
+                // An integer literal too large for i64 reads as a float
+                return Err(CompileError::Predicate(format!(
+                    "Integer literal {} does not fit in 64 bits (max {})",
+                    f, i64::MAX
+                )).with_span(span));
             }
             Value::Float(f) => {
-                // Truncate to integer for now
-                self.emit(Opcode::PushInt as u8);
-                self.emit_i32(*f as i32);
+                let decimal = encode_decimal(*f)
+                    .map_err(|message| CompileError::Predicate(message).with_span(span))?;
+                self.emit(Opcode::PushDec as u8);
+                self.emit_i64(decimal);
             }
             Value::Str(s) => {
                 self.emit(Opcode::PushStr as u8);
@@ -221,9 +442,21 @@ impl<'a> PredicateCompiler<'a> {
                 self.emit_i32(if *b { 1 } else { 0 });
             }
             Value::Var(path) => {
-                self.compile_var(path);
+                self.compile_var(path, span)?;
+            }
+            Value::Time(text) => {
+                let millis = time::parse_time(text)
+                    .map_err(|message| CompileError::Predicate(message).with_span(span))?;
+                self.compile_value(&Value::Int(millis), span)?;
+            }
+            Value::Duration(text) => {
+                // A point in time relative to now: `-P7D` is seven days ago
+                let millis = parse_duration_literal(text, span)?;
+                self.emit(Opcode::Now as u8);
+                self.emit_i64(millis);
             }
         }
+        Ok(())
     }
     
     fn emit(&mut self, byte: u8) {
@@ -234,16 +467,116 @@ impl<'a> PredicateCompiler<'a> {
         self.bytecode.extend_from_slice(&v.to_le_bytes());
     }
     
+    fn emit_i64(&mut self, v: i64) {
+        self.bytecode.extend_from_slice(&v.to_le_bytes());
+    }
+    
     fn emit_u32(&mut self, v: u32) {
         self.bytecode.extend_from_slice(&v.to_le_bytes());
     }
     
-    #[allow(dead_code)]
     fn emit_u16(&mut self, v: u16) {
         self.bytecode.extend_from_slice(&v.to_le_bytes());
     }
 }
 
+/// Milliseconds of a duration literal, as a predicate error at `span`
+fn parse_duration_literal(text: &str, span: Option<Span>) -> Result<i64, CompileError> {
+    time::parse_duration(text).map_err(|message| CompileError::Predicate(message).with_span(span))
+}
+
+/// True if a `<compare>` operand compiles to a string literal
+fn is_string_literal(operand: &str) -> bool {
+    !operand.starts_with('$')
+        && operand_call(operand).is_none()
+        && matches!(Value::from_str_guess(operand), Value::Str(_))
+}
+
+/// Split an operand `name(arg)` into name and argument
+pub(crate) fn operand_call(operand: &str) -> Option<(&str, &str)> {
+    let (name, rest) = operand.split_once('(')?;
+    let arg = rest.strip_suffix(')')?;
+    let is_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    is_name.then(|| (name, arg.trim()))
+}
+
+/// Encode a decimal literal as a `PushDec` operand
+///
+/// The literal's shortest decimal form must fit `DECIMAL_PLACES`
+/// fractional digits and the i64 range exactly; anything else would be
+/// rounded, so it is rejected.
+pub fn encode_decimal(value: f64) -> Result<i64, String> {
+    if !value.is_finite() {
+        return Err(format!("Decimal literal {} is not a finite number", value));
+    }
+    let text = value.abs().to_string();
+    let (int_part, frac_part) = text.split_once('.').unwrap_or((&text, ""));
+    if frac_part.len() > DECIMAL_PLACES as usize {
+        return Err(format!(
+            "Decimal literal {} loses precision: at most {} fractional digits are supported",
+            value, DECIMAL_PLACES
+        ));
+    }
+    let digits = format!("{}{:0<width$}", int_part, frac_part, width = DECIMAL_PLACES as usize);
+    let magnitude = digits.parse::<i128>()
+        .map_err(|_| format!("Decimal literal {} is out of range", value))?;
+    let scaled = if value < 0.0 { -magnitude } else { magnitude };
+    i64::try_from(scaled).map_err(|_| format!(
+        "Decimal literal {} is out of range (max ±{})",
+        value,
+        format_decimal(i64::MAX)
+    ))
+}
+
+/// Format a `PushDec` operand as a decimal literal
+pub fn format_decimal(decimal: i64) -> String {
+    let scale = 10u64.pow(DECIMAL_PLACES);
+    let sign = if decimal < 0 { "-" } else { "" };
+    let magnitude = decimal.unsigned_abs();
+    let frac = format!("{:0width$}", magnitude % scale, width = DECIMAL_PLACES as usize);
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        format!("{}{}.0", sign, magnitude / scale)
+    } else {
+        format!("{}{}.{}", sign, magnitude / scale, frac)
+    }
+}
+
+/// Size in bytes of the operand following `opcode`
+///
+/// `Matches`, like `StartsWith`, takes its pattern from the stack.
+/// `MergeField` takes a selector byte and a field path offset.
+pub fn operand_size(opcode: Opcode) -> usize {
+    match opcode {
+        Opcode::PushInt | Opcode::PushStr | Opcode::LoadVar | Opcode::LoadField | Opcode::In => 4,
+        Opcode::MergeField => 5,
+        Opcode::PushDec | Opcode::PushI64 | Opcode::Now | Opcode::Within => 8,
+        Opcode::CallPred | Opcode::JumpIfFalse | Opcode::JumpIfTrue => 2,
+        _ => 0,
+    }
+}
+
+/// Rewrite the predicate id of every `CallPred` in `bytecode`
+///
+/// Stops at the first invalid opcode or truncated operand; run
+/// `validate_bytecode` first to reject those.
+pub fn remap_calls(bytecode: &mut [u8], remap: impl Fn(PredicateId) -> PredicateId) {
+    let mut pc = 0;
+    while let Some(opcode) = bytecode.get(pc).and_then(|&b| Opcode::from_byte(b)) {
+        pc += 1;
+        let size = operand_size(opcode);
+        if pc + size > bytecode.len() {
+            return;
+        }
+        if opcode == Opcode::CallPred {
+            let id = u16::from_le_bytes([bytecode[pc], bytecode[pc + 1]]);
+            bytecode[pc..pc + 2].copy_from_slice(&remap(id).to_le_bytes());
+        }
+        pc += size;
+    }
+}
+
 /// Disassemble bytecode to human-readable string
 pub fn disassemble(bytecode: &[u8], strings: &StringPool) -> String {
     let mut result = String::new();
@@ -273,6 +606,28 @@ pub fn disassemble(bytecode: &[u8], strings: &StringPool) -> String {
                         break;
                     }
                 }
+                Opcode::PushDec => {
+                    if pc + 8 <= bytecode.len() {
+                        let mut operand = [0u8; 8];
+                        operand.copy_from_slice(&bytecode[pc..pc + 8]);
+                        result.push_str(&format!(" {}", format_decimal(i64::from_le_bytes(operand))));
+                        pc += 8;
+                    } else {
+                        result.push_str(" <truncated>");
+                        break;
+                    }
+                }
+                Opcode::PushI64 => {
+                    if pc + 8 <= bytecode.len() {
+                        let mut operand = [0u8; 8];
+                        operand.copy_from_slice(&bytecode[pc..pc + 8]);
+                        result.push_str(&format!(" {}", i64::from_le_bytes(operand)));
+                        pc += 8;
+                    } else {
+                        result.push_str(" <truncated>");
+                        break;
+                    }
+                }
                 Opcode::PushStr | Opcode::LoadVar | Opcode::LoadField => {
                     if pc + 4 <= bytecode.len() {
                         let offset = u32::from_le_bytes([
@@ -292,8 +647,18 @@ pub fn disassemble(bytecode: &[u8], strings: &StringPool) -> String {
                         break;
                     }
                 }
-                Opcode::Matches => {
-                    // Pattern follows as string offset
+                Opcode::Now | Opcode::Within => {
+                    if pc + 8 <= bytecode.len() {
+                        let mut operand = [0u8; 8];
+                        operand.copy_from_slice(&bytecode[pc..pc + 8]);
+                        result.push_str(&format!(" {}", time::format_duration(i64::from_le_bytes(operand))));
+                        pc += 8;
+                    } else {
+                        result.push_str(" <truncated>");
+                        break;
+                    }
+                }
+                Opcode::In => {
                     if pc + 4 <= bytecode.len() {
                         let offset = u32::from_le_bytes([
                             bytecode[pc],
@@ -302,11 +667,37 @@ pub fn disassemble(bytecode: &[u8], strings: &StringPool) -> String {
                             bytecode[pc + 3],
                         ]);
                         if let Some(s) = strings.get(offset) {
-                            result.push_str(&format!(" /{}/", s));
+                            result.push_str(&format!(" [{}]", s));
                         } else {
                             result.push_str(&format!(" @{}", offset));
                         }
                         pc += 4;
+                    } else {
+                        result.push_str(" <truncated>");
+                        break;
+                    }
+                }
+                Opcode::MergeField => {
+                    if pc + 5 <= bytecode.len() {
+                        let offset = u32::from_le_bytes([
+                            bytecode[pc + 1],
+                            bytecode[pc + 2],
+                            bytecode[pc + 3],
+                            bytecode[pc + 4],
+                        ]);
+                        let context = match bytecode[pc] {
+                            0 => "$a",
+                            1 => "$b",
+                            _ => "$candidate",
+                        };
+                        match strings.get(offset) {
+                            Some(s) => result.push_str(&format!(" {}.{}", context, s)),
+                            None => result.push_str(&format!(" {} @{}", context, offset)),
+                        }
+                        pc += 5;
+                    } else {
+                        result.push_str(" <truncated>");
+                        break;
                     }
                 }
                 Opcode::CallPred => {
@@ -319,6 +710,19 @@ pub fn disassemble(bytecode: &[u8], strings: &StringPool) -> String {
                         break;
                     }
                 }
+                Opcode::JumpIfFalse | Opcode::JumpIfTrue => {
+                    if pc + 2 <= bytecode.len() {
+                        let target = u16::from_le_bytes([bytecode[pc], bytecode[pc + 1]]) as usize;
+                        result.push_str(&format!(" -> {:04x}", target));
+                        if let Err(problem) = check_jump_target(bytecode, pc - 1, target) {
+                            result.push_str(&format!(" <{}>", problem));
+                        }
+                        pc += 2;
+                    } else {
+                        result.push_str(" <truncated>");
+                        break;
+                    }
+                }
                 _ => {}
             }
         } else {
@@ -332,7 +736,35 @@ pub fn disassemble(bytecode: &[u8], strings: &StringPool) -> String {
     result
 }
 
+/// Offsets at which instructions start, stopping at the first invalid
+/// opcode or truncated operand
+fn instruction_starts(bytecode: &[u8]) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut pc = 0;
+    while let Some(opcode) = bytecode.get(pc).and_then(|&b| Opcode::from_byte(b)) {
+        starts.push(pc);
+        pc += 1 + operand_size(opcode);
+    }
+    starts
+}
+
+/// Check that the jump at `at` targets an instruction after it
+fn check_jump_target(bytecode: &[u8], at: usize, target: usize) -> Result<(), &'static str> {
+    if target <= at {
+        Err("backward jump")
+    } else if target >= bytecode.len() {
+        Err("jump out of bounds")
+    } else if instruction_starts(bytecode).binary_search(&target).is_err() {
+        Err("jump into an operand")
+    } else {
+        Ok(())
+    }
+}
+
 /// Validate bytecode without executing
+///
+/// Besides opcodes and operands, every jump must target an instruction
+/// after it, so execution only moves forward.
 pub fn validate_bytecode(bytecode: &[u8]) -> Result<(), String> {
     if bytecode.is_empty() {
         return Err("Empty bytecode".into());
@@ -364,11 +796,7 @@ pub fn validate_bytecode(bytecode: &[u8]) -> Result<(), String> {
         pc += 1;
         
         // Check operand bounds
-        let operand_size = match opcode {
-            Opcode::PushInt | Opcode::PushStr | Opcode::LoadVar | Opcode::LoadField | Opcode::Matches => 4,
-            Opcode::CallPred => 2,
-            _ => 0,
-        };
+        let operand_size = operand_size(opcode);
         
         if pc + operand_size > bytecode.len() {
             return Err(format!(
@@ -378,6 +806,14 @@ pub fn validate_bytecode(bytecode: &[u8]) -> Result<(), String> {
             ));
         }
         
+        if matches!(opcode, Opcode::JumpIfFalse | Opcode::JumpIfTrue) {
+            let target = u16::from_le_bytes([bytecode[pc], bytecode[pc + 1]]) as usize;
+            check_jump_target(bytecode, pc - 1, target).map_err(|problem| format!(
+                "Invalid {:?} at offset {}: {} to {}",
+                opcode, pc - 1, problem, target
+            ))?;
+        }
+        
         pc += operand_size;
     }
     
@@ -439,11 +875,56 @@ mod tests {
             ],
         }).unwrap();
         
-        // Two conditions with AND between them
-        assert!(bytecode.contains(&(Opcode::And as u8)));
+        // PUSH_INT 1, JUMP_IF_FALSE end, PUSH_INT 1, end: RET
+        assert_eq!(bytecode[5], Opcode::JumpIfFalse as u8);
+        assert_eq!(u16::from_le_bytes([bytecode[6], bytecode[7]]), 13);
+        assert_eq!(bytecode[13], Opcode::Ret as u8);
         assert!(validate_bytecode(&bytecode).is_ok());
     }
     
+    #[test]
+    fn test_short_circuit_or() {
+        let mut strings = StringPool::new();
+        let bytecode = PredicateCompiler::new(&mut strings).compile(&PredicateExpr::Or {
+            conditions: vec![
+                PredicateExpr::Eq { left: "$token.role".into(), right: Value::Str("admin".into()) },
+                PredicateExpr::Matches { left: "$input.email".into(), pattern: ".+@corp".into() },
+                PredicateExpr::Always,
+            ],
+        }).unwrap();
+        
+        // Both jumps skip to the final RET, past the MATCHES host call
+        let disasm = disassemble(&bytecode, &strings);
+        let end = bytecode.len() - 1;
+        let jumps: Vec<_> = disasm.lines().filter(|l| l.contains("JumpIfTrue")).collect();
+        assert_eq!(jumps.len(), 2);
+        assert!(jumps.iter().all(|l| l.ends_with(&format!("-> {:04x}", end))), "{}", disasm);
+        assert!(!disasm.lines().any(|l| l.ends_with(": Or")), "{}", disasm);
+        assert!(validate_bytecode(&bytecode).is_ok());
+    }
+    
+    #[test]
+    fn test_validate_jump_targets() {
+        let push = [Opcode::PushInt as u8, 1, 0, 0, 0];
+        let program = |target: u16| {
+            let mut code = push.to_vec();
+            code.push(Opcode::JumpIfFalse as u8);
+            code.extend_from_slice(&target.to_le_bytes());
+            code.extend_from_slice(&push);
+            code.push(Opcode::Ret as u8);
+            code
+        };
+        
+        assert!(validate_bytecode(&program(13)).is_ok());
+        assert!(validate_bytecode(&program(8)).is_ok(), "jump to the next instruction");
+        let err = |target| validate_bytecode(&program(target)).unwrap_err();
+        assert!(err(0).contains("backward jump"), "{}", err(0));
+        assert!(err(5).contains("backward jump"), "a jump to itself loops");
+        assert!(err(14).contains("out of bounds"));
+        assert!(err(10).contains("into an operand"));
+        assert!(disassemble(&program(0), &StringPool::new()).contains("-> 0000 <backward jump>"));
+    }
+    
     #[test]
     fn test_compile_not() {
         let mut strings = StringPool::new();
@@ -456,6 +937,222 @@ mod tests {
         assert!(validate_bytecode(&bytecode).is_ok());
     }
     
+    #[test]
+    fn test_compile_ref() {
+        let mut strings = StringPool::new();
+        let ids = HashMap::from([("is_admin".to_string(), 3)]);
+        let expr = PredicateExpr::Not {
+            condition: Box::new(PredicateExpr::Ref { predicate: "is_admin".into() }),
+        };
+        
+        let bytecode = PredicateCompiler::new(&mut strings).with_predicates(&ids).compile(&expr).unwrap();
+        assert_eq!(bytecode, [Opcode::CallPred as u8, 3, 0, Opcode::Not as u8, Opcode::Ret as u8]);
+        assert!(disassemble(&bytecode, &strings).contains("CallPred #3"));
+        
+        let unknown = PredicateExpr::Ref { predicate: "is_owner".into() };
+        let err = PredicateCompiler::new(&mut strings).with_predicates(&ids).compile(&unknown).unwrap_err();
+        assert!(err.to_string().contains("Unknown predicate reference: is_owner"), "{}", err);
+        assert!(PredicateCompiler::new(&mut strings).compile(&expr).is_err(), "no predicates to resolve against");
+    }
+    
+    #[test]
+    fn test_validate_matches_then_call() {
+        let mut strings = StringPool::new();
+        let ids = HashMap::from([("p".to_string(), 1)]);
+        let mut bytecode = PredicateCompiler::new(&mut strings).with_predicates(&ids).compile(&PredicateExpr::And {
+            conditions: vec![
+                PredicateExpr::Matches { left: "$input.email".into(), pattern: ".+@.+".into() },
+                PredicateExpr::Ref { predicate: "p".into() },
+            ],
+        }).unwrap();
+        assert!(validate_bytecode(&bytecode).is_ok());
+        
+        remap_calls(&mut bytecode, |id| id + 4);
+        assert!(disassemble(&bytecode, &strings).contains("CallPred #5"));
+    }
+    
+    #[test]
+    fn test_compile_decimal() {
+        let mut strings = StringPool::new();
+        let bytecode = PredicateCompiler::new(&mut strings).compile(&PredicateExpr::Lte {
+            left: "$loan.ltv".into(),
+            right: Value::Float(0.97),
+        }).unwrap();
+        
+        // LOAD_VAR "loan.ltv", PUSH_DEC 970000, LTE, RET
+        assert_eq!(bytecode[5], Opcode::PushDec as u8);
+        assert_eq!(bytecode[6..14], 970_000i64.to_le_bytes());
+        assert!(validate_bytecode(&bytecode).is_ok());
+        assert!(disassemble(&bytecode, &strings).contains("PushDec 0.97\n"));
+        assert!(validate_bytecode(&bytecode[..10]).is_err(), "truncated decimal operand");
+    }
+    
+    #[test]
+    fn test_decimal_precision() {
+        assert_eq!(encode_decimal(-2.5), Ok(-2_500_000));
+        assert_eq!(encode_decimal(0.000001), Ok(1));
+        assert_eq!(encode_decimal(1e12), Ok(1_000_000_000_000_000_000));
+        assert_eq!(format_decimal(-2_500_000), "-2.5");
+        assert_eq!(format_decimal(3_000_000), "3.0");
+        assert_eq!(format_decimal(i64::MIN), "-9223372036854.775808");
+        
+        assert!(encode_decimal(0.0000001).unwrap_err().contains("loses precision"));
+        assert!(encode_decimal(0.1 + 0.2).unwrap_err().contains("loses precision"));
+        assert!(encode_decimal(1e13).unwrap_err().contains("out of range"));
+        assert!(encode_decimal(f64::NAN).is_err());
+        
+        let mut strings = StringPool::new();
+        let err = PredicateCompiler::new(&mut strings).compile(&PredicateExpr::Gt {
+            left: "$loan.rate".into(),
+            right: Value::Float(0.06875125),
+        }).unwrap_err();
+        assert!(err.to_string().contains("loses precision"), "{}", err);
+    }
+    
+    #[test]
+    fn test_compile_int_encoding() {
+        let mut strings = StringPool::new();
+        let mut compile = |n: i64| PredicateCompiler::new(&mut strings).compile(&PredicateExpr::Gt {
+            left: "$input.amount_cents".into(),
+            right: Value::Int(n),
+        }).unwrap();
+        
+        // LOAD_VAR "input.amount_cents" (5 bytes), then the literal
+        let small = compile(i32::MAX as i64);
+        assert_eq!(small[5], Opcode::PushInt as u8);
+        assert_eq!(small.len(), 5 + 5 + 2);
+        
+        let cents = compile(2_500_000_000);
+        assert_eq!(cents[5], Opcode::PushI64 as u8);
+        assert_eq!(cents[6..14], 2_500_000_000i64.to_le_bytes());
+        assert!(validate_bytecode(&cents).is_ok());
+        assert!(validate_bytecode(&cents[..12]).is_err(), "truncated i64 operand");
+        
+        let millis = compile(i32::MIN as i64 - 1);
+        assert_eq!(millis[5], Opcode::PushI64 as u8);
+        assert!(disassemble(&millis, &strings).contains("PushI64 -2147483649\n"));
+        
+        let err = PredicateCompiler::new(&mut strings).compile(&PredicateExpr::Eq {
+            left: "$input.id".into(),
+            right: Value::from_str_guess("99999999999999999999"),
+        }).unwrap_err();
+        assert!(err.to_string().contains("does not fit in 64 bits"), "{}", err);
+    }
+    
+    #[test]
+    fn test_compile_arithmetic() {
+        let mut strings = StringPool::new();
+        let (expr, _) = crate::dsl::expr::parse_expr("$input.end - $input.start * 2 < 0.5").unwrap();
+        let bytecode = PredicateCompiler::new(&mut strings).compile(&expr).unwrap();
+        assert!(validate_bytecode(&bytecode).is_ok());
+        let ops: Vec<_> = disassemble(&bytecode, &strings).lines()
+            .map(|l| l[6..].split(' ').next().unwrap_or_default().to_string())
+            .collect();
+        assert_eq!(ops, ["LoadVar", "LoadVar", "PushInt", "Mul", "Sub", "PushDec", "Lt", "Ret"]);
+        
+        let (expr, _) = crate::dsl::expr::parse_expr("-$a > 0").unwrap();
+        let bytecode = PredicateCompiler::new(&mut strings).compile(&expr).unwrap();
+        assert_eq!(bytecode[5], Opcode::Neg as u8);
+        
+        // A string operand would fault at runtime, so it is rejected here
+        let (expr, _) = crate::dsl::expr::parse_expr("$a + abc == 1").unwrap();
+        let err = PredicateCompiler::new(&mut strings).compile(&expr).unwrap_err();
+        assert!(err.to_string().contains("Arithmetic operand 'abc' is not a number"), "{}", err);
+        
+        // Without spaces, `$end-$start` would load a variable named `end-$start`
+        for expr in [
+            PredicateExpr::Lt { left: "$end-$start".into(), right: Value::Int(86400) },
+            PredicateExpr::Eq { left: "$x".into(), right: Value::Var("$a*2".into()) },
+        ] {
+            let err = PredicateCompiler::new(&mut strings).compile(&expr).unwrap_err();
+            assert!(err.to_string().contains("put spaces around arithmetic operators"), "{}", err);
+        }
+    }
+    
+    #[test]
+    fn test_compile_membership() {
+        let mut strings = StringPool::new();
+        let stages = |values: &str| Value::list_from_str(values).unwrap();
+        let bytecode = PredicateCompiler::new(&mut strings).compile(&PredicateExpr::In {
+            left: "$deal.stage".into(),
+            values: stages("won, lost, closed"),
+        }).unwrap();
+        // LOAD_VAR, IN <list>, RET: one instruction however long the list
+        assert_eq!(bytecode.len(), 5 + 5 + 1);
+        assert_eq!(bytecode[5], Opcode::In as u8);
+        assert!(validate_bytecode(&bytecode).is_ok());
+        assert!(validate_bytecode(&[Opcode::In as u8, 0, 0, Opcode::Ret as u8]).is_err(), "truncated list offset");
+        assert!(disassemble(&bytecode, &strings).contains("In [won,lost,closed]\n"));
+        
+        // The same list, however written, is one pool entry
+        let not_in = PredicateCompiler::new(&mut strings).compile(&PredicateExpr::NotIn {
+            left: "$deal.stage".into(),
+            values: stages("won,lost,closed"),
+        }).unwrap();
+        assert_eq!(not_in[..10], bytecode[..10]);
+        assert_eq!(not_in[10], Opcode::Not as u8);
+        
+        for (values, message) in [
+            (vec![Value::Var("$other".into())], "is not a literal"),
+            (vec![Value::Str("a,b".into())], "contains ','"),
+            (vec![Value::Float(0.1234567)], "loses precision"),
+        ] {
+            let err = PredicateCompiler::new(&mut strings)
+                .compile(&PredicateExpr::In { left: "$a".into(), values })
+                .unwrap_err();
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+    
+    #[test]
+    fn test_compile_time() {
+        let mut strings = StringPool::new();
+        let mut compile = |src: &str| {
+            let (expr, _) = crate::dsl::expr::parse_expr(src).unwrap();
+            PredicateCompiler::new(&mut strings).compile(&expr)
+        };
+        
+        // A date is an epoch-millisecond literal
+        let after = compile("$task.created after 2025-01-01").unwrap();
+        assert_eq!(after[5], Opcode::PushI64 as u8);
+        assert_eq!(after[6..14], 1_735_689_600_000i64.to_le_bytes());
+        assert_eq!(after[14], Opcode::Gt as u8);
+        
+        // A duration as a value is relative to the host's now
+        let recent = compile("$task.created after -P7D").unwrap();
+        assert_eq!(recent[5], Opcode::Now as u8);
+        assert_eq!(recent[6..14], (-7 * 86_400_000i64).to_le_bytes());
+        
+        // Outside time comparisons date and duration shapes are strings
+        for src in ["$ticket.priority == P1", "$ticket.code == '2025-01-01'", "$a > -P7D"] {
+            assert_eq!(compile(src).unwrap()[5], Opcode::PushStr as u8, "{}", src);
+        }
+        
+        let due = compile("$task.due within P3D").unwrap();
+        assert!(validate_bytecode(&due).is_ok());
+        assert!(validate_bytecode(&due[..10]).is_err(), "truncated duration");
+        let mut strings = StringPool::new();
+        strings.intern("task.due");
+        assert!(disassemble(&due, &strings).contains("Within P3D\n"));
+        
+        // Inside arithmetic a duration is a length
+        let (expr, _) = crate::dsl::expr::parse_expr("$task.due - $task.created <= PT12H").unwrap();
+        let length = PredicateCompiler::new(&mut strings).compile(&expr).unwrap();
+        assert_eq!(length[11], Opcode::PushInt as u8);
+        assert_eq!(length[12..16], (12 * 3_600_000i32).to_le_bytes());
+        
+        for (src, message) in [
+            ("$a before 2025-02-30", "Invalid date '2025-02-30'"),
+            ("$a after 2025-01-01T09:00:00", "offset"),
+            ("$a within P1M", "no fixed length"),
+            ("$a within soon", "Invalid duration 'soon'"),
+        ] {
+            let (expr, _) = crate::dsl::expr::parse_expr(src).unwrap();
+            let err = PredicateCompiler::new(&mut strings).compile(&expr).unwrap_err();
+            assert!(err.to_string().contains(message), "{}: {}", src, err);
+        }
+    }
+    
     #[test]
     fn test_disassemble() {
         let mut strings = StringPool::new();
@@ -474,6 +1171,31 @@ mod tests {
         assert!(disasm.contains("Ret"));
     }
     
+    #[test]
+    fn test_function_operand() {
+        let mut strings = StringPool::new();
+        let mut compiler = PredicateCompiler::new(&mut strings);
+        let bytecode = compiler.compile(&PredicateExpr::Gt {
+            left: "len($input.items)".into(),
+            right: Value::Int(0),
+        }).unwrap();
+        let err = compiler.compile(&PredicateExpr::Eq {
+            left: "lenn($input.items)".into(),
+            right: Value::Int(0),
+        }).unwrap_err();
+        assert!(err.to_string().contains("Unknown function: lenn"));
+        
+        // LOAD_VAR "input.items", LEN, PUSH_INT 0, GT, RET
+        let ops: Vec<_> = disassemble(&bytecode, &strings).lines()
+            .map(|line| line[6..].split(' ').next().unwrap().to_string())
+            .collect();
+        assert_eq!(ops, ["LoadVar", "Len", "PushInt", "Gt", "Ret"]);
+        
+        assert_eq!(operand_call("len( $x )"), Some(("len", "$x")));
+        assert_eq!(operand_call("$x(1)"), None);
+        assert_eq!(operand_call("a b(c)"), None);
+    }
+    
     #[test]
     fn test_bytecode_too_large() {
         let mut strings = StringPool::new();
@@ -0,0 +1,349 @@
+//! Fragment Expansion
+//!
+//! Replaces every `<use fragment="..." id="..."/>` with a copy of the
+//! fragment's nodes and edges before lowering:
+//!
+//! - `{param}` in attribute values is replaced by the use's argument
+//! - node ids become `<use id>.<node id>`, and fragment edges between
+//!   fragment nodes are rewritten to match; edges to other ids (usually
+//!   passed in as parameters) are left alone
+//! - a fragment may itself use other fragments
+//!
+//! ```xml
+//! <fragment id="guarded_write" params="op,on_error">
+//!   <nodes>
+//!     <node id="write" kind="external" op="{op}"/>
+//!   </nodes>
+//!   <edges>
+//!     <edge from="write" to="{on_error}" fallback="true"/>
+//!   </edges>
+//! </fragment>
+//!
+//! <use fragment="guarded_write" id="save" op="0x0900" on_error="failed"/>
+//! ```
+//!
+//! adds node `save.write` and edge `save.write → failed`.
+
+use crate::CompileError;
+use crate::dsl::ast::*;
+use crate::dsl::vocabulary;
+use std::collections::HashSet;
+
+/// Expand every fragment use in the document's workflows
+pub fn expand_fragments(doc: &OmarDocument) -> Result<OmarDocument, CompileError> {
+    let mut expanded = doc.clone();
+    for workflow in &mut expanded.workflows {
+        for fragment_use in std::mem::take(&mut workflow.uses) {
+            let (nodes, edges) = instantiate(doc, &fragment_use, &mut Vec::new())?;
+            workflow.nodes.extend(nodes);
+            workflow.edges.extend(edges);
+        }
+    }
+    Ok(expanded)
+}
+
+/// Nodes and edges of one fragment use
+///
+/// `stack` holds the fragments being instantiated, outermost first.
+fn instantiate(
+    doc: &OmarDocument,
+    fragment_use: &FragmentUse,
+    stack: &mut Vec<String>,
+) -> Result<(Vec<Node>, Vec<Edge>), CompileError> {
+    let err = |msg: String| CompileError::Lower(msg).with_span(fragment_use.span);
+
+    let fragment = doc.fragments.iter()
+        .find(|f| f.id == fragment_use.fragment)
+        .ok_or_else(|| err(format!("Unknown fragment: {}", fragment_use.fragment)))?;
+
+    if stack.contains(&fragment.id) {
+        return Err(err(format!(
+            "Recursive fragment use: {} → {}",
+            stack.join(" → "), fragment.id
+        )));
+    }
+
+    for (name, _) in &fragment_use.args {
+        if !fragment.params.contains(name) {
+            let hint = vocabulary::suggest(name, fragment.params.iter().map(String::as_str))
+                .map(|s| format!(" (did you mean '{}'?)", s))
+                .unwrap_or_default();
+            return Err(err(format!(
+                "Fragment '{}' has no parameter '{}'{}",
+                fragment.id, name, hint
+            )));
+        }
+    }
+    if let Some(missing) = fragment.params.iter()
+        .find(|p| !fragment_use.args.iter().any(|(name, _)| name == *p))
+    {
+        return Err(err(format!(
+            "Use '{}' of fragment '{}' is missing parameter '{}'",
+            fragment_use.id, fragment.id, missing
+        )));
+    }
+
+    let subst = |s: &mut String| {
+        for (name, value) in &fragment_use.args {
+            let placeholder = format!("{{{}}}", name);
+            if s.contains(&placeholder) {
+                *s = s.replace(&placeholder, value);
+            }
+        }
+    };
+
+    let mut nodes = fragment.nodes.clone();
+    let mut edges = fragment.edges.clone();
+    nodes.iter_mut().for_each(|n| substitute_node(n, &subst));
+    edges.iter_mut().for_each(|e| substitute_edge(e, &subst));
+
+    stack.push(fragment.id.clone());
+    for inner in &fragment.uses {
+        let mut inner = inner.clone();
+        inner.args.iter_mut().for_each(|(_, value)| subst(value));
+        let (inner_nodes, inner_edges) = instantiate(doc, &inner, stack)?;
+        nodes.extend(inner_nodes);
+        edges.extend(inner_edges);
+    }
+    stack.pop();
+
+    // Scope the fragment's own node ids to this use
+    let local: HashSet<String> = nodes.iter().map(|n| n.id.clone()).collect();
+    let scoped = |id: &mut String| {
+        if local.contains(id.as_str()) {
+            *id = format!("{}.{}", fragment_use.id, id);
+        }
+    };
+    for node in &mut nodes {
+        scoped(&mut node.id);
+    }
+    for edge in &mut edges {
+        scoped(&mut edge.from);
+        scoped(&mut edge.to);
+    }
+
+    Ok((nodes, edges))
+}
+
+fn substitute_node(node: &mut Node, subst: &impl Fn(&mut String)) {
+    subst(&mut node.id);
+    for field in [
+        &mut node.op,
+        &mut node.template,
+        &mut node.schema,
+        &mut node.predicate,
+        &mut node.selector,
+        &mut node.message,
+        &mut node.actor,
+        &mut node.confirmation,
+        &mut node.call,
+    ] {
+        field.as_mut().map(subst);
+    }
+    for (signal, value) in &mut node.signals {
+        subst(signal);
+        subst(value);
+    }
+    node.data.values_mut().for_each(subst);
+}
+
+fn substitute_edge(edge: &mut Edge, subst: &impl Fn(&mut String)) {
+    subst(&mut edge.from);
+    subst(&mut edge.to);
+    edge.predicate_ref.as_mut().map(subst);
+    if let Some(expr) = edge.predicate.as_mut() {
+        substitute_expr(expr, subst);
+    }
+}
+
+fn substitute_expr(expr: &mut PredicateExpr, subst: &impl Fn(&mut String)) {
+    match expr {
+        PredicateExpr::Eq { left, right }
+        | PredicateExpr::Neq { left, right }
+        | PredicateExpr::Gt { left, right }
+        | PredicateExpr::Gte { left, right }
+        | PredicateExpr::Lt { left, right }
+        | PredicateExpr::Lte { left, right } => {
+            subst(left);
+            substitute_value(right, subst);
+        }
+        PredicateExpr::Contains { left, right: other }
+        | PredicateExpr::Before { left, right: other }
+        | PredicateExpr::After { left, right: other }
+        | PredicateExpr::Within { left, duration: other }
+        | PredicateExpr::Matches { left, pattern: other }
+        | PredicateExpr::StartsWith { left, prefix: other }
+        | PredicateExpr::EndsWith { left, suffix: other } => {
+            subst(left);
+            subst(other);
+        }
+        PredicateExpr::In { left, values } | PredicateExpr::NotIn { left, values } => {
+            subst(left);
+            values.iter_mut().for_each(|v| substitute_value(v, subst));
+        }
+        PredicateExpr::And { conditions } | PredicateExpr::Or { conditions } => {
+            conditions.iter_mut().for_each(|c| substitute_expr(c, subst));
+        }
+        PredicateExpr::Not { condition } => substitute_expr(condition, subst),
+        PredicateExpr::Ref { predicate } => subst(predicate),
+        PredicateExpr::Fn { name, arg } => {
+            subst(name);
+            subst(arg);
+        }
+        PredicateExpr::Compare { left, right, .. } => {
+            substitute_arith(left, subst);
+            substitute_arith(right, subst);
+        }
+        PredicateExpr::Always | PredicateExpr::Fail => {}
+    }
+}
+
+fn substitute_arith(arith: &mut Arith, subst: &impl Fn(&mut String)) {
+    match arith {
+        Arith::Operand(operand) => subst(operand),
+        Arith::Neg(inner) => substitute_arith(inner, subst),
+        Arith::Binary { left, right, .. } => {
+            substitute_arith(left, subst);
+            substitute_arith(right, subst);
+        }
+    }
+}
+
+/// Substitute inside a string value, re-reading its type afterwards
+/// (`"{limit}"` with `limit="5"` becomes `Int(5)`)
+fn substitute_value(value: &mut Value, subst: &impl Fn(&mut String)) {
+    if let Value::Str(s) | Value::Var(s) = value {
+        let mut text = s.clone();
+        subst(&mut text);
+        if text != *s {
+            *value = Value::from_str_guess(&text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsl::parse;
+
+    const GUARDED_WRITE: &str = r#"
+        <fragment id="guarded_write" params="op,on_error">
+            <nodes>
+                <node id="write" kind="external" op="{op}"/>
+                <node id="failed" kind="error" status="500"/>
+            </nodes>
+            <edges>
+                <edge from="write" to="{on_error}"/>
+                <edge from="write" to="failed" fallback="true"/>
+            </edges>
+        </fragment>
+    "#;
+
+    fn workflow(uses: &str) -> String {
+        format!(r#"
+            <workflow id="orders">
+                <entry p="orders" x="save" node="start"/>
+                <nodes>
+                    <node id="start" kind="transform"/>
+                    {uses}
+                    <node id="done" kind="terminal" status="200"/>
+                </nodes>
+                <edges>
+                    <edge from="start" to="save.write"/>
+                </edges>
+            </workflow>
+        "#)
+    }
+
+    fn doc(body: &str) -> OmarDocument {
+        parse(&format!(r#"<omar version="1.0.0">{}</omar>"#, body)).unwrap()
+    }
+
+    #[test]
+    fn test_expand_fragment() {
+        let doc = doc(&format!("{}{}", GUARDED_WRITE, workflow(
+            r#"<use fragment="guarded_write" id="save" op="0x0900" on_error="done"/>
+               <use fragment="guarded_write" id="log" op="0x0910" on_error="done"/>"#,
+        )));
+        let expanded = expand_fragments(&doc).unwrap();
+        let orders = &expanded.workflows[0];
+
+        let ids: Vec<_> = orders.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["start", "done", "save.write", "save.failed", "log.write", "log.failed"]);
+        assert_eq!(orders.nodes[2].op.as_deref(), Some("0x0900"));
+        assert_eq!(orders.nodes[4].op.as_deref(), Some("0x0910"));
+        assert!(orders.uses.is_empty());
+
+        let edges: Vec<_> = orders.edges.iter().map(|e| (e.from.as_str(), e.to.as_str())).collect();
+        assert!(edges.contains(&("save.write", "done")));
+        assert!(edges.contains(&("save.write", "save.failed")));
+        assert!(edges.contains(&("log.write", "log.failed")));
+    }
+
+    #[test]
+    fn test_predicate_values_are_retyped() {
+        let doc = doc(&format!(r#"
+            <fragment id="limited" params="max">
+                <nodes><node id="check" kind="transform"/></nodes>
+                <edges>
+                    <edge from="check" to="check_ok"><when><lt left="$input.count" right="{{max}}"/></when></edge>
+                </edges>
+            </fragment>
+            {}"#,
+            workflow(r#"<use fragment="limited" id="save" max="5"/>"#),
+        ));
+        let expanded = expand_fragments(&doc).unwrap();
+        let edge = expanded.workflows[0].edges.iter().find(|e| e.from == "save.check").unwrap();
+        assert!(matches!(&edge.predicate, Some(PredicateExpr::Lt { right: Value::Int(5), .. })));
+    }
+
+    #[test]
+    fn test_nested_fragments() {
+        let doc = doc(&format!(r#"{}
+            <fragment id="audited_write" params="op">
+                <nodes>
+                    <use fragment="guarded_write" id="inner" op="{{op}}" on_error="logged"/>
+                    <node id="logged" kind="terminal" status="200"/>
+                </nodes>
+            </fragment>
+            {}"#,
+            GUARDED_WRITE,
+            workflow(r#"<use fragment="audited_write" id="save" op="0x0900"/>"#),
+        ));
+        let expanded = expand_fragments(&doc).unwrap();
+        let orders = &expanded.workflows[0];
+
+        assert!(orders.nodes.iter().any(|n| n.id == "save.inner.write" && n.op.as_deref() == Some("0x0900")));
+        assert!(orders.edges.iter().any(|e| e.from == "save.inner.write" && e.to == "save.logged"));
+    }
+
+    #[test]
+    fn test_argument_errors() {
+        let expand = |uses: &str| {
+            expand_fragments(&doc(&format!("{}{}", GUARDED_WRITE, workflow(uses))))
+                .unwrap_err()
+                .to_string()
+        };
+
+        let err = expand(r#"<use fragment="guarded_write" id="save" op="0x0900"/>"#);
+        assert!(err.contains("missing parameter 'on_error'"), "{}", err);
+
+        let err = expand(r#"<use fragment="guarded_write" id="save" opp="0x0900" on_error="done"/>"#);
+        assert!(err.contains("no parameter 'opp' (did you mean 'op'?)"), "{}", err);
+
+        let err = expand(r#"<use fragment="guarded_writ" id="save"/>"#);
+        assert!(err.contains("Unknown fragment: guarded_writ"), "{}", err);
+    }
+
+    #[test]
+    fn test_recursive_fragment() {
+        let doc = doc(&format!(r#"
+            <fragment id="a"><nodes><use fragment="b" id="x"/></nodes></fragment>
+            <fragment id="b"><nodes><use fragment="a" id="y"/></nodes></fragment>
+            {}"#,
+            workflow(r#"<use fragment="a" id="save"/>"#),
+        ));
+        let err = expand_fragments(&doc).unwrap_err().to_string();
+        assert!(err.contains("Recursive fragment use: a → b → a"), "{}", err);
+    }
+}
@@ -0,0 +1,270 @@
+//! Sub-workflow Call Expansion
+//!
+//! A `kind="call"` node runs another workflow and then continues along its
+//! own outgoing edges. Calls are inlined into the caller before lowering,
+//! so the runtime needs no call stack and every analysis (reachability,
+//! cycles, human-in-the-loop paths) sees the callee's nodes directly.
+//!
+//! For a call node `check` targeting workflow `auth_check`:
+//!
+//! ```text
+//! a → check → b
+//!
+//! becomes
+//!
+//! a → check → check.<entry> → … → check.<ok> → check.return → b
+//!                                 ↘ check.<denied> (terminal, status ≥ 400)
+//! ```
+//!
+//! - `check` stays in the graph as a transform node (the call site)
+//! - every callee node is copied as `check.<id>`
+//! - callee terminals with a success status (< 400, or none) become
+//!   transforms that continue to `check.return`; error terminals stay
+//!   terminal and end the whole request
+//! - the call node's outgoing edges leave from `check.return`, which
+//!   keeps the call node's schema
+//! - callee nodes without a `<schema ref>` get the callee's workflow
+//!   schema
+//!
+//! The call site, `check.return` and the rewritten terminals are marked
+//! `passthrough`: they only route control, so PRAG001 does not treat them
+//! as validation gates and an LLM in the caller is still checked against
+//! irreversible actions in the callee.
+//!
+//! Nested calls are expanded recursively, up to `limits::MAX_CALL_DEPTH`.
+
+use crate::{limits, CompileError};
+use crate::dsl::ast::*;
+
+/// Suffix of the node a call continues from once the callee returns
+const RETURN_NODE: &str = "return";
+
+/// Expand every call node in the document
+pub fn inline_calls(doc: &OmarDocument) -> Result<OmarDocument, CompileError> {
+    let has_calls = doc.workflows.iter()
+        .any(|w| w.nodes.iter().any(|n| n.call.is_some()));
+    if !has_calls {
+        return Ok(doc.clone());
+    }
+
+    let mut expanded = doc.clone();
+    for workflow in &mut expanded.workflows {
+        let mut stack = vec![workflow.id.clone()];
+        let (nodes, edges) = expand(doc, workflow, &mut stack)?;
+        workflow.nodes = nodes;
+        workflow.edges = edges;
+    }
+
+    Ok(expanded)
+}
+
+/// Nodes and edges of `workflow` with its calls (and theirs) inlined
+///
+/// `stack` holds the chain of workflows being expanded, outermost first.
+fn expand(
+    doc: &OmarDocument,
+    workflow: &Workflow,
+    stack: &mut Vec<String>,
+) -> Result<(Vec<Node>, Vec<Edge>), CompileError> {
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+
+    for node in &workflow.nodes {
+        let Some(target) = &node.call else {
+            nodes.push(node.clone());
+            continue;
+        };
+
+        let callee = doc.workflows.iter()
+            .find(|w| w.id == *target)
+            .ok_or_else(|| CompileError::Lower(format!(
+                "Call node '{}' targets unknown workflow: {}",
+                node.id, target
+            )).with_span(node.span))?;
+
+        if stack.contains(target) {
+            return Err(CompileError::Lower(format!(
+                "Recursive workflow call: {} → {}",
+                stack.join(" → "), target
+            )).with_span(node.span));
+        }
+        if stack.len() > limits::MAX_CALL_DEPTH {
+            return Err(CompileError::Lower(format!(
+                "Call depth exceeds MAX_CALL_DEPTH ({}): {} → {}",
+                limits::MAX_CALL_DEPTH, stack.join(" → "), target
+            )).with_span(node.span));
+        }
+
+        stack.push(target.clone());
+        let (callee_nodes, callee_edges) = expand(doc, callee, stack)?;
+        stack.pop();
+
+        let scoped = |id: &str| format!("{}.{}", node.id, id);
+        let return_id = scoped(RETURN_NODE);
+
+        // Call site
+        nodes.push(Node {
+            kind: "transform".into(),
+            call: None,
+            passthrough: true,
+            ..node.clone()
+        });
+        edges.push(Edge {
+            from: node.id.clone(),
+            to: scoped(&callee.entry.node),
+            span: node.span,
+            ..Default::default()
+        });
+
+        // Callee body
+        for callee_node in callee_nodes {
+            // Copies keep the callee's default schema, not the caller's
+            let mut copy = Node {
+                id: scoped(&callee_node.id),
+                schema: callee_node.schema.or_else(|| callee.schema.clone()),
+                ..callee_node
+            };
+            let returns = copy.kind == "terminal" && copy.status.is_none_or(|s| s < 400);
+            if returns {
+                edges.push(Edge {
+                    from: copy.id.clone(),
+                    to: return_id.clone(),
+                    span: copy.span,
+                    ..Default::default()
+                });
+                copy.kind = "transform".into();
+                copy.status = None;
+                copy.passthrough = true;
+            }
+            nodes.push(copy);
+        }
+        edges.extend(callee_edges.into_iter().map(|edge| Edge {
+            from: scoped(&edge.from),
+            to: scoped(&edge.to),
+            ..edge
+        }));
+
+        nodes.push(Node {
+            id: return_id,
+            kind: "transform".into(),
+            schema: node.schema.clone(),
+            passthrough: true,
+            span: node.span,
+            ..Default::default()
+        });
+    }
+
+    // Continue from the return node of each call
+    for edge in &workflow.edges {
+        let mut edge = edge.clone();
+        let is_call = workflow.nodes.iter()
+            .any(|n| n.id == edge.from && n.call.is_some());
+        if is_call {
+            edge.from = format!("{}.{}", edge.from, RETURN_NODE);
+        }
+        edges.push(edge);
+    }
+
+    Ok((nodes, edges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsl::parse;
+
+    fn doc(workflows: &str) -> OmarDocument {
+        parse(&format!(r#"<omar version="1.0.0">{}</omar>"#, workflows)).unwrap()
+    }
+
+    const AUTH_CHECK: &str = r#"
+        <workflow id="auth_check">
+            <entry p="auth" x="check" node="verify"/>
+            <nodes>
+                <node id="verify" kind="transform"/>
+                <node id="ok" kind="terminal" status="200"/>
+                <node id="denied" kind="terminal" status="401"/>
+            </nodes>
+            <edges>
+                <edge from="verify" to="ok"><when><eq left="$token.role" right="admin"/></when></edge>
+                <edge from="verify" to="denied" fallback="true"/>
+            </edges>
+        </workflow>
+    "#;
+
+    fn caller(id: &str, target: &str) -> String {
+        format!(r#"
+            <workflow id="{id}">
+                <entry p="{id}" x="run" node="start"/>
+                <nodes>
+                    <node id="start" kind="transform"/>
+                    <node id="check" kind="call" workflow="{target}"/>
+                    <node id="done" kind="terminal" status="200"/>
+                </nodes>
+                <edges>
+                    <edge from="start" to="check"/>
+                    <edge from="check" to="done"/>
+                </edges>
+            </workflow>
+        "#)
+    }
+
+    #[test]
+    fn test_inline_call() {
+        let doc = doc(&format!("{}{}", AUTH_CHECK, caller("orders", "auth_check")));
+        let expanded = inline_calls(&doc).unwrap();
+        let orders = &expanded.workflows[1];
+
+        let ids: Vec<_> = orders.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["start", "check", "check.verify", "check.ok", "check.denied", "check.return", "done"]);
+
+        let kind = |id: &str| orders.nodes.iter().find(|n| n.id == id).unwrap().kind.as_str();
+        assert_eq!(kind("check"), "transform");
+        assert_eq!(kind("check.ok"), "transform");
+        assert_eq!(kind("check.denied"), "terminal");
+        let passthrough: Vec<_> = orders.nodes.iter().filter(|n| n.passthrough).map(|n| n.id.as_str()).collect();
+        assert_eq!(passthrough, ["check", "check.ok", "check.return"]);
+
+        let edges: Vec<_> = orders.edges.iter().map(|e| (e.from.as_str(), e.to.as_str())).collect();
+        assert!(edges.contains(&("start", "check")));
+        assert!(edges.contains(&("check", "check.verify")));
+        assert!(edges.contains(&("check.ok", "check.return")));
+        assert!(edges.contains(&("check.verify", "check.denied")));
+        assert!(edges.contains(&("check.return", "done")));
+        assert!(!edges.contains(&("check", "done")));
+
+        // The callee itself is untouched
+        assert_eq!(expanded.workflows[0].nodes.len(), 3);
+    }
+
+    #[test]
+    fn test_unknown_workflow() {
+        let doc = doc(&caller("orders", "missing"));
+        let err = inline_calls(&doc).unwrap_err();
+        assert!(err.to_string().contains("unknown workflow: missing"));
+        assert!(err.span().is_some());
+    }
+
+    #[test]
+    fn test_recursive_call() {
+        let doc = doc(&format!("{}{}", caller("a", "b"), caller("b", "a")));
+        let err = inline_calls(&doc).unwrap_err();
+        assert!(err.to_string().contains("Recursive workflow call: a → b → a"), "{}", err);
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        // w0 → w1 → … → wN, one call level per workflow
+        let chain = |n: usize| {
+            let mut xml = AUTH_CHECK.replace("auth_check", &format!("w{}", n));
+            for i in 0..n {
+                xml.push_str(&caller(&format!("w{}", i), &format!("w{}", i + 1)));
+            }
+            doc(&xml)
+        };
+
+        assert!(inline_calls(&chain(limits::MAX_CALL_DEPTH)).is_ok());
+        let err = inline_calls(&chain(limits::MAX_CALL_DEPTH + 1)).unwrap_err();
+        assert!(err.to_string().contains("MAX_CALL_DEPTH"), "{}", err);
+    }
+}
@@ -6,13 +6,18 @@
 use crate::{
     NodeKind, ActorKind, SideEffects, ConfirmationStatus,
     node_flags, edge_flags, is_llm_op, is_irreversible_op, is_write_op,
+    Location, Span,
 };
+use crate::compiler::simplify::SimplifyStats;
+use crate::compiler::typing::FieldType;
+use crate::compiler::verify::PredicateBounds;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 pub type NodeId = u32;
 pub type EdgeId = u32;
 pub type PredicateId = u16;
+pub type TemplateId = u16;
 
 /// Graph intermediate representation
 #[derive(Debug, Clone, Default)]
@@ -20,9 +25,14 @@ pub struct GraphIR {
     pub nodes: Vec<GNode>,
     pub edges: Vec<GEdge>,
     pub predicates: Vec<CompiledPredicate>,
+    /// Template `id` is at index `id - 1` (0 means no template)
+    pub templates: Vec<GTemplate>,
     pub strings: StringPool,
+    pub node_data: NodeDataSection,
     pub entries: Vec<GEntry>,
     pub workflow_ids: Vec<String>,
+    /// What predicate simplification changed (compile-time only)
+    pub simplification: SimplifyStats,
 }
 
 impl GraphIR {
@@ -147,6 +157,15 @@ impl GraphIR {
                 node.id
             );
         }
+
+        // Every node's data_offset must point at a node data record
+        for node in &self.nodes {
+            assert!(
+                decode_node_data(self.node_data.bytes(), node.data_offset).is_some(),
+                "Node {} has invalid data offset {}",
+                node.id, node.data_offset
+            );
+        }
     }
 }
 
@@ -175,12 +194,22 @@ pub struct GNode {
     pub confirmation_status: ConfirmationStatus,
     #[serde(skip)]
     pub template: Option<String>,
+    /// Resolved `template`; None if the reference is unknown
+    #[serde(skip)]
+    pub template_id: Option<TemplateId>,
     #[serde(skip)]
     pub selector: Option<String>,
     #[serde(skip)]
     pub status: Option<u16>,
     #[serde(skip)]
     pub message: Option<String>,
+    /// Inserted by call expansion; passes control on without checking
+    /// anything, so it never counts as a validation gate
+    #[serde(skip)]
+    pub passthrough: bool,
+    /// Source span of the `<node>` element
+    #[serde(skip)]
+    pub span: Option<Span>,
 }
 
 impl GNode {
@@ -200,27 +229,39 @@ impl GNode {
             actor_kind: ActorKind::Agent,
             confirmation_status: ConfirmationStatus::Confirmed,
             template: None,
+            template_id: None,
             selector: None,
             status: None,
             message: None,
+            passthrough: false,
+            span: None,
+        }
+    }
+    
+    /// IO op code, if any: auth and render nodes use the op_code field
+    /// for a predicate or template id instead
+    fn io_op_code(&self) -> u16 {
+        match self.kind {
+            NodeKind::Auth | NodeKind::Render => 0,
+            _ => self.op_code,
         }
     }
     
     /// Check if this node calls an LLM
     pub fn is_llm(&self) -> bool {
-        is_llm_op(self.op_code)
+        is_llm_op(self.io_op_code())
     }
     
     /// Check if this node performs an irreversible action
     pub fn is_irreversible(&self) -> bool {
-        self.side_effects == SideEffects::Irreversible || is_irreversible_op(self.op_code)
+        self.side_effects == SideEffects::Irreversible || is_irreversible_op(self.io_op_code())
     }
     
     /// Check if this node writes data
     pub fn is_write(&self) -> bool {
         self.side_effects == SideEffects::Write || 
         self.side_effects == SideEffects::Irreversible ||
-        is_write_op(self.op_code)
+        is_write_op(self.io_op_code())
     }
     
     /// Check if this node requires human interaction
@@ -233,6 +274,15 @@ impl GNode {
         self.requires_auth || (self.flags & node_flags::REQUIRES_AUTH) != 0
     }
     
+    /// Diagnostic location pointing at this node
+    pub fn location(&self) -> Location {
+        Location {
+            node_id: Some(self.name.clone()),
+            span: self.span,
+            ..Default::default()
+        }
+    }
+    
     /// Set a flag
     pub fn set_flag(&mut self, flag: u8) {
         self.flags |= flag;
@@ -253,6 +303,9 @@ pub struct GEdge {
     pub predicate_id: PredicateId,
     pub weight: u16,
     pub flags: u16,
+    /// Source span of the `<edge>` element
+    #[serde(skip)]
+    pub span: Option<Span>,
 }
 
 impl GEdge {
@@ -264,6 +317,7 @@ impl GEdge {
             predicate_id: 0,
             weight: 0,
             flags: 0,
+            span: None,
         }
     }
     
@@ -282,6 +336,15 @@ impl GEdge {
         (self.flags & edge_flags::ERROR_EDGE) != 0
     }
     
+    /// Diagnostic location pointing at this edge
+    pub fn location(&self) -> Location {
+        Location {
+            edge_id: Some(self.id.to_string()),
+            span: self.span,
+            ..Default::default()
+        }
+    }
+    
     /// Set a flag
     pub fn set_flag(&mut self, flag: u16) {
         self.flags |= flag;
@@ -300,6 +363,8 @@ pub struct GEntry {
     pub node_id: NodeId,
     pub p: String,
     pub x: String,
+    /// Source span of the `<entry>` element
+    pub span: Option<Span>,
 }
 
 impl GEntry {
@@ -309,6 +374,15 @@ impl GEntry {
             node_id,
             p,
             x,
+            span: None,
+        }
+    }
+    
+    /// Diagnostic location pointing at this entry
+    pub fn location(&self) -> Location {
+        Location {
+            span: self.span,
+            ..Default::default()
         }
     }
 }
@@ -319,6 +393,11 @@ pub struct CompiledPredicate {
     pub id: PredicateId,
     pub name: String,
     pub bytecode: Vec<u8>,
+    /// Schema types of the `$entity.*`/`$input.*` paths it reads, as
+    /// resolved by the typing pass (compile-time only)
+    pub types: BTreeMap<String, FieldType>,
+    /// Worst-case stack, steps and call depth, once verified
+    pub bounds: Option<PredicateBounds>,
 }
 
 impl CompiledPredicate {
@@ -327,11 +406,13 @@ impl CompiledPredicate {
             id,
             name,
             bytecode: Vec::new(),
+            types: BTreeMap::new(),
+            bounds: None,
         }
     }
     
     pub fn with_bytecode(id: PredicateId, name: String, bytecode: Vec<u8>) -> Self {
-        Self { id, name, bytecode }
+        Self { id, name, bytecode, types: BTreeMap::new(), bounds: None }
     }
     
     /// Check if bytecode has been compiled
@@ -345,6 +426,29 @@ impl CompiledPredicate {
     }
 }
 
+/// Template from `<templates>`, emitted to the templates section
+#[derive(Debug, Clone)]
+pub struct GTemplate {
+    pub id: TemplateId,
+    pub name: String,
+    /// String pool offsets
+    pub name_offset: u32,
+    pub content_offset: u32,
+    /// Number of nodes referencing this template
+    pub references: usize,
+    pub span: Option<Span>,
+}
+
+impl GTemplate {
+    /// Diagnostic location pointing at the `<template>` definition
+    pub fn location(&self) -> Location {
+        Location {
+            span: self.span,
+            ..Default::default()
+        }
+    }
+}
+
 /// String pool for binary emission
 /// 
 /// Strings are interned with null terminators for C-style access from WASM.
@@ -405,10 +509,125 @@ impl StringPool {
     }
 }
 
+/// Node data section for binary emission
+///
+/// One record per distinct `<set>` content, pointed to by
+/// `GNode::data_offset`: `[data_count:u16][signal_count:u16]` followed by
+/// `data_count + signal_count` pairs of `[key:u32][value:u32]` string pool
+/// offsets, data pairs first. Offset 0 is the empty record shared by every
+/// node without data, so every `data_offset` is valid.
+#[derive(Debug, Clone, Default)]
+pub struct NodeDataSection {
+    pub data: Vec<u8>,
+    pub offsets: HashMap<Vec<u8>, u32>,
+}
+
+/// A decoded node data record
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeData {
+    /// `(key, value)` string pool offsets
+    pub data: Vec<(u32, u32)>,
+    /// `(signal, value)` string pool offsets
+    pub signals: Vec<(u32, u32)>,
+}
+
+impl NodeDataSection {
+    /// Size of a record header
+    pub const RECORD_HEADER_SIZE: usize = 4;
+    
+    /// Size of one key/value pair
+    pub const PAIR_SIZE: usize = 8;
+    
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Add a record, returning its offset in the section
+    ///
+    /// Identical records are stored once.
+    pub fn add(&mut self, record: &NodeData) -> u32 {
+        self.ensure_empty_record();
+        
+        let mut bytes = Vec::with_capacity(
+            Self::RECORD_HEADER_SIZE + (record.data.len() + record.signals.len()) * Self::PAIR_SIZE,
+        );
+        bytes.extend_from_slice(&(record.data.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&(record.signals.len() as u16).to_le_bytes());
+        for (key, value) in record.data.iter().chain(&record.signals) {
+            bytes.extend_from_slice(&key.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        
+        if let Some(&offset) = self.offsets.get(&bytes) {
+            return offset;
+        }
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(&bytes);
+        self.offsets.insert(bytes, offset);
+        offset
+    }
+    
+    /// Decode the record at `offset`
+    pub fn get(&self, offset: u32) -> Option<NodeData> {
+        decode_node_data(self.bytes(), offset)
+    }
+    
+    /// Section contents as emitted: at least the empty record
+    pub fn bytes(&self) -> &[u8] {
+        const EMPTY: [u8; NodeDataSection::RECORD_HEADER_SIZE] = [0; NodeDataSection::RECORD_HEADER_SIZE];
+        if self.data.is_empty() { &EMPTY } else { &self.data }
+    }
+    
+    fn ensure_empty_record(&mut self) {
+        if self.data.is_empty() {
+            let empty = vec![0; Self::RECORD_HEADER_SIZE];
+            self.data.extend_from_slice(&empty);
+            self.offsets.insert(empty, 0);
+        }
+    }
+}
+
+/// Decode the node data record at `offset` of a node data section
+pub fn decode_node_data(section: &[u8], offset: u32) -> Option<NodeData> {
+    let start = offset as usize;
+    let header = section.get(start..start + NodeDataSection::RECORD_HEADER_SIZE)?;
+    let data_count = u16::from_le_bytes([header[0], header[1]]) as usize;
+    let signal_count = u16::from_le_bytes([header[2], header[3]]) as usize;
+    
+    let pairs_start = start + NodeDataSection::RECORD_HEADER_SIZE;
+    let pairs = section.get(pairs_start..pairs_start + (data_count + signal_count) * NodeDataSection::PAIR_SIZE)?;
+    let mut pairs = pairs.chunks_exact(NodeDataSection::PAIR_SIZE).map(|pair| {
+        (
+            u32::from_le_bytes([pair[0], pair[1], pair[2], pair[3]]),
+            u32::from_le_bytes([pair[4], pair[5], pair[6], pair[7]]),
+        )
+    });
+    Some(NodeData {
+        data: pairs.by_ref().take(data_count).collect(),
+        signals: pairs.collect(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     
+    #[test]
+    fn test_node_data_section() {
+        let mut section = NodeDataSection::new();
+        assert_eq!(section.bytes(), [0, 0, 0, 0]);
+        assert_eq!(section.get(0), Some(NodeData::default()));
+        
+        let record = NodeData { data: vec![(1, 2)], signals: vec![(3, 4), (5, 6)] };
+        let offset = section.add(&record);
+        assert_eq!(offset, 4);
+        assert_eq!(section.add(&record), offset); // stored once
+        assert_eq!(section.add(&NodeData::default()), 0);
+        assert_eq!(section.get(offset), Some(record));
+        assert_eq!(section.bytes().len(), 4 + 4 + 3 * 8);
+        assert_eq!(section.get(36), None); // past the end
+    }
+    
     #[test]
     fn test_string_pool_intern() {
         let mut pool = StringPool::new();
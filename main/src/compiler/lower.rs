@@ -5,7 +5,7 @@
 
 use crate::{
     NodeKind, ActorKind, ConfirmationStatus, SideEffects, CompileError,
-    node_flags, edge_flags,
+    node_flags, edge_flags, limits,
     is_irreversible_op, is_write_op,
 };
 use crate::dsl::ast::*;
@@ -32,6 +32,8 @@ struct Lowerer {
     node_map: HashMap<(String, String), NodeId>,
     // predicate_name -> predicate id
     predicate_map: HashMap<String, PredicateId>,
+    // template_name -> template id
+    template_map: HashMap<String, TemplateId>,
 }
 
 impl Lowerer {
@@ -43,12 +45,14 @@ impl Lowerer {
             predicate_id_counter: 1, // 0 is reserved for "always true"
             node_map: HashMap::new(),
             predicate_map: HashMap::new(),
+            template_map: HashMap::new(),
         }
     }
     
     fn lower(mut self, doc: &OmarDocument) -> Result<GraphIR, CompileError> {
         // First pass: Register all named predicates
         self.register_predicates(&doc.predicates)?;
+        self.register_templates(&doc.templates)?;
         
         // Second pass: Process all workflows
         for workflow in &doc.workflows {
@@ -70,7 +74,7 @@ impl Lowerer {
                 return Err(CompileError::Lower(format!(
                     "Duplicate predicate definition: {}",
                     pred_def.id
-                )));
+                )).with_span(pred_def.span));
             }
             
             self.predicate_map.insert(pred_def.id.clone(), self.predicate_id_counter);
@@ -83,26 +87,63 @@ impl Lowerer {
         Ok(())
     }
     
+    fn register_templates(&mut self, templates: &[Template]) -> Result<(), CompileError> {
+        for template in templates {
+            if self.template_map.contains_key(&template.id) {
+                return Err(CompileError::Lower(format!(
+                    "Duplicate template definition: {}",
+                    template.id
+                )).with_span(template.span));
+            }
+            
+            // Ids start at 1: 0 means "no template"
+            let id = TemplateId::try_from(self.ir.templates.len() + 1)
+                .map_err(|_| CompileError::Lower(format!(
+                    "Too many templates (max {})",
+                    TemplateId::MAX
+                )).with_span(template.span))?;
+            
+            self.template_map.insert(template.id.clone(), id);
+            self.ir.templates.push(GTemplate {
+                id,
+                name: template.id.clone(),
+                name_offset: self.ir.strings.intern(&template.id),
+                content_offset: self.ir.strings.intern(&template.content),
+                references: 0,
+                span: template.span,
+            });
+        }
+        Ok(())
+    }
+    
     fn lower_workflow(&mut self, workflow: &Workflow) -> Result<(), CompileError> {
         self.ir.workflow_ids.push(workflow.id.clone());
         
         // Create nodes
         for node in &workflow.nodes {
-            self.lower_node(workflow, node)?;
+            self.lower_node(workflow, node).map_err(|e| e.with_span(node.span))?;
         }
         
         // Create edges
         for edge in &workflow.edges {
-            self.lower_edge(workflow, edge)?;
+            self.lower_edge(workflow, edge).map_err(|e| e.with_span(edge.span))?;
         }
         
         // Create entry point
-        self.lower_entry(workflow)?;
+        self.lower_entry(workflow)
+            .map_err(|e| e.with_span(workflow.entry.span.or(workflow.span)))?;
         
         Ok(())
     }
     
     fn lower_node(&mut self, workflow: &Workflow, node: &Node) -> Result<(), CompileError> {
+        if node.call.is_some() {
+            return Err(CompileError::Lower(format!(
+                "Call node '{}' must be expanded with inline_calls() before lowering",
+                node.id
+            )));
+        }
+        
         let kind: NodeKind = node.kind.parse()
             .map_err(|e: String| CompileError::Lower(e))?;
         
@@ -132,7 +173,7 @@ impl Lowerer {
                 
                 // For auth nodes, store predicate ID in op_code field
                 if kind == NodeKind::Auth {
-                    gnode.op_code = pred_id as u16;
+                    gnode.op_code = pred_id;
                 }
             } else {
                 return Err(CompileError::Lower(format!(
@@ -155,11 +196,27 @@ impl Lowerer {
             gnode.confirmation_status = conf.parse().unwrap_or(ConfirmationStatus::Confirmed);
         }
         
+        // Resolve template reference; unknown references are left
+        // unresolved for SEM008
+        if let Some(ref name) = node.template {
+            if let Some(&id) = self.template_map.get(name) {
+                gnode.template_id = Some(id);
+                self.ir.templates[id as usize - 1].references += 1;
+            }
+        }
+        
+        // For render nodes, store template ID in op_code field
+        if kind == NodeKind::Render {
+            gnode.op_code = gnode.template_id.unwrap_or(0);
+        }
+        
         // Store optional metadata
         gnode.template = node.template.clone();
         gnode.selector = node.selector.clone();
         gnode.status = node.status;
         gnode.message = node.message.clone();
+        gnode.passthrough = node.passthrough;
+        gnode.span = node.span;
         
         // Handle async flag
         if node.async_node {
@@ -171,8 +228,25 @@ impl Lowerer {
             gnode.set_flag(node_flags::CACHEABLE);
         }
         
-        // Intern node name in string pool
-        gnode.data_offset = self.ir.strings.intern(&node.id);
+        // <set> data (by key) and signals (in document order) go to the
+        // node data section; the runtime hands them to the host
+        let pairs = node.data.len() + node.signals.len();
+        if pairs > limits::MAX_NODE_DATA_PAIRS {
+            return Err(CompileError::Lower(format!(
+                "Node '{}' has {} <set> entries (max {})",
+                node.id, pairs, limits::MAX_NODE_DATA_PAIRS
+            )));
+        }
+        let mut data: Vec<_> = node.data.iter().collect();
+        data.sort();
+        let mut intern = |(key, value): (&String, &String)| {
+            (self.ir.strings.intern(key), self.ir.strings.intern(value))
+        };
+        let record = NodeData {
+            data: data.into_iter().map(&mut intern).collect(),
+            signals: node.signals.iter().map(|(s, v)| intern((s, v))).collect(),
+        };
+        gnode.data_offset = self.ir.node_data.add(&record);
         
         // Register in node map
         self.node_map.insert(
@@ -227,6 +301,7 @@ impl Lowerer {
         
         // Set weight
         gedge.weight = edge.weight.unwrap_or(0);
+        gedge.span = edge.span;
         
         self.ir.edges.push(gedge);
         self.edge_id_counter += 1;
@@ -258,6 +333,16 @@ impl Lowerer {
     fn lower_inline_predicate(&mut self, expr: &PredicateExpr) -> Result<PredicateId, CompileError> {
         match expr {
             PredicateExpr::Always => Ok(0), // Special case: always true
+            PredicateExpr::Ref { predicate } => {
+                // A bare reference is the named predicate itself
+                self.predicate_map
+                    .get(predicate)
+                    .copied()
+                    .ok_or_else(|| CompileError::Lower(format!(
+                        "Edge references unknown predicate: {}",
+                        predicate
+                    )))
+            }
             PredicateExpr::Fail => {
                 // Create a "fail" predicate
                 let id = self.predicate_id_counter;
@@ -290,11 +375,13 @@ impl Lowerer {
                 workflow.entry.node, workflow.id
             )))?;
         
-        self.ir.entries.push(GEntry::new(
+        let mut entry = GEntry::new(
             workflow.entry.p.clone(),
             workflow.entry.x.clone(),
             entry_node_id,
-        ));
+        );
+        entry.span = workflow.entry.span;
+        self.ir.entries.push(entry);
         
         Ok(())
     }
@@ -321,15 +408,18 @@ mod tests {
     fn make_minimal_doc() -> OmarDocument {
         OmarDocument {
             version: "1.0".into(),
+            imports: vec![],
             predicates: vec![],
             merge_policies: vec![],
             workflows: vec![Workflow {
                 description: None,
+                schema: None,
                 id: "test".into(),
                 entry: EntryPoint {
                     p: "test".into(),
                     x: "run".into(),
                     node: "start".into(),
+                    span: None,
                 },
                 nodes: vec![
                     Node {
@@ -350,8 +440,11 @@ mod tests {
                     predicate: Some(PredicateExpr::Always),
                     ..Default::default()
                 }],
+                uses: vec![],
+                span: None,
             }],
             templates: vec![],
+            fragments: vec![],
             schemas: vec![],
         }
     }
@@ -412,4 +505,40 @@ mod tests {
         let result = lower(&doc);
         assert!(result.is_err());
     }
+    
+    #[test]
+    fn test_lower_resolves_templates() {
+        let mut doc = make_minimal_doc();
+        doc.templates = ["unused", "card"].iter()
+            .map(|id| Template { id: id.to_string(), content: "<div/>".into(), span: None })
+            .collect();
+        doc.workflows[0].nodes[0].kind = "render".into();
+        doc.workflows[0].nodes[0].template = Some("card".into());
+        doc.workflows[0].nodes[1].template = Some("nope".into());
+        
+        let ir = lower(&doc).unwrap();
+        
+        assert_eq!(ir.templates.len(), 2);
+        assert_eq!(ir.templates[1].id, 2);
+        assert_eq!(ir.templates[1].references, 1);
+        assert_eq!(ir.templates[0].references, 0);
+        assert_eq!(ir.nodes[0].template_id, Some(2));
+        assert_eq!(ir.nodes[0].op_code, 2);
+        assert_eq!(ir.nodes[1].template_id, None);
+        
+        doc.templates.push(doc.templates[0].clone());
+        assert!(lower(&doc).is_err(), "duplicate template id");
+    }
+    
+    #[test]
+    fn test_lower_error_carries_span() {
+        let mut doc = make_minimal_doc();
+        let span = crate::Span { start: 10, end: 20, line: 3, column: 5 };
+        doc.workflows[0].edges[0].to = "nonexistent".into();
+        doc.workflows[0].edges[0].span = Some(span);
+        
+        let err = lower(&doc).unwrap_err();
+        assert_eq!(err.span(), Some(span));
+        assert_eq!(err.to_diagnostic().location.unwrap().span, Some(span));
+    }
 }
\ No newline at end of file
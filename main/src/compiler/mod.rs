@@ -1,35 +1,71 @@
 //! Compiler Module
 //!
 //! All compile-time logic for transforming XML → graph.bin:
+//! - Fragment and sub-workflow call expansion
 //! - IR types and lowering
-//! - Predicate bytecode compilation
+//! - Predicate simplification and bytecode compilation
+//! - Bytecode verification (stack depth, step count, call depth)
+//! - Typing of predicates against schemas
 //! - Constraint validation (syntactic, semantic, pragmatic)
 //! - Optimization passes
 
 pub mod ir;
 pub mod lower;
+pub mod fragment;
+pub mod inline;
 pub mod bytecode;
+pub mod verify;
+pub mod simplify;
 pub mod optimize;
 pub mod syntactic;
 pub mod semantic;
+pub mod typing;
 pub mod pragmatic;
 
-use crate::{Diagnostic, CompileError};
-use crate::dsl::ast::{OmarDocument, PredicateExpr};
+use crate::{Diagnostic, CompileError, Span, limits};
+use crate::compiler::simplify::SimplifyStats;
+use crate::dsl::ast::{OmarDocument, PredicateDef, PredicateExpr};
+use std::collections::HashMap;
 
 // Re-export commonly used items
-pub use ir::{GraphIR, GNode, GEdge, GEntry, CompiledPredicate, StringPool};
+pub use ir::{GraphIR, GNode, GEdge, GEntry, CompiledPredicate, GTemplate, StringPool, NodeDataSection, NodeData, PredicateId};
 pub use lower::lower;
+pub use fragment::expand_fragments;
+pub use inline::inline_calls;
 pub use optimize::optimize;
+pub use simplify::simplify;
 
 /// Compile all predicates in the IR to bytecode
-pub fn compile_predicates(mut ir: GraphIR, doc: &OmarDocument) -> Result<GraphIR, CompileError> {
+///
+/// `<ref predicate>` inside an expression compiles to `CallPred` with the
+/// named predicate's id. References must not be recursive, and no chain
+/// of calls may be deeper than `limits::MAX_CALL_DEPTH` evaluations.
+/// The compiled bytecode is then verified (see `verify`) and the bounds
+/// recorded on each predicate.
+pub fn compile_predicates(ir: GraphIR, doc: &OmarDocument) -> Result<GraphIR, CompileError> {
+    compile_predicates_with(ir, doc, false)
+}
+
+/// Compile all predicates, simplifying each expression first when
+/// `simplify` is set (see `simplify`); the changes are counted in
+/// `GraphIR::simplification`
+pub fn compile_predicates_with(mut ir: GraphIR, doc: &OmarDocument, simplify: bool) -> Result<GraphIR, CompileError> {
+    let depths = predicate_call_depths(doc)?;
+    let ids: HashMap<String, PredicateId> = ir.predicates.iter()
+        .filter(|p| depths.contains_key(p.name.as_str()))
+        .map(|p| (p.name.clone(), p.id))
+        .collect();
+    // Where each predicate came from, for verifier errors
+    let mut sources: HashMap<PredicateId, Option<Span>> = HashMap::new();
+    
     // First, compile named predicates from the document
     for pred_def in &doc.predicates {
         // Find the corresponding CompiledPredicate in IR
         if let Some(pred) = ir.predicates.iter_mut().find(|p| p.name == pred_def.id) {
-            let mut compiler = bytecode::PredicateCompiler::new(&mut ir.strings);
-            pred.bytecode = compiler.compile(&pred_def.expr)?;
+            let stats = simplify.then_some(&mut ir.simplification);
+            pred.bytecode = compile_expr(&mut ir.strings, &pred_def.expr, &pred_def.expr_spans, &ids, stats)
+                .map_err(|e| e.with_span(pred_def.span))?;
+            sources.insert(pred.id, pred_def.span);
         }
     }
     
@@ -37,35 +73,185 @@ pub fn compile_predicates(mut ir: GraphIR, doc: &OmarDocument) -> Result<GraphIR
     for workflow in &doc.workflows {
         for edge in &workflow.edges {
             if let Some(ref expr) = edge.predicate {
+                check_inline_call_depth(expr, &depths)
+                    .map_err(|e| e.with_span(edge.span))?;
                 // Find the inline predicate by looking for _inline_ or _fail_ names
-                compile_inline_predicate(&mut ir, expr)?;
+                let id = compile_inline_predicate(&mut ir, expr, &edge.predicate_spans, &ids, simplify)
+                    .map_err(|e| e.with_span(edge.span))?;
+                if let Some(id) = id {
+                    sources.insert(id, edge.span);
+                }
             }
         }
     }
     
+    verify::verify_predicates(&mut ir.predicates).map_err(|(id, message)| {
+        let name = ir.predicates.iter()
+            .find(|p| p.id == id)
+            .map(|p| p.name.as_str())
+            .unwrap_or_default();
+        let subject = if ids.contains_key(name) {
+            format!("Predicate '{}'", name)
+        } else {
+            "Edge predicate".to_string()
+        };
+        CompileError::Predicate(format!("{} fails verification: {}", subject, message))
+            .with_span(sources.get(&id).copied().flatten())
+    })?;
+    
     Ok(ir)
 }
 
-/// Compile an inline predicate expression
-fn compile_inline_predicate(ir: &mut GraphIR, expr: &PredicateExpr) -> Result<(), CompileError> {
-    // Skip "always" predicates - they use ID 0 and don't need bytecode
-    if matches!(expr, PredicateExpr::Always) {
+/// Named predicates `expr` calls with `<ref>`, in source order
+fn references(expr: &PredicateExpr) -> Vec<&str> {
+    match expr {
+        PredicateExpr::Ref { predicate } => vec![predicate.as_str()],
+        _ => expr.children().into_iter().flat_map(references).collect(),
+    }
+}
+
+/// Evaluation depth of each named predicate, with the callee it reaches
+/// that depth through: 1 if it calls nothing, otherwise one more than the
+/// deepest predicate it references
+///
+/// Unknown references are skipped here; the bytecode compiler reports
+/// them at the `<ref>`.
+fn predicate_call_depths(doc: &OmarDocument) -> Result<HashMap<&str, (usize, Option<&str>)>, CompileError> {
+    let defs: HashMap<&str, &PredicateDef> = doc.predicates.iter()
+        .map(|def| (def.id.as_str(), def))
+        .collect();
+    let mut depths = HashMap::new();
+    for def in &doc.predicates {
+        call_depth(def, &defs, &mut Vec::new(), &mut depths)?;
+    }
+    Ok(depths)
+}
+
+fn call_depth<'a>(
+    def: &'a PredicateDef,
+    defs: &HashMap<&'a str, &'a PredicateDef>,
+    stack: &mut Vec<&'a str>,
+    depths: &mut HashMap<&'a str, (usize, Option<&'a str>)>,
+) -> Result<usize, CompileError> {
+    if let Some(&(depth, _)) = depths.get(def.id.as_str()) {
+        return Ok(depth);
+    }
+    if let Some(start) = stack.iter().position(|id| *id == def.id) {
+        let cycle: Vec<_> = stack[start..].iter().copied().chain([def.id.as_str()]).collect();
+        return Err(CompileError::Predicate(format!(
+            "Recursive predicate reference: {}",
+            cycle.join(" → ")
+        )).with_span(def.span));
+    }
+    
+    stack.push(&def.id);
+    let mut deepest = (1, None);
+    for name in references(&def.expr) {
+        let Some(callee) = defs.get(name) else { continue };
+        let depth = 1 + call_depth(callee, defs, stack, depths)?;
+        if depth > deepest.0 {
+            deepest = (depth, Some(callee.id.as_str()));
+        }
+    }
+    stack.pop();
+    
+    if deepest.0 > limits::MAX_CALL_DEPTH {
+        let mut chain = vec![def.id.as_str()];
+        let mut next = deepest.1;
+        while let Some(name) = next {
+            chain.push(name);
+            next = depths.get(name).and_then(|&(_, callee)| callee);
+        }
+        return Err(CompileError::Predicate(format!(
+            "Predicate call depth {} exceeds MAX_CALL_DEPTH ({}): {}",
+            deepest.0, limits::MAX_CALL_DEPTH, chain.join(" → ")
+        )).with_span(def.span));
+    }
+    
+    depths.insert(&def.id, deepest);
+    Ok(deepest.0)
+}
+
+/// An inline predicate is one more evaluation on top of the predicates it
+/// calls; a bare `<ref>` is the named predicate itself
+fn check_inline_call_depth(
+    expr: &PredicateExpr,
+    depths: &HashMap<&str, (usize, Option<&str>)>,
+) -> Result<(), CompileError> {
+    if matches!(expr, PredicateExpr::Ref { .. }) {
         return Ok(());
     }
+    let deepest = references(expr).into_iter()
+        .filter_map(|name| depths.get(name).map(|&(depth, _)| (depth, name)))
+        .max();
+    if let Some((depth, name)) = deepest {
+        if depth + 1 > limits::MAX_CALL_DEPTH {
+            return Err(CompileError::Predicate(format!(
+                "Predicate call depth {} exceeds MAX_CALL_DEPTH ({}) through '{}'",
+                depth + 1, limits::MAX_CALL_DEPTH, name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Compile an inline predicate expression, returning the predicate it
+/// compiled into
+fn compile_inline_predicate(
+    ir: &mut GraphIR,
+    expr: &PredicateExpr,
+    spans: &[Span],
+    predicates: &HashMap<String, PredicateId>,
+    simplify: bool,
+) -> Result<Option<PredicateId>, CompileError> {
+    // Skip "always" predicates - they use ID 0 and don't need bytecode -
+    // and bare references, which lower to the named predicate's ID
+    if matches!(expr, PredicateExpr::Always | PredicateExpr::Ref { .. }) {
+        return Ok(None);
+    }
     
     // Find uncompiled inline predicates
     for pred in &mut ir.predicates {
         if pred.bytecode.is_empty() && (pred.name.starts_with("_inline_") || pred.name.starts_with("_fail_")) {
-            // Clone strings for borrow checker
-            let mut strings = ir.strings.clone();
-            let mut compiler = bytecode::PredicateCompiler::new(&mut strings);
-            pred.bytecode = compiler.compile(expr)?;
-            ir.strings = strings;
-            return Ok(());
+            let stats = simplify.then_some(&mut ir.simplification);
+            pred.bytecode = compile_expr(&mut ir.strings, expr, spans, predicates, stats)?;
+            return Ok(Some(pred.id));
         }
     }
     
-    Ok(())
+    Ok(None)
+}
+
+/// Compile one expression, simplified first when `stats` is given
+///
+/// A simplified expression may drop operands; the original is still
+/// compiled (into a scratch string pool) so that optimizing never hides
+/// an error in them.
+fn compile_expr(
+    strings: &mut StringPool,
+    expr: &PredicateExpr,
+    spans: &[Span],
+    predicates: &HashMap<String, PredicateId>,
+    stats: Option<&mut SimplifyStats>,
+) -> Result<Vec<u8>, CompileError> {
+    let Some(stats) = stats else {
+        return bytecode::PredicateCompiler::new(strings)
+            .with_spans(spans)
+            .with_predicates(predicates)
+            .compile(expr);
+    };
+    
+    let (simplified, simplified_spans) = simplify::simplify(expr, spans, stats);
+    if simplified != *expr {
+        bytecode::PredicateCompiler::new(&mut strings.clone())
+            .with_spans(spans)
+            .with_predicates(predicates)
+            .compile(expr)?;
+    }
+    bytecode::PredicateCompiler::new(strings)
+        .with_spans(&simplified_spans)
+        .with_predicates(predicates)
+        .compile(&simplified)
 }
 
 /// Run syntactic validation checks
@@ -78,6 +264,11 @@ pub fn check_semantic(ir: &GraphIR) -> Vec<Diagnostic> {
     semantic::check(ir)
 }
 
+/// Type-check schema-bound predicates, recording the types in the IR
+pub fn check_types(ir: &mut GraphIR, doc: &OmarDocument) -> Vec<Diagnostic> {
+    typing::check(ir, doc)
+}
+
 /// Run pragmatic validation checks
 pub fn check_pragmatic(ir: &GraphIR) -> Vec<Diagnostic> {
     pragmatic::check(ir)
@@ -98,7 +289,8 @@ pub fn compile_and_validate(
     doc: &OmarDocument,
 ) -> Result<(GraphIR, Vec<Diagnostic>), CompileError> {
     ir = compile_predicates(ir, doc)?;
-    let diags = validate(&ir);
+    let mut diags = check_types(&mut ir, doc);
+    diags.extend(validate(&ir));
     Ok((ir, diags))
 }
 
@@ -106,11 +298,11 @@ pub fn compile_and_validate(
 mod tests {
     use super::*;
     use crate::dsl::ast::*;
-    use crate::NodeKind;
     
     fn make_test_doc() -> OmarDocument {
         OmarDocument {
             version: "1.0".into(),
+            imports: vec![],
             predicates: vec![
                 PredicateDef {
                     id: "is_admin".into(),
@@ -118,16 +310,21 @@ mod tests {
                         left: "$token.role".into(),
                         right: Value::Str("admin".into()),
                     },
+                    span: None,
+                    expr_spans: vec![],
+                    expr_source: None,
                 },
             ],
             merge_policies: vec![],
             workflows: vec![Workflow {
                 description: None,
+                schema: None,
                 id: "test".into(),
                 entry: EntryPoint {
                     p: "test".into(),
                     x: "run".into(),
                     node: "start".into(),
+                    span: None,
                 },
                 nodes: vec![
                     Node {
@@ -148,8 +345,11 @@ mod tests {
                     predicate: Some(PredicateExpr::Always),
                     ..Default::default()
                 }],
+                uses: vec![],
+                span: None,
             }],
             templates: vec![],
+            fragments: vec![],
             schemas: vec![],
         }
     }
@@ -167,6 +367,101 @@ mod tests {
         assert!(!is_admin.unwrap().bytecode.is_empty());
     }
     
+    fn predicate(id: &str, expr: PredicateExpr) -> PredicateDef {
+        PredicateDef { id: id.into(), expr, span: None, expr_spans: vec![], expr_source: None }
+    }
+    
+    fn reference(id: &str) -> PredicateExpr {
+        PredicateExpr::Ref { predicate: id.into() }
+    }
+    
+    #[test]
+    fn test_compile_predicate_refs() {
+        let mut doc = make_test_doc();
+        doc.predicates.push(predicate("not_admin", PredicateExpr::Not { condition: Box::new(reference("is_admin")) }));
+        doc.workflows[0].edges[0].predicate = Some(PredicateExpr::Or {
+            conditions: vec![reference("not_admin"), PredicateExpr::Fail],
+        });
+        let ir = compile_predicates(lower(&doc).unwrap(), &doc).unwrap();
+        
+        let not_admin = ir.predicates.iter().find(|p| p.name == "not_admin").unwrap();
+        assert_eq!(not_admin.bytecode[..3], [crate::Opcode::CallPred as u8, 1, 0]);
+        let inline = ir.predicates.iter().find(|p| p.name.starts_with("_inline_")).unwrap();
+        assert_eq!(inline.bytecode[..3], [crate::Opcode::CallPred as u8, 2, 0]);
+        
+        // A bare reference is the named predicate, with no inline copy
+        doc.workflows[0].edges[0].predicate = Some(reference("not_admin"));
+        let ir = compile_predicates(lower(&doc).unwrap(), &doc).unwrap();
+        assert_eq!(ir.edges[0].predicate_id, 2);
+        assert_eq!(ir.predicates.len(), 2);
+    }
+    
+    #[test]
+    fn test_recursive_predicate_refs() {
+        let mut doc = make_test_doc();
+        doc.predicates = vec![
+            predicate("a", PredicateExpr::And { conditions: vec![PredicateExpr::Always, reference("b")] }),
+            predicate("b", PredicateExpr::Not { condition: Box::new(reference("a")) }),
+        ];
+        let err = compile_predicates(lower(&doc).unwrap(), &doc).unwrap_err();
+        assert!(err.to_string().contains("Recursive predicate reference: a → b → a"), "{}", err);
+        
+        doc.predicates = vec![predicate("self", reference("self"))];
+        let err = compile_predicates(lower(&doc).unwrap(), &doc).unwrap_err();
+        assert!(err.to_string().contains("self → self"), "{}", err);
+    }
+    
+    #[test]
+    fn test_predicate_call_depth() {
+        // p0 calls p1 ... calls p{n-1}: n evaluations deep
+        let chain = |n: usize| {
+            let mut doc = make_test_doc();
+            doc.predicates = (0..n)
+                .map(|i| match i + 1 < n {
+                    true => predicate(&format!("p{}", i), reference(&format!("p{}", i + 1))),
+                    false => predicate(&format!("p{}", i), PredicateExpr::Always),
+                })
+                .collect();
+            doc
+        };
+        
+        let doc = chain(limits::MAX_CALL_DEPTH);
+        assert!(compile_predicates(lower(&doc).unwrap(), &doc).is_ok());
+        
+        let doc = chain(limits::MAX_CALL_DEPTH + 1);
+        let err = compile_predicates(lower(&doc).unwrap(), &doc).unwrap_err();
+        assert!(err.to_string().contains("MAX_CALL_DEPTH (4): p0 → p1 → p2 → p3 → p4"), "{}", err);
+        
+        // An inline predicate adds an evaluation on top of the chain
+        let mut doc = chain(limits::MAX_CALL_DEPTH);
+        doc.workflows[0].edges[0].predicate = Some(PredicateExpr::Not { condition: Box::new(reference("p0")) });
+        let err = compile_predicates(lower(&doc).unwrap(), &doc).unwrap_err();
+        assert!(err.to_string().contains("through 'p0'"), "{}", err);
+    }
+    
+    #[test]
+    fn test_compile_predicates_simplified() {
+        let mut doc = make_test_doc();
+        doc.workflows[0].edges[0].predicate = Some(PredicateExpr::And {
+            conditions: vec![PredicateExpr::Always, reference("is_admin"), reference("is_admin")],
+        });
+        
+        let plain = compile_predicates(lower(&doc).unwrap(), &doc).unwrap();
+        let ir = compile_predicates_with(lower(&doc).unwrap(), &doc, true).unwrap();
+        let inline = |ir: &GraphIR| ir.predicates.iter().find(|p| p.name.starts_with("_inline_")).unwrap().bytecode.clone();
+        assert_eq!(inline(&ir), [crate::Opcode::CallPred as u8, 1, 0, crate::Opcode::Ret as u8]);
+        assert!(inline(&plain).len() > inline(&ir).len());
+        assert_eq!(ir.simplification.predicates_simplified, 1);
+        assert_eq!(ir.simplification.operands_deduplicated, 1);
+        
+        // Operands dropped by simplification are still checked
+        doc.workflows[0].edges[0].predicate = Some(PredicateExpr::Or {
+            conditions: vec![PredicateExpr::Always, reference("missing")],
+        });
+        let err = compile_predicates_with(lower(&doc).unwrap(), &doc, true).unwrap_err();
+        assert!(err.to_string().contains("Unknown predicate reference: missing"), "{}", err);
+    }
+    
     #[test]
     fn test_validate_all() {
         let doc = make_test_doc();
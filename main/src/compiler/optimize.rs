@@ -1,19 +1,21 @@
 //! Graph Optimization Passes
 //!
-//! Optimizations run after lowering, before emission:
+//! Optimizations run after lowering, before emission (predicate
+//! expressions are simplified earlier, see `simplify`):
 //! - Dead code elimination (unreachable nodes)
 //! - Predicate deduplication (merge identical bytecode)
 //! - Edge ordering (by weight for faster matching)
 
 use crate::compiler::ir::*;
+use crate::NodeKind;
+use crate::compiler::bytecode::remap_calls;
 use std::collections::{HashSet, VecDeque};
 
 /// Run all optimization passes
 pub fn optimize(ir: GraphIR) -> GraphIR {
     let ir = remove_unreachable_nodes(ir);
     let ir = deduplicate_predicates(ir);
-    let ir = optimize_edge_order(ir);
-    ir
+    optimize_edge_order(ir)
 }
 
 /// Remove nodes not reachable from any entry point
@@ -89,13 +91,17 @@ fn compute_reachable(ir: &GraphIR) -> HashSet<NodeId> {
 }
 
 /// Merge predicates with identical bytecode
+///
+/// The runtime finds predicate `n` at entry `n - 1` of the emitted table,
+/// so the survivors are renumbered 1..=n and every reference (edge
+/// guards, auth predicates, `CallPred` operands) is remapped to match.
 pub fn deduplicate_predicates(mut ir: GraphIR) -> GraphIR {
     use std::collections::HashMap;
     
     // Map bytecode -> canonical predicate ID
     let mut bytecode_to_id: HashMap<Vec<u8>, PredicateId> = HashMap::new();
-    // Map old ID -> new ID (for duplicates)
-    let mut id_remap: HashMap<PredicateId, PredicateId> = HashMap::new();
+    // Map old ID -> canonical ID (for duplicates)
+    let mut canonical: HashMap<PredicateId, PredicateId> = HashMap::new();
     
     // Find duplicates
     for pred in &ir.predicates {
@@ -106,34 +112,18 @@ pub fn deduplicate_predicates(mut ir: GraphIR) -> GraphIR {
         
         if let Some(&canonical_id) = bytecode_to_id.get(&pred.bytecode) {
             // This is a duplicate
-            id_remap.insert(pred.id, canonical_id);
+            canonical.insert(pred.id, canonical_id);
         } else {
             // This is canonical
             bytecode_to_id.insert(pred.bytecode.clone(), pred.id);
         }
     }
     
-    if id_remap.is_empty() {
+    if canonical.is_empty() {
         // No duplicates found
         return ir;
     }
     
-    // Remap edge predicate IDs
-    for edge in &mut ir.edges {
-        if let Some(&new_id) = id_remap.get(&edge.predicate_id) {
-            edge.predicate_id = new_id;
-        }
-    }
-    
-    // Remap node auth predicates
-    for node in &mut ir.nodes {
-        if let Some(pred_id) = node.auth_predicate {
-            if let Some(&new_id) = id_remap.get(&pred_id) {
-                node.auth_predicate = Some(new_id);
-            }
-        }
-    }
-    
     // Remove duplicate predicates
     let keep_ids: HashSet<PredicateId> = bytecode_to_id.values().copied().collect();
     ir.predicates.retain(|p| {
@@ -141,6 +131,32 @@ pub fn deduplicate_predicates(mut ir: GraphIR) -> GraphIR {
         keep_ids.contains(&p.id) || p.bytecode.is_empty()
     });
     
+    // Renumber the survivors by position; duplicates follow their canonical
+    let mut id_remap: HashMap<PredicateId, PredicateId> = ir.predicates.iter()
+        .enumerate()
+        .map(|(i, p)| (p.id, i as PredicateId + 1))
+        .collect();
+    for (duplicate, canonical_id) in &canonical {
+        id_remap.insert(*duplicate, id_remap[canonical_id]);
+    }
+    // ID 0 is "always" and never remapped
+    let remap = |id: PredicateId| id_remap.get(&id).copied().unwrap_or(id);
+    
+    for pred in &mut ir.predicates {
+        pred.id = remap(pred.id);
+        remap_calls(&mut pred.bytecode, remap);
+    }
+    for edge in &mut ir.edges {
+        edge.predicate_id = remap(edge.predicate_id);
+    }
+    for node in &mut ir.nodes {
+        node.auth_predicate = node.auth_predicate.map(remap);
+        // Auth nodes carry their predicate in the emitted op_code field
+        if node.kind == NodeKind::Auth {
+            node.op_code = remap(node.op_code);
+        }
+    }
+    
     ir
 }
 
@@ -220,6 +236,10 @@ pub fn compute_stats(before: &GraphIR, after: &GraphIR) -> OptimizationStats {
         predicates_before: before.predicates.len(),
         predicates_after: after.predicates.len(),
         predicates_deduplicated: before.predicates.len().saturating_sub(after.predicates.len()),
+        predicates_simplified: after.simplification.predicates_simplified,
+        predicate_nodes_removed: after.simplification.nodes_removed,
+        constants_folded: after.simplification.constants_folded,
+        operands_deduplicated: after.simplification.operands_deduplicated,
     }
 }
 
@@ -234,11 +254,19 @@ pub struct OptimizationStats {
     pub predicates_before: usize,
     pub predicates_after: usize,
     pub predicates_deduplicated: usize,
+    /// Predicate expressions changed by `simplify`
+    pub predicates_simplified: usize,
+    pub predicate_nodes_removed: usize,
+    pub constants_folded: usize,
+    pub operands_deduplicated: usize,
 }
 
 impl OptimizationStats {
     pub fn has_changes(&self) -> bool {
-        self.nodes_removed > 0 || self.edges_removed > 0 || self.predicates_deduplicated > 0
+        self.nodes_removed > 0
+            || self.edges_removed > 0
+            || self.predicates_deduplicated > 0
+            || self.predicates_simplified > 0
     }
 }
 
@@ -319,6 +347,46 @@ mod tests {
         assert!(ir.edges.iter().filter(|e| e.predicate_id == 1).count() >= 1);
     }
     
+    #[test]
+    fn test_deduplicate_remaps_calls() {
+        let mut ir = GraphIR::new();
+        let always = vec![0x01, 0x01, 0x00, 0x00, 0x00, 0xFF];
+        ir.predicates.push(CompiledPredicate::with_bytecode(1, "a".into(), always.clone()));
+        ir.predicates.push(CompiledPredicate::with_bytecode(2, "b".into(), always));
+        // CALL_PRED #2, NOT, RET
+        ir.predicates.push(CompiledPredicate::with_bytecode(3, "not_b".into(), vec![0xF0, 0x02, 0x00, 0x22, 0xFF]));
+        
+        let ir = deduplicate_predicates(ir);
+        
+        assert_eq!(ir.predicates.len(), 2);
+        assert_eq!(ir.predicates[1].bytecode, [0xF0, 0x01, 0x00, 0x22, 0xFF]);
+    }
+    
+    #[test]
+    fn test_deduplicate_renumbers() {
+        let mut ir = GraphIR::new();
+        let always = vec![0x01, 0x01, 0x00, 0x00, 0x00, 0xFF];
+        ir.predicates.push(CompiledPredicate::with_bytecode(1, "a".into(), always.clone()));
+        ir.predicates.push(CompiledPredicate::with_bytecode(2, "b".into(), always));
+        ir.predicates.push(CompiledPredicate::with_bytecode(3, "c".into(), vec![0x01, 0x00, 0x00, 0x00, 0x00, 0xFF]));
+        // CALL_PRED #3, NOT, RET
+        ir.predicates.push(CompiledPredicate::with_bytecode(4, "not_c".into(), vec![0xF0, 0x03, 0x00, 0x22, 0xFF]));
+        ir.nodes.push(GNode { auth_predicate: Some(4), op_code: 4, ..GNode::new(0, "start".into(), NodeKind::Auth) });
+        ir.edges.push(GEdge { predicate_id: 2, ..GEdge::new(0, 0, 0) });
+        ir.edges.push(GEdge { predicate_id: 3, ..GEdge::new(1, 0, 0) });
+        ir.edges.push(GEdge::new(2, 0, 0));
+        
+        let ir = deduplicate_predicates(ir);
+        
+        // Ids match table positions, with no gap where `b` was
+        let ids: Vec<_> = ir.predicates.iter().map(|p| (p.name.as_str(), p.id)).collect();
+        assert_eq!(ids, [("a", 1), ("c", 2), ("not_c", 3)]);
+        let guards: Vec<_> = ir.edges.iter().map(|e| e.predicate_id).collect();
+        assert_eq!(guards, [1, 2, 0]);
+        assert_eq!((ir.nodes[0].auth_predicate, ir.nodes[0].op_code), (Some(3), 3));
+        assert_eq!(ir.predicates[2].bytecode, [0xF0, 0x02, 0x00, 0x22, 0xFF]);
+    }
+    
     #[test]
     fn test_edge_ordering() {
         let mut ir = GraphIR::new();
@@ -349,6 +417,13 @@ mod tests {
         assert_eq!(stats.nodes_after, 3);
         assert_eq!(stats.nodes_removed, 1);
         assert!(stats.has_changes());
+        
+        let mut simplified = before.clone();
+        simplified.simplification.predicates_simplified = 2;
+        simplified.simplification.constants_folded = 3;
+        let stats = compute_stats(&before, &simplified);
+        assert_eq!((stats.predicates_simplified, stats.constants_folded), (2, 3));
+        assert!(stats.has_changes());
     }
     
     #[test]
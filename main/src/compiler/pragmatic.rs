@@ -7,7 +7,7 @@
 //!
 //! These are the "you probably don't want to do that" checks.
 
-use crate::{Diagnostic, Location, Severity, NodeKind, ActorKind, ConfirmationStatus, SideEffects};
+use crate::{Diagnostic, Severity, NodeKind, ActorKind, ConfirmationStatus, SideEffects};
 use crate::compiler::ir::*;
 use std::collections::{HashSet, VecDeque, HashMap};
 
@@ -43,14 +43,15 @@ fn check_llm_to_irreversible(ir: &GraphIR) -> Vec<Diagnostic> {
     for llm_node in &llm_nodes {
         for irrev_node in &irreversible_nodes {
             if let Some(path) = find_path(ir, llm_node.id, irrev_node.id) {
-                // Check if there's a gate (auth or transform) in between
+                // Check if there's a gate (auth or transform) in between;
+                // call plumbing is not a gate
                 let has_gate = path.iter().skip(1).any(|&node_id| {
                     ir.nodes
                         .iter()
                         .find(|n| n.id == node_id)
                         .map(|n| {
                             n.kind == NodeKind::Auth || 
-                            n.kind == NodeKind::Transform ||
+                            (n.kind == NodeKind::Transform && !n.passthrough) ||
                             n.actor_kind == ActorKind::Human
                         })
                         .unwrap_or(false)
@@ -72,7 +73,7 @@ fn check_llm_to_irreversible(ir: &GraphIR) -> Vec<Diagnostic> {
                             "Path: {}. Add auth/transform node before irreversible action.",
                             path_names.join(" → ")
                         )),
-                        location: None,
+                        location: Some(llm_node.location()),
                     });
                 }
             }
@@ -113,10 +114,7 @@ fn check_write_error_branches(ir: &GraphIR) -> Vec<Diagnostic> {
                     node.name
                 ),
                 hint: Some("Add edge with <when><fail/></when> to error node".into()),
-                location: Some(Location {
-                    node_id: Some(node.name.clone()),
-                    ..Default::default()
-                }),
+                location: Some(node.location()),
             });
         }
     }
@@ -169,10 +167,7 @@ fn check_irreversible_human_path(ir: &GraphIR) -> Vec<Diagnostic> {
                     node.name
                 ),
                 hint: Some("Add a node with actor=\"human\" before irreversible action".into()),
-                location: Some(Location {
-                    node_id: Some(node.name.clone()),
-                    ..Default::default()
-                }),
+                location: Some(node.location()),
             });
         }
     }
@@ -220,7 +215,7 @@ fn check_irreversible_confirmed(ir: &GraphIR) -> Vec<Diagnostic> {
                             suggested.name, irrev.name
                         ),
                         hint: Some("Add human confirmation before irreversible action".into()),
-                        location: None,
+                        location: Some(suggested.location()),
                     });
                 }
             }
@@ -255,7 +250,7 @@ fn check_quarantined_escape(ir: &GraphIR) -> Vec<Diagnostic> {
                             node.name, target.name
                         ),
                         hint: Some("Quarantined data must pass through review before external use".into()),
-                        location: None,
+                        location: Some(edge.location()),
                     });
                 }
                 
@@ -269,7 +264,7 @@ fn check_quarantined_escape(ir: &GraphIR) -> Vec<Diagnostic> {
                             node.name, target.name
                         ),
                         hint: Some("Quarantined data must be reviewed before irreversible actions".into()),
-                        location: None,
+                        location: Some(edge.location()),
                     });
                 }
             }
@@ -360,7 +355,6 @@ fn dfs_all_paths(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::is_llm_op;
     
     fn make_valid_ir() -> GraphIR {
         let mut ir = GraphIR::new();
@@ -7,7 +7,7 @@
 //!
 //! Errors here indicate logic issues, not syntax problems.
 
-use crate::{Diagnostic, Location, Severity, NodeKind};
+use crate::{Diagnostic, Severity, NodeKind};
 use crate::compiler::ir::*;
 use std::collections::{HashSet, VecDeque, HashMap};
 
@@ -22,6 +22,8 @@ pub fn check(ir: &GraphIR) -> Vec<Diagnostic> {
     diags.extend(check_all_nodes_reachable(ir));
     diags.extend(check_error_nodes_have_incoming(ir));
     diags.extend(check_render_nodes_have_templates(ir));
+    diags.extend(check_template_references(ir));
+    diags.extend(check_templates_used(ir));
     
     diags
 }
@@ -41,10 +43,7 @@ fn check_auth_nodes_have_predicates(ir: &GraphIR) -> Vec<Diagnostic> {
                     code: "SEM001".into(),
                     message: format!("Auth node '{}' has no predicate", node.name),
                     hint: Some("Add <require predicate=\"...\"/> to auth node".into()),
-                    location: Some(Location {
-                        node_id: Some(node.name.clone()),
-                        ..Default::default()
-                    }),
+                    location: Some(node.location()),
                 });
             }
         }
@@ -64,10 +63,7 @@ fn check_external_nodes_have_opcodes(ir: &GraphIR) -> Vec<Diagnostic> {
                 code: "SEM002".into(),
                 message: format!("External node '{}' has no op code", node.name),
                 hint: Some("Add op=\"0x0300\" or similar to external node".into()),
-                location: Some(Location {
-                    node_id: Some(node.name.clone()),
-                    ..Default::default()
-                }),
+                location: Some(node.location()),
             });
         }
     }
@@ -101,10 +97,7 @@ fn check_terminal_nodes_no_outgoing(ir: &GraphIR) -> Vec<Diagnostic> {
                     node.name, outgoing_edges.len()
                 ),
                 hint: Some("Terminal nodes end traversal, edges will never be taken".into()),
-                location: Some(Location {
-                    node_id: Some(node.name.clone()),
-                    ..Default::default()
-                }),
+                location: Some(node.location()),
             });
         }
     }
@@ -120,7 +113,9 @@ fn check_no_cycles(ir: &GraphIR) -> Vec<Diagnostic> {
             code: "SEM004".into(),
             message: format!("Cycle detected: {}", cycle.join(" → ")),
             hint: Some("Workflows must be directed acyclic graphs (DAG)".into()),
-            location: None,
+            location: cycle.first()
+                .and_then(|name| ir.get_node_by_name(name))
+                .map(|n| n.location()),
         }]
     } else {
         vec![]
@@ -211,10 +206,7 @@ fn check_all_nodes_reachable(ir: &GraphIR) -> Vec<Diagnostic> {
                 code: "SEM005".into(),
                 message: format!("Node '{}' is unreachable from any entry point", node.name),
                 hint: Some("Remove dead node or add edge to it".into()),
-                location: Some(Location {
-                    node_id: Some(node.name.clone()),
-                    ..Default::default()
-                }),
+                location: Some(node.location()),
             });
         }
     }
@@ -261,10 +253,7 @@ fn check_error_nodes_have_incoming(ir: &GraphIR) -> Vec<Diagnostic> {
                     code: "SEM006".into(),
                     message: format!("Error node '{}' has no incoming edges", node.name),
                     hint: Some("Add error handling edges pointing to this node".into()),
-                    location: Some(Location {
-                        node_id: Some(node.name.clone()),
-                        ..Default::default()
-                    }),
+                    location: Some(node.location()),
                 });
             }
         }
@@ -284,10 +273,7 @@ fn check_render_nodes_have_templates(ir: &GraphIR) -> Vec<Diagnostic> {
                 code: "SEM007".into(),
                 message: format!("Render node '{}' has no template", node.name),
                 hint: Some("Add <template ref=\"...\"/> or inline template".into()),
-                location: Some(Location {
-                    node_id: Some(node.name.clone()),
-                    ..Default::default()
-                }),
+                location: Some(node.location()),
             });
         }
     }
@@ -295,6 +281,39 @@ fn check_render_nodes_have_templates(ir: &GraphIR) -> Vec<Diagnostic> {
     diags
 }
 
+/// SEM008: Template references must name a defined template
+fn check_template_references(ir: &GraphIR) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    
+    for node in &ir.nodes {
+        if let (Some(name), None) = (&node.template, node.template_id) {
+            diags.push(Diagnostic {
+                severity: Severity::Error,
+                code: "SEM008".into(),
+                message: format!("Node '{}' references unknown template '{}'", node.name, name),
+                hint: Some("Define it in <templates> or fix the reference".into()),
+                location: Some(node.location()),
+            });
+        }
+    }
+    
+    diags
+}
+
+/// SEM009: Templates should be used
+fn check_templates_used(ir: &GraphIR) -> Vec<Diagnostic> {
+    ir.templates.iter()
+        .filter(|t| t.references == 0)
+        .map(|t| Diagnostic {
+            severity: Severity::Warn,
+            code: "SEM009".into(),
+            message: format!("Template '{}' is never used", t.name),
+            hint: Some("Reference it with <template ref=\"...\"/> or remove it".into()),
+            location: Some(t.location()),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -426,4 +445,37 @@ mod tests {
         
         assert!(diags.iter().any(|d| d.code == "SEM007"));
     }
+    
+    #[test]
+    fn test_sem008_unknown_template() {
+        let mut ir = make_valid_ir();
+        let mut render = GNode::new(2, "render".into(), NodeKind::Render);
+        render.template = Some("missing".into());
+        ir.nodes.push(render);
+        
+        let diags = check(&ir);
+        
+        assert!(diags.iter().any(|d| d.code == "SEM008" && d.severity == Severity::Error));
+        assert!(!diags.iter().any(|d| d.code == "SEM007"));
+    }
+    
+    #[test]
+    fn test_sem009_unused_template() {
+        let mut ir = make_valid_ir();
+        for (id, references) in [(1, 0), (2, 1)] {
+            ir.templates.push(GTemplate {
+                id,
+                name: format!("t{}", id),
+                name_offset: 0,
+                content_offset: 0,
+                references,
+                span: None,
+            });
+        }
+        
+        let diags: Vec<_> = check(&ir).into_iter().filter(|d| d.code == "SEM009").collect();
+        
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].message, "Template 't1' is never used");
+    }
 }
\ No newline at end of file
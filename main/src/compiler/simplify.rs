@@ -0,0 +1,358 @@
+//! Predicate Simplification
+//!
+//! With `CompileOptions::optimize`, every predicate expression is
+//! simplified before it is compiled to bytecode, so constant or redundant
+//! structure costs no VM steps at runtime:
+//!
+//! - `<and>`/`<or>` nested in the same operator are flattened
+//! - `<always/>` is dropped from `<and>`, `<fail/>` from `<or>`; a
+//!   `<fail/>` operand makes an `<and>` fail, an `<always/>` makes an
+//!   `<or>` hold, unless an operand before it may fault (see below)
+//! - identical operands of `<and>`/`<or>` are kept once; an empty
+//!   `<and>` is `<always/>`, an empty `<or>` `<fail/>`, and a single
+//!   operand stands for itself
+//! - `<not><not>x</not></not>` is `x`; `<not>` of a constant is the
+//!   other constant
+//! - comparisons whose outcome is known at compile time are folded:
+//!   `eq`/`neq` of a string literal with a string, and
+//!   `starts_with`/`ends_with` of a string literal, and `in`/`not_in`
+//!   of a string literal in a list of strings
+//!
+//! Predicates are pure, so dropping an operand never drops an effect,
+//! but `<compare>` arithmetic can fault, and a fault fails the whole
+//! evaluation (including through `<ref>`). An operand that may fault is
+//! therefore never folded away: `<or>` of a `<compare>` and `<always/>`
+//! keeps both, so optimized and unoptimized builds agree.
+//! Source spans are carried along, so errors in the simplified expression
+//! still point at the original XML.
+
+use crate::Span;
+use crate::compiler::bytecode::operand_call;
+use crate::dsl::ast::{PredicateExpr, Value};
+
+/// What simplification changed, summed over all predicates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimplifyStats {
+    /// Predicates whose expression changed
+    pub predicates_simplified: usize,
+    /// Expression nodes removed
+    pub nodes_removed: usize,
+    /// Constant operands and comparisons folded away
+    pub constants_folded: usize,
+    /// Repeated `<and>`/`<or>` operands removed
+    pub operands_deduplicated: usize,
+}
+
+/// Simplify `expr`, returning the new expression and its pre-order spans
+///
+/// `spans` are the pre-order spans of `expr` (see
+/// `PredicateExpr::children`); if they do not cover it, the result has no
+/// spans either.
+pub fn simplify(expr: &PredicateExpr, spans: &[Span], stats: &mut SimplifyStats) -> (PredicateExpr, Vec<Span>) {
+    let before = node_count(expr);
+    let spans = if spans.len() == before { spans } else { &[] };
+    let (simplified, simplified_spans) = simplify_expr(expr, spans, stats);
+    if simplified != *expr {
+        stats.predicates_simplified += 1;
+        stats.nodes_removed += before.saturating_sub(node_count(&simplified));
+    }
+    (simplified, simplified_spans)
+}
+
+/// Whether evaluating `expr` may fault: `<compare>` arithmetic, or a
+/// `<ref>` to a predicate that might contain some
+fn can_fault(expr: &PredicateExpr) -> bool {
+    matches!(expr, PredicateExpr::Compare { .. } | PredicateExpr::Ref { .. })
+        || expr.children().into_iter().any(can_fault)
+}
+
+/// Number of expression nodes, i.e. of pre-order spans
+fn node_count(expr: &PredicateExpr) -> usize {
+    1 + expr.children().into_iter().map(node_count).sum::<usize>()
+}
+
+/// Split `spans` (the pre-order spans following a parent's own span)
+/// among `children`; empty when there are no spans
+fn child_spans<'a>(children: &[&PredicateExpr], spans: &'a [Span]) -> Vec<&'a [Span]> {
+    let mut rest = spans;
+    children.iter()
+        .map(|child| {
+            let (own, tail) = rest.split_at(node_count(child).min(rest.len()));
+            rest = tail;
+            own
+        })
+        .collect()
+}
+
+fn simplify_expr(expr: &PredicateExpr, spans: &[Span], stats: &mut SimplifyStats) -> (PredicateExpr, Vec<Span>) {
+    let own: Vec<Span> = spans.first().copied().into_iter().collect();
+    let tail = spans.get(1..).unwrap_or_default();
+
+    match expr {
+        PredicateExpr::And { conditions } | PredicateExpr::Or { conditions } => {
+            let is_and = matches!(expr, PredicateExpr::And { .. });
+            let (identity, absorbing) = if is_and {
+                (PredicateExpr::Always, PredicateExpr::Fail)
+            } else {
+                (PredicateExpr::Fail, PredicateExpr::Always)
+            };
+
+            let children: Vec<&PredicateExpr> = conditions.iter().collect();
+            let mut operands: Vec<(PredicateExpr, Vec<Span>)> = Vec::new();
+            'operands: for (child, spans) in children.iter().zip(child_spans(&children, tail)) {
+                let (operand, operand_spans) = simplify_expr(child, spans, stats);
+                for (operand, operand_spans) in flatten(operand, operand_spans, is_and) {
+                    if operand == identity {
+                        stats.constants_folded += 1;
+                    } else if operand == absorbing {
+                        // Operands before it still run and may fail the
+                        // evaluation; operands after it never run
+                        if operands.iter().any(|(kept, _)| can_fault(kept)) {
+                            operands.push((operand, operand_spans));
+                            break 'operands;
+                        }
+                        stats.constants_folded += 1;
+                        return (absorbing, own);
+                    } else if operands.iter().any(|(kept, _)| *kept == operand) {
+                        stats.operands_deduplicated += 1;
+                    } else {
+                        operands.push((operand, operand_spans));
+                    }
+                }
+            }
+
+            match operands.len() {
+                0 => (identity, own),
+                1 => operands.pop().unwrap_or((identity, own)),
+                _ => {
+                    let mut spans = own;
+                    let mut conditions = Vec::with_capacity(operands.len());
+                    for (operand, operand_spans) in operands {
+                        conditions.push(operand);
+                        spans.extend(operand_spans);
+                    }
+                    let expr = if is_and {
+                        PredicateExpr::And { conditions }
+                    } else {
+                        PredicateExpr::Or { conditions }
+                    };
+                    (expr, spans)
+                }
+            }
+        }
+
+        PredicateExpr::Not { condition } => {
+            let (inner, inner_spans) = simplify_expr(condition, tail, stats);
+            match inner {
+                PredicateExpr::Not { condition } => {
+                    (*condition, inner_spans.get(1..).unwrap_or_default().to_vec())
+                }
+                PredicateExpr::Always => {
+                    stats.constants_folded += 1;
+                    (PredicateExpr::Fail, own)
+                }
+                PredicateExpr::Fail => {
+                    stats.constants_folded += 1;
+                    (PredicateExpr::Always, own)
+                }
+                inner => {
+                    let mut spans = own;
+                    spans.extend(inner_spans);
+                    (PredicateExpr::Not { condition: Box::new(inner) }, spans)
+                }
+            }
+        }
+
+        _ => match fold_comparison(expr) {
+            Some(holds) => {
+                stats.constants_folded += 1;
+                let constant = if holds { PredicateExpr::Always } else { PredicateExpr::Fail };
+                (constant, own)
+            }
+            None => (expr.clone(), spans.to_vec()),
+        },
+    }
+}
+
+/// Operands of `expr` as seen by an enclosing `<and>` (`is_and`) or
+/// `<or>`: the same operator is spliced in, anything else is one operand
+fn flatten(expr: PredicateExpr, spans: Vec<Span>, is_and: bool) -> Vec<(PredicateExpr, Vec<Span>)> {
+    match expr {
+        PredicateExpr::And { conditions } if is_and => split(conditions, &spans),
+        PredicateExpr::Or { conditions } if !is_and => split(conditions, &spans),
+        expr => vec![(expr, spans)],
+    }
+}
+
+fn split(conditions: Vec<PredicateExpr>, spans: &[Span]) -> Vec<(PredicateExpr, Vec<Span>)> {
+    let children: Vec<&PredicateExpr> = conditions.iter().collect();
+    let spans: Vec<Vec<Span>> = child_spans(&children, spans.get(1..).unwrap_or_default())
+        .into_iter()
+        .map(<[Span]>::to_vec)
+        .collect();
+    conditions.into_iter().zip(spans).collect()
+}
+
+/// Outcome of a comparison between compile-time constants
+///
+/// An operand that is neither a `$` variable nor a function call is a
+/// string literal, and equal strings share one string pool entry, so
+/// string equality is known exactly. Other comparisons are left to the VM.
+fn fold_comparison(expr: &PredicateExpr) -> Option<bool> {
+    match expr {
+        PredicateExpr::Eq { left, right: Value::Str(right) } if is_literal(left) => Some(left == right),
+        PredicateExpr::Neq { left, right: Value::Str(right) } if is_literal(left) => Some(left != right),
+        PredicateExpr::StartsWith { left, prefix } if is_literal(left) => Some(left.starts_with(prefix.as_str())),
+        PredicateExpr::EndsWith { left, suffix } if is_literal(left) => Some(left.ends_with(suffix.as_str())),
+        PredicateExpr::In { left, values } if is_literal(left) && all_strings(values) => {
+            Some(values.iter().any(|v| matches!(v, Value::Str(s) if s == left)))
+        }
+        PredicateExpr::NotIn { left, values } if is_literal(left) && all_strings(values) => {
+            Some(!values.iter().any(|v| matches!(v, Value::Str(s) if s == left)))
+        }
+        _ => None,
+    }
+}
+
+fn is_literal(operand: &str) -> bool {
+    !operand.starts_with('$') && operand_call(operand).is_none()
+}
+
+fn all_strings(values: &[Value]) -> bool {
+    values.iter().all(|v| matches!(v, Value::Str(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eq(var: &str, value: &str) -> PredicateExpr {
+        PredicateExpr::Eq { left: var.into(), right: Value::Str(value.into()) }
+    }
+
+    fn simplified(expr: &PredicateExpr) -> (PredicateExpr, SimplifyStats) {
+        let mut stats = SimplifyStats::default();
+        let (expr, _) = simplify(expr, &[], &mut stats);
+        (expr, stats)
+    }
+
+    #[test]
+    fn test_boolean_algebra() {
+        let admin = eq("$token.role", "admin");
+        let and = PredicateExpr::And { conditions: vec![PredicateExpr::Always, admin.clone()] };
+        assert_eq!(simplified(&and).0, admin);
+
+        let or = PredicateExpr::Or { conditions: vec![admin.clone(), PredicateExpr::Always] };
+        assert_eq!(simplified(&or).0, PredicateExpr::Always);
+
+        let not_not = PredicateExpr::Not {
+            condition: Box::new(PredicateExpr::Not { condition: Box::new(admin.clone()) }),
+        };
+        let (expr, stats) = simplified(&not_not);
+        assert_eq!(expr, admin);
+        assert_eq!(stats, SimplifyStats { predicates_simplified: 1, nodes_removed: 2, ..Default::default() });
+
+        assert_eq!(simplified(&PredicateExpr::And { conditions: vec![] }).0, PredicateExpr::Always);
+        assert_eq!(simplified(&PredicateExpr::Not { condition: Box::new(PredicateExpr::Fail) }).0, PredicateExpr::Always);
+
+        let (unchanged, stats) = simplified(&admin);
+        assert_eq!(unchanged, admin);
+        assert_eq!(stats, SimplifyStats::default());
+    }
+
+    #[test]
+    fn test_faulting_operands_kept() {
+        use crate::dsl::ast::{Arith, ArithOp, Comparison};
+        // $a / 0 > 1 faults at runtime, failing the whole evaluation
+        let divide = PredicateExpr::Compare {
+            cmp: Comparison::Gt,
+            left: Arith::Binary {
+                op: ArithOp::Div,
+                left: Box::new(Arith::Operand("$a".into())),
+                right: Box::new(Arith::Operand("0".into())),
+            },
+            right: Arith::Operand("1".into()),
+        };
+        let admin = eq("$token.role", "admin");
+
+        let or = PredicateExpr::Or { conditions: vec![divide.clone(), PredicateExpr::Always, admin.clone()] };
+        assert_eq!(
+            simplified(&or).0,
+            PredicateExpr::Or { conditions: vec![divide.clone(), PredicateExpr::Always] },
+            "the operand after <always/> never runs"
+        );
+        let and = PredicateExpr::And { conditions: vec![divide.clone(), PredicateExpr::Fail] };
+        assert_eq!(simplified(&and).0, and);
+        let call = PredicateExpr::Ref { predicate: "ratio_ok".into() };
+        let or = PredicateExpr::Or { conditions: vec![call.clone(), PredicateExpr::Always] };
+        assert_eq!(simplified(&or).0, or);
+
+        // After the absorbing operand, or with nothing that faults, it folds
+        let or = PredicateExpr::Or { conditions: vec![admin, PredicateExpr::Always, divide] };
+        assert_eq!(simplified(&or).0, PredicateExpr::Always);
+    }
+
+    #[test]
+    fn test_flatten_and_dedup() {
+        let admin = eq("$token.role", "admin");
+        let owner = eq("$entity.owner", "$token.sub");
+        let nested = PredicateExpr::And {
+            conditions: vec![
+                admin.clone(),
+                PredicateExpr::And { conditions: vec![owner.clone(), admin.clone()] },
+                PredicateExpr::Or { conditions: vec![owner.clone()] },
+            ],
+        };
+        let (expr, stats) = simplified(&nested);
+        assert_eq!(expr, PredicateExpr::And { conditions: vec![admin, owner] });
+        assert_eq!(stats.operands_deduplicated, 2);
+        assert_eq!(stats.nodes_removed, 4);
+    }
+
+    #[test]
+    fn test_constant_comparisons() {
+        let (expr, stats) = simplified(&PredicateExpr::Or {
+            conditions: vec![eq("draft", "final"), eq("$doc.status", "final")],
+        });
+        assert_eq!(expr, eq("$doc.status", "final"));
+        // The comparison folds to <fail/>, which then drops out of the <or>
+        assert_eq!(stats.constants_folded, 2);
+
+        assert_eq!(simplified(&eq("final", "final")).0, PredicateExpr::Always);
+        assert_eq!(simplified(&PredicateExpr::StartsWith { left: "us-east".into(), prefix: "eu-".into() }).0, PredicateExpr::Fail);
+        let regions = Value::list_from_str("us-east,eu-west").unwrap();
+        assert_eq!(simplified(&PredicateExpr::In { left: "eu-west".into(), values: regions.clone() }).0, PredicateExpr::Always);
+        assert_eq!(simplified(&PredicateExpr::NotIn { left: "eu-west".into(), values: regions }).0, PredicateExpr::Fail);
+
+        // Variables, function calls and numbers are left to the VM
+        for expr in [
+            eq("$a", "a"),
+            eq("len($a)", "3"),
+            PredicateExpr::Eq { left: "3".into(), right: Value::Int(3) },
+        ] {
+            assert_eq!(simplified(&expr).0, expr);
+        }
+    }
+
+    #[test]
+    fn test_spans_follow_simplification() {
+        let span = |line| Span { start: 0, end: 0, line, column: 1 };
+        // <and>(1) <always/>(2) <not>(3) <not>(4) <gt/>(5) </not></not> <gt/>(6) </and>
+        let gt = |var: &str| PredicateExpr::Gt { left: var.into(), right: Value::Int(0) };
+        let expr = PredicateExpr::And {
+            conditions: vec![
+                PredicateExpr::Always,
+                PredicateExpr::Not {
+                    condition: Box::new(PredicateExpr::Not { condition: Box::new(gt("$a")) }),
+                },
+                gt("$b"),
+            ],
+        };
+        let spans: Vec<Span> = (1..=6).map(span).collect();
+
+        let (simplified, spans) = simplify(&expr, &spans, &mut SimplifyStats::default());
+        assert_eq!(simplified, PredicateExpr::And { conditions: vec![gt("$a"), gt("$b")] });
+        let lines: Vec<u32> = spans.iter().map(|s| s.line).collect();
+        assert_eq!(lines, [1, 5, 6]);
+    }
+}
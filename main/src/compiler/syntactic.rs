@@ -7,7 +7,7 @@
 //!
 //! Errors here indicate malformed input, not business logic issues.
 
-use crate::{Diagnostic, Location, Severity};
+use crate::{Diagnostic, Severity};
 use crate::compiler::ir::*;
 use std::collections::HashSet;
 
@@ -41,10 +41,7 @@ fn check_edge_targets_exist(ir: &GraphIR) -> Vec<Diagnostic> {
                     edge.id, edge.target
                 ),
                 hint: Some("Check that target node is defined in the workflow".into()),
-                location: Some(Location {
-                    edge_id: Some(edge.id.to_string()),
-                    ..Default::default()
-                }),
+                location: Some(edge.location()),
             });
         }
     }
@@ -67,7 +64,7 @@ fn check_entry_points_exist(ir: &GraphIR) -> Vec<Diagnostic> {
                     entry.p, entry.x, entry.node_id
                 ),
                 hint: Some("Check that entry node is defined".into()),
-                location: None,
+                location: Some(entry.location()),
             });
         }
     }
@@ -91,10 +88,7 @@ fn check_predicate_refs_exist(ir: &GraphIR) -> Vec<Diagnostic> {
                     edge.id, edge.predicate_id
                 ),
                 hint: Some("Check predicate definition".into()),
-                location: Some(Location {
-                    edge_id: Some(edge.id.to_string()),
-                    ..Default::default()
-                }),
+                location: Some(edge.location()),
             });
         }
     }
@@ -111,10 +105,7 @@ fn check_predicate_refs_exist(ir: &GraphIR) -> Vec<Diagnostic> {
                         node.name, pred_id
                     ),
                     hint: Some("Check predicate definition".into()),
-                    location: Some(Location {
-                        node_id: Some(node.name.clone()),
-                        ..Default::default()
-                    }),
+                    location: Some(node.location()),
                 });
             }
         }
@@ -136,10 +127,7 @@ fn check_no_duplicate_node_ids(ir: &GraphIR) -> Vec<Diagnostic> {
                 code: "SYN004".into(),
                 message: format!("Duplicate node ID: {}", node.id),
                 hint: None,
-                location: Some(Location {
-                    node_id: Some(node.name.clone()),
-                    ..Default::default()
-                }),
+                location: Some(node.location()),
             });
         }
         
@@ -149,10 +137,7 @@ fn check_no_duplicate_node_ids(ir: &GraphIR) -> Vec<Diagnostic> {
                 code: "SYN004".into(),
                 message: format!("Duplicate node name: {}", node.name),
                 hint: Some("Node names must be unique within a workflow".into()),
-                location: Some(Location {
-                    node_id: Some(node.name.clone()),
-                    ..Default::default()
-                }),
+                location: Some(node.location()),
             });
         }
     }
@@ -190,7 +175,7 @@ fn check_no_duplicate_entries(ir: &GraphIR) -> Vec<Diagnostic> {
                     entry.p, entry.x
                 ),
                 hint: Some("Each (P, X) combination can only have one entry".into()),
-                location: None,
+                location: Some(entry.location()),
             });
         }
     }
@@ -213,10 +198,7 @@ fn check_edge_sources_exist(ir: &GraphIR) -> Vec<Diagnostic> {
                     edge.id, edge.from
                 ),
                 hint: Some("Check that source node is defined".into()),
-                location: Some(Location {
-                    edge_id: Some(edge.id.to_string()),
-                    ..Default::default()
-                }),
+                location: Some(edge.location()),
             });
         }
     }
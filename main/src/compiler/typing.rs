@@ -0,0 +1,620 @@
+//! Predicate Typing - Schema Validation of Variable Paths
+//!
+//! A node bound to a schema (`<schema ref>`, or its workflow's `schema`
+//! attribute) declares the data its predicates read: `$entity.<field>`
+//! and `$input.<field>` must name a field of that schema, and the field's
+//! type must suit what the predicate does with it. The schema applies to
+//! the node's `<require>` predicate and to the conditions on its outgoing
+//! edges, including the named predicates they reference.
+//!
+//! - SEM010: a path names a field the schema does not define
+//! - SEM011: a field's type does not suit the comparison or operation
+//! - SEM012: a schema reference names an undefined schema
+//!
+//! Field types that predicates cannot inspect (`object`, `array`, ...)
+//! are `Any` and accept everything, including nested paths. The resolved
+//! types are recorded on each `CompiledPredicate`.
+
+use crate::{Diagnostic, Severity, Location, Span};
+use crate::compiler::bytecode::operand_call;
+use crate::compiler::ir::{GraphIR, PredicateId};
+use crate::dsl::ast::*;
+use crate::dsl::vocabulary;
+use crate::dsl::writer::value_text;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Variable roots resolved against the bound schema
+const SCHEMA_ROOTS: [&str; 2] = ["$entity.", "$input."];
+
+/// Type of a schema field, as far as predicates can tell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    String,
+    Number,
+    Boolean,
+    /// Epoch milliseconds
+    Time,
+    /// Structured or unknown: not checked
+    Any,
+}
+
+impl FieldType {
+    /// Type of a `<field type="...">`
+    pub fn from_schema(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "string" | "text" | "uuid" | "enum" | "email" | "url" => Self::String,
+            "number" | "integer" | "int" | "float" | "decimal" => Self::Number,
+            "boolean" | "bool" => Self::Boolean,
+            "timestamp" | "datetime" | "date" => Self::Time,
+            _ => Self::Any,
+        }
+    }
+
+    /// Name with article, for messages (`a number`)
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::String => "a string",
+            Self::Number => "a number",
+            Self::Boolean => "a boolean",
+            Self::Time => "a time",
+            Self::Any => "untyped",
+        }
+    }
+
+    /// Type of a literal value; variables have none
+    fn of_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int(_) | Value::Float(_) => Some(Self::Number),
+            Value::Bool(_) => Some(Self::Boolean),
+            Value::Str(_) => Some(Self::String),
+            Value::Time(_) | Value::Duration(_) => Some(Self::Time),
+            Value::Var(_) => None,
+        }
+    }
+
+    /// True if a value of type `other` can be compared with this type;
+    /// a time also takes a plain number of epoch milliseconds
+    fn accepts(self, other: Self) -> bool {
+        self == other || self == Self::Any || other == Self::Any || (self == Self::Time && other == Self::Number)
+    }
+
+    /// True if `<gt>`, arithmetic and the like can use this type
+    fn is_ordered(self) -> bool {
+        matches!(self, Self::Number | Self::Time | Self::Any)
+    }
+}
+
+/// Type-check every predicate bound to a schema and record the types on
+/// the IR's predicates
+///
+/// Runs on the expanded document (see `expand_fragments`/`inline_calls`)
+/// after lowering, whose edge ids follow document order.
+pub fn check(ir: &mut GraphIR, doc: &OmarDocument) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    let schemas: HashMap<&str, &Schema> = doc.schemas.iter()
+        .map(|s| (s.name.as_str(), s))
+        .collect();
+    let defs: HashMap<&str, &PredicateDef> = doc.predicates.iter()
+        .map(|def| (def.id.as_str(), def))
+        .collect();
+    let ids: HashMap<String, PredicateId> = ir.predicates.iter()
+        .map(|p| (p.name.clone(), p.id))
+        .collect();
+    let inline: HashMap<u32, PredicateId> = ir.edges.iter()
+        .map(|e| (e.id, e.predicate_id))
+        .collect();
+    let mut types: HashMap<PredicateId, BTreeMap<String, FieldType>> = HashMap::new();
+    let mut checked = HashSet::new();
+
+    let mut edge_id = 0;
+    for workflow in &doc.workflows {
+        diags.extend(check_schema_ref(workflow.schema.as_deref(), &doc.schemas, || Location {
+            workflow_id: Some(workflow.id.clone()),
+            span: workflow.span,
+            ..Default::default()
+        }));
+        for node in &workflow.nodes {
+            diags.extend(check_schema_ref(node.schema.as_deref(), &doc.schemas, || Location {
+                workflow_id: Some(workflow.id.clone()),
+                node_id: Some(node.id.clone()),
+                span: node.span,
+                ..Default::default()
+            }));
+        }
+        let bound = |node_id: &str| {
+            let node = workflow.nodes.iter().find(|n| n.id == node_id)?;
+            let name = node.schema.as_ref().or(workflow.schema.as_ref())?;
+            schemas.get(name.as_str()).copied()
+        };
+
+        let mut named = Checker { defs: &defs, ids: &ids, types: &mut types, checked: &mut checked, diags: &mut diags };
+        for node in &workflow.nodes {
+            if let (Some(schema), Some(name)) = (bound(&node.id), &node.predicate) {
+                named.named(name, schema);
+            }
+        }
+        for edge in &workflow.edges {
+            let id = edge_id;
+            edge_id += 1;
+            let Some(schema) = bound(&edge.from) else { continue };
+            if let Some(name) = &edge.predicate_ref {
+                named.named(name, schema);
+            }
+            match &edge.predicate {
+                None | Some(PredicateExpr::Always) => {}
+                Some(PredicateExpr::Ref { predicate }) => named.named(predicate, schema),
+                Some(expr) => {
+                    let subject = format!("Condition on edge '{}' → '{}'", edge.from, edge.to);
+                    let location = Location {
+                        workflow_id: Some(workflow.id.clone()),
+                        edge_id: Some(id.to_string()),
+                        span: edge.span,
+                        ..Default::default()
+                    };
+                    let mut typer = Typer::new(schema, &edge.predicate_spans, subject, location);
+                    typer.expr(expr);
+                    if let Some(&pred) = inline.get(&id) {
+                        let recorded = named.types.entry(pred).or_default();
+                        for (path, ty) in std::mem::take(&mut typer.types) {
+                            recorded.entry(path).or_insert(ty);
+                        }
+                    }
+                    let refs = std::mem::take(&mut typer.refs);
+                    named.diags.extend(typer.diags);
+                    for name in refs {
+                        named.named(&name, schema);
+                    }
+                }
+            }
+        }
+    }
+
+    for pred in &mut ir.predicates {
+        if let Some(recorded) = types.remove(&pred.id) {
+            pred.types = recorded;
+        }
+    }
+    diags
+}
+
+/// SEM012: a schema reference must name a defined schema
+fn check_schema_ref(
+    name: Option<&str>,
+    schemas: &[Schema],
+    location: impl FnOnce() -> Location,
+) -> Option<Diagnostic> {
+    let name = name?;
+    if schemas.iter().any(|s| s.name == name) {
+        return None;
+    }
+    let hint = match vocabulary::suggest(name, schemas.iter().map(|s| s.name.as_str())) {
+        Some(s) => format!("Did you mean '{}'?", s),
+        None => "Define it in <schemas> or fix the reference".into(),
+    };
+    Some(Diagnostic {
+        severity: Severity::Error,
+        code: "SEM012".into(),
+        message: format!("Unknown schema '{}'", name),
+        hint: Some(hint),
+        location: Some(location()),
+    })
+}
+
+/// Named predicates checked so far, per schema, with what they found
+struct Checker<'a, 'b> {
+    defs: &'b HashMap<&'a str, &'a PredicateDef>,
+    ids: &'b HashMap<String, PredicateId>,
+    types: &'b mut HashMap<PredicateId, BTreeMap<String, FieldType>>,
+    checked: &'b mut HashSet<(String, String)>,
+    diags: &'b mut Vec<Diagnostic>,
+}
+
+impl Checker<'_, '_> {
+    /// Check a named predicate (and those it references) against `schema`,
+    /// once per pair; unknown names are reported by lowering
+    fn named(&mut self, name: &str, schema: &Schema) {
+        if !self.checked.insert((name.to_string(), schema.name.clone())) {
+            return;
+        }
+        let Some(def) = self.defs.get(name) else { return };
+        let location = Location {
+            predicate_id: Some(def.id.clone()),
+            span: def.span,
+            ..Default::default()
+        };
+        let mut typer = Typer::new(schema, &def.expr_spans, format!("Predicate '{}'", def.id), location);
+        typer.expr(&def.expr);
+
+        if let Some(&pred) = self.ids.get(name) {
+            let recorded = self.types.entry(pred).or_default();
+            for (path, ty) in std::mem::take(&mut typer.types) {
+                recorded.entry(path).or_insert(ty);
+            }
+        }
+        self.diags.extend(std::mem::take(&mut typer.diags));
+        for name in std::mem::take(&mut typer.refs) {
+            self.named(&name, schema);
+        }
+    }
+}
+
+/// Type checker for one expression against one schema
+struct Typer<'a> {
+    schema: &'a Schema,
+    /// Source spans of the expression nodes, in pre-order
+    spans: &'a [Span],
+    /// Pre-order index of the next expression node
+    index: usize,
+    /// Who reads the paths, for messages (`Predicate 'x'`)
+    subject: String,
+    location: Location,
+    types: BTreeMap<String, FieldType>,
+    /// Named predicates referenced with `<ref>`
+    refs: Vec<String>,
+    diags: Vec<Diagnostic>,
+}
+
+impl<'a> Typer<'a> {
+    fn new(schema: &'a Schema, spans: &'a [Span], subject: String, location: Location) -> Self {
+        Self {
+            schema,
+            spans,
+            index: 0,
+            subject,
+            location,
+            types: BTreeMap::new(),
+            refs: Vec::new(),
+            diags: Vec::new(),
+        }
+    }
+
+    fn expr(&mut self, expr: &PredicateExpr) {
+        let span = self.spans.get(self.index).copied();
+        self.index += 1;
+
+        match expr {
+            PredicateExpr::Always | PredicateExpr::Fail => {}
+            PredicateExpr::Eq { left, right } | PredicateExpr::Neq { left, right } => {
+                self.compare(left, right, false, span);
+            }
+            PredicateExpr::Gt { left, right }
+            | PredicateExpr::Gte { left, right }
+            | PredicateExpr::Lt { left, right }
+            | PredicateExpr::Lte { left, right } => {
+                self.compare(left, right, true, span);
+            }
+            PredicateExpr::Contains { left, right } => {
+                self.require(left, "contains", |ty| matches!(ty, FieldType::String | FieldType::Any), span);
+                self.resolve(right, span);
+            }
+            PredicateExpr::Matches { left, .. } => {
+                self.require(left, "matches", |ty| matches!(ty, FieldType::String | FieldType::Any), span);
+            }
+            PredicateExpr::StartsWith { left, .. } => {
+                self.require(left, "startsWith", |ty| matches!(ty, FieldType::String | FieldType::Any), span);
+            }
+            PredicateExpr::EndsWith { left, .. } => {
+                self.require(left, "endsWith", |ty| matches!(ty, FieldType::String | FieldType::Any), span);
+            }
+            PredicateExpr::In { left, values } | PredicateExpr::NotIn { left, values } => {
+                let Some(ty) = self.resolve(left, span) else { return };
+                let member = values.iter()
+                    .find(|v| FieldType::of_value(v).is_some_and(|m| !ty.accepts(m)));
+                if let Some(member) = member {
+                    let message = format!(
+                        "{} compares '{}' ({}) with list member '{}' ({})",
+                        self.subject, left, ty.describe(),
+                        value_text(member),
+                        FieldType::of_value(member).map_or("untyped", FieldType::describe)
+                    );
+                    self.mismatch(message, span);
+                }
+            }
+            PredicateExpr::Before { left, right } | PredicateExpr::After { left, right } => {
+                if let Some(right) = Value::time_from_str(right) {
+                    self.compare(left, &right, true, span);
+                }
+            }
+            PredicateExpr::Within { left, .. } => {
+                self.require(left, "within", |ty| matches!(ty, FieldType::Time | FieldType::Any), span);
+            }
+            PredicateExpr::Fn { arg, .. } => {
+                self.resolve(arg, span);
+            }
+            PredicateExpr::Compare { left, right, .. } => {
+                self.arith(left, span);
+                self.arith(right, span);
+            }
+            PredicateExpr::Ref { predicate } => self.refs.push(predicate.clone()),
+            PredicateExpr::And { .. } | PredicateExpr::Or { .. } | PredicateExpr::Not { .. } => {
+                for child in expr.children() {
+                    self.expr(child);
+                }
+            }
+        }
+    }
+
+    /// `left` compared with `right`; `ordered` for `<gt>`, `<lt>`, ...
+    fn compare(&mut self, left: &str, right: &Value, ordered: bool, span: Option<Span>) {
+        let left_ty = self.resolve(left, span);
+        let right_ty = match right {
+            Value::Var(path) => self.resolve(path, span),
+            value => FieldType::of_value(value),
+        };
+
+        if ordered {
+            for (operand, ty) in [(left.to_string(), left_ty), (value_text(right), right_ty)] {
+                if let Some(ty) = ty.filter(|ty| !ty.is_ordered()) {
+                    let message = format!(
+                        "{} orders '{}', which is {}; <gt>, <lt> and their variants compare numbers and times",
+                        self.subject, operand, ty.describe()
+                    );
+                    self.mismatch(message, span);
+                    return;
+                }
+            }
+        }
+
+        let (Some(l), Some(r)) = (left_ty, right_ty) else { return };
+        let compatible = l.accepts(r) || (matches!(right, Value::Var(_)) && r.accepts(l));
+        if !compatible {
+            let message = format!(
+                "{} compares '{}' ({}) with '{}' ({})",
+                self.subject, left, l.describe(), value_text(right), r.describe()
+            );
+            self.mismatch(message, span);
+        }
+    }
+
+    /// `operand` must have a type `ok` accepts when it is schema-bound
+    fn require(&mut self, operand: &str, op: &str, ok: fn(FieldType) -> bool, span: Option<Span>) {
+        if let Some(ty) = self.resolve(operand, span).filter(|&ty| !ok(ty)) {
+            let message = format!(
+                "{} applies <{}> to '{}', which is {}",
+                self.subject, op, operand, ty.describe()
+            );
+            self.mismatch(message, span);
+        }
+    }
+
+    /// Arithmetic operands must be numbers or times
+    fn arith(&mut self, arith: &Arith, span: Option<Span>) {
+        match arith {
+            Arith::Operand(operand) => {
+                if let Some(ty) = self.resolve(operand, span).filter(|ty| !ty.is_ordered()) {
+                    let message = format!(
+                        "{} uses '{}' in <compare>, but it is {}",
+                        self.subject, operand, ty.describe()
+                    );
+                    self.mismatch(message, span);
+                }
+            }
+            Arith::Neg(inner) => self.arith(inner, span),
+            Arith::Binary { left, right, .. } => {
+                self.arith(left, span);
+                self.arith(right, span);
+            }
+        }
+    }
+
+    /// Type of a schema-bound path (`$input.total`), checking that the
+    /// schema defines it; `None` for anything else
+    ///
+    /// The argument of a function call is resolved too, but the call's
+    /// result is untyped.
+    fn resolve(&mut self, operand: &str, span: Option<Span>) -> Option<FieldType> {
+        if let Some((_, arg)) = operand_call(operand) {
+            self.resolve(arg, span);
+            return None;
+        }
+        let (root, rest) = SCHEMA_ROOTS.iter()
+            .find_map(|root| Some((*root, operand.strip_prefix(root)?)))?;
+        let (name, nested) = match rest.split_once('.') {
+            Some((name, _)) => (name, true),
+            None => (rest, false),
+        };
+
+        let Some(field) = self.schema.fields.iter().find(|f| f.name == name) else {
+            let fields = self.schema.fields.iter().map(|f| f.name.as_str());
+            let hint = match vocabulary::suggest(name, fields.clone()) {
+                Some(s) => format!("Did you mean '{}'?", s),
+                None => format!("Fields of '{}': {}", self.schema.name, fields.collect::<Vec<_>>().join(", ")),
+            };
+            let message = format!(
+                "{} reads '{}', but schema '{}' has no field '{}'",
+                self.subject, operand, self.schema.name, name
+            );
+            self.report("SEM010", message, Some(hint), span);
+            return None;
+        };
+
+        let ty = FieldType::from_schema(&field.field_type);
+        if nested && ty != FieldType::Any {
+            let message = format!(
+                "{} reads '{}', but field '{}' of schema '{}' is {} and has no fields",
+                self.subject, operand, name, self.schema.name, ty.describe()
+            );
+            self.report("SEM010", message, Some(format!("Use '{}{}'", root, name)), span);
+            return None;
+        }
+        self.types.insert(operand.to_string(), ty);
+        Some(ty)
+    }
+
+    /// SEM011
+    fn mismatch(&mut self, message: String, span: Option<Span>) {
+        let hint = "Compare the field with a value of its schema type, or fix the field's type";
+        self.report("SEM011", message, Some(hint.into()), span);
+    }
+
+    fn report(&mut self, code: &str, message: String, hint: Option<String>, span: Option<Span>) {
+        self.diags.push(Diagnostic {
+            severity: Severity::Error,
+            code: code.into(),
+            message,
+            hint,
+            location: Some(Location {
+                span: span.or(self.location.span),
+                ..self.location.clone()
+            }),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{compile_predicates, expand_fragments, inline_calls, lower};
+    use crate::dsl::parse;
+    
+    const SCHEMAS: &str = r#"<schemas>
+        <schema name="deal">
+            <field name="amount" type="number"/>
+            <field name="stage" type="enum"/>
+            <field name="closed" type="boolean"/>
+            <field name="due" type="timestamp"/>
+            <field name="meta" type="object"/>
+        </schema>
+    </schemas>"#;
+    
+    /// Type-check a document with `SCHEMAS`, a `workflow` tag and body
+    fn check_doc(predicates: &str, workflow: &str, body: &str) -> (GraphIR, Vec<Diagnostic>) {
+        let doc = parse(&format!(
+            r#"<omar version="1.0.0">{}<predicates>{}</predicates>{}<entry p="d" x="go" node="start"/>{}</workflow></omar>"#,
+            SCHEMAS, predicates, workflow, body
+        )).unwrap();
+        let doc = inline_calls(&expand_fragments(&doc).unwrap()).unwrap();
+        let mut ir = compile_predicates(lower(&doc).unwrap(), &doc).unwrap();
+        let diags = check(&mut ir, &doc);
+        (ir, diags)
+    }
+    
+    /// A `start` node bound to `deal` with `when` on its edge to `end`
+    fn check_when(when: &str) -> Vec<Diagnostic> {
+        let body = format!(
+            r#"<nodes><node id="start"><schema ref="deal"/></node><node id="end" kind="terminal"/></nodes>
+            <edges><edge from="start" to="end"><when>{}</when></edge></edges>"#,
+            when
+        );
+        check_doc("", r#"<workflow id="w">"#, &body).1
+    }
+    
+    #[test]
+    fn test_unknown_field() {
+        let diags = check_when(r#"<gt left="$entity.amout" right="5"/>"#);
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        assert_eq!(diags[0].code, "SEM010");
+        assert_eq!(diags[0].severity, Severity::Error);
+        assert!(diags[0].message.contains("'$entity.amout'"), "{}", diags[0].message);
+        assert_eq!(diags[0].hint.as_deref(), Some("Did you mean 'amount'?"));
+        assert_eq!(diags[0].location.as_ref().and_then(|l| l.span).map(|s| s.line), Some(10));
+        
+        let diags = check_when(r#"<eq left="$input.owner" right="me"/>"#);
+        assert_eq!(diags[0].hint.as_deref(), Some("Fields of 'deal': amount, stage, closed, due, meta"));
+        
+        // Only scalar fields are leaves; other roots are not schema-bound
+        let diags = check_when(r#"<and><gt left="$input.amount.cents" right="5"/><eq left="$input.meta.source" right="web"/><eq left="$token.amout" right="5"/></and>"#);
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        assert_eq!(diags[0].hint.as_deref(), Some("Use '$input.amount'"));
+    }
+    
+    #[test]
+    fn test_type_mismatch() {
+        for when in [
+            r#"<eq left="$entity.stage" right="5"/>"#,
+            r#"<eq left="$entity.amount" right="five"/>"#,
+            r#"<gt left="$entity.stage" right="$entity.amount"/>"#,
+            r#"<after left="$entity.amount" right="2025-01-01"/>"#,
+            r#"<startsWith left="$entity.amount" prefix="1"/>"#,
+            r#"<within left="$entity.amount" duration="P3D"/>"#,
+            r#"<in left="$entity.amount" values="1,two"/>"#,
+            r#"<compare left="$entity.amount - $entity.stage" cmp="gt" right="0"/>"#,
+        ] {
+            let diags = check_when(when);
+            assert_eq!(diags.iter().map(|d| d.code.as_str()).collect::<Vec<_>>(), ["SEM011"], "{}: {:?}", when, diags);
+        }
+        
+        for when in [
+            r#"<eq left="$entity.stage" right="won"/>"#,
+            // Date and duration shapes outside time comparisons are strings
+            r#"<eq left="$entity.stage" right="2025-01-01"/>"#,
+            r#"<neq left="$entity.stage" right="P1"/>"#,
+            r#"<gte left="$entity.amount" right="0.5"/>"#,
+            r#"<eq left="$entity.closed" right="true"/>"#,
+            r#"<after left="$entity.due" right="-P7D"/>"#,
+            r#"<gt left="$entity.due" right="1735689600000"/>"#,
+            r#"<within left="$entity.due" duration="P3D"/>"#,
+            r#"<not_in left="$entity.stage" values="won,lost"/>"#,
+            r#"<compare left="$entity.due - $input.due" cmp="lt" right="P1D"/>"#,
+            r#"<gt left="len($entity.stage)" right="3"/>"#,
+            r#"<contains left="$entity.meta" right="x"/>"#,
+        ] {
+            assert!(check_when(when).is_empty(), "{}: {:?}", when, check_when(when));
+        }
+    }
+    
+    #[test]
+    fn test_named_predicates_and_types() {
+        // The workflow schema binds every node; named predicates are checked
+        // through `require`, edges and `<ref>`, once per schema
+        let (ir, diags) = check_doc(
+            r#"<predicate id="big"><gt left="$input.amount" right="1000"/></predicate>
+            <predicate id="open"><and><ref predicate="big"/><eq left="$input.closd" right="false"/></and></predicate>"#,
+            r#"<workflow id="w" schema="deal">"#,
+            r#"<nodes><node id="start" kind="auth"><require predicate="open"/></node><node id="end" kind="terminal"/></nodes>
+            <edges>
+                <edge from="start" to="end"><when><ref predicate="big"/></when></edge>
+                <edge from="start" to="end"><when><after left="$entity.due" right="2025-01-01"/></when></edge>
+            </edges>"#,
+        );
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        assert!(diags[0].message.starts_with("Predicate 'open' reads '$input.closd'"));
+        assert_eq!(diags[0].location.as_ref().and_then(|l| l.predicate_id.as_deref()), Some("open"));
+        
+        let types = |name: &str| &ir.predicates.iter().find(|p| p.name == name).unwrap().types;
+        assert_eq!(types("big").get("$input.amount"), Some(&FieldType::Number));
+        assert!(types("open").is_empty());
+        let inline = ir.predicates.iter().find(|p| p.name.starts_with("_inline_")).unwrap();
+        assert_eq!(inline.types.get("$entity.due"), Some(&FieldType::Time));
+        
+        // Unbound nodes are not checked
+        let (ir, diags) = check_doc(
+            r#"<predicate id="big"><gt left="$input.amont" right="1000"/></predicate>"#,
+            r#"<workflow id="w">"#,
+            r#"<nodes><node id="start"/><node id="end" kind="terminal"/></nodes>
+            <edges><edge from="start" to="end"><when><ref predicate="big"/></when></edge></edges>"#,
+        );
+        assert!(diags.is_empty());
+        assert!(ir.predicates[0].types.is_empty());
+    }
+    
+    #[test]
+    fn test_unknown_schema() {
+        let (_, diags) = check_doc(
+            r#"<predicate id="big"><gt left="$input.amont" right="1000"/></predicate>"#,
+            r#"<workflow id="w" schema="deals">"#,
+            r#"<nodes><node id="start"/><node id="end" kind="terminal"/></nodes>
+            <edges><edge from="start" to="end"><when><ref predicate="big"/></when></edge></edges>"#,
+        );
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        assert_eq!(diags[0].code, "SEM012");
+        assert_eq!(diags[0].hint.as_deref(), Some("Did you mean 'deal'?"));
+    }
+    
+    #[test]
+    fn test_called_workflow_keeps_its_schema() {
+        let (_, diags) = check_doc(
+            "",
+            r#"<workflow id="inner" schema="deal">
+                <entry p="i" x="go" node="start"/>
+                <nodes><node id="start"/><node id="end" kind="terminal"/></nodes>
+                <edges><edge from="start" to="end"><when><eq left="$input.stage" right="5"/></when></edge></edges>
+            </workflow>
+            <workflow id="outer">"#,
+            r#"<nodes><node id="start" kind="call" workflow="inner"/><node id="end" kind="terminal"/></nodes>
+            <edges><edge from="start" to="end"/></edges>"#,
+        );
+        // Once in `inner` itself and once inlined into `outer`
+        assert_eq!(diags.iter().filter(|d| d.code == "SEM011").count(), 2, "{:?}", diags);
+    }
+}
@@ -0,0 +1,451 @@
+//! Predicate Bytecode Verifier
+//!
+//! Abstract interpretation of predicate bytecode. Instead of values, the
+//! verifier tracks the stack depth on entry to every instruction. Jumps
+//! only go forward, so a single pass in offset order reaches every path
+//! into an instruction before the instruction itself; paths that meet
+//! must agree on the depth. For each predicate it proves:
+//!
+//! - the stack never underflows and RET leaves exactly one value
+//! - the stack never holds more than `limits::MAX_STACK_DEPTH` values,
+//!   counting predicates reached through CALL_PRED, which run on top of
+//!   the caller's operands
+//! - no evaluation executes more than `limits::MAX_PREDICATE_STEPS`
+//!   instructions, counting callees, which share the caller's budget
+//! - no chain of CALL_PRED nests more than `limits::MAX_CALL_DEPTH`
+//!   evaluations, and none is recursive
+//!
+//! The same check runs on compiled predicates and on the predicate table
+//! of a binary (see `crate::inspect`).
+
+use crate::{Opcode, limits};
+use crate::compiler::bytecode::{operand_size, validate_bytecode};
+use crate::compiler::ir::{CompiledPredicate, PredicateId};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Worst-case resource use of one predicate evaluation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PredicateBounds {
+    /// Deepest the VM stack gets, callees included
+    pub max_stack: usize,
+    /// Most instructions executed (RET included), callees included
+    pub max_steps: usize,
+    /// Nested evaluations: 1, plus the deepest callee
+    pub call_depth: usize,
+}
+
+/// Stack cells popped and pushed by `opcode`
+///
+/// CALL_PRED and RET are accounted for by `analyze`; jumps pop their
+/// condition only when they fall through.
+fn stack_effect(opcode: Opcode) -> (usize, usize) {
+    use Opcode::*;
+    match opcode {
+        Noop => (0, 0),
+        PushInt | PushStr | LoadVar | PushDec | PushI64 | Now | MergeField | CallPred => (0, 1),
+        LoadField | Not | In | Within | Neg | Len | IsNull | IsDefined | IsConfirmed
+        | Timestamp | IsFlagged | Origin => (1, 1),
+        Eq | Neq | Gt | Gte | Lt | Lte | And | Or | Contains | Matches | StartsWith | EndsWith
+        | Add | Sub | Mul | Div | Mod | Get | VClockGt => (2, 1),
+        JumpIfFalse | JumpIfTrue => (1, 0),
+        Ret => (0, 0),
+    }
+}
+
+/// Decoded instruction: offset, opcode and the offset after its operand
+struct Instruction {
+    pc: usize,
+    opcode: Opcode,
+    next: usize,
+}
+
+fn operand_u16(bytecode: &[u8], ins: &Instruction) -> u16 {
+    u16::from_le_bytes([bytecode[ins.pc + 1], bytecode[ins.pc + 2]])
+}
+
+/// Bounds of one predicate, given the bounds of the predicates it calls
+///
+/// `callee` returns `None` for a predicate that does not exist. Predicate
+/// id 0 is always true and costs nothing. The limits are not checked here;
+/// see `check_limits`.
+pub fn analyze(
+    bytecode: &[u8],
+    callee: impl Fn(PredicateId) -> Option<PredicateBounds>,
+) -> Result<PredicateBounds, String> {
+    validate_bytecode(bytecode)?;
+
+    let mut instructions = Vec::new();
+    let mut pc = 0;
+    while pc < bytecode.len() {
+        // validate_bytecode guarantees the opcode and its operand
+        let opcode = Opcode::from_byte(bytecode[pc]).expect("validated opcode");
+        let next = pc + 1 + operand_size(opcode);
+        instructions.push(Instruction { pc, opcode, next });
+        pc = next;
+    }
+
+    let call_bounds = |ins: &Instruction| -> Result<PredicateBounds, String> {
+        match operand_u16(bytecode, ins) {
+            0 => Ok(PredicateBounds::default()),
+            id => callee(id).ok_or_else(|| format!(
+                "CallPred at offset {} calls unknown predicate {}", ins.pc, id
+            )),
+        }
+    };
+
+    // Forward pass: stack depth on entry to each offset (None = unreachable)
+    let mut depth_at: Vec<Option<usize>> = vec![None; bytecode.len() + 1];
+    depth_at[0] = Some(0);
+    let mut max_stack = 0;
+    let mut call_depth = 1;
+
+    for ins in &instructions {
+        let Some(depth) = depth_at[ins.pc] else { continue };
+        let (pops, pushes) = stack_effect(ins.opcode);
+        if depth < pops {
+            return Err(format!(
+                "Stack underflow at offset {}: {:?} needs {} value(s), stack has {}",
+                ins.pc, ins.opcode, pops, depth
+            ));
+        }
+        let after = depth - pops + pushes;
+
+        match ins.opcode {
+            Opcode::Ret => {
+                if depth != 1 {
+                    return Err(format!(
+                        "RET at offset {} with {} values on the stack (expected 1)",
+                        ins.pc, depth
+                    ));
+                }
+                continue;
+            }
+            Opcode::JumpIfFalse | Opcode::JumpIfTrue => {
+                // The taken jump keeps its condition as the result
+                let target = operand_u16(bytecode, ins) as usize;
+                merge(&mut depth_at, target, depth)?;
+            }
+            Opcode::CallPred => {
+                let called = call_bounds(ins)?;
+                max_stack = max_stack.max(depth + called.max_stack);
+                call_depth = call_depth.max(1 + called.call_depth);
+            }
+            _ => {}
+        }
+
+        max_stack = max_stack.max(after);
+        merge(&mut depth_at, ins.next, after)?;
+    }
+
+    if depth_at[bytecode.len()].is_some() {
+        return Err("Execution can run past the end without RET".into());
+    }
+
+    // Backward pass: most steps from each reachable instruction to RET
+    let mut steps_from = vec![0usize; bytecode.len() + 1];
+    for ins in instructions.iter().rev() {
+        if depth_at[ins.pc].is_none() {
+            continue;
+        }
+        let mut steps = 1;
+        match ins.opcode {
+            Opcode::Ret => {}
+            Opcode::JumpIfFalse | Opcode::JumpIfTrue => {
+                let target = operand_u16(bytecode, ins) as usize;
+                steps += steps_from[ins.next].max(steps_from[target]);
+            }
+            Opcode::CallPred => {
+                steps += call_bounds(ins)?.max_steps + steps_from[ins.next];
+            }
+            _ => steps += steps_from[ins.next],
+        }
+        steps_from[ins.pc] = steps;
+    }
+
+    Ok(PredicateBounds { max_stack, max_steps: steps_from[0], call_depth })
+}
+
+/// Record the stack depth on entry to `at`; every path must agree
+fn merge(depth_at: &mut [Option<usize>], at: usize, depth: usize) -> Result<(), String> {
+    match depth_at[at] {
+        Some(existing) if existing != depth => Err(format!(
+            "Inconsistent stack depth at offset {}: {} or {}", at, existing, depth
+        )),
+        _ => {
+            depth_at[at] = Some(depth);
+            Ok(())
+        }
+    }
+}
+
+/// Check bounds against the VM limits
+pub fn check_limits(bounds: PredicateBounds) -> Result<(), String> {
+    if bounds.max_stack > limits::MAX_STACK_DEPTH {
+        return Err(format!(
+            "Stack depth {} exceeds MAX_STACK_DEPTH ({})",
+            bounds.max_stack, limits::MAX_STACK_DEPTH
+        ));
+    }
+    if bounds.max_steps > limits::MAX_PREDICATE_STEPS {
+        return Err(format!(
+            "Worst case of {} steps exceeds MAX_PREDICATE_STEPS ({})",
+            bounds.max_steps, limits::MAX_PREDICATE_STEPS
+        ));
+    }
+    if bounds.call_depth > limits::MAX_CALL_DEPTH {
+        return Err(format!(
+            "Call depth {} exceeds MAX_CALL_DEPTH ({})",
+            bounds.call_depth, limits::MAX_CALL_DEPTH
+        ));
+    }
+    Ok(())
+}
+
+/// Verify a predicate table, callees before callers
+///
+/// `count` is the number of predicates in the runtime's table, which
+/// reads predicate n at entry n - 1; a CallPred beyond it is rejected
+/// even if `table` has an entry with that id. Returns the bounds of every
+/// predicate, or the first predicate found unsafe with the reason. Calls
+/// must not be recursive.
+pub fn verify_table(
+    table: &[(PredicateId, &[u8])],
+    count: usize,
+) -> Result<HashMap<PredicateId, PredicateBounds>, (PredicateId, String)> {
+    let bytecode: HashMap<PredicateId, &[u8]> = table.iter().copied().collect();
+    let mut bounds = HashMap::new();
+    for &(id, _) in table {
+        verify_one(id, &bytecode, count, &mut Vec::new(), &mut bounds)?;
+    }
+    Ok(bounds)
+}
+
+fn verify_one(
+    id: PredicateId,
+    table: &HashMap<PredicateId, &[u8]>,
+    count: usize,
+    stack: &mut Vec<PredicateId>,
+    bounds: &mut HashMap<PredicateId, PredicateBounds>,
+) -> Result<(), (PredicateId, String)> {
+    if bounds.contains_key(&id) {
+        return Ok(());
+    }
+    if stack.contains(&id) {
+        let start = stack.iter().position(|&p| p == id).unwrap_or(0);
+        let cycle: Vec<_> = stack[start..].iter().chain([&id]).map(|p| p.to_string()).collect();
+        return Err((id, format!("Recursive CallPred: {}", cycle.join(" → "))));
+    }
+    // Deeper chains fail `check_limits` anyway; stop before recursing further
+    if stack.len() >= limits::MAX_CALL_DEPTH {
+        return Err((id, format!(
+            "Call depth exceeds MAX_CALL_DEPTH ({})", limits::MAX_CALL_DEPTH
+        )));
+    }
+    let code = table[&id];
+
+    // Callees first, so `analyze` can look their bounds up
+    stack.push(id);
+    let mut pc = 0;
+    while let Some(opcode) = code.get(pc).and_then(|&b| Opcode::from_byte(b)) {
+        if opcode == Opcode::CallPred && pc + 2 < code.len() {
+            let callee = u16::from_le_bytes([code[pc + 1], code[pc + 2]]);
+            if callee as usize > count {
+                return Err((id, format!(
+                    "CallPred at offset {} calls predicate {}, beyond the {} in the table",
+                    pc, callee, count
+                )));
+            }
+            if callee != 0 && table.contains_key(&callee) {
+                verify_one(callee, table, count, stack, bounds)?;
+            }
+        }
+        pc += 1 + operand_size(opcode);
+    }
+    stack.pop();
+
+    let result = analyze(code, |callee| bounds.get(&callee).copied())
+        .and_then(|b| check_limits(b).map(|_| b))
+        .map_err(|message| (id, message))?;
+    bounds.insert(id, result);
+    Ok(())
+}
+
+/// Verify every compiled predicate, recording its bounds
+///
+/// Predicates without bytecode are skipped; a call to one is a call to
+/// an unknown predicate.
+pub fn verify_predicates(predicates: &mut [CompiledPredicate]) -> Result<(), (PredicateId, String)> {
+    let table: Vec<(PredicateId, &[u8])> = predicates.iter()
+        .filter(|p| p.is_compiled())
+        .map(|p| (p.id, p.bytecode.as_slice()))
+        .collect();
+    let bounds = verify_table(&table, predicates.len())?;
+    for pred in predicates {
+        pred.bounds = bounds.get(&pred.id).copied();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RET: u8 = Opcode::Ret as u8;
+
+    fn push(value: i32) -> Vec<u8> {
+        let mut code = vec![Opcode::PushInt as u8];
+        code.extend_from_slice(&value.to_le_bytes());
+        code
+    }
+
+    fn none(_: PredicateId) -> Option<PredicateBounds> {
+        None
+    }
+
+    #[test]
+    fn test_straight_line() {
+        // 1 == 2
+        let code = [push(1), push(2), vec![Opcode::Eq as u8, RET]].concat();
+        assert_eq!(analyze(&code, none), Ok(PredicateBounds { max_stack: 2, max_steps: 4, call_depth: 1 }));
+    }
+
+    #[test]
+    fn test_underflow() {
+        let code = [push(1), vec![Opcode::And as u8, RET]].concat();
+        let err = analyze(&code, none).unwrap_err();
+        assert!(err.contains("underflow at offset 5"), "{}", err);
+    }
+
+    #[test]
+    fn test_ret_leaves_one_value() {
+        let err = analyze(&[RET], none).unwrap_err();
+        assert!(err.contains("0 values"), "{}", err);
+        let err = analyze(&[push(1), push(2), vec![RET]].concat(), none).unwrap_err();
+        assert!(err.contains("2 values"), "{}", err);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        // 17 pushes folded by 16 ANDs: within the byte limit, over the stack limit
+        let mut code: Vec<u8> = (0..17).flat_map(push).collect();
+        code.extend([Opcode::And as u8; 16]);
+        code.push(RET);
+        let bounds = analyze(&code, none).unwrap();
+        assert_eq!(bounds.max_stack, 17);
+        let err = check_limits(bounds).unwrap_err();
+        assert!(err.contains("MAX_STACK_DEPTH"), "{}", err);
+    }
+
+    #[test]
+    fn test_jump_paths() {
+        // a && b: the taken jump keeps `a`, the fall-through pops it
+        let mut code = push(1);
+        code.extend([Opcode::JumpIfFalse as u8, 13, 0]);
+        code.extend(push(2));
+        code.push(RET);
+        let bounds = analyze(&code, none).unwrap();
+        assert_eq!(bounds, PredicateBounds { max_stack: 1, max_steps: 4, call_depth: 1 });
+
+        // Paths reaching RET with different depths
+        let mut code = push(1);
+        code.extend([Opcode::JumpIfFalse as u8, 18, 0]);
+        code.extend(push(2));
+        code.extend(push(3));
+        code.push(RET);
+        let err = analyze(&code, none).unwrap_err();
+        assert!(err.contains("Inconsistent stack depth at offset 18"), "{}", err);
+    }
+
+    #[test]
+    fn test_longest_path() {
+        // The fall-through runs three more instructions than the jump
+        let mut code = push(1);
+        code.extend([Opcode::JumpIfTrue as u8, 19, 0]);
+        code.extend(push(2));
+        code.extend(push(3));
+        code.extend([Opcode::Eq as u8, Opcode::Noop as u8, Opcode::Noop as u8, Opcode::Noop as u8, RET]);
+        let bounds = analyze(&code, none).unwrap();
+        assert_eq!(bounds.max_steps, 9);
+    }
+
+    #[test]
+    fn test_merge_field_operand() {
+        // MERGE_FIELD selector + field path offset, then IS_NULL
+        let code = [Opcode::MergeField as u8, 2, 0, 0, 0, 0, Opcode::IsNull as u8, RET];
+        assert_eq!(analyze(&code, none), Ok(PredicateBounds { max_stack: 1, max_steps: 3, call_depth: 1 }));
+    }
+
+    #[test]
+    fn test_calls_compose() {
+        let leaf = [push(1), push(2), vec![Opcode::Eq as u8, RET]].concat();
+        let caller = [push(7), vec![Opcode::CallPred as u8, 1, 0, Opcode::And as u8, RET]].concat();
+        let bounds = verify_table(&[(2, caller.as_slice()), (1, leaf.as_slice())], 2).unwrap();
+        assert_eq!(bounds[&1], PredicateBounds { max_stack: 2, max_steps: 4, call_depth: 1 });
+        // The callee runs on top of the pushed 7 and its steps count
+        assert_eq!(bounds[&2], PredicateBounds { max_stack: 3, max_steps: 8, call_depth: 2 });
+
+        // Id 0 is always true without running anything
+        let always = [Opcode::CallPred as u8, 0, 0, RET];
+        assert_eq!(analyze(&always, none), Ok(PredicateBounds { max_stack: 1, max_steps: 2, call_depth: 1 }));
+    }
+
+    #[test]
+    fn test_bad_calls() {
+        let unknown = [Opcode::CallPred as u8, 2, 0, RET];
+        let (id, err) = verify_table(&[(1, unknown.as_slice())], 2).unwrap_err();
+        assert_eq!(id, 1);
+        assert!(err.contains("unknown predicate 2"), "{}", err);
+
+        // The runtime reads predicate 3 at entry 2, past a two-entry
+        // table, even though an entry with id 3 exists here
+        let call = [Opcode::CallPred as u8, 3, 0, RET];
+        let leaf = [push(1), vec![RET]].concat();
+        let (id, err) = verify_table(&[(1, call.as_slice()), (3, leaf.as_slice())], 2).unwrap_err();
+        assert_eq!(id, 1);
+        assert_eq!(err, "CallPred at offset 0 calls predicate 3, beyond the 2 in the table");
+
+        let a = [Opcode::CallPred as u8, 2, 0, RET];
+        let b = [Opcode::CallPred as u8, 1, 0, RET];
+        let (_, err) = verify_table(&[(1, a.as_slice()), (2, b.as_slice())], 2).unwrap_err();
+        assert!(err.contains("Recursive CallPred: 1 → 2 → 1"), "{}", err);
+
+        // Five nested evaluations
+        let mut chain = vec![[push(1), vec![RET]].concat()];
+        for id in 1..5u8 {
+            chain.push(vec![Opcode::CallPred as u8, id, 0, RET]);
+        }
+        let entries: Vec<(PredicateId, &[u8])> = chain.iter()
+            .enumerate()
+            .map(|(i, code)| (i as PredicateId + 1, code.as_slice()))
+            .collect();
+        assert_eq!(verify_table(&entries[..4], 4).unwrap()[&4].call_depth, 4);
+        let (id, err) = verify_table(&entries, entries.len()).unwrap_err();
+        assert_eq!(id, 5);
+        assert!(err.contains("Call depth 5 exceeds MAX_CALL_DEPTH"), "{}", err);
+    }
+
+    #[test]
+    fn test_step_limit() {
+        // Each predicate ANDs eight calls to the previous one
+        let mut table: Vec<Vec<u8>> = vec![[push(1), vec![RET]].concat()];
+        for id in 1..3u16 {
+            let [lo, hi] = id.to_le_bytes();
+            let mut code = vec![Opcode::CallPred as u8, lo, hi];
+            for _ in 0..7 {
+                code.extend([Opcode::CallPred as u8, lo, hi, Opcode::And as u8]);
+            }
+            code.push(RET);
+            table.push(code);
+        }
+        let entries: Vec<(PredicateId, &[u8])> = table.iter()
+            .enumerate()
+            .map(|(i, code)| (i as PredicateId + 1, code.as_slice()))
+            .collect();
+        let bounds = verify_table(&entries[..2], 2).unwrap();
+        assert_eq!(bounds[&2].max_steps, 32);
+        let (id, err) = verify_table(&entries, entries.len()).unwrap_err();
+        assert_eq!(id, 3);
+        assert!(err.contains("272 steps exceeds MAX_PREDICATE_STEPS"), "{}", err);
+    }
+}
@@ -1,45 +1,127 @@
-//! AST Types for XML DSL
+//! AST Types for the workflow DSL
+//!
+//! Every front-end (XML, `.pxyz` text, JSON) produces an `OmarDocument`.
+//!
+//! ## JSON shape
+//!
+//! The serde form of these types is the JSON input format (see
+//! `dsl::json`) and is kept stable:
+//!
+//! - keys are the Rust field names, except `Node::async_node` (`"async"`)
+//! - optional fields, lists and flags may be left out; unknown keys are
+//!   errors
+//! - `PredicateExpr` is tagged by `"op"` and `MergePolicy` by `"policy"`,
+//!   both with snake_case variant names
+//! - a `Value` is a plain number, boolean or string
+//! - `Node::signals` and `FragmentUse::args` are `[name, value]` pairs
+//! - source spans are not serialized
+//!
+//! ```json
+//! {
+//!   "version": "1.0.0",
+//!   "workflows": [{
+//!     "id": "hello",
+//!     "entry": { "p": "hello", "x": "world", "node": "start" },
+//!     "nodes": [
+//!       { "id": "start", "kind": "transform" },
+//!       { "id": "done", "kind": "terminal", "status": 200 }
+//!     ],
+//!     "edges": [{
+//!       "from": "start", "to": "done",
+//!       "predicate": { "op": "eq", "left": "$token.role", "right": "admin" }
+//!     }]
+//!   }]
+//! }
+//! ```
 
+use super::time;
+use crate::Span;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// Root document
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct OmarDocument {
     pub version: String,
+    /// Other documents whose definitions this one uses
+    pub imports: Vec<Import>,
     pub schemas: Vec<Schema>,
     pub predicates: Vec<PredicateDef>,
     pub workflows: Vec<Workflow>,
     pub templates: Vec<Template>,
+    /// Reusable node/edge patterns, expanded by `<use>`
+    pub fragments: Vec<Fragment>,
     /// Merge policies for CRDT conflict resolution (Y-constraint application)
     pub merge_policies: Vec<EntityMerge>,
 }
 
+/// `<import href="..." as="..."/>`
+///
+/// Predicates and schemas of the imported document are visible here as
+/// `namespace.id`; the namespace defaults to the file stem of `href`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Import {
+    pub href: String,
+    pub namespace: Option<String>,
+    #[serde(skip)]
+    pub span: Option<Span>,
+}
+
 /// Schema definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Schema {
     pub name: String,
+    #[serde(default)]
     pub fields: Vec<FieldDef>,
+    #[serde(skip)]
+    pub span: Option<Span>,
 }
 
 /// Field definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct FieldDef {
     pub name: String,
+    #[serde(default = "default_field_type")]
     pub field_type: String,
+    #[serde(default)]
     pub required: bool,
     pub default: Option<String>,
     pub pattern: Option<String>,
+    #[serde(skip)]
+    pub span: Option<Span>,
+}
+
+fn default_field_type() -> String {
+    "string".into()
 }
 
 /// Predicate definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct PredicateDef {
     pub id: String,
     pub expr: PredicateExpr,
+    #[serde(skip)]
+    pub span: Option<Span>,
+    /// Spans of the sub-expressions of `expr`, in pre-order
+    /// (see [`PredicateExpr::children`])
+    #[serde(skip)]
+    pub expr_spans: Vec<Span>,
+    /// The `expr` attribute `expr` was parsed from, for `pxyz fmt`
+    #[serde(skip)]
+    pub expr_source: Option<String>,
 }
 
 /// Predicate expression
-#[derive(Debug, Clone)]
+///
+/// JSON: `{"op": "<variant in snake_case>", ...fields}`, e.g.
+/// `{"op": "eq", "left": "$token.role", "right": "admin"}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
 pub enum PredicateExpr {
     Always,
     Fail,
@@ -53,21 +135,218 @@ pub enum PredicateExpr {
     Matches { left: String, pattern: String },
     StartsWith { left: String, prefix: String },
     EndsWith { left: String, suffix: String },
+    /// `left` equals one of `values`: `<in left="$deal.stage"
+    /// values="won,lost,closed"/>`
+    In { left: String, values: Vec<Value> },
+    NotIn { left: String, values: Vec<Value> },
+    /// `left` is earlier than `right`, a date, a duration relative to now
+    /// or a variable: `<before left="$deal.updated" right="-P30D"/>`
+    Before { left: String, right: String },
+    /// `left` is later than `right`, read as for `Before`
+    After { left: String, right: String },
+    /// `left` lies between now and now + `duration` (an ISO 8601
+    /// duration, negative for the past): `<within left="$task.due"
+    /// duration="P3D"/>`
+    Within { left: String, duration: String },
     And { conditions: Vec<PredicateExpr> },
     Or { conditions: Vec<PredicateExpr> },
     Not { condition: Box<PredicateExpr> },
     Ref { predicate: String },
     Fn { name: String, arg: String },
+    /// Comparison of arithmetic operands: `<compare left="$input.end -
+    /// $input.start" cmp="lt" right="86400"/>`
+    Compare { cmp: Comparison, left: Arith, right: Arith },
+}
+
+impl PredicateExpr {
+    /// Immediate sub-expressions, in source order
+    ///
+    /// Visiting a node and then its children in this order gives the
+    /// pre-order used to index `expr_spans` / `predicate_spans`.
+    pub fn children(&self) -> Vec<&PredicateExpr> {
+        match self {
+            PredicateExpr::And { conditions } | PredicateExpr::Or { conditions } => {
+                conditions.iter().collect()
+            }
+            PredicateExpr::Not { condition } => vec![condition.as_ref()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Comparison operator of `PredicateExpr::Compare`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Eq,
+    Neq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Comparison {
+    pub const ALL: [Comparison; 6] = [Self::Eq, Self::Neq, Self::Gt, Self::Gte, Self::Lt, Self::Lte];
+
+    /// Name as written in `cmp` and as a comparison element (`lte`)
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Eq => "eq",
+            Self::Neq => "neq",
+            Self::Gt => "gt",
+            Self::Gte => "gte",
+            Self::Lt => "lt",
+            Self::Lte => "lte",
+        }
+    }
+
+    /// Infix operator (`<=`)
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Eq => "==",
+            Self::Neq => "!=",
+            Self::Gt => ">",
+            Self::Gte => ">=",
+            Self::Lt => "<",
+            Self::Lte => "<=",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.symbol() == symbol)
+    }
+}
+
+/// Binary arithmetic operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+impl ArithOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "%",
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        [Self::Add, Self::Sub, Self::Mul, Self::Div, Self::Mod].into_iter().find(|op| op.symbol() == symbol)
+    }
+
+    /// Binding strength: `*`, `/` and `%` bind tighter than `+` and `-`
+    pub fn precedence(self) -> u8 {
+        match self {
+            Self::Add | Self::Sub => 1,
+            Self::Mul | Self::Div | Self::Mod => 2,
+        }
+    }
+}
+
+/// Arithmetic over predicate operands, e.g. `$input.price * 0.2`
+///
+/// Written as infix text in XML attributes and JSON (see
+/// `dsl::expr::parse_arith`): `+ - * / %`, unary `-` and parentheses,
+/// with operators separated by spaces so that `us-east` stays one
+/// operand. JSON also accepts a plain number.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "JsonValue", into = "String")]
+pub enum Arith {
+    /// `$var`, `name(arg)` or a literal, read like a comparison operand
+    Operand(String),
+    Neg(Box<Arith>),
+    Binary { op: ArithOp, left: Box<Arith>, right: Box<Arith> },
+}
+
+impl Arith {
+    /// Binding strength, as `ArithOp::precedence`; operands and negation
+    /// bind tightest
+    fn precedence(&self) -> u8 {
+        match self {
+            Arith::Binary { op, .. } => op.precedence(),
+            _ => 3,
+        }
+    }
+}
+
+impl fmt::Display for Arith {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arith::Operand(operand) => {
+                if operand.is_empty() || operand.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+                    write!(f, "\"{}\"", operand.replace('\\', "\\\\").replace('"', "\\\""))
+                } else {
+                    f.write_str(operand)
+                }
+            }
+            Arith::Neg(inner) if inner.precedence() < 3 => write!(f, "- ({})", inner),
+            Arith::Neg(inner) => write!(f, "- {}", inner),
+            Arith::Binary { op, left, right } => {
+                // Left-associative: a right operand of equal precedence
+                // needs parentheses, `a - (b - c)`
+                if left.precedence() < op.precedence() {
+                    write!(f, "({})", left)?;
+                } else {
+                    write!(f, "{}", left)?;
+                }
+                write!(f, " {} ", op.symbol())?;
+                if right.precedence() <= op.precedence() {
+                    write!(f, "({})", right)
+                } else {
+                    write!(f, "{}", right)
+                }
+            }
+        }
+    }
+}
+
+impl From<Arith> for String {
+    fn from(arith: Arith) -> Self {
+        arith.to_string()
+    }
+}
+
+impl TryFrom<JsonValue> for Arith {
+    type Error = String;
+
+    fn try_from(value: JsonValue) -> Result<Self, Self::Error> {
+        let text = match value {
+            JsonValue::Str(s) => s,
+            value => crate::dsl::writer::value_text(&Value::from(value)),
+        };
+        super::expr::parse_arith(&text).map_err(|e| e.message)
+    }
 }
 
 /// Value in predicate
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// JSON: a plain number, boolean or string; strings starting with `$`
+/// are variables, and dates and durations are read as in XML.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "JsonValue", into = "JsonValue")]
 pub enum Value {
     Int(i64),
     Float(f64),
     Str(String),
     Bool(bool),
     Var(String),
+    /// ISO 8601 date or date-time, `2025-01-01` (see `dsl::time`)
+    Time(String),
+    /// ISO 8601 duration, `P3D`; as a comparison value, relative to now
+    Duration(String),
 }
 
 impl Value {
@@ -89,28 +368,142 @@ impl Value {
         }
         Value::Str(s.to_string())
     }
+
+    /// Read the operand of a time comparison (`<before>`, `<after>`, a
+    /// `<compare>` literal): a variable, a date or a duration by its
+    /// shape (see `dsl::time`), else `None`
+    ///
+    /// Elsewhere `2025-01-01` or `P1` is just a string.
+    pub fn time_from_str(s: &str) -> Option<Self> {
+        if s.starts_with('$') {
+            Some(Value::Var(s.to_string()))
+        } else if time::is_time_literal(s) {
+            Some(Value::Time(s.to_string()))
+        } else if time::is_duration_literal(s) {
+            Some(Value::Duration(s.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// Read a comma-separated list (`won,lost,closed`), each member as by
+    /// `from_str_guess`; members are trimmed and must not be empty
+    pub fn list_from_str(s: &str) -> Result<Vec<Value>, String> {
+        s.split(',')
+            .map(str::trim)
+            .map(|member| match member {
+                "" => Err(format!("Empty member in list '{}'", s)),
+                member => Ok(Value::from_str_guess(member)),
+            })
+            .collect()
+    }
+}
+
+/// Serialized form of `Value`
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum JsonValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+impl From<JsonValue> for Value {
+    fn from(value: JsonValue) -> Self {
+        match value {
+            JsonValue::Int(i) => Value::Int(i),
+            JsonValue::Float(f) => Value::Float(f),
+            JsonValue::Bool(b) => Value::Bool(b),
+            JsonValue::Str(s) if s.starts_with('$') => Value::Var(s),
+            JsonValue::Str(s) => Value::Str(s),
+        }
+    }
+}
+
+impl From<Value> for JsonValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Int(i) => JsonValue::Int(i),
+            Value::Float(f) => JsonValue::Float(f),
+            Value::Bool(b) => JsonValue::Bool(b),
+            Value::Str(s) | Value::Var(s) | Value::Time(s) | Value::Duration(s) => JsonValue::Str(s),
+        }
+    }
 }
 
 /// Workflow definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Workflow {
     pub id: String,
     pub description: Option<String>,
+    /// Schema of the nodes that have no `<schema ref>` of their own
+    pub schema: Option<String>,
     pub entry: EntryPoint,
+    #[serde(default)]
     pub nodes: Vec<Node>,
+    #[serde(default)]
     pub edges: Vec<Edge>,
+    /// Fragment instances, expanded into `nodes`/`edges` before lowering
+    #[serde(default)]
+    pub uses: Vec<FragmentUse>,
+    #[serde(skip)]
+    pub span: Option<Span>,
+}
+
+/// `<fragment id="..." params="a,b">`: nodes and edges written once and
+/// instantiated by `<use>`
+///
+/// `{param}` in an attribute value is replaced by the argument of each
+/// use. Numeric and boolean attributes (`status`, `weight`, `kind`, ...)
+/// are read while parsing and cannot be parameters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fragment {
+    pub id: String,
+    #[serde(default)]
+    pub params: Vec<String>,
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+    #[serde(default)]
+    pub edges: Vec<Edge>,
+    #[serde(default)]
+    pub uses: Vec<FragmentUse>,
+    #[serde(skip)]
+    pub span: Option<Span>,
+}
+
+/// `<use fragment="..." id="..." param="value" .../>` inside `<nodes>`
+///
+/// Instantiated node ids are prefixed with `id.`, so a fragment node
+/// `write` used as `save` becomes `save.write`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FragmentUse {
+    pub fragment: String,
+    pub id: String,
+    /// Parameter arguments, in attribute order
+    #[serde(default)]
+    pub args: Vec<(String, String)>,
+    #[serde(skip)]
+    pub span: Option<Span>,
 }
 
 /// Entry point
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct EntryPoint {
     pub p: String,
     pub x: String,
     pub node: String,
+    #[serde(skip)]
+    pub span: Option<Span>,
 }
 
 /// Node definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Node {
     pub id: String,
     pub kind: String,
@@ -124,9 +517,17 @@ pub struct Node {
     pub signals: Vec<(String, String)>,
     pub actor: Option<String>,
     pub confirmation: Option<String>,
+    #[serde(rename = "async")]
     pub async_node: bool,
     pub cacheable: bool,
     pub data: std::collections::HashMap<String, String>,
+    /// Workflow invoked by a `kind="call"` node (expanded before lowering)
+    pub call: Option<String>,
+    /// Set on the plumbing nodes produced by call expansion
+    #[serde(skip)]
+    pub passthrough: bool,
+    #[serde(skip)]
+    pub span: Option<Span>,
 }
 
 impl Default for Node {
@@ -147,12 +548,16 @@ impl Default for Node {
             async_node: false,
             cacheable: false,
             data: std::collections::HashMap::new(),
+            call: None,
+            passthrough: false,
+            span: None,
         }
     }
 }
 
 /// Edge definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Edge {
     pub from: String,
     pub to: String,
@@ -161,27 +566,25 @@ pub struct Edge {
     pub weight: Option<u16>,
     pub parallel: bool,
     pub fallback: bool,
-}
-
-impl Default for Edge {
-    fn default() -> Self {
-        Self {
-            from: String::new(),
-            to: String::new(),
-            predicate: None,
-            predicate_ref: None,
-            weight: None,
-            parallel: false,
-            fallback: false,
-        }
-    }
+    #[serde(skip)]
+    pub span: Option<Span>,
+    /// Spans of the sub-expressions of `predicate`, in pre-order
+    #[serde(skip)]
+    pub predicate_spans: Vec<Span>,
+    /// The `<when expr>` attribute `predicate` was parsed from, for
+    /// `pxyz fmt`
+    #[serde(skip)]
+    pub predicate_source: Option<String>,
 }
 
 /// Template definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Template {
     pub id: String,
     pub content: String,
+    #[serde(skip)]
+    pub span: Option<Span>,
 }
 
 // ═══════════════════════════════════════════════════════════════════════════
@@ -196,18 +599,26 @@ pub struct Template {
 /// - `$candidate` - The proposed merged value (for validation)
 ///
 /// Built-in strategies compile to bytecode; custom predicates use the full VM.
-#[derive(Debug, Clone)]
+///
+/// JSON: `{"policy": "lww"}`, `{"policy": "prefer_origin", "actor": "..."}`,
+/// `{"policy": "custom", "predicate": "..."}`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case", deny_unknown_fields)]
 pub enum MergePolicy {
     /// Last-Writer-Wins: Compare timestamps, higher wins
     /// Compiles to: timestamp($a) > timestamp($b) ? $a : $b
+    #[default]
+    #[serde(rename = "lww")]
     LWW,
 
     /// First-Writer-Wins: Compare timestamps, lower wins (immutable-ish)
     /// Compiles to: timestamp($a) < timestamp($b) ? $a : $b
+    #[serde(rename = "fww")]
     FWW,
 
     /// Vector clock dominance: True partial ordering
     /// Compiles to: vclock_gt($a, $b) ? $a : (vclock_gt($b, $a) ? $b : CONFLICT)
+    #[serde(rename = "vclock")]
     VClock,
 
     /// Maximum value wins (for counters, versions)
@@ -239,14 +650,9 @@ pub enum MergePolicy {
     Custom { predicate: String },
 }
 
-impl Default for MergePolicy {
-    fn default() -> Self {
-        MergePolicy::LWW
-    }
-}
-
 /// Field-level merge configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct FieldMerge {
     /// Field name/path
     pub field: String,
@@ -254,19 +660,26 @@ pub struct FieldMerge {
     pub policy: MergePolicy,
     /// Optional validation predicate for merged result
     pub validate: Option<String>,
+    #[serde(skip)]
+    pub span: Option<Span>,
 }
 
 /// Entity-level merge configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct EntityMerge {
     /// Entity/schema name this applies to
     pub entity: String,
     /// Default policy for fields without explicit config
+    #[serde(default)]
     pub default_policy: MergePolicy,
     /// Field-specific overrides
+    #[serde(default)]
     pub fields: Vec<FieldMerge>,
     /// Predicate that must pass for any merge to proceed
     pub pre_condition: Option<String>,
     /// Predicate to validate final merged entity
     pub post_validate: Option<String>,
-}
\ No newline at end of file
+    #[serde(skip)]
+    pub span: Option<Span>,
+}
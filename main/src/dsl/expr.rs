@@ -0,0 +1,645 @@
+//! Infix Predicate Expressions
+//!
+//! Parses the compact condition syntax used by the text front-end and by
+//! `expr` attributes in XML (`<when expr="...">`, `<predicate expr>`):
+//!
+//! ```text
+//! $token.role == "admin" and not ($input.count > 10 or is_locked)
+//! ```
+//!
+//! | Syntax                                   | Expression            |
+//! |------------------------------------------|-----------------------|
+//! | `a == b`, `!=`, `>`, `>=`, `<`, `<=`     | Eq, Neq, Gt, ...      |
+//! | `a contains b`                           | Contains              |
+//! | `a matches "re"`                         | Matches               |
+//! | `a startsWith "p"`, `a endsWith "s"`     | StartsWith, EndsWith  |
+//! | `a in won,lost`, `a not_in "x, y"`        | In, NotIn             |
+//! | `a before b`, `a after b`                | Before, After         |
+//! | `a within P3D`                           | Within                |
+//! | `x and y`, `x && y`                      | And                   |
+//! | `x or y`, `x \|\| y`                     | Or                    |
+//! | `not x`, `!x`                            | Not                   |
+//! | `name(arg)`                              | Fn                    |
+//! | `name(arg) > b` (any comparison)         | compares the result   |
+//! | `a + b * 2 < c`, `-`, `/`, `%`, `- a`      | Compare               |
+//! | `name` (a bare identifier)               | Ref                   |
+//! | `always`, `fail`                         | Always, Fail          |
+//!
+//! `and` binds tighter than `or`; a chain `a and b and c` is a single
+//! `And` with three conditions, like `<and>` with three children.
+//! Right-hand values go through `Value::from_str_guess` whether quoted or
+//! not, exactly as XML attribute values do; the right of `before` and
+//! `after` must be a date, a duration or a variable.
+//!
+//! Either side of `==`, `!=`, `>`, `>=`, `<` and `<=` may be arithmetic
+//! (see `parse_arith`); arithmetic operators must be separated by spaces,
+//! so `us-east` and `a*b` stay single operands. A variable with an
+//! operator inside (`$end-$start`) is an error rather than a path. `*`, `/` and `%` bind
+//! tighter than `+` and `-`, and a leading `-$x` negates the variable.
+
+use super::ast::{Arith, ArithOp, Comparison, PredicateExpr, Value};
+
+/// A parse error at byte range `start..end` of the expression source
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Parse an expression
+///
+/// Returns the expression and the byte range of every sub-expression in
+/// pre-order (the order of `PredicateDef::expr_spans`).
+pub fn parse_expr(src: &str) -> Result<(PredicateExpr, Vec<(usize, usize)>), ExprError> {
+    let tokens = tokenize(src)?;
+    let mut parser = Parser { tokens, pos: 0, end: src.len() };
+    let parsed = parser.or()?;
+    if let Some(token) = parser.peek() {
+        return Err(parser.error_at(token, format!("Unexpected '{}'", token.text)));
+    }
+    Ok((parsed.expr, parsed.spans))
+}
+
+/// Parse arithmetic, as written in `<compare left="..." right="...">`
+///
+/// Operands are `$` variables, `name(arg)` calls and literals;
+/// parentheses group sub-expressions.
+pub fn parse_arith(src: &str) -> Result<Arith, ExprError> {
+    let tokens = tokenize(src)?;
+    let mut parser = Parser { tokens, pos: 0, end: src.len() };
+    let arith = parser.arith(None)?;
+    if let Some(token) = parser.peek() {
+        return Err(parser.error_at(token, format!("Unexpected '{}'", token.text)));
+    }
+    Ok(arith)
+}
+
+/// True if the `$` variable `operand` has an arithmetic operator written
+/// without spaces, so it would read as one variable: `$end-$start`,
+/// `$a*2`
+///
+/// A `-` only counts before `$` or a digit, so `$region.us-east` is a path.
+pub fn is_unspaced_arith(operand: &str) -> bool {
+    let Some(path) = operand.strip_prefix('$') else { return false };
+    let mut chars = path.chars().peekable();
+    while let Some(c) = chars.next() {
+        let operator = match c {
+            '$' | '*' | '/' | '%' | '+' => true,
+            '-' => chars.peek().is_some_and(|&n| n == '$' || n.is_ascii_digit()),
+            _ => false,
+        };
+        if operator {
+            return true;
+        }
+    }
+    false
+}
+
+/// Message for an operand rejected by `is_unspaced_arith`
+pub fn unspaced_arith_message(operand: &str) -> String {
+    format!("'{}' reads as a single variable; put spaces around arithmetic operators", operand)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Word,
+    Str,
+    Op,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: Kind,
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, ExprError> {
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let token = |kind, text: String, end| Token { kind, text, start, end };
+        match c {
+            '(' | ')' => {
+                chars.next();
+                let kind = if c == '(' { Kind::LParen } else { Kind::RParen };
+                tokens.push(token(kind, c.to_string(), start + 1));
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut text = String::new();
+                let mut closed = None;
+                while let Some((i, ch)) = chars.next() {
+                    match ch {
+                        '\\' => {
+                            if let Some((_, escaped)) = chars.next() {
+                                text.push(escaped);
+                            }
+                        }
+                        ch if ch == c => {
+                            closed = Some(i + 1);
+                            break;
+                        }
+                        ch => text.push(ch),
+                    }
+                }
+                let Some(end) = closed else {
+                    return Err(ExprError {
+                        message: "Unterminated string".into(),
+                        start,
+                        end: src.len(),
+                    });
+                };
+                tokens.push(token(Kind::Str, text, end));
+            }
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                chars.next();
+                let next = chars.peek().map(|&(_, n)| n);
+                let op = match (c, next) {
+                    ('=', Some('=')) | ('!', Some('=')) | ('<', Some('=')) | ('>', Some('='))
+                    | ('&', Some('&')) | ('|', Some('|')) => {
+                        chars.next();
+                        format!("{}{}", c, next.unwrap_or_default())
+                    }
+                    ('!' | '<' | '>', _) => c.to_string(),
+                    _ => {
+                        return Err(ExprError {
+                            message: format!("Unexpected '{}'", c),
+                            start,
+                            end: start + c.len_utf8(),
+                        });
+                    }
+                };
+                let end = start + op.len();
+                tokens.push(token(Kind::Op, op, end));
+            }
+            _ => {
+                let mut end = start;
+                while let Some(&(i, ch)) = chars.peek() {
+                    if ch.is_whitespace() || "()\"'=!<>&|".contains(ch) {
+                        break;
+                    }
+                    end = i + ch.len_utf8();
+                    chars.next();
+                }
+                tokens.push(token(Kind::Word, src[start..end].to_string(), end));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// A parsed sub-expression with its pre-order spans
+struct Parsed {
+    expr: PredicateExpr,
+    spans: Vec<(usize, usize)>,
+}
+
+impl Parsed {
+    fn leaf(expr: PredicateExpr, start: usize, end: usize) -> Self {
+        Self { expr, spans: vec![(start, end)] }
+    }
+
+    fn range(&self) -> (usize, usize) {
+        self.spans[0]
+    }
+
+    /// Combine `parts` under `expr`, which covers all of them
+    fn node(expr: PredicateExpr, start: usize, end: usize, parts: Vec<Parsed>) -> Self {
+        let mut spans = vec![(start, end)];
+        spans.extend(parts.into_iter().flat_map(|p| p.spans));
+        Self { expr, spans }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Length of the source, for errors at the end of input
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn error_at(&self, token: &Token, message: String) -> ExprError {
+        ExprError { message, start: token.start, end: token.end }
+    }
+
+    fn error_at_end(&self, message: String) -> ExprError {
+        ExprError { message, start: self.end, end: self.end }
+    }
+
+    fn is_keyword(&self, keywords: &[&str]) -> bool {
+        self.peek().is_some_and(|t| {
+            matches!(t.kind, Kind::Word | Kind::Op) && keywords.contains(&t.text.as_str())
+        })
+    }
+
+    fn or(&mut self) -> Result<Parsed, ExprError> {
+        self.chain(&["or", "||"], Self::and, |conditions| PredicateExpr::Or { conditions })
+    }
+
+    fn and(&mut self) -> Result<Parsed, ExprError> {
+        self.chain(&["and", "&&"], Self::unary, |conditions| PredicateExpr::And { conditions })
+    }
+
+    /// `operand (op operand)*`, collapsed into one node when there are two or more
+    fn chain(
+        &mut self,
+        ops: &[&str],
+        operand: fn(&mut Self) -> Result<Parsed, ExprError>,
+        build: fn(Vec<PredicateExpr>) -> PredicateExpr,
+    ) -> Result<Parsed, ExprError> {
+        let first = operand(self)?;
+        if !self.is_keyword(ops) {
+            return Ok(first);
+        }
+
+        let mut parts = vec![first];
+        while self.is_keyword(ops) {
+            self.next();
+            parts.push(operand(self)?);
+        }
+        let start = parts[0].range().0;
+        let end = parts[parts.len() - 1].range().1;
+        let conditions = parts.iter().map(|p| p.expr.clone()).collect();
+        Ok(Parsed::node(build(conditions), start, end, parts))
+    }
+
+    fn unary(&mut self) -> Result<Parsed, ExprError> {
+        if self.is_keyword(&["not", "!"]) {
+            let start = self.next().expect("peeked").start;
+            let inner = self.unary()?;
+            let end = inner.range().1;
+            let expr = PredicateExpr::Not { condition: Box::new(inner.expr.clone()) };
+            return Ok(Parsed::node(expr, start, end, vec![inner]));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Parsed, ExprError> {
+        let Some(token) = self.next() else {
+            return Err(self.error_at_end("Expected a condition".into()));
+        };
+
+        match token.kind {
+            Kind::LParen => {
+                let inner = self.or()?;
+                match self.next() {
+                    Some(t) if t.kind == Kind::RParen => Ok(inner),
+                    Some(t) => Err(self.error_at(&t, format!("Expected ')', found '{}'", t.text))),
+                    None => Err(self.error_at_end("Expected ')'".into())),
+                }
+            }
+            Kind::Word if token.text == "always" => {
+                Ok(Parsed::leaf(PredicateExpr::Always, token.start, token.end))
+            }
+            Kind::Word if token.text == "fail" => {
+                Ok(Parsed::leaf(PredicateExpr::Fail, token.start, token.end))
+            }
+            Kind::Word if self.peek().is_some_and(|t| t.kind == Kind::LParen) => {
+                let (name, arg, operand) = self.call(token)?;
+                match self.peek() {
+                    Some(t) if is_comparison(t) || is_arith_op(t) => self.comparison(operand),
+                    _ => Ok(Parsed::leaf(PredicateExpr::Fn { name, arg }, operand.start, operand.end)),
+                }
+            }
+            Kind::Word => match self.peek() {
+                Some(t) if is_comparison(t) || is_arith_op(t) => self.comparison(token),
+                _ if token.text == "-" => self.comparison(token),
+                _ if token.text.starts_with('$') => Err(self.error_at(
+                    &token,
+                    format!("Expected a comparison after '{}'", token.text),
+                )),
+                _ => Ok(Parsed::leaf(
+                    PredicateExpr::Ref { predicate: token.text },
+                    token.start,
+                    token.end,
+                )),
+            },
+            _ => Err(self.error_at(&token, format!("Expected a condition, found '{}'", token.text))),
+        }
+    }
+
+    /// `name(arg)`, returned with the call as a single operand token
+    fn call(&mut self, name: Token) -> Result<(String, String, Token), ExprError> {
+        self.next(); // (
+        let arg = match self.next() {
+            Some(t) if matches!(t.kind, Kind::Word | Kind::Str) => t.text,
+            Some(t) if t.kind == Kind::RParen => {
+                self.pos -= 1;
+                String::new()
+            }
+            Some(t) => return Err(self.error_at(&t, format!("Expected an argument, found '{}'", t.text))),
+            None => return Err(self.error_at_end("Expected ')'".into())),
+        };
+        match self.next() {
+            Some(t) if t.kind == Kind::RParen => {
+                let operand = Token {
+                    kind: Kind::Word,
+                    text: format!("{}({})", name.text, arg),
+                    start: name.start,
+                    end: t.end,
+                };
+                Ok((name.text, arg, operand))
+            }
+            Some(t) => Err(self.error_at(&t, format!("Expected ')', found '{}'", t.text))),
+            None => Err(self.error_at_end("Expected ')'".into())),
+        }
+    }
+
+    /// `left op right`, where either side of an ordering or equality
+    /// comparison may be arithmetic
+    fn comparison(&mut self, left: Token) -> Result<Parsed, ExprError> {
+        let (start, l) = (left.start, left.text.clone());
+        let left = self.arith(Some(left))?;
+        let op = match self.next() {
+            Some(t) if is_comparison(&t) => t,
+            Some(t) => return Err(self.error_at(&t, format!("Expected a comparison, found '{}'", t.text))),
+            None => return Err(self.error_at_end("Expected a comparison".into())),
+        };
+        let right = match self.next() {
+            Some(t) if matches!(t.kind, Kind::Word | Kind::Str) => t,
+            Some(t) => return Err(self.error_at(&t, format!("Expected a value after '{}'", op.text))),
+            None => return Err(self.error_at_end(format!("Expected a value after '{}'", op.text))),
+        };
+        let r = right.text.clone();
+        let (right_start, right_end) = (right.start, right.end);
+        let right_error = |message| ExprError { message, start: right_start, end: right_end };
+        let right = self.arith(Some(right))?;
+        let end = self.tokens[self.pos - 1].end;
+
+        let plain = |arith: &Arith, text: &str| matches!(arith, Arith::Operand(operand) if operand == text);
+        if !plain(&left, &l) || !plain(&right, &r) {
+            let Some(cmp) = Comparison::from_symbol(&op.text) else {
+                return Err(self.error_at(&op, format!("'{}' does not take arithmetic operands", op.text)));
+            };
+            return Ok(Parsed::leaf(PredicateExpr::Compare { cmp, left, right }, start, end));
+        }
+
+        let value = || Value::from_str_guess(&r);
+        let expr = match op.text.as_str() {
+            "==" => PredicateExpr::Eq { left: l, right: value() },
+            "!=" => PredicateExpr::Neq { left: l, right: value() },
+            ">" => PredicateExpr::Gt { left: l, right: value() },
+            ">=" => PredicateExpr::Gte { left: l, right: value() },
+            "<" => PredicateExpr::Lt { left: l, right: value() },
+            "<=" => PredicateExpr::Lte { left: l, right: value() },
+            "before" | "after" if Value::time_from_str(&r).is_none() => {
+                return Err(right_error(format!("Expected a date, a duration or a $variable after '{}', found '{}'", op.text, r)));
+            }
+            "before" => PredicateExpr::Before { left: l, right: r },
+            "after" => PredicateExpr::After { left: l, right: r },
+            "within" => PredicateExpr::Within { left: l, duration: r },
+            "contains" => PredicateExpr::Contains { left: l, right: r },
+            "matches" => PredicateExpr::Matches { left: l, pattern: r },
+            "startsWith" | "starts_with" => PredicateExpr::StartsWith { left: l, prefix: r },
+            "in" => PredicateExpr::In { left: l, values: Value::list_from_str(&r).map_err(right_error)? },
+            "not_in" | "notIn" => PredicateExpr::NotIn { left: l, values: Value::list_from_str(&r).map_err(right_error)? },
+            _ => PredicateExpr::EndsWith { left: l, suffix: r },
+        };
+        Ok(Parsed::leaf(expr, start, end))
+    }
+
+    /// `term (+|- term)*`, continuing from `first` if the first operand
+    /// has already been read
+    fn arith(&mut self, first: Option<Token>) -> Result<Arith, ExprError> {
+        let mut left = self.term(first)?;
+        while let Some(op) = self.arith_op(&[ArithOp::Add, ArithOp::Sub]) {
+            let right = self.term(None)?;
+            left = Arith::Binary { op, left: Box::new(left), right: Box::new(right) };
+        }
+        Ok(left)
+    }
+
+    /// `factor (*|/|% factor)*`
+    fn term(&mut self, first: Option<Token>) -> Result<Arith, ExprError> {
+        let mut left = self.factor(first)?;
+        while let Some(op) = self.arith_op(&[ArithOp::Mul, ArithOp::Div, ArithOp::Mod]) {
+            let right = self.factor(None)?;
+            left = Arith::Binary { op, left: Box::new(left), right: Box::new(right) };
+        }
+        Ok(left)
+    }
+
+    /// An operand, `- factor` or `-$var`; after an operator also a
+    /// parenthesized expression or `name(arg)`
+    fn factor(&mut self, first: Option<Token>) -> Result<Arith, ExprError> {
+        let after_operator = first.is_none();
+        let Some(token) = first.or_else(|| self.next()) else {
+            return Err(self.error_at_end("Expected an operand".into()));
+        };
+
+        match token.kind {
+            Kind::Word if token.text == "-" => Ok(Arith::Neg(Box::new(self.factor(None)?))),
+            Kind::Word if token.text.starts_with("-$") => {
+                Ok(Arith::Neg(Box::new(Arith::Operand(token.text[1..].to_string()))))
+            }
+            Kind::LParen if after_operator => {
+                let inner = self.arith(None)?;
+                match self.next() {
+                    Some(t) if t.kind == Kind::RParen => Ok(inner),
+                    Some(t) => Err(self.error_at(&t, format!("Expected ')', found '{}'", t.text))),
+                    None => Err(self.error_at_end("Expected ')'".into())),
+                }
+            }
+            Kind::Word if after_operator && self.peek().is_some_and(|t| t.kind == Kind::LParen) => {
+                let (_, _, operand) = self.call(token)?;
+                Ok(Arith::Operand(operand.text))
+            }
+            Kind::Word if is_unspaced_arith(&token.text) => {
+                Err(self.error_at(&token, unspaced_arith_message(&token.text)))
+            }
+            Kind::Word if !is_arith_op(&token) => Ok(Arith::Operand(token.text)),
+            Kind::Str => Ok(Arith::Operand(token.text)),
+            _ => Err(self.error_at(&token, format!("Expected an operand, found '{}'", token.text))),
+        }
+    }
+
+    /// Consume the next token if it is one of `ops`
+    fn arith_op(&mut self, ops: &[ArithOp]) -> Option<ArithOp> {
+        let op = self.peek()
+            .filter(|t| t.kind == Kind::Word)
+            .and_then(|t| ArithOp::from_symbol(&t.text))
+            .filter(|op| ops.contains(op))?;
+        self.pos += 1;
+        Some(op)
+    }
+}
+
+fn is_arith_op(token: &Token) -> bool {
+    token.kind == Kind::Word && ArithOp::from_symbol(&token.text).is_some()
+}
+
+fn is_comparison(token: &Token) -> bool {
+    match token.kind {
+        Kind::Op => matches!(token.text.as_str(), "==" | "!=" | ">" | ">=" | "<" | "<="),
+        Kind::Word => matches!(
+            token.text.as_str(),
+            "contains" | "matches" | "startsWith" | "starts_with" | "endsWith" | "ends_with"
+                | "in" | "not_in" | "notIn" | "before" | "after" | "within"
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> PredicateExpr {
+        parse_expr(src).unwrap().0
+    }
+
+    #[test]
+    fn test_comparison() {
+        assert!(matches!(parse(r#"$token.role == "admin""#),
+            PredicateExpr::Eq { left, right: Value::Str(s) } if left == "$token.role" && s == "admin"));
+        assert!(matches!(parse("$input.count >= 10"),
+            PredicateExpr::Gte { right: Value::Int(10), .. }));
+        assert!(matches!(parse(r#"$input.name startsWith "a b""#),
+            PredicateExpr::StartsWith { prefix, .. } if prefix == "a b"));
+    }
+
+    #[test]
+    fn test_precedence_and_chains() {
+        let expr = parse("a and b and c or not d");
+        let PredicateExpr::Or { conditions } = expr else { panic!("expected or") };
+        assert!(matches!(&conditions[0], PredicateExpr::And { conditions } if conditions.len() == 3));
+        assert!(matches!(&conditions[1], PredicateExpr::Not { condition }
+            if matches!(condition.as_ref(), PredicateExpr::Ref { predicate } if predicate == "d")));
+
+        // Parentheses keep their own node
+        let expr = parse("a && (b && c)");
+        assert!(matches!(expr, PredicateExpr::And { conditions } if conditions.len() == 2));
+    }
+
+    #[test]
+    fn test_spans_are_pre_order() {
+        let src = r#"is_admin or $x == 1"#;
+        let (_, spans) = parse_expr(src).unwrap();
+        let text: Vec<_> = spans.iter().map(|&(s, e)| &src[s..e]).collect();
+        assert_eq!(text, [src, "is_admin", "$x == 1"]);
+    }
+
+    #[test]
+    fn test_fn_always_fail() {
+        assert!(matches!(parse("has_role(admin)"),
+            PredicateExpr::Fn { name, arg } if name == "has_role" && arg == "admin"));
+        assert!(matches!(parse("always"), PredicateExpr::Always));
+        assert!(matches!(parse("(fail)"), PredicateExpr::Fail));
+    }
+
+    #[test]
+    fn test_function_operand() {
+        let expr = parse("$token.role == 'admin' && len($items) > 0");
+        let PredicateExpr::And { conditions } = expr else { panic!("expected and") };
+        assert!(matches!(&conditions[1],
+            PredicateExpr::Gt { left, right: Value::Int(0) } if left == "len($items)"));
+
+        let err = parse_expr("len($items) > len($other)").unwrap_err();
+        assert_eq!((err.message.as_str(), err.start), ("Unexpected '('", 17));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let expr = parse("$input.end - $input.start < 86400");
+        let PredicateExpr::Compare { cmp: Comparison::Lt, left, right } = expr else { panic!("expected compare") };
+        assert_eq!(left.to_string(), "$input.end - $input.start");
+        assert_eq!(right, Arith::Operand("86400".into()));
+
+        // * binds tighter than +, chains are left-associative
+        let arith = parse_arith("$a + $b * 2 - -$c").unwrap();
+        assert_eq!(arith.to_string(), "$a + $b * 2 - - $c");
+        let Arith::Binary { op: ArithOp::Sub, left, .. } = &arith else { panic!("expected sub") };
+        assert!(matches!(left.as_ref(), Arith::Binary { op: ArithOp::Add, .. }));
+
+        let arith = parse_arith("($a + 1) * len($items) % 7").unwrap();
+        assert_eq!(arith.to_string(), "($a + 1) * len($items) % 7");
+        assert_eq!(parse_arith(&arith.to_string()).unwrap(), arith);
+        assert_eq!(parse_arith("$a - ($b - $c)").unwrap().to_string(), "$a - ($b - $c)");
+
+        // Without spaces an operator is part of the operand
+        assert!(matches!(parse("$region == us-east"), PredicateExpr::Eq { right: Value::Str(s), .. } if s == "us-east"));
+        assert!(matches!(parse("$input.us-east == 1"), PredicateExpr::Eq { left, .. } if left == "$input.us-east"));
+        // ... but not inside a variable, where it would name a missing path
+        let err = parse_expr("$end-$start < 86400").unwrap_err();
+        assert_eq!(err.message, "'$end-$start' reads as a single variable; put spaces around arithmetic operators");
+        assert_eq!((err.start, err.end), (0, 11));
+        for src in ["$a*2", "$a-1", "$a+$b", "$a/2", "$a%2"] {
+            assert!(parse_arith(src).is_err(), "{}", src);
+        }
+
+        let err = parse_expr("$a + 1 contains x").unwrap_err();
+        assert_eq!((err.message.as_str(), err.start), ("'contains' does not take arithmetic operands", 7));
+        let err = parse_arith("$a * ").unwrap_err();
+        assert_eq!((err.message.as_str(), err.start), ("Expected an operand", 5));
+        let err = parse_arith("$a + + 1").unwrap_err();
+        assert_eq!(err.message, "Expected an operand, found '+'");
+    }
+
+    #[test]
+    fn test_membership() {
+        assert!(matches!(parse("$deal.stage in won,lost,closed"),
+            PredicateExpr::In { left, values } if left == "$deal.stage" && values.len() == 3));
+        assert!(matches!(parse(r#"$http.status not_in "500, 502""#),
+            PredicateExpr::NotIn { values, .. } if values == [Value::Int(500), Value::Int(502)]));
+
+        let err = parse_expr("$x in a,,b").unwrap_err();
+        assert_eq!((err.message.as_str(), err.start, err.end), ("Empty member in list 'a,,b'", 6, 10));
+    }
+
+    #[test]
+    fn test_time_comparison() {
+        assert!(matches!(parse("$deal.updated before -P30D"),
+            PredicateExpr::Before { left, right } if left == "$deal.updated" && right == "-P30D"));
+        assert!(matches!(parse("$task.done after $task.due"),
+            PredicateExpr::After { right, .. } if right == "$task.due"));
+        assert!(matches!(parse("$ticket.priority == P1"),
+            PredicateExpr::Eq { right: Value::Str(s), .. } if s == "P1"));
+
+        let err = parse_expr("$deal.created after tomorrow").unwrap_err();
+        assert_eq!(err.message, "Expected a date, a duration or a $variable after 'after', found 'tomorrow'");
+        assert_eq!((err.start, err.end), (20, 28));
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let err = parse_expr("$token.role == ").unwrap_err();
+        assert_eq!(err.message, "Expected a value after '=='");
+        assert_eq!((err.start, err.end), (15, 15));
+
+        let err = parse_expr("$token.role admin").unwrap_err();
+        assert_eq!(err.message, "Expected a comparison after '$token.role'");
+        assert_eq!((err.start, err.end), (0, 11));
+
+        let err = parse_expr("(a or b").unwrap_err();
+        assert_eq!(err.message, "Expected ')'");
+
+        let err = parse_expr("a = b").unwrap_err();
+        assert_eq!((err.message.as_str(), err.start), ("Unexpected '='", 2));
+
+        let err = parse_expr(r#"$x == "open"#).unwrap_err();
+        assert_eq!(err.message, "Unterminated string");
+    }
+}
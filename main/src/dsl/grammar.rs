@@ -0,0 +1,288 @@
+//! Published Grammar
+//!
+//! Schemas for editors and external tools, behind `pxyz schema`:
+//!
+//! - `xsd()`: XML Schema for workflow XML, generated from the element
+//!   table in `vocabulary`, so it accepts exactly the elements and
+//!   attributes the parser reads
+//! - `json_schema()`: JSON Schema (draft 2020-12) for the JSON form of the
+//!   AST documented in `ast`
+//!
+//! Both are checked against the workflow fixtures in
+//! `tests/pxyz_schema.rs`.
+
+use super::ast::Comparison;
+use super::vocabulary::{self, ElementSpec, ANY_ATTRIBUTE, ELEMENTS};
+use super::version::DSL_VERSION;
+use serde_json::{json, Value};
+use std::fmt::Write;
+
+const XS_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema";
+
+/// XML Schema for workflow XML
+///
+/// Every vocabulary entry becomes a named complex type. Attribute values
+/// are plain strings: kinds, numbers and booleans are still checked by
+/// the parser (PARSE004).
+pub fn xsd() -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(out, "<!-- PXYZ workflow DSL {}: generated by `pxyz schema`, do not edit -->", DSL_VERSION);
+    let _ = writeln!(out, "<xs:schema xmlns:xs=\"{}\">", XS_NAMESPACE);
+
+    for spec in ELEMENTS.iter().filter(|e| e.parents.contains(&"")) {
+        let _ = writeln!(out, "  <xs:element name=\"{}\" type=\"{}\"/>", spec.name, type_name(spec));
+    }
+
+    for spec in ELEMENTS {
+        let mixed = if spec.text { " mixed=\"true\"" } else { "" };
+        let _ = writeln!(out, "  <xs:complexType name=\"{}\"{}>", type_name(spec), mixed);
+
+        let children: Vec<_> = vocabulary::children_of(spec.name)
+            .filter_map(|child| vocabulary::lookup(spec.name, child))
+            .collect();
+        if !children.is_empty() {
+            out.push_str("    <xs:choice minOccurs=\"0\" maxOccurs=\"unbounded\">\n");
+            for child in children {
+                let _ = writeln!(out, "      <xs:element name=\"{}\" type=\"{}\"/>", child.name, type_name(child));
+            }
+            out.push_str("    </xs:choice>\n");
+        }
+
+        for attr in spec.attributes.iter().filter(|a| **a != ANY_ATTRIBUTE) {
+            let usage = if spec.required.contains(attr) { " use=\"required\"" } else { "" };
+            let _ = writeln!(out, "    <xs:attribute name=\"{}\" type=\"xs:string\"{}/>", attr, usage);
+        }
+        if spec.attributes.contains(&ANY_ATTRIBUTE) {
+            out.push_str("    <xs:anyAttribute processContents=\"skip\"/>\n");
+        }
+        out.push_str("  </xs:complexType>\n");
+    }
+
+    out.push_str("</xs:schema>\n");
+    out
+}
+
+/// Type name for a vocabulary entry: the element name, qualified by its
+/// parent when the name means different things in different places
+/// (`templates.template`, `node.template`)
+fn type_name(spec: &ElementSpec) -> String {
+    if ELEMENTS.iter().filter(|e| e.name == spec.name).count() > 1 {
+        format!("{}.{}", spec.parents[0], spec.name)
+    } else {
+        spec.name.to_string()
+    }
+}
+
+/// JSON Schema for the JSON form of `OmarDocument`
+pub fn json_schema() -> Value {
+    let string = json!({ "type": "string" });
+    let optional_string = json!({ "type": ["string", "null"] });
+    let optional_u16 = json!({ "type": ["integer", "null"], "minimum": 0, "maximum": u16::MAX });
+    let boolean = json!({ "type": "boolean" });
+    let pairs = json!({
+        "type": "array",
+        "items": { "type": "array", "items": string, "minItems": 2, "maxItems": 2 }
+    });
+    let array_of = |def: &str| json!({ "type": "array", "items": { "$ref": format!("#/$defs/{}", def) } });
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": format!("PXYZ workflow document (DSL {})", DSL_VERSION),
+        "$ref": "#/$defs/document",
+        "$defs": {
+            "document": object(&[], json!({
+                "version": string,
+                "imports": array_of("import"),
+                "schemas": array_of("schema"),
+                "predicates": array_of("predicate_def"),
+                "workflows": array_of("workflow"),
+                "templates": array_of("template"),
+                "fragments": array_of("fragment"),
+                "merge_policies": array_of("entity_merge"),
+            })),
+            "import": object(&["href"], json!({ "href": string, "namespace": optional_string })),
+            "schema": object(&["name"], json!({ "name": string, "fields": array_of("field_def") })),
+            "field_def": object(&["name"], json!({
+                "name": string,
+                "field_type": string,
+                "required": boolean,
+                "default": optional_string,
+                "pattern": optional_string,
+            })),
+            "predicate_def": object(&["id", "expr"], json!({
+                "id": string,
+                "expr": { "$ref": "#/$defs/predicate" },
+            })),
+            "predicate": { "oneOf": predicate_ops() },
+            "value": { "type": ["integer", "number", "boolean", "string"] },
+            "workflow": object(&["id", "entry"], json!({
+                "id": string,
+                "description": optional_string,
+                "schema": optional_string,
+                "entry": { "$ref": "#/$defs/entry" },
+                "nodes": array_of("node"),
+                "edges": array_of("edge"),
+                "uses": array_of("use"),
+            })),
+            "fragment": object(&["id"], json!({
+                "id": string,
+                "params": { "type": "array", "items": string },
+                "nodes": array_of("node"),
+                "edges": array_of("edge"),
+                "uses": array_of("use"),
+            })),
+            "use": object(&["fragment", "id"], json!({ "fragment": string, "id": string, "args": pairs })),
+            "entry": object(&["p", "x", "node"], json!({ "p": string, "x": string, "node": string })),
+            "node": object(&["id"], json!({
+                "id": string,
+                "kind": { "enum": ["transform", "external", "render", "signal", "auth", "terminal", "error", "call"] },
+                "op": optional_string,
+                "template": optional_string,
+                "schema": optional_string,
+                "predicate": optional_string,
+                "selector": optional_string,
+                "status": optional_u16,
+                "message": optional_string,
+                "signals": pairs,
+                "actor": optional_string,
+                "confirmation": optional_string,
+                "async": boolean,
+                "cacheable": boolean,
+                "data": { "type": "object", "additionalProperties": string },
+                "call": optional_string,
+            })),
+            "edge": object(&["from", "to"], json!({
+                "from": string,
+                "to": string,
+                "predicate": { "oneOf": [{ "type": "null" }, { "$ref": "#/$defs/predicate" }] },
+                "predicate_ref": optional_string,
+                "weight": optional_u16,
+                "parallel": boolean,
+                "fallback": boolean,
+            })),
+            "template": object(&["id", "content"], json!({ "id": string, "content": string })),
+            "merge_policy": { "oneOf": merge_policies() },
+            "entity_merge": object(&["entity"], json!({
+                "entity": string,
+                "default_policy": { "$ref": "#/$defs/merge_policy" },
+                "fields": array_of("field_merge"),
+                "pre_condition": optional_string,
+                "post_validate": optional_string,
+            })),
+            "field_merge": object(&["field", "policy"], json!({
+                "field": string,
+                "policy": { "$ref": "#/$defs/merge_policy" },
+                "validate": optional_string,
+            })),
+        }
+    })
+}
+
+/// A closed object: unknown keys are rejected, as serde does
+fn object(required: &[&str], properties: Value) -> Value {
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// One closed object per variant of an internally tagged enum
+fn tagged(tag: &str, variant: &str, fields: &[(&str, Value)]) -> Value {
+    let mut properties = serde_json::Map::new();
+    properties.insert(tag.into(), json!({ "const": variant }));
+    let mut required = vec![tag];
+    for (name, schema) in fields {
+        properties.insert((*name).into(), schema.clone());
+        required.push(name);
+    }
+    object(&required, Value::Object(properties))
+}
+
+fn predicate_ops() -> Vec<Value> {
+    let string = json!({ "type": "string" });
+    let value = json!({ "$ref": "#/$defs/value" });
+    let predicate = json!({ "$ref": "#/$defs/predicate" });
+    let conditions = json!({ "type": "array", "items": predicate });
+    let compare = |op| tagged("op", op, &[("left", string.clone()), ("right", value.clone())]);
+    let values = json!({ "type": "array", "items": value.clone() });
+    let text = |op, arg| tagged("op", op, &[("left", string.clone()), (arg, string.clone())]);
+    // Infix text (`$input.end - $input.start`) or a plain number
+    let arith = json!({ "type": ["string", "number"] });
+
+    vec![
+        tagged("op", "always", &[]),
+        tagged("op", "fail", &[]),
+        compare("eq"),
+        compare("neq"),
+        compare("gt"),
+        compare("gte"),
+        compare("lt"),
+        compare("lte"),
+        text("contains", "right"),
+        text("matches", "pattern"),
+        text("starts_with", "prefix"),
+        text("ends_with", "suffix"),
+        tagged("op", "in", &[("left", string.clone()), ("values", values.clone())]),
+        tagged("op", "not_in", &[("left", string.clone()), ("values", values)]),
+        text("before", "right"),
+        text("after", "right"),
+        tagged("op", "within", &[("left", string.clone()), ("duration", string.clone())]),
+        tagged("op", "and", &[("conditions", conditions.clone())]),
+        tagged("op", "or", &[("conditions", conditions)]),
+        tagged("op", "not", &[("condition", predicate)]),
+        tagged("op", "ref", &[("predicate", string.clone())]),
+        tagged("op", "fn", &[("name", string.clone()), ("arg", string)]),
+        tagged("op", "compare", &[
+            ("cmp", json!({ "enum": Comparison::ALL.map(Comparison::name) })),
+            ("left", arith.clone()),
+            ("right", arith),
+        ]),
+    ]
+}
+
+fn merge_policies() -> Vec<Value> {
+    let string = json!({ "type": "string" });
+    let mut policies: Vec<Value> = ["lww", "fww", "vclock", "max", "min", "union", "intersect", "human_review"]
+        .into_iter()
+        .map(|policy| tagged("policy", policy, &[]))
+        .collect();
+    policies.push(tagged("policy", "prefer_origin", &[("actor", string.clone())]));
+    policies.push(tagged("policy", "custom", &[("predicate", string)]));
+    policies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xsd_types_are_unique() {
+        let names: Vec<_> = ELEMENTS.iter().map(type_name).collect();
+        for (i, name) in names.iter().enumerate() {
+            assert!(!names[..i].contains(name), "duplicate type {}", name);
+        }
+        assert!(names.contains(&"node.template".to_string()));
+        assert!(names.contains(&"templates.template".to_string()));
+    }
+
+    #[test]
+    fn test_xsd_reflects_vocabulary() {
+        let xsd = xsd();
+        assert!(xsd.contains(r#"<xs:element name="omar" type="omar"/>"#));
+        assert!(xsd.contains(r#"<xs:attribute name="from" type="xs:string" use="required"/>"#));
+        assert!(xsd.contains(r#"<xs:complexType name="templates.template" mixed="true">"#));
+        assert!(xsd.contains(r#"<xs:anyAttribute processContents="skip"/>"#));
+    }
+
+    #[test]
+    fn test_json_schema_lists_every_op() {
+        let schema = json_schema();
+        let ops = schema["$defs"]["predicate"]["oneOf"].as_array().unwrap();
+        assert_eq!(ops.len(), 23);
+        let policies = schema["$defs"]["merge_policy"]["oneOf"].as_array().unwrap();
+        assert_eq!(policies.len(), 10);
+    }
+}
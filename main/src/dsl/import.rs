@@ -0,0 +1,497 @@
+//! Multi-file Documents
+//!
+//! Resolves `<import href="..."/>` elements into a single `OmarDocument`.
+//! Each file is read with the front-end for its extension (see
+//! `parse_source`), so XML and `.pxyz` files may import each other.
+//! Paths are relative to the importing file and are loaded through a
+//! `SourceResolver`, so callers decide where sources come from (disk,
+//! memory, a bundle).
+//!
+//! Predicates, schemas and fragments of an imported file are renamed to
+//! `namespace.id`, together with every reference to them inside that file,
+//! so two libraries may both define `is_admin`. Imported workflows,
+//! templates and merge policies are merged as they are.
+//!
+//! Imported definitions lose their source spans: spans are offsets into a
+//! single file and would point into the wrong text once merged.
+//!
+//! Diagnostic codes:
+//! - IMP001: Import cannot be loaded
+//! - IMP002: Import cycle
+//! - IMP003: Namespace used by two imports
+
+use super::ast::*;
+use super::parser::ParseOptions;
+use super::parse_source;
+use crate::{Diagnostic, Location, Severity, Span};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+/// Loads the source text of an imported document
+pub trait SourceResolver {
+    /// Load the document at `path` (already joined to the importing file's
+    /// directory and normalized)
+    fn load(&self, path: &Path) -> std::io::Result<String>;
+}
+
+/// Loads imports from the file system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileResolver;
+
+impl SourceResolver for FileResolver {
+    fn load(&self, path: &Path) -> std::io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+}
+
+/// Loads imports from an in-memory map of path → source
+#[derive(Debug, Clone, Default)]
+pub struct MemoryResolver {
+    files: HashMap<PathBuf, String>,
+}
+
+impl MemoryResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file (builder style)
+    pub fn with_file(mut self, path: impl AsRef<Path>, source: impl Into<String>) -> Self {
+        self.insert(path, source);
+        self
+    }
+
+    pub fn insert(&mut self, path: impl AsRef<Path>, source: impl Into<String>) {
+        self.files.insert(normalize(path.as_ref()), source.into());
+    }
+}
+
+impl SourceResolver for MemoryResolver {
+    fn load(&self, path: &Path) -> std::io::Result<String> {
+        self.files.get(path).cloned().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} not found", path.display()))
+        })
+    }
+}
+
+/// A document with all of its imports merged in
+#[derive(Debug, Clone)]
+pub struct ResolvedDocument {
+    pub document: OmarDocument,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Every source that was read, root first, in load order
+    pub sources: Vec<(PathBuf, String)>,
+}
+
+/// Parse `xml` (located at `path`) and resolve its imports
+pub fn parse_with_imports(
+    xml: &str,
+    path: &Path,
+    resolver: &dyn SourceResolver,
+    options: &ParseOptions,
+) -> ResolvedDocument {
+    let mut loader = Loader {
+        resolver,
+        options,
+        stack: Vec::new(),
+        sources: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    let document = loader.load(&normalize(path), xml);
+
+    ResolvedDocument {
+        document,
+        diagnostics: loader.diagnostics,
+        sources: loader.sources,
+    }
+}
+
+struct Loader<'a> {
+    resolver: &'a dyn SourceResolver,
+    options: &'a ParseOptions,
+    /// Files currently being loaded, for cycle detection
+    stack: Vec<PathBuf>,
+    sources: Vec<(PathBuf, String)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Loader<'_> {
+    fn load(&mut self, path: &Path, xml: &str) -> OmarDocument {
+        self.stack.push(path.to_path_buf());
+        self.sources.push((path.to_path_buf(), xml.to_string()));
+
+        let (mut doc, diags) = parse_source(path, xml, self.options);
+        for diag in diags {
+            self.report(diag);
+        }
+
+        let mut namespaces = HashSet::new();
+        for import in doc.imports.clone() {
+            let namespace = import.namespace.clone().unwrap_or_else(|| {
+                Path::new(&import.href)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+            if !namespaces.insert(namespace.clone()) {
+                self.error(
+                    "IMP003",
+                    format!("Namespace '{}' is used by more than one import", namespace),
+                    Some("Give one of the imports a different name with as=\"...\"".into()),
+                    import.span,
+                );
+                continue;
+            }
+
+            let target = normalize(&path.parent().unwrap_or(Path::new("")).join(&import.href));
+
+            if let Some(pos) = self.stack.iter().position(|p| *p == target) {
+                let cycle: Vec<_> = self.stack[pos..].iter()
+                    .chain(std::iter::once(&target))
+                    .map(|p| p.display().to_string())
+                    .collect();
+                self.error(
+                    "IMP002",
+                    format!("Import cycle: {}", cycle.join(" → ")),
+                    None,
+                    import.span,
+                );
+                continue;
+            }
+
+            let source = match self.resolver.load(&target) {
+                Ok(source) => source,
+                Err(e) => {
+                    self.error(
+                        "IMP001",
+                        format!("Cannot import '{}': {}", import.href, e),
+                        None,
+                        import.span,
+                    );
+                    continue;
+                }
+            };
+
+            let mut imported = self.load(&target, &source);
+            apply_namespace(&mut imported, &namespace);
+            forget_spans(&mut imported);
+            merge(&mut doc, imported);
+        }
+
+        self.stack.pop();
+        doc
+    }
+
+    fn error(&mut self, code: &str, message: String, hint: Option<String>, span: Option<Span>) {
+        self.report(Diagnostic {
+            severity: Severity::Error,
+            code: code.into(),
+            message,
+            hint,
+            location: Some(Location {
+                span,
+                ..Default::default()
+            }),
+        });
+    }
+
+    /// Record a diagnostic, attributing it to the current file if that is
+    /// not the root document
+    fn report(&mut self, mut diag: Diagnostic) {
+        if self.stack.len() > 1 {
+            if let Some(location) = diag.location.as_mut() {
+                location.file = self.stack.last().map(|p| p.display().to_string());
+            }
+        }
+        self.diagnostics.push(diag);
+    }
+}
+
+/// Prefix the document's own predicate, schema and fragment ids (and
+/// references to them) with `namespace.`
+fn apply_namespace(doc: &mut OmarDocument, namespace: &str) {
+    let predicates: HashSet<String> = doc.predicates.iter().map(|p| p.id.clone()).collect();
+    let schemas: HashSet<String> = doc.schemas.iter().map(|s| s.name.clone()).collect();
+    let fragments: HashSet<String> = doc.fragments.iter().map(|f| f.id.clone()).collect();
+
+    let pred = |id: &mut String| {
+        if predicates.contains(id.as_str()) {
+            *id = format!("{}.{}", namespace, id);
+        }
+    };
+    let schema = |id: &mut String| {
+        if schemas.contains(id.as_str()) {
+            *id = format!("{}.{}", namespace, id);
+        }
+    };
+    let fragment = |id: &mut String| {
+        if fragments.contains(id.as_str()) {
+            *id = format!("{}.{}", namespace, id);
+        }
+    };
+
+    for def in &mut doc.predicates {
+        pred(&mut def.id);
+        rename_refs(&mut def.expr, &pred);
+    }
+    for def in &mut doc.schemas {
+        schema(&mut def.name);
+    }
+    let graphs = doc.workflows.iter_mut()
+        .map(|w| {
+            w.schema.as_mut().map(schema);
+            (&mut w.nodes, &mut w.edges, &mut w.uses)
+        })
+        .chain(doc.fragments.iter_mut().map(|f| {
+            fragment(&mut f.id);
+            (&mut f.nodes, &mut f.edges, &mut f.uses)
+        }));
+    for (nodes, edges, uses) in graphs {
+        for node in nodes {
+            node.predicate.as_mut().map(pred);
+            node.schema.as_mut().map(schema);
+        }
+        for edge in edges {
+            edge.predicate_ref.as_mut().map(pred);
+            if let Some(expr) = edge.predicate.as_mut() {
+                rename_refs(expr, &pred);
+            }
+        }
+        for fragment_use in uses {
+            fragment(&mut fragment_use.fragment);
+        }
+    }
+    for entity in &mut doc.merge_policies {
+        schema(&mut entity.entity);
+        entity.pre_condition.as_mut().map(pred);
+        entity.post_validate.as_mut().map(pred);
+        for field in &mut entity.fields {
+            field.validate.as_mut().map(pred);
+            if let MergePolicy::Custom { predicate } = &mut field.policy {
+                pred(predicate);
+            }
+        }
+    }
+}
+
+fn rename_refs(expr: &mut PredicateExpr, rename: &impl Fn(&mut String)) {
+    match expr {
+        PredicateExpr::Ref { predicate } => rename(predicate),
+        PredicateExpr::And { conditions } | PredicateExpr::Or { conditions } => {
+            for condition in conditions {
+                rename_refs(condition, rename);
+            }
+        }
+        PredicateExpr::Not { condition } => rename_refs(condition, rename),
+        _ => {}
+    }
+}
+
+fn forget_spans(doc: &mut OmarDocument) {
+    for import in &mut doc.imports {
+        import.span = None;
+    }
+    for schema in &mut doc.schemas {
+        schema.span = None;
+        for field in &mut schema.fields {
+            field.span = None;
+        }
+    }
+    for def in &mut doc.predicates {
+        def.span = None;
+        def.expr_spans.clear();
+    }
+    for workflow in &mut doc.workflows {
+        workflow.span = None;
+        workflow.entry.span = None;
+        for node in &mut workflow.nodes {
+            node.span = None;
+        }
+        for edge in &mut workflow.edges {
+            edge.span = None;
+            edge.predicate_spans.clear();
+        }
+        for fragment_use in &mut workflow.uses {
+            fragment_use.span = None;
+        }
+    }
+    for template in &mut doc.templates {
+        template.span = None;
+    }
+    for fragment in &mut doc.fragments {
+        fragment.span = None;
+        for node in &mut fragment.nodes {
+            node.span = None;
+        }
+        for edge in &mut fragment.edges {
+            edge.span = None;
+            edge.predicate_spans.clear();
+        }
+        for fragment_use in &mut fragment.uses {
+            fragment_use.span = None;
+        }
+    }
+    for entity in &mut doc.merge_policies {
+        entity.span = None;
+        for field in &mut entity.fields {
+            field.span = None;
+        }
+    }
+}
+
+/// Merge imported definitions ahead of the importing document's own
+fn merge(doc: &mut OmarDocument, imported: OmarDocument) {
+    fn prepend<T>(target: &mut Vec<T>, mut items: Vec<T>) {
+        items.append(target);
+        *target = items;
+    }
+
+    prepend(&mut doc.schemas, imported.schemas);
+    prepend(&mut doc.predicates, imported.predicates);
+    prepend(&mut doc.workflows, imported.workflows);
+    prepend(&mut doc.templates, imported.templates);
+    prepend(&mut doc.fragments, imported.fragments);
+    prepend(&mut doc.merge_policies, imported.merge_policies);
+}
+
+/// Lexically normalize a path (resolve `.` and `..` without touching the
+/// file system) so the same file always has the same key
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match out.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                _ => out.push(".."),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUTH: &str = r#"
+        <omar version="1.0.0">
+            <predicates>
+                <predicate id="is_admin"><eq left="$token.role" right="admin"/></predicate>
+                <predicate id="can_edit"><or><ref predicate="is_admin"/><eq left="$token.role" right="editor"/></or></predicate>
+            </predicates>
+        </omar>
+    "#;
+
+    fn resolve(root: &str, resolver: &MemoryResolver) -> ResolvedDocument {
+        parse_with_imports(root, Path::new("app/main.xml"), resolver, &ParseOptions::default())
+    }
+
+    #[test]
+    fn test_import_namespaces_predicates() {
+        let resolver = MemoryResolver::new().with_file("app/lib/auth.xml", AUTH);
+        let root = r#"
+            <omar version="1.0.0">
+                <import href="lib/auth.xml"/>
+                <predicates>
+                    <predicate id="is_admin"><eq left="$token.sub" right="root"/></predicate>
+                </predicates>
+            </omar>
+        "#;
+
+        let resolved = resolve(root, &resolver);
+        assert!(resolved.diagnostics.is_empty(), "{:?}", resolved.diagnostics);
+
+        let ids: Vec<_> = resolved.document.predicates.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, ["auth.is_admin", "auth.can_edit", "is_admin"]);
+
+        // Internal reference follows the rename
+        let can_edit = &resolved.document.predicates[1];
+        assert!(matches!(&can_edit.expr, PredicateExpr::Or { conditions }
+            if matches!(&conditions[0], PredicateExpr::Ref { predicate } if predicate == "auth.is_admin")));
+        assert!(can_edit.span.is_none());
+
+        assert_eq!(resolved.sources.len(), 2);
+    }
+
+    #[test]
+    fn test_import_explicit_namespace_and_nesting() {
+        let resolver = MemoryResolver::new()
+            .with_file("app/lib/auth.xml", AUTH)
+            .with_file("app/lib/roles.xml", r#"
+                <omar version="1.0.0">
+                    <import href="./auth.xml" as="a"/>
+                    <predicates>
+                        <predicate id="staff"><ref predicate="a.can_edit"/></predicate>
+                    </predicates>
+                </omar>
+            "#);
+        let root = r#"<omar version="1.0.0"><import href="lib/roles.xml" as="r"/></omar>"#;
+
+        let resolved = resolve(root, &resolver);
+        assert!(resolved.diagnostics.is_empty(), "{:?}", resolved.diagnostics);
+
+        let ids: Vec<_> = resolved.document.predicates.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, ["r.a.is_admin", "r.a.can_edit", "r.staff"]);
+        assert!(matches!(&resolved.document.predicates[2].expr,
+            PredicateExpr::Ref { predicate } if predicate == "r.a.can_edit"));
+    }
+
+    #[test]
+    fn test_import_namespaces_fragments() {
+        let resolver = MemoryResolver::new().with_file("app/patterns.xml", r#"
+            <omar version="1.0.0">
+                <fragment id="checked"><nodes><use fragment="inner" id="i"/></nodes></fragment>
+                <fragment id="inner"><nodes><node id="n"/></nodes></fragment>
+            </omar>
+        "#);
+        let root = r#"<omar version="1.0.0"><import href="patterns.xml"/></omar>"#;
+
+        let resolved = resolve(root, &resolver);
+        let fragments = &resolved.document.fragments;
+        assert_eq!(fragments[0].id, "patterns.checked");
+        assert_eq!(fragments[0].uses[0].fragment, "patterns.inner");
+        assert_eq!(fragments[1].nodes[0].id, "n");
+    }
+
+    #[test]
+    fn test_import_cycle_detected() {
+        let resolver = MemoryResolver::new()
+            .with_file("app/a.xml", r#"<omar version="1.0.0"><import href="b.xml"/></omar>"#)
+            .with_file("app/b.xml", r#"<omar version="1.0.0"><import href="a.xml"/></omar>"#);
+        let root = r#"<omar version="1.0.0"><import href="a.xml"/></omar>"#;
+
+        let resolved = resolve(root, &resolver);
+        assert_eq!(resolved.diagnostics.len(), 1);
+        let diag = &resolved.diagnostics[0];
+        assert_eq!(diag.code, "IMP002");
+        assert_eq!(diag.message, "Import cycle: app/a.xml → app/b.xml → app/a.xml");
+        assert_eq!(diag.location.as_ref().unwrap().file.as_deref(), Some("app/b.xml"));
+    }
+
+    #[test]
+    fn test_import_missing_and_duplicate_namespace() {
+        let resolver = MemoryResolver::new().with_file("app/auth.xml", AUTH);
+        let root = r#"
+            <omar version="1.0.0">
+                <import href="auth.xml"/>
+                <import href="other/auth.xml"/>
+                <import href="nowhere.xml"/>
+            </omar>
+        "#;
+
+        let resolved = resolve(root, &resolver);
+        let codes: Vec<_> = resolved.diagnostics.iter().map(|d| d.code.as_str()).collect();
+        assert_eq!(codes, ["IMP003", "IMP001"]);
+        assert!(resolved.diagnostics[0].location.as_ref().unwrap().span.is_some());
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("a/./b/../c.xml")), PathBuf::from("a/c.xml"));
+        assert_eq!(normalize(Path::new("../x.xml")), PathBuf::from("../x.xml"));
+    }
+}
@@ -0,0 +1,159 @@
+//! JSON Front-end
+//!
+//! Reads the serde form of `OmarDocument`, whose shape is documented in
+//! `ast`. Files ending in `.json` are read with this front-end, so tools
+//! that generate workflows can skip producing XML.
+//!
+//! Unlike the XML and text parsers there is no partial recovery: a
+//! document that is not valid JSON, or does not fit the shape (unknown
+//! key, wrong type, missing required field), yields an empty document and
+//! one PARSE001 diagnostic at the offending position. A missing
+//! `"version"` means the current DSL version.
+
+use super::ast::OmarDocument;
+use super::parser::{error, LineIndex};
+use super::version::{check_version, DSL_VERSION};
+use crate::{CompileError, Diagnostic, Severity};
+
+/// Parse JSON to AST
+///
+/// Fails with `CompileError::Validation` carrying the parse diagnostic.
+pub fn parse(src: &str) -> Result<OmarDocument, CompileError> {
+    let (doc, diagnostics) = parse_recovering(src);
+
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return Err(CompileError::Validation { diagnostics });
+    }
+
+    Ok(doc)
+}
+
+/// Parse JSON to AST, reporting a failure as a diagnostic
+pub fn parse_recovering(src: &str) -> (OmarDocument, Vec<Diagnostic>) {
+    match serde_json::from_str(src) {
+        Ok(doc) => {
+            let mut doc: OmarDocument = doc;
+            if doc.version.is_empty() {
+                doc.version = DSL_VERSION.into();
+            }
+            let mut diags = Vec::new();
+            check_version(&doc.version, None, &mut diags);
+            (doc, diags)
+        }
+        Err(e) => {
+            let offset = offset_of(src, e.line(), e.column());
+            let mut diags = Vec::new();
+            error(
+                &mut diags,
+                "PARSE001",
+                format!("Invalid workflow JSON: {}", without_position(&e)),
+                Some("See the JSON shape documented in dsl::ast".into()),
+                LineIndex::new(src).span(offset, offset),
+            );
+            (OmarDocument::default(), diags)
+        }
+    }
+}
+
+/// Render a document as pretty-printed JSON
+pub fn to_json(doc: &OmarDocument) -> String {
+    serde_json::to_string_pretty(doc).expect("AST serialization cannot fail")
+}
+
+/// Byte offset of serde_json's 1-based line and byte column
+fn offset_of(src: &str, line: usize, column: usize) -> usize {
+    let line_start: usize = src.split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    (line_start + column.saturating_sub(1)).min(src.len())
+}
+
+/// serde_json's message without its trailing " at line L column C"
+fn without_position(e: &serde_json::Error) -> String {
+    let message = e.to_string();
+    match message.rfind(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsl::ast::*;
+    use crate::dsl::writer::to_xml;
+
+    const XML: &str = r#"<omar version="1.0.0">
+        <predicates>
+            <predicate id="is_admin"><eq left="$token.role" right="admin"/></predicate>
+        </predicates>
+        <workflow id="orders">
+            <entry p="orders" x="create" node="start"/>
+            <nodes>
+                <node id="start" kind="auth" async="true"><require predicate="is_admin"/></node>
+                <node id="done" kind="terminal" status="200"><set signal="saved" value="true"/></node>
+            </nodes>
+            <edges>
+                <edge from="start" to="done" weight="2">
+                    <when><and><gt left="$input.total" right="1.5"/><fn name="defined" arg="$input.id"/><after left="$input.created" right="-P7D"/><compare left="$input.total - $input.discount" cmp="gte" right="0"/></and></when>
+                </edge>
+            </edges>
+        </workflow>
+        <merge>
+            <entity name="Order" default="max">
+                <field name="owner" prefer_origin="admin"/>
+            </entity>
+        </merge>
+    </omar>"#;
+
+    #[test]
+    fn test_round_trip() {
+        let doc = crate::dsl::parse(XML).unwrap();
+        let json = to_json(&doc);
+        let back = parse(&json).unwrap();
+        assert_eq!(to_xml(&back), to_xml(&doc));
+    }
+
+    #[test]
+    fn test_shape() {
+        let doc = parse(r#"{
+            "workflows": [{
+                "id": "w",
+                "entry": { "p": "w", "x": "run", "node": "a" },
+                "nodes": [{ "id": "a", "async": true }, { "id": "b", "kind": "terminal", "status": 200 }],
+                "edges": [{
+                    "from": "a", "to": "b",
+                    "predicate": { "op": "or", "conditions": [
+                        { "op": "eq", "left": "$x", "right": 5 },
+                        { "op": "neq", "left": "$y", "right": "$z" },
+                        { "op": "compare", "cmp": "lt", "left": "$end - $start", "right": 86400 }
+                    ]}
+                }]
+            }],
+            "merge_policies": [{ "entity": "Order", "default_policy": { "policy": "lww" },
+                "fields": [{ "field": "owner", "policy": { "policy": "prefer_origin", "actor": "admin" } }] }]
+        }"#).unwrap();
+
+        let node = &doc.workflows[0].nodes[0];
+        assert_eq!(node.kind, "transform");
+        assert!(node.async_node);
+        let Some(PredicateExpr::Or { conditions }) = &doc.workflows[0].edges[0].predicate else {
+            panic!("expected or");
+        };
+        assert!(matches!(&conditions[0], PredicateExpr::Eq { right: Value::Int(5), .. }));
+        assert!(matches!(&conditions[1], PredicateExpr::Neq { right: Value::Var(v), .. } if v == "$z"));
+        assert!(matches!(&conditions[2], PredicateExpr::Compare { cmp: Comparison::Lt, right: Arith::Operand(n), .. } if n == "86400"));
+        assert!(matches!(&doc.merge_policies[0].fields[0].policy, MergePolicy::PreferOrigin { actor } if actor == "admin"));
+    }
+
+    #[test]
+    fn test_errors_have_positions() {
+        let src = "{\n  \"workflows\": [{\n    \"id\": \"w\",\n    \"wieght\": 1\n  }]\n}";
+        let (_, diags) = parse_recovering(src);
+        assert_eq!(diags[0].code, "PARSE001");
+        assert!(diags[0].message.contains("unknown field `wieght`"), "{}", diags[0].message);
+        let span = diags[0].location.as_ref().unwrap().span.unwrap();
+        assert_eq!(span.line, 4);
+    }
+}
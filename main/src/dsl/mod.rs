@@ -1,7 +1,39 @@
-//! DSL Module - XML parsing
+//! DSL Module - XML, text and JSON front-ends
 
 pub mod ast;
 pub mod parser;
+pub mod vocabulary;
+pub mod grammar;
+pub mod expr;
+pub mod text;
+pub mod json;
+pub mod import;
+pub mod writer;
+pub mod version;
+pub mod profile;
+pub mod time;
 
 pub use ast::*;
-pub use parser::parse;
+pub use parser::{parse, parse_recovering, parse_with_options, ParseOptions};
+pub use profile::Profile;
+pub use version::{migrate, Migration, Version, DSL_VERSION};
+pub use import::{parse_with_imports, FileResolver, MemoryResolver, ResolvedDocument, SourceResolver};
+
+use crate::Diagnostic;
+use std::path::Path;
+
+/// File extension read by the text front-end
+pub const TEXT_EXTENSION: &str = "pxyz";
+
+/// File extension read by the JSON front-end
+pub const JSON_EXTENSION: &str = "json";
+
+/// Recovering parse with the front-end chosen by `path`'s extension
+/// (`.pxyz` text, `.json`, anything else XML)
+pub fn parse_source(path: &Path, source: &str, options: &ParseOptions) -> (OmarDocument, Vec<Diagnostic>) {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(TEXT_EXTENSION) => text::parse_with_options(source, options),
+        Some(JSON_EXTENSION) => json::parse_recovering(source),
+        _ => parser::parse_with_options(source, options),
+    }
+}
@@ -1,610 +1,995 @@
 //! XML Parser
+//!
+//! Parsing runs in two phases:
+//! 1. XML text → element tree, recording the source span of every element
+//! 2. Element tree → AST
+//!
+//! Both phases recover from errors: problems are collected as diagnostics
+//! and the offending element is skipped, so a single pass reports every
+//! problem and still yields a partial document.
+//!
+//! Diagnostic codes:
+//! - PARSE001: Malformed XML (syntax error, bad attribute)
+//! - PARSE002: Mismatched or unclosed tag
+//! - PARSE003: Missing required element or attribute
+//! - PARSE004: Invalid attribute value
+//! - PARSE005: Unknown element (see `vocabulary`)
+//! - PARSE006: Unknown attribute (see `vocabulary`)
+//!
+//! Unknown elements and attributes are warnings, or errors in strict mode.
+//! With a profile, `${name}` in attribute values is substituted between
+//! the phases (VAR001-002, see `profile`).
 
 use super::ast::*;
-use crate::CompileError;
+use super::expr;
+use super::profile::Profile;
+use super::version::{check_version, DSL_VERSION};
+use super::vocabulary;
+use crate::{CompileError, Diagnostic, Location, NodeKind, Severity, Span};
 use quick_xml::events::{BytesStart, Event};
 use quick_xml::Reader;
+use std::borrow::Cow;
+
+/// Parser options
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Report unknown elements and attributes as errors instead of warnings
+    pub strict: bool,
+    /// Variables for `${name}` in attribute values; `None` leaves
+    /// references as written (formatting, migration)
+    pub profile: Option<Profile>,
+}
 
 /// Parse XML to AST
+///
+/// Fails with `CompileError::Validation` carrying every parse diagnostic
+/// if the document has errors.
 pub fn parse(xml: &str) -> Result<OmarDocument, CompileError> {
-    let mut reader = Reader::from_str(xml);
-    reader.trim_text(true);
-    
-    let mut doc = OmarDocument::default();
-    let mut buf = Vec::new();
+    let (doc, diagnostics) = parse_recovering(xml);
     
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => {
-                match e.name().as_ref() {
-                    b"omar" => {
-                        doc.version = get_attr(&e, "version").unwrap_or_else(|| "1.0.0".into());
-                    }
-                    b"schemas" => {
-                        doc.schemas = parse_schemas(&mut reader)?;
-                    }
-                    b"predicates" => {
-                        doc.predicates = parse_predicates(&mut reader)?;
-                    }
-                    b"workflow" => {
-                        doc.workflows.push(parse_workflow(&mut reader, &e)?);
-                    }
-                    b"templates" => {
-                        doc.templates = parse_templates(&mut reader)?;
-                    }
-                    b"merge" | b"merge_policies" => {
-                        doc.merge_policies = parse_merge_policies(&mut reader)?;
-                    }
-                    _ => {}
-                }
-            }
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
-        }
-        buf.clear();
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return Err(CompileError::Validation { diagnostics });
     }
     
     Ok(doc)
 }
 
-fn get_attr(e: &BytesStart, name: &str) -> Option<String> {
-    e.attributes()
-        .filter_map(|a| a.ok())
-        .find(|a| a.key.as_ref() == name.as_bytes())
-        .and_then(|a| String::from_utf8(a.value.to_vec()).ok())
+/// Parse XML to a (possibly partial) AST, collecting all parse diagnostics
+///
+/// Elements that cannot be parsed are dropped from the document; everything
+/// else is kept so later stages can report their own problems.
+pub fn parse_recovering(xml: &str) -> (OmarDocument, Vec<Diagnostic>) {
+    parse_with_options(xml, &ParseOptions::default())
 }
 
-fn parse_schemas(reader: &mut Reader<&[u8]>) -> Result<Vec<Schema>, CompileError> {
-    let mut schemas = Vec::new();
-    let mut buf = Vec::new();
+/// Recovering parse with explicit options
+pub fn parse_with_options(xml: &str, options: &ParseOptions) -> (OmarDocument, Vec<Diagnostic>) {
+    let mut diags = Vec::new();
+    let mut root = read_tree(xml, &mut diags);
+    if let Some(profile) = &options.profile {
+        substitute(&mut root, profile, &mut diags);
+    }
     
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) if e.name().as_ref() == b"schema" => {
-                let name = get_attr(&e, "name").unwrap_or_default();
-                let fields = parse_schema_fields(reader)?;
-                schemas.push(Schema { name, fields });
-            }
-            Ok(Event::End(e)) if e.name().as_ref() == b"schemas" => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
-        }
-        buf.clear();
+    let severity = if options.strict { Severity::Error } else { Severity::Warn };
+    for el in &root.children {
+        check_vocabulary(el, "", severity, &mut diags);
+    }
+    
+    let mut doc = OmarDocument::default();
+    for el in &root.children {
+        parse_top_level(el, &mut doc, &mut diags);
     }
     
-    Ok(schemas)
+    (doc, diags)
 }
 
-fn parse_schema_fields(reader: &mut Reader<&[u8]>) -> Result<Vec<FieldDef>, CompileError> {
-    let mut fields = Vec::new();
-    let mut buf = Vec::new();
-    
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Empty(e)) | Ok(Event::Start(e)) if e.name().as_ref() == b"field" => {
-                fields.push(FieldDef {
-                    name: get_attr(&e, "name").unwrap_or_default(),
-                    field_type: get_attr(&e, "type").unwrap_or_else(|| "string".into()),
-                    required: get_attr(&e, "required").map(|s| s == "true").unwrap_or(false),
-                    default: get_attr(&e, "default"),
-                    pattern: get_attr(&e, "pattern"),
+fn parse_top_level(el: &Element, doc: &mut OmarDocument, diags: &mut Vec<Diagnostic>) {
+    match el.name.as_str() {
+        "omar" => {
+            doc.version = el.attr("version").unwrap_or_else(|| DSL_VERSION.into());
+            check_version(&doc.version, Some(el.span), diags);
+            for child in &el.children {
+                parse_top_level(child, doc, diags);
+            }
+        }
+        "import" => {
+            if let Some(href) = el.require("href", diags) {
+                doc.imports.push(Import {
+                    href,
+                    namespace: el.attr("as"),
+                    span: Some(el.span),
                 });
             }
-            Ok(Event::End(e)) if e.name().as_ref() == b"schema" => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
         }
-        buf.clear();
+        "schemas" => doc.schemas = parse_schemas(el, diags),
+        "predicates" => doc.predicates = parse_predicates(el, diags),
+        "workflow" => doc.workflows.push(parse_workflow(el, diags)),
+        "templates" => doc.templates = parse_templates(el, diags),
+        "fragment" => doc.fragments.extend(parse_fragment(el, diags)),
+        "merge" | "merge_policies" => doc.merge_policies = parse_merge_policies(el, diags),
+        _ => {}
     }
-    
-    Ok(fields)
 }
 
-fn parse_predicates(reader: &mut Reader<&[u8]>) -> Result<Vec<PredicateDef>, CompileError> {
-    let mut predicates = Vec::new();
-    let mut buf = Vec::new();
+pub(super) fn error(diags: &mut Vec<Diagnostic>, code: &str, message: String, hint: Option<String>, span: Span) {
+    report(diags, Severity::Error, code, message, hint, span);
+}
+
+pub(super) fn report(
+    diags: &mut Vec<Diagnostic>,
+    severity: Severity,
+    code: &str,
+    message: String,
+    hint: Option<String>,
+    span: Span,
+) {
+    diags.push(Diagnostic {
+        severity,
+        code: code.into(),
+        message,
+        hint,
+        location: Some(Location {
+            span: Some(span),
+            ..Default::default()
+        }),
+    });
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// PHASE 1: XML → ELEMENT TREE
+// ═══════════════════════════════════════════════════════════════════════════
+
+/// XML element with its source position
+struct Element {
+    name: String,
+    attrs: Vec<Attr>,
+    children: Vec<Element>,
+    /// Concatenated text and CDATA content
+    text: String,
+    /// Span of the start tag (`<name ...>` or `<name .../>`)
+    span: Span,
+}
+
+impl Element {
+    fn new(name: String, span: Span) -> Self {
+        Self { name, attrs: Vec::new(), children: Vec::new(), text: String::new(), span }
+    }
     
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) if e.name().as_ref() == b"predicate" => {
-                let id = get_attr(&e, "id").unwrap_or_default();
-                let expr = parse_predicate_expr(reader)?;
-                predicates.push(PredicateDef { id, expr });
+    fn attr(&self, name: &str) -> Option<String> {
+        self.attrs.iter().find(|a| a.name == name).map(|a| a.value.clone())
+    }
+    
+    /// Get a required, non-empty attribute (PARSE003 if missing)
+    fn require(&self, name: &str, diags: &mut Vec<Diagnostic>) -> Option<String> {
+        match self.attr(name) {
+            Some(value) if !value.is_empty() => Some(value),
+            _ => {
+                error(
+                    diags,
+                    "PARSE003",
+                    format!("<{}> is missing required attribute '{}'", self.name, name),
+                    Some(format!("Add {}=\"...\" to <{}>", name, self.name)),
+                    self.span,
+                );
+                None
+            }
+        }
+    }
+    
+    /// Get a u16 attribute (PARSE004 if present but not a valid number)
+    fn u16_attr(&self, name: &str, diags: &mut Vec<Diagnostic>) -> Option<u16> {
+        let value = self.attr(name)?;
+        match value.trim().parse() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                error(
+                    diags,
+                    "PARSE004",
+                    format!("Invalid {} '{}' on <{}>", name, value, self.name),
+                    Some(format!("{} must be an integer between 0 and {}", name, u16::MAX)),
+                    self.span,
+                );
+                None
             }
-            Ok(Event::End(e)) if e.name().as_ref() == b"predicates" => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
         }
-        buf.clear();
     }
     
-    Ok(predicates)
+    fn bool_attr(&self, name: &str) -> bool {
+        self.attr(name).map(|s| s == "true").unwrap_or(false)
+    }
+    
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+    
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |c| c.name == name)
+    }
 }
 
-fn parse_predicate_expr(reader: &mut Reader<&[u8]>) -> Result<PredicateExpr, CompileError> {
-    let mut buf = Vec::new();
+/// An attribute, with enough of its source to locate positions inside
+/// the value
+struct Attr {
+    name: String,
+    /// Unescaped value
+    value: String,
+    /// Value as written, entities and all
+    raw: String,
+    /// Position of the value's first character (the element's span if
+    /// unknown)
+    start: Span,
+    /// `value` had variables substituted and no longer matches `raw`
+    substituted: bool,
+}
+
+impl Attr {
+    /// Source span of bytes `from..to` of the unescaped value
+    ///
+    /// After substitution offsets no longer line up with the source, so
+    /// this is the whole value as written.
+    fn span(&self, from: usize, to: usize) -> Span {
+        if self.substituted {
+            return Span { end: self.start.start + self.raw.len(), ..self.start };
+        }
+        let (from, to) = (self.raw_offset(from), self.raw_offset(to));
+        let before = &self.raw[..from];
+        let (line, column) = match before.rfind('\n') {
+            Some(nl) => (
+                self.start.line + before.matches('\n').count() as u32,
+                before[nl + 1..].chars().count() as u32 + 1,
+            ),
+            None => (self.start.line, self.start.column + before.chars().count() as u32),
+        };
+        Span { start: self.start.start + from, end: self.start.start + to, line, column }
+    }
     
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Empty(e)) | Ok(Event::Start(e)) => {
-                let expr = match e.name().as_ref() {
-                    b"always" => PredicateExpr::Always,
-                    b"fail" => PredicateExpr::Fail,
-                    b"eq" => PredicateExpr::Eq {
-                        left: get_attr(&e, "left").unwrap_or_default(),
-                        right: Value::from_str_guess(&get_attr(&e, "right").unwrap_or_default()),
-                    },
-                    b"neq" => PredicateExpr::Neq {
-                        left: get_attr(&e, "left").unwrap_or_default(),
-                        right: Value::from_str_guess(&get_attr(&e, "right").unwrap_or_default()),
-                    },
-                    b"gt" => PredicateExpr::Gt {
-                        left: get_attr(&e, "left").unwrap_or_default(),
-                        right: Value::from_str_guess(&get_attr(&e, "right").unwrap_or_default()),
-                    },
-                    b"gte" => PredicateExpr::Gte {
-                        left: get_attr(&e, "left").unwrap_or_default(),
-                        right: Value::from_str_guess(&get_attr(&e, "right").unwrap_or_default()),
-                    },
-                    b"lt" => PredicateExpr::Lt {
-                        left: get_attr(&e, "left").unwrap_or_default(),
-                        right: Value::from_str_guess(&get_attr(&e, "right").unwrap_or_default()),
-                    },
-                    b"lte" => PredicateExpr::Lte {
-                        left: get_attr(&e, "left").unwrap_or_default(),
-                        right: Value::from_str_guess(&get_attr(&e, "right").unwrap_or_default()),
-                    },
-                    b"contains" => PredicateExpr::Contains {
-                        left: get_attr(&e, "left").unwrap_or_default(),
-                        right: get_attr(&e, "right").unwrap_or_default(),
-                    },
-                    b"matches" => PredicateExpr::Matches {
-                        left: get_attr(&e, "left").unwrap_or_default(),
-                        pattern: get_attr(&e, "pattern").unwrap_or_default(),
-                    },
-                    b"startsWith" | b"starts_with" => PredicateExpr::StartsWith {
-                        left: get_attr(&e, "left").unwrap_or_default(),
-                        prefix: get_attr(&e, "prefix").unwrap_or_default(),
-                    },
-                    b"endsWith" | b"ends_with" => PredicateExpr::EndsWith {
-                        left: get_attr(&e, "left").unwrap_or_default(),
-                        suffix: get_attr(&e, "suffix").unwrap_or_default(),
-                    },
-                    b"ref" => PredicateExpr::Ref {
-                        predicate: get_attr(&e, "predicate").unwrap_or_default(),
-                    },
-                    b"fn" => PredicateExpr::Fn {
-                        name: get_attr(&e, "name").unwrap_or_default(),
-                        arg: get_attr(&e, "arg").unwrap_or_default(),
-                    },
-                    b"and" => {
-                        let conditions = parse_predicate_list(reader, b"and")?;
-                        PredicateExpr::And { conditions }
-                    }
-                    b"or" => {
-                        let conditions = parse_predicate_list(reader, b"or")?;
-                        PredicateExpr::Or { conditions }
-                    }
-                    b"not" => {
-                        let inner = parse_predicate_expr(reader)?;
-                        skip_to_end(reader, b"not")?;
-                        PredicateExpr::Not { condition: Box::new(inner) }
-                    }
-                    _ => continue,
-                };
-                return Ok(expr);
+    /// Offset in `raw` of byte `offset` of the unescaped value
+    fn raw_offset(&self, offset: usize) -> usize {
+        let mut unescaped = 0;
+        let mut chars = self.raw.char_indices();
+        while let Some((i, c)) = chars.next() {
+            if unescaped >= offset {
+                return i;
             }
-            Ok(Event::End(e)) if e.name().as_ref() == b"predicate" => {
-                return Ok(PredicateExpr::Always);
+            if c == '&' {
+                // One entity, up to ';', stands for one character
+                let entity: String = chars.by_ref().map(|(_, c)| c).take_while(|&c| c != ';').collect();
+                unescaped += unescape_entity(&entity).map_or(1, char::len_utf8);
+            } else {
+                unescaped += c.len_utf8();
             }
-            Ok(Event::Eof) => return Ok(PredicateExpr::Always),
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
         }
-        buf.clear();
+        self.raw.len()
     }
 }
 
-fn parse_predicate_list(reader: &mut Reader<&[u8]>, end_tag: &[u8]) -> Result<Vec<PredicateExpr>, CompileError> {
-    let mut conditions = Vec::new();
-    let mut buf = Vec::new();
-    
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
-                let expr = parse_single_predicate_from_event(&e, reader)?;
-                conditions.push(expr);
-            }
-            Ok(Event::End(e)) if e.name().as_ref() == end_tag => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
+fn unescape_entity(entity: &str) -> Option<char> {
+    match entity {
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "amp" => Some('&'),
+        "apos" => Some('\''),
+        "quot" => Some('"'),
+        _ => {
+            let code = match entity.strip_prefix("#x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => entity.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code)
         }
-        buf.clear();
     }
-    
-    Ok(conditions)
 }
 
-fn parse_single_predicate_from_event(e: &BytesStart, reader: &mut Reader<&[u8]>) -> Result<PredicateExpr, CompileError> {
-    match e.name().as_ref() {
-        b"always" => Ok(PredicateExpr::Always),
-        b"fail" => Ok(PredicateExpr::Fail),
-        b"eq" => Ok(PredicateExpr::Eq {
-            left: get_attr(e, "left").unwrap_or_default(),
-            right: Value::from_str_guess(&get_attr(e, "right").unwrap_or_default()),
-        }),
-        b"neq" => Ok(PredicateExpr::Neq {
-            left: get_attr(e, "left").unwrap_or_default(),
-            right: Value::from_str_guess(&get_attr(e, "right").unwrap_or_default()),
-        }),
-        b"gt" => Ok(PredicateExpr::Gt {
-            left: get_attr(e, "left").unwrap_or_default(),
-            right: Value::from_str_guess(&get_attr(e, "right").unwrap_or_default()),
-        }),
-        b"gte" => Ok(PredicateExpr::Gte {
-            left: get_attr(e, "left").unwrap_or_default(),
-            right: Value::from_str_guess(&get_attr(e, "right").unwrap_or_default()),
-        }),
-        b"lt" => Ok(PredicateExpr::Lt {
-            left: get_attr(e, "left").unwrap_or_default(),
-            right: Value::from_str_guess(&get_attr(e, "right").unwrap_or_default()),
-        }),
-        b"lte" => Ok(PredicateExpr::Lte {
-            left: get_attr(e, "left").unwrap_or_default(),
-            right: Value::from_str_guess(&get_attr(e, "right").unwrap_or_default()),
-        }),
-        b"contains" => Ok(PredicateExpr::Contains {
-            left: get_attr(e, "left").unwrap_or_default(),
-            right: get_attr(e, "right").unwrap_or_default(),
-        }),
-        b"matches" => Ok(PredicateExpr::Matches {
-            left: get_attr(e, "left").unwrap_or_default(),
-            pattern: get_attr(e, "pattern").unwrap_or_default(),
-        }),
-        b"startsWith" | b"starts_with" => Ok(PredicateExpr::StartsWith {
-            left: get_attr(e, "left").unwrap_or_default(),
-            prefix: get_attr(e, "prefix").unwrap_or_default(),
-        }),
-        b"endsWith" | b"ends_with" => Ok(PredicateExpr::EndsWith {
-            left: get_attr(e, "left").unwrap_or_default(),
-            suffix: get_attr(e, "suffix").unwrap_or_default(),
-        }),
-        b"ref" => Ok(PredicateExpr::Ref {
-            predicate: get_attr(e, "predicate").unwrap_or_default(),
-        }),
-        b"fn" => Ok(PredicateExpr::Fn {
-            name: get_attr(e, "name").unwrap_or_default(),
-            arg: get_attr(e, "arg").unwrap_or_default(),
-        }),
-        b"and" => {
-            let conditions = parse_predicate_list(reader, b"and")?;
-            Ok(PredicateExpr::And { conditions })
-        }
-        b"or" => {
-            let conditions = parse_predicate_list(reader, b"or")?;
-            Ok(PredicateExpr::Or { conditions })
-        }
-        b"not" => {
-            let inner = parse_predicate_expr(reader)?;
-            skip_to_end(reader, b"not")?;
-            Ok(PredicateExpr::Not { condition: Box::new(inner) })
-        }
-        _ => Ok(PredicateExpr::Always),
+/// Byte offset → line/column lookup
+pub(super) struct LineIndex<'a> {
+    src: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub(super) fn new(src: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(src.match_indices('\n').map(|(i, _)| i + 1));
+        Self { src, line_starts }
+    }
+    
+    pub(super) fn span(&self, start: usize, end: usize) -> Span {
+        let line = self.line_starts.partition_point(|&s| s <= start);
+        let line_start = self.line_starts[line - 1];
+        let column = self.src.get(line_start..start).map(|s| s.chars().count()).unwrap_or(0) + 1;
+        Span { start, end, line: line as u32, column: column as u32 }
     }
 }
 
-fn skip_to_end(reader: &mut Reader<&[u8]>, tag: &[u8]) -> Result<(), CompileError> {
-    let mut buf = Vec::new();
-    let mut depth = 1;
+/// Read the whole document into an element tree under a synthetic root
+///
+/// Mismatched end tags close the elements they skip over (PARSE002);
+/// a syntax error stops reading and keeps what was read so far (PARSE001).
+fn read_tree(xml: &str, diags: &mut Vec<Diagnostic>) -> Element {
+    let lines = LineIndex::new(xml);
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+    reader.check_end_names(false);
+    
+    let mut stack = vec![Element::new(String::new(), lines.span(0, 0))];
     
     loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) if e.name().as_ref() == tag => depth += 1,
-            Ok(Event::End(e)) if e.name().as_ref() == tag => {
-                depth -= 1;
-                if depth == 0 { break; }
+        let event = reader.read_event();
+        let end = reader.buffer_position();
+        match event {
+            Ok(Event::Start(e)) => {
+                stack.push(element_from(&e, xml, end, &lines, diags));
+            }
+            Ok(Event::Empty(e)) => {
+                let el = element_from(&e, xml, end, &lines, diags);
+                stack.last_mut().expect("root element").children.push(el);
+            }
+            Ok(Event::End(e)) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                let start = xml[..end].rfind('<').unwrap_or(0);
+                
+                match stack.iter().skip(1).rposition(|el| el.name == name) {
+                    Some(pos) => {
+                        // Everything opened after the match was never closed
+                        while stack.len() > pos + 2 {
+                            let unclosed = stack.pop().expect("unclosed element");
+                            error(
+                                diags,
+                                "PARSE002",
+                                format!("Element <{}> is not closed before </{}>", unclosed.name, name),
+                                Some(format!("Add </{}>", unclosed.name)),
+                                unclosed.span,
+                            );
+                            stack.last_mut().expect("root element").children.push(unclosed);
+                        }
+                        let el = stack.pop().expect("matched element");
+                        stack.last_mut().expect("root element").children.push(el);
+                    }
+                    None => {
+                        let hint = (stack.len() > 1)
+                            .then(|| format!("Expected </{}>", stack.last().expect("open element").name));
+                        error(
+                            diags,
+                            "PARSE002",
+                            format!("Unexpected closing tag </{}>", name),
+                            hint,
+                            lines.span(start, end),
+                        );
+                    }
+                }
+            }
+            Ok(Event::Text(t)) => {
+                let text = t.unescape()
+                    .map(|s| s.into_owned())
+                    .unwrap_or_else(|_| String::from_utf8_lossy(&t).into_owned());
+                stack.last_mut().expect("root element").text.push_str(&text);
+            }
+            Ok(Event::CData(c)) => {
+                stack.last_mut().expect("root element").text.push_str(&String::from_utf8_lossy(&c));
             }
             Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
+            Err(e) => {
+                let pos = reader.buffer_position();
+                error(diags, "PARSE001", format!("Malformed XML: {}", e), None, lines.span(pos, pos));
+                break;
+            }
             _ => {}
         }
-        buf.clear();
     }
     
-    Ok(())
+    while stack.len() > 1 {
+        let unclosed = stack.pop().expect("unclosed element");
+        error(
+            diags,
+            "PARSE002",
+            format!("Element <{}> is never closed", unclosed.name),
+            Some(format!("Add </{}>", unclosed.name)),
+            unclosed.span,
+        );
+        stack.last_mut().expect("root element").children.push(unclosed);
+    }
+    
+    stack.pop().expect("root element")
 }
 
-fn parse_workflow(reader: &mut Reader<&[u8]>, start: &BytesStart) -> Result<Workflow, CompileError> {
-    let id = get_attr(start, "id").unwrap_or_default();
-    let description = get_attr(start, "description");
+/// Build an element from a start tag that ends at byte offset `end`
+fn element_from(
+    e: &BytesStart,
+    xml: &str,
+    end: usize,
+    lines: &LineIndex,
+    diags: &mut Vec<Diagnostic>,
+) -> Element {
+    // Attribute values cannot contain a raw '<', so the last one before
+    // `end` opens this tag
+    let start = xml[..end].rfind('<').unwrap_or(0);
     
-    let mut entry = EntryPoint { p: String::new(), x: String::new(), node: String::new() };
-    let mut nodes = Vec::new();
-    let mut edges = Vec::new();
-    let mut buf = Vec::new();
-    
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Empty(e)) if e.name().as_ref() == b"entry" => {
-                entry.p = get_attr(&e, "p").unwrap_or_default();
-                entry.x = get_attr(&e, "x").unwrap_or_default();
-                entry.node = get_attr(&e, "node").unwrap_or_default();
+    let mut el = Element::new(
+        String::from_utf8_lossy(e.name().as_ref()).into_owned(),
+        lines.span(start, end),
+    );
+    for attr in e.attributes() {
+        match attr {
+            Ok(attr) => {
+                let name = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+                let value = attr.unescape_value()
+                    .map(|v| v.into_owned())
+                    .unwrap_or_else(|_| String::from_utf8_lossy(&attr.value).into_owned());
+                // The reader borrows values from `xml`, so the offset is
+                // the distance between the two
+                let start = match attr.value {
+                    Cow::Borrowed(bytes) => {
+                        let offset = bytes.as_ptr() as usize - xml.as_ptr() as usize;
+                        lines.span(offset, offset)
+                    }
+                    Cow::Owned(_) => el.span,
+                };
+                let raw = String::from_utf8_lossy(&attr.value).into_owned();
+                el.attrs.push(Attr { name, value, raw, start, substituted: false });
+            }
+            Err(err) => {
+                error(diags, "PARSE001", format!("Malformed attribute in <{}>: {}", el.name, err), None, el.span);
+                break;
             }
-            Ok(Event::Start(e)) if e.name().as_ref() == b"nodes" => {
-                nodes = parse_nodes(reader)?;
+        }
+    }
+    el
+}
+
+/// Substitute profile variables in the attribute values of an element
+/// and its descendants
+///
+/// A value with an unresolved reference is reported and left as written.
+fn substitute(el: &mut Element, profile: &Profile, diags: &mut Vec<Diagnostic>) {
+    for attr in &mut el.attrs {
+        match profile.substitute(&attr.value) {
+            Ok(Cow::Borrowed(_)) => {}
+            Ok(Cow::Owned(value)) => {
+                attr.value = value;
+                attr.substituted = true;
             }
-            Ok(Event::Start(e)) if e.name().as_ref() == b"edges" => {
-                edges = parse_edges(reader)?;
+            Err(err) => {
+                let span = attr.span(err.range.0, err.range.1);
+                error(diags, err.code, err.message, err.hint, span);
             }
-            Ok(Event::End(e)) if e.name().as_ref() == b"workflow" => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
         }
-        buf.clear();
     }
-    
-    Ok(Workflow { id, description, entry, nodes, edges })
+    for child in &mut el.children {
+        substitute(child, profile, diags);
+    }
 }
 
-fn parse_nodes(reader: &mut Reader<&[u8]>) -> Result<Vec<Node>, CompileError> {
-    let mut nodes = Vec::new();
-    let mut buf = Vec::new();
+/// Check an element and its descendants against the vocabulary
+///
+/// Unknown elements are reported once; their contents are not checked.
+fn check_vocabulary(el: &Element, parent: &str, severity: Severity, diags: &mut Vec<Diagnostic>) {
+    let Some(spec) = vocabulary::lookup(parent, &el.name) else {
+        let context = if parent.is_empty() {
+            "at the top level".to_string()
+        } else {
+            format!("inside <{}>", parent)
+        };
+        let (message, hint) = if vocabulary::is_known_element(&el.name) {
+            let allowed: Vec<_> = vocabulary::children_of(parent).map(|c| format!("<{}>", c)).collect();
+            (
+                format!("Element <{}> is not allowed {}", el.name, context),
+                (!allowed.is_empty()).then(|| format!("Allowed here: {}", allowed.join(", "))),
+            )
+        } else {
+            (
+                format!("Unknown element <{}> {}", el.name, context),
+                vocabulary::suggest(&el.name, vocabulary::children_of(parent))
+                    .map(|s| format!("Did you mean <{}>?", s)),
+            )
+        };
+        report(diags, severity, "PARSE005", message, hint, el.span);
+        return;
+    };
     
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"node" => {
-                let mut node = Node::default();
-                node.id = get_attr(&e, "id").unwrap_or_default();
-                node.kind = get_attr(&e, "kind").unwrap_or_else(|| "transform".into());
-                node.op = get_attr(&e, "op");
-                node.template = get_attr(&e, "template");
-                node.status = get_attr(&e, "status").and_then(|s| s.parse().ok());
-                node.actor = get_attr(&e, "actor");
-                node.confirmation = get_attr(&e, "confirmation");
-                node.async_node = get_attr(&e, "async").map(|s| s == "true").unwrap_or(false);
-                node.cacheable = get_attr(&e, "cacheable").map(|s| s == "true").unwrap_or(false);
-                
-                // Parse child elements if this is a Start event (not Empty)
-                if matches!(reader.read_event_into(&mut buf), Ok(Event::Start(_))) {
-                    parse_node_children(reader, &mut node)?;
-                }
-                
-                nodes.push(node);
-            }
-            Ok(Event::End(e)) if e.name().as_ref() == b"nodes" => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
+    let any_attribute = spec.attributes.contains(&vocabulary::ANY_ATTRIBUTE);
+    for Attr { name: attr, .. } in &el.attrs {
+        if any_attribute || spec.attributes.contains(&attr.as_str()) || attr.starts_with("xmlns") {
+            continue;
         }
-        buf.clear();
+        let hint = match vocabulary::suggest(attr, spec.attributes.iter().copied()) {
+            Some(s) => format!("Did you mean '{}'?", s),
+            None if spec.attributes.is_empty() => format!("<{}> takes no attributes", el.name),
+            None => format!("Known attributes: {}", spec.attributes.join(", ")),
+        };
+        report(
+            diags,
+            severity,
+            "PARSE006",
+            format!("Unknown attribute '{}' on <{}>", attr, el.name),
+            Some(hint),
+            el.span,
+        );
     }
     
-    Ok(nodes)
+    for child in &el.children {
+        check_vocabulary(child, &el.name, severity, diags);
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// PHASE 2: ELEMENT TREE → AST
+// ═══════════════════════════════════════════════════════════════════════════
+
+fn parse_schemas(el: &Element, diags: &mut Vec<Diagnostic>) -> Vec<Schema> {
+    el.children_named("schema")
+        .filter_map(|schema| {
+            Some(Schema {
+                name: schema.require("name", diags)?,
+                fields: schema.children_named("field")
+                    .filter_map(|field| parse_field_def(field, diags))
+                    .collect(),
+                span: Some(schema.span),
+            })
+        })
+        .collect()
+}
+
+fn parse_field_def(el: &Element, diags: &mut Vec<Diagnostic>) -> Option<FieldDef> {
+    Some(FieldDef {
+        name: el.require("name", diags)?,
+        field_type: el.attr("type").unwrap_or_else(|| "string".into()),
+        required: el.bool_attr("required"),
+        default: el.attr("default"),
+        pattern: el.attr("pattern"),
+        span: Some(el.span),
+    })
 }
 
-fn parse_node_children(reader: &mut Reader<&[u8]>, node: &mut Node) -> Result<(), CompileError> {
-    let mut buf = Vec::new();
+fn parse_predicates(el: &Element, diags: &mut Vec<Diagnostic>) -> Vec<PredicateDef> {
+    el.children_named("predicate")
+        .filter_map(|pred| {
+            let id = pred.require("id", diags)?;
+            let mut expr_spans = Vec::new();
+            let expr = parse_condition(pred, &mut expr_spans, diags);
+            Some(PredicateDef {
+                id,
+                expr,
+                span: Some(pred.span),
+                expr_spans,
+                expr_source: pred.attr("expr"),
+            })
+        })
+        .collect()
+}
+
+fn is_predicate_element(name: &str) -> bool {
+    vocabulary::lookup("when", name).is_some()
+}
+
+/// Parse the condition of `<predicate>` or `<when>`: its `expr` attribute
+/// (see `dsl::expr`), or else the expression element inside
+///
+/// Errors in `expr` point into the attribute value.
+fn parse_condition(el: &Element, spans: &mut Vec<Span>, diags: &mut Vec<Diagnostic>) -> PredicateExpr {
+    let Some(attr) = el.attrs.iter().find(|a| a.name == "expr") else {
+        return parse_predicate_body(el, spans, diags);
+    };
+    if el.children.iter().any(|c| is_predicate_element(&c.name)) {
+        error(
+            diags,
+            "PARSE004",
+            format!("<{}> has both an expr attribute and a condition element", el.name),
+            Some("Keep one of them".into()),
+            el.span,
+        );
+    }
     
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Empty(e)) | Ok(Event::Start(e)) => {
-                match e.name().as_ref() {
-                    b"template" => node.template = get_attr(&e, "ref"),
-                    b"schema" => node.schema = get_attr(&e, "ref"),
-                    b"require" => node.predicate = get_attr(&e, "predicate"),
-                    b"selector" => {
-                        if let Ok(Event::Text(t)) = reader.read_event_into(&mut buf) {
-                            node.selector = String::from_utf8(t.to_vec()).ok();
-                        }
-                    }
-                    b"message" => {
-                        if let Ok(Event::Text(t)) = reader.read_event_into(&mut buf) {
-                            node.message = String::from_utf8(t.to_vec()).ok();
-                        }
-                    }
-                    b"set" => {
-                        if let (Some(signal), Some(value)) = (get_attr(&e, "signal"), get_attr(&e, "value")) {
-                            node.signals.push((signal, value));
-                        }
-                    }
-                    _ => {}
-                }
+    match expr::parse_expr(&attr.value) {
+        Ok((expr, ranges)) => {
+            spans.extend(ranges.iter().map(|&(start, end)| attr.span(start, end)));
+            expr
+        }
+        Err(e) => {
+            error(
+                diags,
+                "PARSE004",
+                format!("Invalid expr on <{}>: {}", el.name, e.message),
+                Some("Conditions look like: $token.role == 'admin' && len($input.items) > 0".into()),
+                attr.span(e.start, e.end),
+            );
+            PredicateExpr::Always
+        }
+    }
+}
+
+/// Parse `<compare left="..." cmp="lt" right="..."/>`
+///
+/// Errors in `left` or `right` point into the attribute value; an invalid
+/// comparison reads as `<fail/>` so the document never compiles to a
+/// guard that holds by accident.
+fn parse_compare(el: &Element, diags: &mut Vec<Diagnostic>) -> PredicateExpr {
+    let mut side = |name: &str| {
+        el.require(name, diags)?;
+        let attr = el.attrs.iter().find(|a| a.name == name)?;
+        match expr::parse_arith(&attr.value) {
+            Ok(arith) => Some(arith),
+            Err(e) => {
+                error(
+                    diags,
+                    "PARSE004",
+                    format!("Invalid {} on <compare>: {}", name, e.message),
+                    Some("Arithmetic looks like: $input.end - $input.start, with spaces around operators".into()),
+                    attr.span(e.start, e.end),
+                );
+                None
             }
-            Ok(Event::End(e)) if e.name().as_ref() == b"node" => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
         }
-        buf.clear();
+    };
+    let (left, right) = (side("left"), side("right"));
+
+    let cmp = el.require("cmp", diags).and_then(|name| {
+        let cmp = Comparison::from_name(&name);
+        if cmp.is_none() {
+            let names: Vec<_> = Comparison::ALL.iter().map(|c| c.name()).collect();
+            error(
+                diags,
+                "PARSE004",
+                format!("Invalid cmp '{}' on <compare>", name),
+                Some(format!("cmp must be one of: {}", names.join(", "))),
+                el.span,
+            );
+        }
+        cmp
+    });
+
+    match (cmp, left, right) {
+        (Some(cmp), Some(left), Some(right)) => PredicateExpr::Compare { cmp, left, right },
+        _ => PredicateExpr::Fail,
     }
-    
-    Ok(())
 }
 
-fn parse_edges(reader: &mut Reader<&[u8]>) -> Result<Vec<Edge>, CompileError> {
-    let mut edges = Vec::new();
-    let mut buf = Vec::new();
+/// Parse `<before left="..." right="..."/>` or `<after .../>`, whose
+/// `right` must be a date, a duration or a variable
+///
+/// Any other `right` reads as `<fail/>`, like an invalid `<compare>`.
+fn parse_time_comparison(el: &Element, diags: &mut Vec<Diagnostic>) -> PredicateExpr {
+    let left = el.attr("left").unwrap_or_default();
+    let Some(right) = el.require("right", diags) else {
+        return PredicateExpr::Fail;
+    };
+    if Value::time_from_str(&right).is_none() {
+        error(
+            diags,
+            "PARSE004",
+            format!("Invalid right '{}' on <{}>: expected a date, a duration or a $variable", right, el.name),
+            Some("e.g. right=\"2025-01-01\", right=\"-P30D\" (30 days ago) or right=\"$task.due\"".into()),
+            el.span,
+        );
+        return PredicateExpr::Fail;
+    }
+    match el.name.as_str() {
+        "before" => PredicateExpr::Before { left, right },
+        _ => PredicateExpr::After { left, right },
+    }
+}
+
+/// Parse the single expression inside `<predicate>`, `<when>` or `<not>`
+///
+/// Unrecognised children are skipped; an empty body means "always".
+fn parse_predicate_body(el: &Element, spans: &mut Vec<Span>, diags: &mut Vec<Diagnostic>) -> PredicateExpr {
+    match el.children.iter().find(|c| is_predicate_element(&c.name)) {
+        Some(child) => parse_predicate_expr(child, spans, diags),
+        None => PredicateExpr::Always,
+    }
+}
+
+/// Parse one predicate expression element, recording spans in pre-order
+fn parse_predicate_expr(el: &Element, spans: &mut Vec<Span>, diags: &mut Vec<Diagnostic>) -> PredicateExpr {
+    spans.push(el.span);
     
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) if e.name().as_ref() == b"edge" => {
-                let mut edge = Edge::default();
-                edge.from = get_attr(&e, "from").unwrap_or_default();
-                edge.to = get_attr(&e, "to").unwrap_or_default();
-                edge.weight = get_attr(&e, "weight").and_then(|s| s.parse().ok());
-                edge.parallel = get_attr(&e, "parallel").map(|s| s == "true").unwrap_or(false);
-                edge.fallback = get_attr(&e, "fallback").map(|s| s == "true").unwrap_or(false);
-                
-                parse_edge_children(reader, &mut edge)?;
-                
-                edges.push(edge);
-            }
-            Ok(Event::Empty(e)) if e.name().as_ref() == b"edge" => {
-                let mut edge = Edge::default();
-                edge.from = get_attr(&e, "from").unwrap_or_default();
-                edge.to = get_attr(&e, "to").unwrap_or_default();
-                edge.predicate = Some(PredicateExpr::Always);
-                edges.push(edge);
+    let left = || el.attr("left").unwrap_or_default();
+    let right = || Value::from_str_guess(&el.attr("right").unwrap_or_default());
+    
+    match el.name.as_str() {
+        "always" => PredicateExpr::Always,
+        "fail" => PredicateExpr::Fail,
+        "eq" => PredicateExpr::Eq { left: left(), right: right() },
+        "neq" => PredicateExpr::Neq { left: left(), right: right() },
+        "gt" => PredicateExpr::Gt { left: left(), right: right() },
+        "gte" => PredicateExpr::Gte { left: left(), right: right() },
+        "lt" => PredicateExpr::Lt { left: left(), right: right() },
+        "lte" => PredicateExpr::Lte { left: left(), right: right() },
+        "contains" => PredicateExpr::Contains {
+            left: left(),
+            right: el.attr("right").unwrap_or_default(),
+        },
+        "matches" => PredicateExpr::Matches {
+            left: left(),
+            pattern: el.attr("pattern").unwrap_or_default(),
+        },
+        "startsWith" | "starts_with" => PredicateExpr::StartsWith {
+            left: left(),
+            prefix: el.attr("prefix").unwrap_or_default(),
+        },
+        "endsWith" | "ends_with" => PredicateExpr::EndsWith {
+            left: left(),
+            suffix: el.attr("suffix").unwrap_or_default(),
+        },
+        "before" | "after" => parse_time_comparison(el, diags),
+        "within" => PredicateExpr::Within {
+            left: left(),
+            duration: el.attr("duration").unwrap_or_default(),
+        },
+        "ref" => PredicateExpr::Ref {
+            predicate: el.attr("predicate").unwrap_or_default(),
+        },
+        "fn" => PredicateExpr::Fn {
+            name: el.attr("name").unwrap_or_default(),
+            arg: el.attr("arg").unwrap_or_default(),
+        },
+        "compare" => parse_compare(el, diags),
+        "in" | "not_in" | "notIn" => {
+            let left = left();
+            let values = el.require("values", diags)
+                .and_then(|values| Value::list_from_str(&values).map_err(|message| {
+                    error(
+                        diags,
+                        "PARSE004",
+                        format!("Invalid values on <{}>: {}", el.name, message),
+                        Some("List the members separated by commas: values=\"won,lost,closed\"".into()),
+                        el.span,
+                    );
+                }).ok());
+            match (values, el.name.as_str()) {
+                (Some(values), "in") => PredicateExpr::In { left, values },
+                (Some(values), _) => PredicateExpr::NotIn { left, values },
+                // An invalid list never holds
+                (None, _) => PredicateExpr::Fail,
             }
-            Ok(Event::End(e)) if e.name().as_ref() == b"edges" => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
         }
-        buf.clear();
+        "and" => PredicateExpr::And {
+            conditions: el.children.iter().map(|c| parse_predicate_expr(c, spans, diags)).collect(),
+        },
+        "or" => PredicateExpr::Or {
+            conditions: el.children.iter().map(|c| parse_predicate_expr(c, spans, diags)).collect(),
+        },
+        "not" => PredicateExpr::Not {
+            condition: Box::new(parse_predicate_body(el, spans, diags)),
+        },
+        _ => PredicateExpr::Always,
     }
+}
+
+fn parse_workflow(el: &Element, diags: &mut Vec<Diagnostic>) -> Workflow {
+    let id = el.require("id", diags).unwrap_or_default();
+    
+    let entry = match el.child("entry") {
+        Some(entry) => EntryPoint {
+            p: entry.require("p", diags).unwrap_or_default(),
+            x: entry.require("x", diags).unwrap_or_default(),
+            node: entry.require("node", diags).unwrap_or_default(),
+            span: Some(entry.span),
+        },
+        None => {
+            error(
+                diags,
+                "PARSE003",
+                format!("Workflow '{}' has no <entry>", id),
+                Some("Add <entry p=\"...\" x=\"...\" node=\"...\"/> to workflow".into()),
+                el.span,
+            );
+            EntryPoint { p: String::new(), x: String::new(), node: String::new(), span: None }
+        }
+    };
     
-    Ok(edges)
+    let (nodes, edges, uses) = parse_graph(el, diags);
+    Workflow {
+        id,
+        description: el.attr("description"),
+        schema: el.attr("schema"),
+        entry,
+        nodes,
+        edges,
+        uses,
+        span: Some(el.span),
+    }
 }
 
-fn parse_edge_children(reader: &mut Reader<&[u8]>, edge: &mut Edge) -> Result<(), CompileError> {
-    let mut buf = Vec::new();
+fn parse_fragment(el: &Element, diags: &mut Vec<Diagnostic>) -> Option<Fragment> {
+    let id = el.require("id", diags)?;
+    let params = el.attr("params")
+        .map(|p| p.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let (nodes, edges, uses) = parse_graph(el, diags);
     
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) if e.name().as_ref() == b"when" => {
-                edge.predicate = Some(parse_predicate_expr(reader)?);
-                skip_to_end(reader, b"when")?;
-            }
-            Ok(Event::End(e)) if e.name().as_ref() == b"edge" => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
+    Some(Fragment { id, params, nodes, edges, uses, span: Some(el.span) })
+}
+
+/// `<nodes>` (including `<use>`) and `<edges>` of a workflow or fragment
+fn parse_graph(el: &Element, diags: &mut Vec<Diagnostic>) -> (Vec<Node>, Vec<Edge>, Vec<FragmentUse>) {
+    let mut nodes = Vec::new();
+    let mut uses = Vec::new();
+    for child in el.children_named("nodes").flat_map(|n| &n.children) {
+        match child.name.as_str() {
+            "node" => nodes.extend(parse_node(child, diags)),
+            "use" => uses.extend(parse_use(child, diags)),
             _ => {}
         }
-        buf.clear();
     }
+    let edges = el.children_named("edges")
+        .flat_map(|edges| edges.children_named("edge"))
+        .filter_map(|edge| parse_edge(edge, diags))
+        .collect();
     
-    if edge.predicate.is_none() {
-        edge.predicate = Some(PredicateExpr::Always);
-    }
+    (nodes, edges, uses)
+}
+
+fn parse_use(el: &Element, diags: &mut Vec<Diagnostic>) -> Option<FragmentUse> {
+    let fragment = el.require("fragment", diags);
+    let id = el.require("id", diags);
+    let args = el.attrs.iter()
+        .filter(|a| a.name != "fragment" && a.name != "id")
+        .map(|a| (a.name.clone(), a.value.clone()))
+        .collect();
     
-    Ok(())
+    Some(FragmentUse { fragment: fragment?, id: id?, args, span: Some(el.span) })
 }
 
-fn parse_templates(reader: &mut Reader<&[u8]>) -> Result<Vec<Template>, CompileError> {
-    let mut templates = Vec::new();
-    let mut buf = Vec::new();
+fn parse_node(el: &Element, diags: &mut Vec<Diagnostic>) -> Option<Node> {
+    let id = el.require("id", diags)?;
     
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) if e.name().as_ref() == b"template" => {
-                let id = get_attr(&e, "id").unwrap_or_default();
-                let mut content = String::new();
-                
-                loop {
-                    match reader.read_event_into(&mut buf) {
-                        Ok(Event::CData(c)) => {
-                            content = String::from_utf8_lossy(&c).to_string();
-                        }
-                        Ok(Event::Text(t)) => {
-                            content = String::from_utf8_lossy(&t).to_string();
-                        }
-                        Ok(Event::End(e)) if e.name().as_ref() == b"template" => break,
-                        Ok(Event::Eof) => break,
-                        Err(e) => return Err(CompileError::Parse(e.to_string())),
-                        _ => {}
+    // An unknown kind is reported here and the node kept as a transform,
+    // so edges pointing at it still resolve
+    let mut kind = el.attr("kind").unwrap_or_else(|| "transform".into());
+    let mut call = None;
+    if kind == "call" {
+        // Call nodes are expanded into the target workflow before lowering
+        call = el.require("workflow", diags);
+        if call.is_none() {
+            kind = "transform".into();
+        }
+    } else if let Err(e) = kind.parse::<NodeKind>() {
+        error(
+            diags,
+            "PARSE004",
+            format!("Node '{}': {}", id, e),
+            Some("Valid kinds: transform, external, render, signal, auth, terminal, error, call".into()),
+            el.span,
+        );
+        kind = "transform".into();
+    }
+    
+    let mut node = Node {
+        id,
+        kind,
+        op: el.attr("op"),
+        template: el.attr("template"),
+        status: el.u16_attr("status", diags),
+        actor: el.attr("actor"),
+        confirmation: el.attr("confirmation"),
+        async_node: el.bool_attr("async"),
+        cacheable: el.bool_attr("cacheable"),
+        call,
+        span: Some(el.span),
+        ..Default::default()
+    };
+    
+    for child in &el.children {
+        match child.name.as_str() {
+            "template" => node.template = child.attr("ref"),
+            "schema" => node.schema = child.attr("ref"),
+            "require" => node.predicate = child.attr("predicate"),
+            "selector" => node.selector = Some(child.text.clone()),
+            "message" => node.message = Some(child.text.clone()),
+            "set" => {
+                // An empty value is allowed: it clears the key or signal
+                let Some(value) = child.attr("value") else {
+                    error(
+                        diags,
+                        "PARSE003",
+                        "<set> is missing required attribute 'value'".into(),
+                        Some("Add value=\"...\" to <set>".into()),
+                        child.span,
+                    );
+                    continue;
+                };
+                match (child.attr("key"), child.attr("signal")) {
+                    (Some(key), None) => {
+                        node.data.insert(key, value);
                     }
-                    buf.clear();
+                    (None, Some(signal)) => node.signals.push((signal, value)),
+                    _ => error(
+                        diags,
+                        "PARSE003",
+                        "<set> needs exactly one of 'key' or 'signal'".into(),
+                        Some("Use key=\"...\" for node data or signal=\"...\" for a signal update".into()),
+                        child.span,
+                    ),
                 }
-                
-                templates.push(Template { id, content });
             }
-            Ok(Event::End(e)) if e.name().as_ref() == b"templates" => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
             _ => {}
         }
-        buf.clear();
     }
     
-    Ok(templates)
+    Some(node)
+}
+
+fn parse_edge(el: &Element, diags: &mut Vec<Diagnostic>) -> Option<Edge> {
+    // Check both endpoints so a single pass reports each missing one
+    let from = el.require("from", diags);
+    let to = el.require("to", diags);
+    let weight = el.u16_attr("weight", diags);
+    
+    let mut predicate_spans = Vec::new();
+    let predicate = match el.child("when") {
+        Some(when) => parse_condition(when, &mut predicate_spans, diags),
+        None => PredicateExpr::Always,
+    };
+    
+    Some(Edge {
+        from: from?,
+        to: to?,
+        predicate: Some(predicate),
+        weight,
+        parallel: el.bool_attr("parallel"),
+        fallback: el.bool_attr("fallback"),
+        span: Some(el.span),
+        predicate_spans,
+        predicate_source: el.child("when").and_then(|when| when.attr("expr")),
+        ..Default::default()
+    })
+}
+
+fn parse_templates(el: &Element, diags: &mut Vec<Diagnostic>) -> Vec<Template> {
+    el.children_named("template")
+        .filter_map(|template| {
+            Some(Template {
+                id: template.require("id", diags)?,
+                content: template.text.clone(),
+                span: Some(template.span),
+            })
+        })
+        .collect()
 }
 
 // ═══════════════════════════════════════════════════════════════════════════
 // MERGE POLICY PARSING (Y-constraint application for CRDT conflict resolution)
 // ═══════════════════════════════════════════════════════════════════════════
 
-fn parse_merge_policies(reader: &mut Reader<&[u8]>) -> Result<Vec<EntityMerge>, CompileError> {
-    let mut policies = Vec::new();
-    let mut buf = Vec::new();
-
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) if e.name().as_ref() == b"entity" => {
-                let entity = get_attr(&e, "name").unwrap_or_default();
-                let default_str = get_attr(&e, "default").unwrap_or_else(|| "lww".into());
-                let default_policy = parse_merge_policy_name(&default_str);
-                let pre_condition = get_attr(&e, "pre");
-                let post_validate = get_attr(&e, "post");
-
-                let fields = parse_field_merges(reader)?;
-
-                policies.push(EntityMerge {
-                    entity,
-                    default_policy,
-                    fields,
-                    pre_condition,
-                    post_validate,
-                });
-            }
-            Ok(Event::End(e)) if e.name().as_ref() == b"merge" || e.name().as_ref() == b"merge_policies" => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
-        }
-        buf.clear();
-    }
-
-    Ok(policies)
+fn parse_merge_policies(el: &Element, diags: &mut Vec<Diagnostic>) -> Vec<EntityMerge> {
+    el.children_named("entity")
+        .filter_map(|entity| {
+            Some(EntityMerge {
+                entity: entity.require("name", diags)?,
+                default_policy: parse_merge_policy_name(&entity.attr("default").unwrap_or_else(|| "lww".into())),
+                fields: entity.children_named("field")
+                    .filter_map(|field| parse_field_merge(field, diags))
+                    .collect(),
+                pre_condition: entity.attr("pre"),
+                post_validate: entity.attr("post"),
+                span: Some(entity.span),
+            })
+        })
+        .collect()
 }
 
-fn parse_field_merges(reader: &mut Reader<&[u8]>) -> Result<Vec<FieldMerge>, CompileError> {
-    let mut fields = Vec::new();
-    let mut buf = Vec::new();
-
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Empty(e)) | Ok(Event::Start(e)) if e.name().as_ref() == b"field" => {
-                let field = get_attr(&e, "name").unwrap_or_default();
-                let policy_str = get_attr(&e, "policy").unwrap_or_else(|| "lww".into());
-                let policy = parse_merge_policy_name(&policy_str);
-                let validate = get_attr(&e, "validate");
-
-                // Handle custom predicate reference
-                let final_policy = if policy_str.starts_with("predicate:") {
-                    MergePolicy::Custom {
-                        predicate: policy_str.trim_start_matches("predicate:").to_string()
-                    }
-                } else if let Some(actor) = get_attr(&e, "prefer_origin") {
-                    MergePolicy::PreferOrigin { actor }
-                } else {
-                    policy
-                };
+fn parse_field_merge(el: &Element, diags: &mut Vec<Diagnostic>) -> Option<FieldMerge> {
+    let field = el.require("name", diags)?;
+    let policy_str = el.attr("policy").unwrap_or_else(|| "lww".into());
 
-                fields.push(FieldMerge {
-                    field,
-                    policy: final_policy,
-                    validate,
-                });
-            }
-            Ok(Event::End(e)) if e.name().as_ref() == b"entity" => break,
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(CompileError::Parse(e.to_string())),
-            _ => {}
-        }
-        buf.clear();
-    }
+    // Handle custom predicate reference
+    let policy = if let Some(predicate) = policy_str.strip_prefix("predicate:") {
+        MergePolicy::Custom { predicate: predicate.to_string() }
+    } else if let Some(actor) = el.attr("prefer_origin") {
+        MergePolicy::PreferOrigin { actor }
+    } else {
+        parse_merge_policy_name(&policy_str)
+    };
 
-    Ok(fields)
+    Some(FieldMerge {
+        field,
+        policy,
+        validate: el.attr("validate"),
+        span: Some(el.span),
+    })
 }
 
-fn parse_merge_policy_name(name: &str) -> MergePolicy {
+pub(super) fn parse_merge_policy_name(name: &str) -> MergePolicy {
     match name.to_lowercase().as_str() {
         "lww" | "last-writer-wins" | "lastwriterwins" => MergePolicy::LWW,
         "fww" | "first-writer-wins" | "firstwriterwins" => MergePolicy::FWW,
@@ -747,4 +1132,393 @@ mod tests {
         assert_eq!(transaction.post_validate, Some("is_valid".into()));
         assert!(matches!(&transaction.fields[0].policy, MergePolicy::Custom { predicate } if predicate == "resolve_amount"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_multiple_empty_nodes() {
+        let xml = r#"
+            <omar version="1.0.0">
+                <workflow id="test">
+                    <entry p="t" x="r" node="a"/>
+                    <nodes>
+                        <node id="a" kind="transform"/>
+                        <node id="b" kind="transform"/>
+                        <node id="c" kind="terminal"/>
+                    </nodes>
+                    <edges>
+                        <edge from="a" to="b" weight="5" fallback="true"/>
+                        <edge from="b" to="c"/>
+                    </edges>
+                </workflow>
+            </omar>
+        "#;
+
+        let doc = parse(xml).unwrap();
+        let wf = &doc.workflows[0];
+        assert_eq!(wf.nodes.len(), 3);
+        assert_eq!(wf.edges.len(), 2);
+        assert_eq!(wf.edges[0].weight, Some(5));
+        assert!(wf.edges[0].fallback);
+    }
+
+    #[test]
+    fn test_parse_records_spans() {
+        let xml = "<omar version=\"1.0.0\">\n  <workflow id=\"test\">\n    <entry p=\"t\" x=\"r\" node=\"s\"/>\n    <nodes><node id=\"s\" kind=\"terminal\"/></nodes>\n  </workflow>\n</omar>";
+
+        let doc = parse(xml).unwrap();
+        let wf = &doc.workflows[0];
+
+        let span = wf.span.unwrap();
+        assert_eq!((span.line, span.column), (2, 3));
+        assert_eq!(&xml[span.start..span.end], "<workflow id=\"test\">");
+
+        let span = wf.entry.span.unwrap();
+        assert_eq!((span.line, span.column), (3, 5));
+
+        let span = wf.nodes[0].span.unwrap();
+        assert_eq!((span.line, span.column), (4, 12));
+        assert_eq!(&xml[span.start..span.end], "<node id=\"s\" kind=\"terminal\"/>");
+    }
+
+    #[test]
+    fn test_expr_attribute() {
+        let tree = parse(r#"<omar version="1.0.0">
+            <predicates><predicate id="p"><and>
+                <eq left="$token.role" right="admin"/><gt left="len($items)" right="0"/>
+            </and></predicate></predicates>
+        </omar>"#).unwrap();
+        let xml = "<omar version=\"1.0.0\">\n<predicates>\n  <predicate id=\"p\" expr=\"$token.role == 'admin' &amp;&amp; len($items) &gt; 0\"/>\n</predicates>\n</omar>";
+        let doc = parse(xml).unwrap();
+        assert_eq!(crate::dsl::writer::to_xml(&doc), crate::dsl::writer::to_xml(&tree));
+
+        // Spans point into the attribute value, past the escaped '&&'
+        let pred = &doc.predicates[0];
+        let gt = pred.expr_spans[2];
+        assert_eq!(&xml[gt.start..gt.end], "len($items) &gt; 0");
+        assert_eq!((gt.line, gt.column), (3, 61));
+    }
+    
+    #[test]
+    fn test_expr_attribute_errors() {
+        let xml = "<omar version=\"1.0.0\">\n  <workflow id=\"w\">\n    <entry p=\"t\" x=\"r\" node=\"a\"/>\n    <nodes><node id=\"a\"/><node id=\"b\"/></nodes>\n    <edges><edge from=\"a\" to=\"b\"><when expr=\"$x &gt; 1 &amp;&amp; = 2\"/></edge></edges>\n  </workflow>\n</omar>";
+        let (_, diags) = parse_recovering(xml);
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        assert_eq!(diags[0].code, "PARSE004");
+        assert_eq!(diags[0].message, "Invalid expr on <when>: Unexpected '='");
+        let span = diags[0].location.as_ref().unwrap().span.unwrap();
+        assert_eq!(&xml[span.start..span.end], "=");
+        assert_eq!((span.line, span.column), (5, 67));
+
+        let (_, diags) = parse_recovering(r#"<omar><predicates>
+            <predicate id="p" expr="always"><fail/></predicate>
+        </predicates></omar>"#);
+        assert_eq!(diags[0].message, "<predicate> has both an expr attribute and a condition element");
+    }
+    
+    #[test]
+    fn test_parse_compare() {
+        let doc = parse(r#"<omar version="1.0.0"><predicates>
+            <predicate id="short"><compare left="$input.end - $input.start" cmp="lt" right="86400"/></predicate>
+            <predicate id="infix" expr="$input.end - $input.start &lt; 86400"/>
+        </predicates></omar>"#).unwrap();
+        let PredicateExpr::Compare { cmp, left, right } = &doc.predicates[0].expr else {
+            panic!("expected compare");
+        };
+        assert_eq!((*cmp, left.to_string(), right.to_string()), (Comparison::Lt, "$input.end - $input.start".into(), "86400".into()));
+        assert_eq!(doc.predicates[0].expr, doc.predicates[1].expr);
+        let xml = crate::dsl::writer::to_xml(&doc);
+        assert!(xml.contains(r#"<compare left="$input.end - $input.start" cmp="lt" right="86400"/>"#), "{}", xml);
+
+        let xml = "<omar version=\"1.0.0\"><predicates>\n  <predicate id=\"p\"><compare left=\"$a * \" cmp=\"below\" right=\"1\"/></predicate>\n</predicates></omar>";
+        let (doc, diags) = parse_recovering(xml);
+        let messages: Vec<_> = diags.iter().map(|d| (d.code.as_str(), d.message.as_str())).collect();
+        assert_eq!(messages, [
+            ("PARSE004", "Invalid left on <compare>: Expected an operand"),
+            ("PARSE004", "Invalid cmp 'below' on <compare>"),
+        ]);
+        let span = diags[0].location.as_ref().unwrap().span.unwrap();
+        assert_eq!((span.line, span.column), (2, 41));
+        // An invalid comparison never holds
+        assert_eq!(doc.predicates[0].expr, PredicateExpr::Fail);
+    }
+    
+    #[test]
+    fn test_parse_membership() {
+        let doc = parse(r#"<omar version="1.0.0"><predicates>
+            <predicate id="closed"><in left="$deal.stage" values="won, lost,closed"/></predicate>
+            <predicate id="open"><not_in left="$http.status" values="500,502"/></predicate>
+        </predicates></omar>"#).unwrap();
+        assert!(matches!(&doc.predicates[0].expr, PredicateExpr::In { left, values }
+            if left == "$deal.stage" && values.len() == 3 && values[1] == Value::Str("lost".into())));
+        assert!(matches!(&doc.predicates[1].expr, PredicateExpr::NotIn { values, .. }
+            if *values == [Value::Int(500), Value::Int(502)]));
+        let xml = crate::dsl::writer::to_xml(&doc);
+        assert!(xml.contains(r#"<in left="$deal.stage" values="won,lost,closed"/>"#), "{}", xml);
+
+        let (doc, diags) = parse_recovering(r#"<omar version="1.0.0"><predicates>
+            <predicate id="p"><in left="$a" values="x,,y"/></predicate>
+            <predicate id="q"><in left="$a"/></predicate>
+        </predicates></omar>"#);
+        let codes: Vec<_> = diags.iter().map(|d| d.code.as_str()).collect();
+        assert_eq!(codes, ["PARSE004", "PARSE003"]);
+        assert_eq!(diags[0].message, "Invalid values on <in>: Empty member in list 'x,,y'");
+        assert_eq!(doc.predicates[0].expr, PredicateExpr::Fail);
+    }
+    
+    #[test]
+    fn test_parse_time_predicates() {
+        let doc = parse(r#"<omar version="1.0.0"><predicates>
+            <predicate id="new"><after left="$deal.created" right="2025-01-01"/></predicate>
+            <predicate id="stale"><before left="$deal.updated" right="-P30D"/></predicate>
+            <predicate id="due_soon"><within left="$task.due" duration="P3D"/></predicate>
+        </predicates></omar>"#).unwrap();
+        assert!(matches!(&doc.predicates[0].expr,
+            PredicateExpr::After { right, .. } if right == "2025-01-01"));
+        assert!(matches!(&doc.predicates[1].expr,
+            PredicateExpr::Before { right, .. } if right == "-P30D"));
+        assert!(matches!(&doc.predicates[2].expr,
+            PredicateExpr::Within { left, duration } if left == "$task.due" && duration == "P3D"));
+        let xml = crate::dsl::writer::to_xml(&doc);
+        for element in [
+            r#"<after left="$deal.created" right="2025-01-01"/>"#,
+            r#"<before left="$deal.updated" right="-P30D"/>"#,
+            r#"<within left="$task.due" duration="P3D"/>"#,
+        ] {
+            assert!(xml.contains(element), "{}", xml);
+        }
+        
+        // Date and duration shapes are only read in time comparisons
+        let doc = parse(r#"<omar version="1.0.0"><predicates>
+            <predicate id="urgent"><eq left="$ticket.priority" right="P1"/></predicate>
+            <predicate id="tagged"><eq left="$ticket.code" right="2025-01-01"/></predicate>
+        </predicates></omar>"#).unwrap();
+        assert!(matches!(&doc.predicates[0].expr, PredicateExpr::Eq { right: Value::Str(s), .. } if s == "P1"));
+        assert!(matches!(&doc.predicates[1].expr, PredicateExpr::Eq { right: Value::Str(s), .. } if s == "2025-01-01"));
+        
+        let (doc, diags) = parse_recovering(r#"<omar version="1.0.0"><predicates>
+            <predicate id="p"><after left="$deal.created" right="tomorrow"/></predicate>
+        </predicates></omar>"#);
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        assert_eq!(diags[0].code, "PARSE004");
+        assert_eq!(diags[0].message, "Invalid right 'tomorrow' on <after>: expected a date, a duration or a $variable");
+        assert!(diags[0].hint.as_deref().unwrap().contains(r#"right="-P30D""#));
+        assert_eq!(doc.predicates[0].expr, PredicateExpr::Fail);
+    }
+    
+    #[test]
+    fn test_profile_variables() {
+        let xml = "<omar version=\"1.0.0\">\n  <workflow id=\"w\">\n    <entry p=\"t\" x=\"r\" node=\"a\"/>\n    <nodes><node id=\"a\" kind=\"external\" op=\"${op}\"/><node id=\"b\" kind=\"terminal\" status=\"${code}\"/></nodes>\n    <edges><edge from=\"a\" to=\"b\"><when expr=\"$input.total &gt; ${limit}\"/></edge></edges>\n  </workflow>\n</omar>";
+        let mut profile = Profile::default();
+        for definition in ["op=0x0340", "code=202", "limit=500"] {
+            profile.define(definition).unwrap();
+        }
+        let options = ParseOptions { profile: Some(profile.clone()), ..Default::default() };
+        let (doc, diags) = parse_with_options(xml, &options);
+        assert!(diags.is_empty(), "{:?}", diags);
+        let workflow = &doc.workflows[0];
+        assert_eq!(workflow.nodes[0].op.as_deref(), Some("0x0340"));
+        assert_eq!(workflow.nodes[1].status, Some(202));
+        assert!(matches!(
+            &workflow.edges[0].predicate,
+            Some(PredicateExpr::Gt { right: Value::Int(500), .. })
+        ));
+
+        // Undefined: reported at the reference, and the value is left as written
+        profile.variables.remove("code");
+        let (_, diags) = parse_with_options(xml, &ParseOptions { profile: Some(profile), ..Default::default() });
+        let codes: Vec<_> = diags.iter().map(|d| d.code.as_str()).collect();
+        assert_eq!(codes, ["VAR001", "PARSE004"]);
+        assert_eq!(diags[0].message, "Undefined variable 'code'");
+        let span = diags[0].location.as_ref().unwrap().span.unwrap();
+        assert_eq!(&xml[span.start..span.end], "${code}");
+        assert_eq!(span.line, 4);
+
+        // Without a profile references are not touched
+        let (doc, _) = parse_recovering(xml);
+        assert_eq!(doc.workflows[0].nodes[0].op.as_deref(), Some("${op}"));
+    }
+
+    #[test]
+    fn test_parse_node_set() {
+        let xml = r#"<omar version="1.0.0"><workflow id="w"><entry p="t" x="r" node="s"/><nodes>
+            <node id="s" kind="signal"><set signal="busy" value="true"/><set key="target" value=""/></node>
+            <node id="bad" kind="signal"><set key="a" signal="b" value="1"/><set key="c"/></node>
+        </nodes></workflow></omar>"#;
+        let (doc, diags) = parse_recovering(xml);
+        let node = &doc.workflows[0].nodes[0];
+        assert_eq!(node.signals, [("busy".to_string(), "true".to_string())]);
+        assert_eq!(node.data["target"], "");
+
+        let messages: Vec<_> = diags.iter().map(|d| (d.code.as_str(), d.message.as_str())).collect();
+        assert_eq!(messages, [
+            ("PARSE003", "<set> needs exactly one of 'key' or 'signal'"),
+            ("PARSE003", "<set> is missing required attribute 'value'"),
+        ]);
+        assert!(doc.workflows[0].nodes[1].data.is_empty());
+    }
+
+    #[test]
+    fn test_parse_predicate_expr_spans() {
+        let xml = r#"
+            <omar version="1.0.0">
+                <predicates>
+                    <predicate id="p">
+                        <and>
+                            <eq left="$a" right="1"/>
+                            <not><fn name="defined" arg="$b"/></not>
+                        </and>
+                    </predicate>
+                </predicates>
+            </omar>
+        "#;
+
+        let doc = parse(xml).unwrap();
+        let pred = &doc.predicates[0];
+
+        // Pre-order: and, eq, not, fn
+        assert_eq!(pred.expr_spans.len(), 4);
+        let fn_span = pred.expr_spans[3];
+        assert!(xml[fn_span.start..fn_span.end].starts_with("<fn "));
+        assert_eq!(fn_span.line, 7);
+    }
+
+    #[test]
+    fn test_parse_unclosed_element() {
+        let xml = "<omar version=\"1.0.0\">\n  <workflow id=\"test\">\n</omar>";
+
+        let (doc, diags) = parse_recovering(xml);
+        assert_eq!(diags.len(), 2); // unclosed <workflow>, missing <entry>
+        assert_eq!(diags[0].code, "PARSE002");
+        assert_eq!(diags[0].location.as_ref().unwrap().span.unwrap().line, 2);
+
+        // The unclosed workflow is still part of the document
+        assert_eq!(doc.workflows.len(), 1);
+        assert_eq!(doc.workflows[0].id, "test");
+    }
+
+    #[test]
+    fn test_parse_reports_all_errors() {
+        let xml = r#"
+            <omar version="1.0.0">
+                <workflow id="test">
+                    <entry p="t" x="r" node="a"/>
+                    <nodes>
+                        <node id="a" kind="transform"/>
+                        <node kind="transform"/>
+                        <node id="b" kind="terminal" status="ok"/>
+                        <node id="c" kind="bogus"/>
+                    </nodes>
+                    <edges>
+                        <edge from="a" to="b"><when><always/></edge>
+                        <edge from="a"/>
+                    </edges>
+                </workflow>
+            </omar>
+        "#;
+
+        let (doc, diags) = parse_recovering(xml);
+        let codes: Vec<_> = diags.iter().map(|d| d.code.as_str()).collect();
+        assert_eq!(codes, ["PARSE002", "PARSE003", "PARSE004", "PARSE004", "PARSE003"]);
+        assert!(diags.iter().all(|d| d.location.as_ref().and_then(|l| l.span).is_some()));
+
+        // Partial document: unparseable elements are dropped, the rest kept
+        let wf = &doc.workflows[0];
+        assert_eq!(wf.nodes.len(), 3);
+        assert_eq!(wf.nodes[1].status, None);
+        assert_eq!(wf.nodes[2].kind, "transform");
+        assert_eq!(wf.edges.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_fails_with_all_diagnostics() {
+        let xml = r#"
+            <omar version="1.0.0">
+                <workflow>
+                    <entry p="t" node="a"/>
+                </workflow>
+            </omar>
+        "#;
+
+        match parse(xml) {
+            Err(CompileError::Validation { diagnostics }) => assert_eq!(diagnostics.len(), 2),
+            other => panic!("expected validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_stray_closing_tag() {
+        let xml = r#"
+            <omar version="1.0.0">
+                </nodes>
+                <workflow id="test">
+                    <entry p="t" x="r" node="a"/>
+                    <nodes><node id="a" kind="terminal"/></nodes>
+                </workflow>
+            </omar>
+        "#;
+
+        let (doc, diags) = parse_recovering(xml);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].code, "PARSE002");
+        assert!(diags[0].message.contains("</nodes>"));
+        assert_eq!(doc.workflows[0].nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_unescapes_attributes() {
+        let xml = r#"
+            <omar version="1.0.0">
+                <predicates>
+                    <predicate id="p"><eq left="$a" right="a &amp; b"/></predicate>
+                </predicates>
+            </omar>
+        "#;
+
+        let doc = parse(xml).unwrap();
+        assert!(matches!(&doc.predicates[0].expr, PredicateExpr::Eq { right: Value::Str(s), .. } if s == "a & b"));
+    }
+
+    #[test]
+    fn test_unknown_names_are_warnings() {
+        let xml = r#"
+            <omar version="1.0.0">
+                <workflow id="test">
+                    <entry p="t" x="r" node="a"/>
+                    <nodes>
+                        <nod id="b"/>
+                        <node id="a" kind="terminal" stauts="200"/>
+                    </nodes>
+                </workflow>
+            </omar>
+        "#;
+
+        let (doc, diags) = parse_recovering(xml);
+        assert_eq!(diags.len(), 2);
+        assert!(diags.iter().all(|d| d.severity == Severity::Warn));
+
+        assert_eq!(diags[0].code, "PARSE005");
+        assert_eq!(diags[0].hint.as_deref(), Some("Did you mean <node>?"));
+        assert_eq!(diags[1].code, "PARSE006");
+        assert_eq!(diags[1].hint.as_deref(), Some("Did you mean 'status'?"));
+
+        assert_eq!(doc.workflows[0].nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_unknown_names() {
+        let xml = r#"
+            <omar version="1.0.0">
+                <workflow id="test">
+                    <entry p="t" x="r" node="a"/>
+                    <nodes><node id="a" kind="terminal"/></nodes>
+                    <edges><node id="b"/></edges>
+                </workflow>
+            </omar>
+        "#;
+
+        let (_, diags) = parse_with_options(xml, &ParseOptions { strict: true, ..Default::default() });
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Error);
+        assert!(diags[0].message.contains("not allowed inside <edges>"));
+    }
+}
+
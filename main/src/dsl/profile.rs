@@ -0,0 +1,251 @@
+//! Compile-time Profiles
+//!
+//! The same workflows are often deployed to several environments that
+//! differ in a handful of values: webhook ops, thresholds, feature
+//! toggles. Instead of forking the XML, attribute values may reference
+//! compile-time variables as `${name}`, resolved from a `Profile` when the
+//! document is compiled:
+//!
+//! ```xml
+//! <node id="notify" kind="external" op="${webhook_op}"/>
+//! <gt left="$input.total" right="${review_threshold}"/>
+//! ```
+//!
+//! A profile file holds one `name = value` per line; blank lines and lines
+//! starting with `#` are ignored. `pxyz compile --define name=value` adds
+//! or overrides variables. The chosen profile and its values are recorded
+//! in the audit.
+//!
+//! Variables are substituted in XML attribute values before they are read,
+//! so a variable may stand for any attribute: an op, a status code, an
+//! operand, part of an `expr`. `$${` is a literal `${`. Text (`.pxyz`) and
+//! JSON documents are read as written.
+//!
+//! Diagnostic codes:
+//! - VAR001: Undefined variable
+//! - VAR002: Malformed variable reference (unterminated `${`, bad name)
+//! - VAR003: Invalid profile line or definition
+
+use super::parser::LineIndex;
+use crate::{CompileError, Diagnostic, Location, Severity};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Variable values for one deployment target
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Profile {
+    /// Profile name (the file stem when loaded from a file)
+    pub name: Option<String>,
+    pub variables: BTreeMap<String, String>,
+}
+
+/// A `${...}` reference that could not be resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableError {
+    pub code: &'static str,
+    pub message: String,
+    pub hint: Option<String>,
+    /// Byte range of the reference in the substituted value
+    pub range: (usize, usize),
+}
+
+impl Profile {
+    /// Read a profile file; the profile is named after the file stem
+    pub fn load(path: &Path) -> Result<Self, CompileError> {
+        let source = std::fs::read_to_string(path)?;
+        let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        Self::parse(&name, &source).map_err(|err| match err {
+            CompileError::Validation { mut diagnostics } => {
+                for diag in &mut diagnostics {
+                    if let Some(location) = &mut diag.location {
+                        location.file = Some(path.display().to_string());
+                    }
+                }
+                CompileError::Validation { diagnostics }
+            }
+            err => err,
+        })
+    }
+
+    /// Parse `name = value` lines
+    ///
+    /// Every invalid line is reported (VAR003). Values are trimmed; a
+    /// later line for the same name replaces an earlier one.
+    pub fn parse(name: &str, source: &str) -> Result<Self, CompileError> {
+        let lines = LineIndex::new(source);
+        let mut profile = Self { name: Some(name.to_string()), variables: BTreeMap::new() };
+        let mut diagnostics = Vec::new();
+
+        let mut offset = 0;
+        for line in source.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(message) = profile.insert(line) {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    code: "VAR003".into(),
+                    message,
+                    hint: Some("Write one `name = value` per line".into()),
+                    location: Some(Location {
+                        span: Some(lines.span(start, offset)),
+                        ..Default::default()
+                    }),
+                });
+            }
+        }
+
+        if !diagnostics.is_empty() {
+            return Err(CompileError::Validation { diagnostics });
+        }
+        Ok(profile)
+    }
+
+    /// Set a variable from a `name=value` definition, as given to `--define`
+    pub fn define(&mut self, definition: &str) -> Result<(), CompileError> {
+        self.insert(definition).map_err(|message| CompileError::Validation {
+            diagnostics: vec![Diagnostic {
+                severity: Severity::Error,
+                code: "VAR003".into(),
+                message,
+                hint: Some("Use --define name=value".into()),
+                location: None,
+            }],
+        })
+    }
+
+    fn insert(&mut self, definition: &str) -> Result<(), String> {
+        let Some((name, value)) = definition.split_once('=') else {
+            return Err(format!("Expected `name = value`, found '{}'", definition));
+        };
+        let name = name.trim();
+        if !is_variable_name(name) {
+            return Err(format!("Invalid variable name '{}'", name));
+        }
+        self.variables.insert(name.to_string(), value.trim().to_string());
+        Ok(())
+    }
+
+    /// True if the profile has neither a name nor variables
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.variables.is_empty()
+    }
+
+    /// Replace every `${name}` in `value`, stopping at the first reference
+    /// that cannot be resolved
+    pub fn substitute<'a>(&self, value: &'a str) -> Result<Cow<'a, str>, VariableError> {
+        if !value.contains("${") {
+            return Ok(Cow::Borrowed(value));
+        }
+
+        let mut out = String::with_capacity(value.len());
+        let mut rest = 0;
+        while let Some(found) = value[rest..].find('$') {
+            let at = rest + found;
+            out.push_str(&value[rest..at]);
+            let tail = &value[at..];
+
+            if tail.starts_with("$${") {
+                out.push_str("${");
+                rest = at + 3;
+            } else if tail.starts_with("${") {
+                let Some(close) = tail.find('}') else {
+                    return Err(VariableError {
+                        code: "VAR002",
+                        message: format!("Unterminated variable reference '{}'", tail),
+                        hint: Some("Close it with '}', or write $${ for a literal ${".into()),
+                        range: (at, value.len()),
+                    });
+                };
+                let name = &tail[2..close];
+                let range = (at, at + close + 1);
+                if !is_variable_name(name) {
+                    return Err(VariableError {
+                        code: "VAR002",
+                        message: format!("Invalid variable name '{}'", name),
+                        hint: Some("Names are letters, digits, '_', '-' and '.'".into()),
+                        range,
+                    });
+                }
+                let Some(resolved) = self.variables.get(name) else {
+                    return Err(VariableError {
+                        code: "VAR001",
+                        message: format!("Undefined variable '{}'", name),
+                        hint: Some(format!("Define it in the profile or with --define {}=...", name)),
+                        range,
+                    });
+                };
+                out.push_str(resolved);
+                rest = range.1;
+            } else {
+                out.push('$');
+                rest = at + 1;
+            }
+        }
+        out.push_str(&value[rest..]);
+        Ok(Cow::Owned(out))
+    }
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(pairs: &[(&str, &str)]) -> Profile {
+        Profile {
+            name: None,
+            variables: pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let p = Profile::parse("staging", "# staging\n\nwebhook_op = 0x0340\nthreshold=100 \nthreshold = 250\n").unwrap();
+        assert_eq!(p.name.as_deref(), Some("staging"));
+        assert_eq!(p.variables["webhook_op"], "0x0340");
+        assert_eq!(p.variables["threshold"], "250");
+
+        let Err(CompileError::Validation { diagnostics }) = Profile::parse("bad", "ok = 1\nnot a pair\n1x = 2\n") else {
+            panic!("expected diagnostics");
+        };
+        let lines: Vec<_> = diagnostics.iter()
+            .map(|d| (d.code.as_str(), d.location.as_ref().unwrap().span.unwrap().line))
+            .collect();
+        assert_eq!(lines, [("VAR003", 2), ("VAR003", 3)]);
+    }
+
+    #[test]
+    fn test_define_overrides() {
+        let mut p = profile(&[("mode", "strict")]);
+        p.define("mode=lenient").unwrap();
+        p.define("empty=").unwrap();
+        assert_eq!(p.variables["mode"], "lenient");
+        assert_eq!(p.variables["empty"], "");
+        assert!(p.define("no_equals").is_err());
+    }
+
+    #[test]
+    fn test_substitute() {
+        let p = profile(&[("op", "0x0340"), ("env", "prod")]);
+        assert!(matches!(p.substitute("plain $input.x"), Ok(Cow::Borrowed(_))));
+        assert_eq!(p.substitute("${op}").unwrap(), "0x0340");
+        assert_eq!(p.substitute("svc.${env}.${env}$").unwrap(), "svc.prod.prod$");
+        assert_eq!(p.substitute("$${op} is ${op}").unwrap(), "${op} is 0x0340");
+
+        let err = p.substitute("a ${missing} b").unwrap_err();
+        assert_eq!((err.code, err.range), ("VAR001", (2, 12)));
+        assert_eq!(p.substitute("${op").unwrap_err().code, "VAR002");
+        assert_eq!(p.substitute("${}").unwrap_err().code, "VAR002");
+    }
+}
@@ -0,0 +1,1185 @@
+//! Compact Text Front-end
+//!
+//! A line-oriented alternative to the XML syntax. It reads into the same
+//! `OmarDocument`, so everything after parsing (and the binary) is shared
+//! with XML. Files ending in `.pxyz` are read with this parser.
+//!
+//! ```text
+//! omar 1.0.0
+//! import "shared/auth.pxyz" as auth
+//!
+//! predicate is_admin = $token.role == "admin"
+//!
+//! workflow hello description="Say hello" {
+//!   entry hello world -> start
+//!   node start transform
+//!   node done terminal status=200
+//!   node denied error status=403
+//!   start -> done when is_admin or $token.sub == "root"
+//!   start -> denied fallback
+//! }
+//! ```
+//!
+//! | Statement                                               | XML equivalent            |
+//! |---------------------------------------------------------|---------------------------|
+//! | `omar <version>`                                        | `<omar version>`          |
+//! | `import <href> [as <ns>]`                               | `<import>`                |
+//! | `schema <name> {` … `}`                                 | `<schema>`                |
+//! | `field <name> [<type>] [required] [default=…] [pattern=…]` | `<field>` in a schema  |
+//! | `predicate <id> = <expr>`                               | `<predicate>`             |
+//! | `workflow <id> [description=…] [schema=…] {` … `}`      | `<workflow>`              |
+//! | `entry <p> <x> -> <node>`                               | `<entry>`                 |
+//! | `node <id> [<kind>] [key=value \| flag]…`               | `<node>`                  |
+//! | `<from> -> <to> [weight=…] [parallel] [fallback] [when <expr>]` | `<edge>`          |
+//! | `use <fragment> as <id> [param=value]…`                 | `<use>`                   |
+//! | `fragment <id>(<param>, …) {` … `}`                     | `<fragment>`              |
+//! | `template <id> <string>`                                | `<template>`              |
+//! | `merge <entity> [default=…] [pre=…] [post=…] [{` … `}]` | `<entity>`                |
+//! | `field <name> [policy=…] [validate=…] [prefer_origin=…]` | `<field>` in an entity   |
+//!
+//! Node options are the `<node>` attributes plus `schema`, `require`,
+//! `selector`, `message`, `set:<signal>=<value>` and `data:<key>=<value>`
+//! for its child elements.
+//! Expressions use the infix syntax of [`super::expr`].
+//!
+//! Values are bare words or double-quoted strings (`\"`, `\\`, `\n`, `\t`
+//! escapes); `"""…"""` strings may span lines and are kept verbatim. `#`
+//! starts a comment. A block opens with `{` at the end of its header line
+//! and closes with `}` on a line of its own.
+//!
+//! Diagnostics use the XML parser's codes: PARSE001 for syntax errors,
+//! PARSE002 for unbalanced braces, PARSE003-004 for missing and invalid
+//! values, PARSE005-006 for unknown statements and options (warnings, or
+//! errors in strict mode).
+
+use super::ast::*;
+use super::expr;
+use super::parser::{error, parse_merge_policy_name, report, LineIndex, ParseOptions};
+use super::version::{check_version, DSL_VERSION};
+use super::vocabulary;
+use crate::{CompileError, Diagnostic, NodeKind, Severity, Span};
+
+const TOP_LEVEL: &[&str] = &["omar", "import", "schema", "predicate", "workflow", "fragment", "template", "merge"];
+const WORKFLOW_BODY: &[&str] = &["entry", "node", "use"];
+const NODE_OPTIONS: &[&str] = &[
+    "op", "template", "status", "actor", "confirmation", "async", "cacheable", "workflow",
+    "schema", "require", "selector", "message",
+];
+const EDGE_OPTIONS: &[&str] = &["weight", "parallel", "fallback"];
+const FIELD_OPTIONS: &[&str] = &["required", "default", "pattern"];
+const MERGE_OPTIONS: &[&str] = &["default", "pre", "post"];
+const FIELD_MERGE_OPTIONS: &[&str] = &["policy", "validate", "prefer_origin"];
+
+/// Parse text to AST
+///
+/// Fails with `CompileError::Validation` carrying every parse diagnostic
+/// if the document has errors.
+pub fn parse(src: &str) -> Result<OmarDocument, CompileError> {
+    let (doc, diagnostics) = parse_recovering(src);
+
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return Err(CompileError::Validation { diagnostics });
+    }
+
+    Ok(doc)
+}
+
+/// Parse text to a (possibly partial) AST, collecting all parse diagnostics
+///
+/// A statement that cannot be parsed is dropped (with its block, if it
+/// opens one); everything else is kept.
+pub fn parse_recovering(src: &str) -> (OmarDocument, Vec<Diagnostic>) {
+    parse_with_options(src, &ParseOptions::default())
+}
+
+/// Recovering parse with explicit options
+pub fn parse_with_options(src: &str, options: &ParseOptions) -> (OmarDocument, Vec<Diagnostic>) {
+    let mut parser = TextParser {
+        src,
+        lines: LineIndex::new(src),
+        statements: statements(src),
+        next: 0,
+        severity: if options.strict { Severity::Error } else { Severity::Warn },
+        diags: Vec::new(),
+    };
+    let doc = parser.document();
+    (doc, parser.diags)
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// STATEMENTS AND TOKENS
+// ═══════════════════════════════════════════════════════════════════════════
+
+/// Byte range of one statement, trimmed and without its comment
+#[derive(Debug, Clone, Copy)]
+struct Stmt {
+    start: usize,
+    end: usize,
+}
+
+/// Split the source into statements: one per line, except that a `"""`
+/// string carries its statement over to the lines it spans
+fn statements(src: &str) -> Vec<Stmt> {
+    let bytes = src.as_bytes();
+    let mut stmts = Vec::new();
+    let mut push = |start: usize, end: usize| {
+        let text = &src[start..end];
+        let start = start + (text.len() - text.trim_start().len());
+        let end = start + text.trim().len();
+        if start < end {
+            stmts.push(Stmt { start, end });
+        }
+    };
+
+    let (mut start, mut i) = (0, 0);
+    let (mut in_string, mut in_block_string) = (false, false);
+    let mut comment = None;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if in_block_string {
+            if src[i..].starts_with(r#"""""#) {
+                in_block_string = false;
+                i += 3;
+            } else {
+                i += 1;
+            }
+            continue;
+        }
+        if b == b'\n' {
+            push(start, comment.unwrap_or(i));
+            start = i + 1;
+            in_string = false;
+            comment = None;
+        } else if comment.is_some() {
+            // Rest of the line is a comment
+        } else if in_string {
+            match b {
+                b'\\' => i += 1,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else if src[i..].starts_with(r#"""""#) {
+            in_block_string = true;
+            i += 3;
+            continue;
+        } else if b == b'"' {
+            in_string = true;
+        } else if b == b'#' {
+            comment = Some(i);
+        }
+        i += 1;
+    }
+    push(start, comment.unwrap_or(bytes.len()));
+
+    stmts
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tok {
+    Word,
+    Str,
+    Arrow,
+    Equals,
+    LBrace,
+    RBrace,
+    LParen,
+    RParen,
+    Comma,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: Tok,
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+/// A syntax error at byte range `start..end` of the source
+#[derive(Debug)]
+struct SyntaxError {
+    message: String,
+    start: usize,
+    end: usize,
+}
+
+impl SyntaxError {
+    fn at(token: &Token, message: String) -> Self {
+        Self { message, start: token.start, end: token.end }
+    }
+}
+
+/// One option of a statement: `key=value` or a bare `flag`
+#[derive(Debug)]
+struct Opt {
+    key: String,
+    value: Option<String>,
+    start: usize,
+    end: usize,
+}
+
+/// How a statement's options end
+#[derive(Debug, PartialEq)]
+enum End {
+    Line,
+    /// `when`, followed by an expression starting at this offset
+    When(usize),
+    /// `{`, opening a block
+    Block,
+}
+
+/// Tokenizer over one statement
+#[derive(Clone)]
+struct Cursor<'a> {
+    src: &'a str,
+    pos: usize,
+    end: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(src: &'a str, stmt: Stmt) -> Self {
+        Self { src, pos: stmt.start, end: stmt.end }
+    }
+
+    fn next(&mut self) -> Result<Option<Token>, SyntaxError> {
+        let rest = &self.src[self.pos..self.end];
+        self.pos += rest.len() - rest.trim_start().len();
+        let rest = &self.src[self.pos..self.end];
+        let start = self.pos;
+
+        let Some(c) = rest.chars().next() else {
+            return Ok(None);
+        };
+        let (kind, text, len) = if rest.starts_with("->") {
+            (Tok::Arrow, "->".to_string(), 2)
+        } else if let Some(kind) = punctuation(c) {
+            (kind, c.to_string(), 1)
+        } else if let Some(body) = rest.strip_prefix(r#"""""#) {
+            let Some(close) = body.find(r#"""""#) else {
+                return Err(SyntaxError {
+                    message: "Unterminated \"\"\" string".into(),
+                    start,
+                    end: self.end,
+                });
+            };
+            (Tok::Str, body[..close].to_string(), close + 6)
+        } else if c == '"' {
+            let (text, len) = quoted(rest).ok_or_else(|| SyntaxError {
+                message: "Unterminated string".into(),
+                start,
+                end: self.end,
+            })?;
+            (Tok::Str, text, len)
+        } else {
+            let len = rest.char_indices()
+                .find(|&(i, ch)| {
+                    ch.is_whitespace() || ch == '"' || punctuation(ch).is_some() || rest[i..].starts_with("->")
+                })
+                .map(|(i, _)| i)
+                .unwrap_or(rest.len());
+            (Tok::Word, rest[..len].to_string(), len)
+        };
+
+        self.pos += len;
+        Ok(Some(Token { kind, text, start, end: self.pos }))
+    }
+
+    fn peek(&self) -> Result<Option<Token>, SyntaxError> {
+        self.clone().next()
+    }
+
+    /// The token after the next one
+    fn peek2(&self) -> Result<Option<Token>, SyntaxError> {
+        let mut ahead = self.clone();
+        ahead.next()?;
+        ahead.next()
+    }
+
+    /// Error for a missing token at the end of the statement
+    fn missing(&self, what: &str) -> SyntaxError {
+        SyntaxError {
+            message: format!("Expected {} at end of line", what),
+            start: self.end,
+            end: self.end,
+        }
+    }
+
+    fn expect(&mut self, kind: Tok, what: &str) -> Result<Token, SyntaxError> {
+        match self.next()? {
+            Some(token) if token.kind == kind => Ok(token),
+            Some(token) => Err(SyntaxError::at(&token, format!("Expected {}, found '{}'", what, token.text))),
+            None => Err(self.missing(what)),
+        }
+    }
+
+    /// A bare word or a string
+    fn value(&mut self, what: &str) -> Result<Token, SyntaxError> {
+        match self.next()? {
+            Some(token) if matches!(token.kind, Tok::Word | Tok::Str) => Ok(token),
+            Some(token) => Err(SyntaxError::at(&token, format!("Expected {}, found '{}'", what, token.text))),
+            None => Err(self.missing(what)),
+        }
+    }
+
+    /// Options up to the end of the statement, `when` or `{`
+    fn options(&mut self, allow_when: bool) -> Result<(Vec<Opt>, End), SyntaxError> {
+        let mut opts = Vec::new();
+        loop {
+            let Some(token) = self.next()? else {
+                return Ok((opts, End::Line));
+            };
+            match token.kind {
+                Tok::Word if allow_when && token.text == "when" => {
+                    return Ok((opts, End::When(token.end)));
+                }
+                Tok::Word => {
+                    let mut opt = Opt { key: token.text, value: None, start: token.start, end: token.end };
+                    if self.peek()?.is_some_and(|t| t.kind == Tok::Equals) {
+                        self.next()?;
+                        let value = self.value("a value")?;
+                        opt.end = value.end;
+                        opt.value = Some(value.text);
+                    }
+                    opts.push(opt);
+                }
+                Tok::LBrace => {
+                    if let Some(extra) = self.next()? {
+                        return Err(SyntaxError::at(&extra, "'{' must end the line".into()));
+                    }
+                    return Ok((opts, End::Block));
+                }
+                _ => return Err(SyntaxError::at(&token, format!("Unexpected '{}'", token.text))),
+            }
+        }
+    }
+
+    /// Everything left in the statement
+    fn rest(&mut self) -> (usize, &'a str) {
+        let start = self.pos;
+        self.pos = self.end;
+        (start, &self.src[start..self.end])
+    }
+}
+
+fn punctuation(c: char) -> Option<Tok> {
+    match c {
+        '=' => Some(Tok::Equals),
+        '{' => Some(Tok::LBrace),
+        '}' => Some(Tok::RBrace),
+        '(' => Some(Tok::LParen),
+        ')' => Some(Tok::RParen),
+        ',' => Some(Tok::Comma),
+        _ => None,
+    }
+}
+
+/// Unescape a `"..."` string at the start of `s`, returning the text and
+/// the length consumed
+fn quoted(s: &str) -> Option<(String, usize)> {
+    let mut text = String::new();
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((text, i + 1)),
+            '\\' => match chars.next()?.1 {
+                'n' => text.push('\n'),
+                't' => text.push('\t'),
+                'r' => text.push('\r'),
+                other => text.push(other),
+            },
+            c => text.push(c),
+        }
+    }
+    None
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// STATEMENTS → AST
+// ═══════════════════════════════════════════════════════════════════════════
+
+struct TextParser<'a> {
+    src: &'a str,
+    lines: LineIndex<'a>,
+    statements: Vec<Stmt>,
+    next: usize,
+    /// Severity of unknown statements and options
+    severity: Severity,
+    diags: Vec<Diagnostic>,
+}
+
+/// Nodes, edges and uses of a workflow or fragment body
+#[derive(Default)]
+struct Graph {
+    entry: Option<EntryPoint>,
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    uses: Vec<FragmentUse>,
+}
+
+impl<'a> TextParser<'a> {
+    fn span(&self, start: usize, end: usize) -> Span {
+        self.lines.span(start, end)
+    }
+
+    fn stmt_span(&self, stmt: Stmt) -> Span {
+        self.span(stmt.start, stmt.end)
+    }
+
+    fn text(&self, stmt: Stmt) -> &'a str {
+        &self.src[stmt.start..stmt.end]
+    }
+
+    fn next_statement(&mut self) -> Option<Stmt> {
+        let stmt = self.statements.get(self.next).copied();
+        self.next += 1;
+        stmt
+    }
+
+    fn syntax(&mut self, e: SyntaxError) {
+        let span = self.span(e.start, e.end);
+        error(&mut self.diags, "PARSE001", e.message, None, span);
+    }
+
+    /// Report a statement that failed to parse, skipping its block
+    fn reject(&mut self, stmt: Stmt, e: SyntaxError) {
+        self.syntax(e);
+        if self.text(stmt).ends_with('{') {
+            self.skip_block();
+        }
+    }
+
+    /// Skip to the `}` closing the block just opened
+    fn skip_block(&mut self) {
+        let mut depth = 1;
+        while let Some(stmt) = self.next_statement() {
+            let text = self.text(stmt);
+            if text == "}" {
+                depth -= 1;
+                if depth == 0 {
+                    return;
+                }
+            } else if text.ends_with('{') {
+                depth += 1;
+            }
+        }
+    }
+
+    /// Statements of a block up to its closing `}` (PARSE002 if missing)
+    fn block(&mut self, header: Stmt) -> Vec<Stmt> {
+        let mut body = Vec::new();
+        while let Some(stmt) = self.next_statement() {
+            if self.text(stmt) == "}" {
+                return body;
+            }
+            body.push(stmt);
+        }
+        let span = self.stmt_span(header);
+        error(
+            &mut self.diags,
+            "PARSE002",
+            "Block is never closed".into(),
+            Some("Add a line containing only '}'".into()),
+            span,
+        );
+        body
+    }
+
+    fn unknown_statement(&mut self, stmt: Stmt, keyword: &Token, known: &[&str], context: &str) {
+        let hint = vocabulary::suggest(&keyword.text, known.iter().copied())
+            .map(|s| format!("Did you mean '{}'?", s))
+            .unwrap_or_else(|| format!("Expected one of: {}", known.join(", ")));
+        let span = self.span(keyword.start, keyword.end);
+        report(
+            &mut self.diags,
+            self.severity,
+            "PARSE005",
+            format!("Unknown statement '{}' {}", keyword.text, context),
+            Some(hint),
+            span,
+        );
+        if self.text(stmt).ends_with('{') {
+            self.skip_block();
+        }
+    }
+
+    fn unknown_option(&mut self, opt: &Opt, context: &str, known: &[&str]) {
+        let hint = match vocabulary::suggest(&opt.key, known.iter().copied()) {
+            Some(s) => format!("Did you mean '{}'?", s),
+            None => format!("Known options: {}", known.join(", ")),
+        };
+        let span = self.span(opt.start, opt.end);
+        report(
+            &mut self.diags,
+            self.severity,
+            "PARSE006",
+            format!("Unknown option '{}' on {}", opt.key, context),
+            Some(hint),
+            span,
+        );
+    }
+
+    /// Value of a `key=value` option (PARSE003 for a bare `key`)
+    fn option_value(&mut self, opt: &Opt) -> Option<String> {
+        if opt.value.is_none() {
+            let span = self.span(opt.start, opt.end);
+            error(
+                &mut self.diags,
+                "PARSE003",
+                format!("Option '{}' needs a value", opt.key),
+                Some(format!("Write {}=<value>", opt.key)),
+                span,
+            );
+        }
+        opt.value.clone()
+    }
+
+    /// A flag option: bare `flag`, or `flag=true`/`flag=false`
+    fn flag(opt: &Opt) -> bool {
+        opt.value.as_deref().is_none_or(|v| v == "true")
+    }
+
+    fn u16_option(&mut self, opt: &Opt) -> Option<u16> {
+        let value = self.option_value(opt)?;
+        match value.trim().parse() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                let span = self.span(opt.start, opt.end);
+                error(
+                    &mut self.diags,
+                    "PARSE004",
+                    format!("Invalid {} '{}'", opt.key, value),
+                    Some(format!("{} must be an integer between 0 and {}", opt.key, u16::MAX)),
+                    span,
+                );
+                None
+            }
+        }
+    }
+
+    /// Parse an infix expression at `offset`, returning it with the spans
+    /// of its sub-expressions
+    fn expression(&mut self, offset: usize, src: &str) -> Option<(PredicateExpr, Vec<Span>)> {
+        match expr::parse_expr(src) {
+            Ok((expr, ranges)) => {
+                let spans = ranges.iter()
+                    .map(|&(start, end)| self.span(offset + start, offset + end))
+                    .collect();
+                Some((expr, spans))
+            }
+            Err(e) => {
+                self.syntax(SyntaxError {
+                    message: e.message,
+                    start: offset + e.start,
+                    end: offset + e.end,
+                });
+                None
+            }
+        }
+    }
+
+    fn document(&mut self) -> OmarDocument {
+        let mut doc = OmarDocument { version: DSL_VERSION.into(), ..Default::default() };
+
+        while let Some(stmt) = self.next_statement() {
+            if let Err(e) = self.top_level(stmt, &mut doc) {
+                self.reject(stmt, e);
+            }
+        }
+
+        doc
+    }
+
+    fn top_level(&mut self, stmt: Stmt, doc: &mut OmarDocument) -> Result<(), SyntaxError> {
+        let mut cur = Cursor::new(self.src, stmt);
+        let keyword = cur.next()?.ok_or_else(|| cur.missing("a statement"))?;
+        if keyword.kind == Tok::RBrace {
+            let span = self.span(keyword.start, keyword.end);
+            error(&mut self.diags, "PARSE002", "Unexpected '}'".into(), Some("No block is open here".into()), span);
+            return Ok(());
+        }
+        if keyword.kind != Tok::Word {
+            return Err(SyntaxError::at(&keyword, format!("Expected a statement, found '{}'", keyword.text)));
+        }
+        let stmt_span = self.stmt_span(stmt);
+        let span = Some(stmt_span);
+
+        match keyword.text.as_str() {
+            "omar" => {
+                let version = cur.value("a version")?;
+                check_version(&version.text, Some(self.span(version.start, version.end)), &mut self.diags);
+                doc.version = version.text;
+            }
+            "import" => {
+                let href = cur.value("an import path")?.text;
+                let namespace = match cur.next()? {
+                    Some(t) if t.kind == Tok::Word && t.text == "as" => Some(cur.value("a namespace")?.text),
+                    Some(t) => return Err(SyntaxError::at(&t, format!("Expected 'as', found '{}'", t.text))),
+                    None => None,
+                };
+                doc.imports.push(Import { href, namespace, span });
+            }
+            "schema" => {
+                let name = cur.value("a schema name")?.text;
+                cur.expect(Tok::LBrace, "'{'")?;
+                let fields = self.schema_fields(stmt);
+                doc.schemas.push(Schema { name, fields, span });
+            }
+            "predicate" => {
+                let id = cur.value("a predicate id")?.text;
+                cur.expect(Tok::Equals, "'='")?;
+                let (offset, src) = cur.rest();
+                if let Some((expr, expr_spans)) = self.expression(offset, src) {
+                    doc.predicates.push(PredicateDef { id, expr, span, expr_spans, expr_source: None });
+                }
+            }
+            "workflow" => {
+                let id = cur.value("a workflow id")?;
+                let (opts, end) = cur.options(false)?;
+                if end != End::Block {
+                    return Err(cur.missing("'{'"));
+                }
+                let mut description = None;
+                let mut schema = None;
+                for opt in &opts {
+                    match opt.key.as_str() {
+                        "description" => description = self.option_value(opt),
+                        "schema" => schema = self.option_value(opt),
+                        _ => self.unknown_option(opt, "workflow", &["description", "schema"]),
+                    }
+                }
+                let graph = self.graph(stmt, true);
+                let entry = graph.entry.unwrap_or_else(|| {
+                    error(
+                        &mut self.diags,
+                        "PARSE003",
+                        format!("Workflow '{}' has no entry", id.text),
+                        Some("Add 'entry <p> <x> -> <node>' to the workflow".into()),
+                        stmt_span,
+                    );
+                    EntryPoint { p: String::new(), x: String::new(), node: String::new(), span: None }
+                });
+                doc.workflows.push(Workflow {
+                    id: id.text,
+                    description,
+                    schema,
+                    entry,
+                    nodes: graph.nodes,
+                    edges: graph.edges,
+                    uses: graph.uses,
+                    span,
+                });
+            }
+            "fragment" => {
+                let id = cur.value("a fragment id")?.text;
+                let mut params = Vec::new();
+                if cur.peek()?.is_some_and(|t| t.kind == Tok::LParen) {
+                    cur.next()?;
+                    loop {
+                        let token = cur.next()?.ok_or_else(|| cur.missing("')'"))?;
+                        match token.kind {
+                            Tok::RParen => break,
+                            Tok::Comma => {}
+                            Tok::Word => params.push(token.text),
+                            _ => return Err(SyntaxError::at(&token, format!("Unexpected '{}'", token.text))),
+                        }
+                    }
+                }
+                cur.expect(Tok::LBrace, "'{'")?;
+                let graph = self.graph(stmt, false);
+                doc.fragments.push(Fragment {
+                    id,
+                    params,
+                    nodes: graph.nodes,
+                    edges: graph.edges,
+                    uses: graph.uses,
+                    span,
+                });
+            }
+            "template" => {
+                let id = cur.value("a template id")?.text;
+                let content = cur.value("the template content")?.text;
+                doc.templates.push(Template { id, content, span });
+            }
+            "merge" => {
+                let entity = cur.value("an entity name")?.text;
+                let (opts, end) = cur.options(false)?;
+                let mut merge = EntityMerge {
+                    entity,
+                    default_policy: MergePolicy::LWW,
+                    fields: Vec::new(),
+                    pre_condition: None,
+                    post_validate: None,
+                    span,
+                };
+                for opt in &opts {
+                    match opt.key.as_str() {
+                        "default" => {
+                            if let Some(v) = self.option_value(opt) {
+                                merge.default_policy = parse_merge_policy_name(&v);
+                            }
+                        }
+                        "pre" => merge.pre_condition = self.option_value(opt),
+                        "post" => merge.post_validate = self.option_value(opt),
+                        _ => self.unknown_option(opt, "merge", MERGE_OPTIONS),
+                    }
+                }
+                if end == End::Block {
+                    merge.fields = self.merge_fields(stmt);
+                }
+                doc.merge_policies.push(merge);
+            }
+            _ => {
+                self.unknown_statement(stmt, &keyword, TOP_LEVEL, "at the top level");
+                return Ok(());
+            }
+        }
+
+        if let Some(extra) = cur.next()? {
+            return Err(SyntaxError::at(&extra, format!("Unexpected '{}'", extra.text)));
+        }
+        Ok(())
+    }
+
+    /// Statements of a block whose lines all start with `field`
+    fn fields(&mut self, header: Stmt) -> Vec<(Stmt, Cursor<'a>)> {
+        let mut fields = Vec::new();
+        for stmt in self.block(header) {
+            let mut cur = Cursor::new(self.src, stmt);
+            match cur.next() {
+                Ok(Some(t)) if t.kind == Tok::Word && t.text == "field" => fields.push((stmt, cur)),
+                Ok(Some(t)) => self.unknown_statement(stmt, &t, &["field"], "in this block"),
+                Ok(None) => {}
+                Err(e) => self.reject(stmt, e),
+            }
+        }
+        fields
+    }
+
+    fn schema_fields(&mut self, header: Stmt) -> Vec<FieldDef> {
+        let mut fields = Vec::new();
+        for (stmt, mut cur) in self.fields(header) {
+            match self.schema_field(stmt, &mut cur) {
+                Ok(field) => fields.push(field),
+                Err(e) => self.syntax(e),
+            }
+        }
+        fields
+    }
+
+    fn schema_field(&mut self, stmt: Stmt, cur: &mut Cursor) -> Result<FieldDef, SyntaxError> {
+        let name = cur.value("a field name")?.text;
+        let mut field_type = "string".to_string();
+        if let Some(t) = cur.peek()? {
+            let is_option = FIELD_OPTIONS.contains(&t.text.as_str())
+                || cur.peek2()?.is_some_and(|next| next.kind == Tok::Equals);
+            if t.kind == Tok::Word && !is_option {
+                field_type = cur.value("a type")?.text;
+            }
+        }
+
+        let mut field = FieldDef {
+            name,
+            field_type,
+            required: false,
+            default: None,
+            pattern: None,
+            span: Some(self.stmt_span(stmt)),
+        };
+        let (opts, end) = cur.options(false)?;
+        if end == End::Block {
+            return Err(cur.missing("end of line"));
+        }
+        for opt in &opts {
+            match opt.key.as_str() {
+                "required" => field.required = Self::flag(opt),
+                "default" => field.default = self.option_value(opt),
+                "pattern" => field.pattern = self.option_value(opt),
+                _ => self.unknown_option(opt, "field", FIELD_OPTIONS),
+            }
+        }
+        Ok(field)
+    }
+
+    fn merge_fields(&mut self, header: Stmt) -> Vec<FieldMerge> {
+        let mut fields = Vec::new();
+        for (stmt, mut cur) in self.fields(header) {
+            match self.merge_field(stmt, &mut cur) {
+                Ok(field) => fields.push(field),
+                Err(e) => self.syntax(e),
+            }
+        }
+        fields
+    }
+
+    fn merge_field(&mut self, stmt: Stmt, cur: &mut Cursor) -> Result<FieldMerge, SyntaxError> {
+        let field = cur.value("a field name")?.text;
+        let (opts, end) = cur.options(false)?;
+        if end == End::Block {
+            return Err(cur.missing("end of line"));
+        }
+
+        let mut policy_str = "lww".to_string();
+        let mut prefer_origin = None;
+        let mut validate = None;
+        for opt in &opts {
+            match opt.key.as_str() {
+                "policy" => policy_str = self.option_value(opt).unwrap_or(policy_str),
+                "validate" => validate = self.option_value(opt),
+                "prefer_origin" => prefer_origin = self.option_value(opt),
+                _ => self.unknown_option(opt, "field", FIELD_MERGE_OPTIONS),
+            }
+        }
+
+        // Same precedence as the XML <field> attributes
+        let policy = if let Some(predicate) = policy_str.strip_prefix("predicate:") {
+            MergePolicy::Custom { predicate: predicate.to_string() }
+        } else if let Some(actor) = prefer_origin {
+            MergePolicy::PreferOrigin { actor }
+        } else {
+            parse_merge_policy_name(&policy_str)
+        };
+
+        Ok(FieldMerge { field, policy, validate, span: Some(self.stmt_span(stmt)) })
+    }
+
+    /// Body of a workflow (`has_entry`) or fragment
+    fn graph(&mut self, header: Stmt, has_entry: bool) -> Graph {
+        let mut graph = Graph::default();
+        let context = if has_entry { "in a workflow" } else { "in a fragment" };
+        let known: &[&str] = if has_entry { WORKFLOW_BODY } else { &WORKFLOW_BODY[1..] };
+
+        for stmt in self.block(header) {
+            let mut cur = Cursor::new(self.src, stmt);
+            let result = cur.next().and_then(|first| {
+                let first = first.ok_or_else(|| cur.missing("a statement"))?;
+                let is_edge = cur.peek()?.is_some_and(|t| t.kind == Tok::Arrow);
+                match first.text.as_str() {
+                    _ if is_edge && matches!(first.kind, Tok::Word | Tok::Str) => {
+                        graph.edges.push(self.edge(stmt, first, &mut cur)?);
+                    }
+                    "entry" if has_entry && first.kind == Tok::Word => {
+                        let p = cur.value("a p value")?.text;
+                        let x = cur.value("an x value")?.text;
+                        cur.expect(Tok::Arrow, "'->'")?;
+                        let node = cur.value("the entry node")?.text;
+                        graph.entry = Some(EntryPoint { p, x, node, span: Some(self.stmt_span(stmt)) });
+                    }
+                    "node" if first.kind == Tok::Word => graph.nodes.extend(self.node(stmt, &mut cur)?),
+                    "use" if first.kind == Tok::Word => graph.uses.push(self.fragment_use(stmt, &mut cur)?),
+                    _ if first.kind == Tok::Word => {
+                        self.unknown_statement(stmt, &first, known, context);
+                        cur.rest();
+                    }
+                    _ => return Err(SyntaxError::at(&first, format!("Unexpected '{}'", first.text))),
+                }
+                match cur.next()? {
+                    Some(extra) => Err(SyntaxError::at(&extra, format!("Unexpected '{}'", extra.text))),
+                    None => Ok(()),
+                }
+            });
+            if let Err(e) = result {
+                self.reject(stmt, e);
+            }
+        }
+
+        graph
+    }
+
+    fn node(&mut self, stmt: Stmt, cur: &mut Cursor) -> Result<Option<Node>, SyntaxError> {
+        let id = cur.value("a node id")?.text;
+        let mut kind = "transform".to_string();
+        if let Some(t) = cur.peek()? {
+            let is_option = cur.peek2()?.is_some_and(|next| next.kind == Tok::Equals)
+                || ["async", "cacheable"].contains(&t.text.as_str());
+            if t.kind == Tok::Word && !is_option {
+                kind = cur.value("a node kind")?.text;
+            }
+        }
+        let (opts, end) = cur.options(false)?;
+        if end == End::Block {
+            return Err(cur.missing("end of line"));
+        }
+
+        let span = self.stmt_span(stmt);
+        let mut node = Node { id, span: Some(span), ..Default::default() };
+        for opt in &opts {
+            if let Some(signal) = opt.key.strip_prefix("set:") {
+                if let Some(value) = self.option_value(opt) {
+                    node.signals.push((signal.to_string(), value));
+                }
+                continue;
+            }
+            if let Some(key) = opt.key.strip_prefix("data:") {
+                if let Some(value) = self.option_value(opt) {
+                    node.data.insert(key.to_string(), value);
+                }
+                continue;
+            }
+            match opt.key.as_str() {
+                "op" => node.op = self.option_value(opt),
+                "template" => node.template = self.option_value(opt),
+                "status" => node.status = self.u16_option(opt),
+                "actor" => node.actor = self.option_value(opt),
+                "confirmation" => node.confirmation = self.option_value(opt),
+                "async" => node.async_node = Self::flag(opt),
+                "cacheable" => node.cacheable = Self::flag(opt),
+                "workflow" => node.call = self.option_value(opt),
+                "schema" => node.schema = self.option_value(opt),
+                "require" => node.predicate = self.option_value(opt),
+                "selector" => node.selector = self.option_value(opt),
+                "message" => node.message = self.option_value(opt),
+                _ => self.unknown_option(opt, "node", NODE_OPTIONS),
+            }
+        }
+
+        // Same kind handling as the XML parser: bad kinds are reported and
+        // the node kept as a transform so edges to it still resolve
+        if kind == "call" {
+            if node.call.is_none() {
+                error(
+                    &mut self.diags,
+                    "PARSE003",
+                    format!("Call node '{}' is missing option 'workflow'", node.id),
+                    Some("Add workflow=<id> to the node".into()),
+                    span,
+                );
+                kind = "transform".into();
+            }
+        } else {
+            node.call = None;
+            if let Err(e) = kind.parse::<NodeKind>() {
+                error(
+                    &mut self.diags,
+                    "PARSE004",
+                    format!("Node '{}': {}", node.id, e),
+                    Some("Valid kinds: transform, external, render, signal, auth, terminal, error, call".into()),
+                    span,
+                );
+                kind = "transform".into();
+            }
+        }
+        node.kind = kind;
+
+        Ok(Some(node))
+    }
+
+    fn edge(&mut self, stmt: Stmt, from: Token, cur: &mut Cursor) -> Result<Edge, SyntaxError> {
+        cur.expect(Tok::Arrow, "'->'")?;
+        let to = cur.value("a target node")?.text;
+        let (opts, end) = cur.options(true)?;
+
+        let mut edge = Edge {
+            from: from.text,
+            to,
+            predicate: Some(PredicateExpr::Always),
+            span: Some(self.stmt_span(stmt)),
+            ..Default::default()
+        };
+        for opt in &opts {
+            match opt.key.as_str() {
+                "weight" => edge.weight = self.u16_option(opt),
+                "parallel" => edge.parallel = Self::flag(opt),
+                "fallback" => edge.fallback = Self::flag(opt),
+                _ => self.unknown_option(opt, "edge", EDGE_OPTIONS),
+            }
+        }
+        match end {
+            End::When(_) => {
+                let (offset, src) = cur.rest();
+                if let Some((expr, spans)) = self.expression(offset, src) {
+                    edge.predicate = Some(expr);
+                    edge.predicate_spans = spans;
+                }
+            }
+            End::Block => return Err(cur.missing("end of line")),
+            End::Line => {}
+        }
+
+        Ok(edge)
+    }
+
+    fn fragment_use(&mut self, stmt: Stmt, cur: &mut Cursor) -> Result<FragmentUse, SyntaxError> {
+        let fragment = cur.value("a fragment id")?.text;
+        let as_token = cur.expect(Tok::Word, "'as'")?;
+        if as_token.text != "as" {
+            return Err(SyntaxError::at(&as_token, format!("Expected 'as', found '{}'", as_token.text)));
+        }
+        let id = cur.value("a use id")?.text;
+        let (opts, end) = cur.options(false)?;
+        if end == End::Block {
+            return Err(cur.missing("end of line"));
+        }
+
+        let mut args = Vec::new();
+        for opt in &opts {
+            if let Some(value) = self.option_value(opt) {
+                args.push((opt.key.clone(), value));
+            }
+        }
+        Ok(FragmentUse { fragment, id, args, span: Some(self.stmt_span(stmt)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsl::writer::to_xml;
+
+    const TEXT: &str = r#"
+omar 1.0.0
+import "shared/auth.pxyz" as auth
+
+schema Order {
+  field id string required
+  field total number default=0
+}
+
+# Predicates
+predicate is_admin = $token.role == "admin"
+predicate big = $input.total > 1000 and not is_admin
+
+workflow orders description="Order intake" {
+  entry orders create -> start
+  node start transform schema=Order
+  node save external op=0x0900 data:channel=email set:saving=true
+  node done terminal status=200
+  use retry as again times=3
+  start -> save when is_admin or $token.sub == "root"
+  start -> done fallback weight=2
+  save -> done
+}
+
+fragment retry(times) {
+  node wait transform
+}
+
+template greeting """Hello,
+  {name}!"""
+
+merge Order default=max {
+  field total policy=lww
+  field owner prefer_origin=admin
+}
+"#;
+
+    const XML: &str = r#"<omar version="1.0.0">
+        <import href="shared/auth.pxyz" as="auth"/>
+        <schemas>
+            <schema name="Order">
+                <field name="id" type="string" required="true"/>
+                <field name="total" type="number" default="0"/>
+            </schema>
+        </schemas>
+        <predicates>
+            <predicate id="is_admin"><eq left="$token.role" right="admin"/></predicate>
+            <predicate id="big">
+                <and><gt left="$input.total" right="1000"/><not><ref predicate="is_admin"/></not></and>
+            </predicate>
+        </predicates>
+        <workflow id="orders" description="Order intake">
+            <entry p="orders" x="create" node="start"/>
+            <nodes>
+                <node id="start" kind="transform"><schema ref="Order"/></node>
+                <node id="save" kind="external" op="0x0900"><set key="channel" value="email"/><set signal="saving" value="true"/></node>
+                <node id="done" kind="terminal" status="200"/>
+                <use fragment="retry" id="again" times="3"/>
+            </nodes>
+            <edges>
+                <edge from="start" to="save">
+                    <when><or><ref predicate="is_admin"/><eq left="$token.sub" right="root"/></or></when>
+                </edge>
+                <edge from="start" to="done" fallback="true" weight="2"/>
+                <edge from="save" to="done"/>
+            </edges>
+        </workflow>
+        <fragment id="retry" params="times">
+            <nodes><node id="wait" kind="transform"/></nodes>
+        </fragment>
+        <templates>
+            <template id="greeting"><![CDATA[Hello,
+  {name}!]]></template>
+        </templates>
+        <merge>
+            <entity name="Order" default="max">
+                <field name="total" policy="lww"/>
+                <field name="owner" prefer_origin="admin"/>
+            </entity>
+        </merge>
+    </omar>"#;
+
+    #[test]
+    fn test_same_document_as_xml() {
+        let (doc, diags) = parse_recovering(TEXT);
+        assert!(diags.is_empty(), "{:?}", diags);
+        let xml = crate::dsl::parse(XML).unwrap();
+        assert_eq!(to_xml(&doc), to_xml(&xml));
+    }
+
+    #[test]
+    fn test_records_spans() {
+        let doc = parse(TEXT).unwrap();
+        let edge = &doc.workflows[0].edges[0];
+        let span = edge.span.unwrap();
+        assert!(TEXT[span.start..span.end].starts_with("start -> save"));
+
+        // Sub-expression spans point into the line
+        let ref_span = edge.predicate_spans[1];
+        assert_eq!(&TEXT[ref_span.start..ref_span.end], "is_admin");
+        assert_eq!(ref_span.line, span.line);
+    }
+
+    #[test]
+    fn test_reports_all_errors() {
+        let src = r#"
+workflow w {
+  entry w run -> a
+  node a transform status=abc
+  a -> b when $x ==
+  node b termnal
+}
+predicate p = (
+"#;
+        let (doc, diags) = parse_recovering(src);
+        let codes: Vec<_> = diags.iter().map(|d| d.code.as_str()).collect();
+        assert_eq!(codes, ["PARSE004", "PARSE001", "PARSE004", "PARSE001"]);
+
+        // Expression errors point at the offending column
+        let span = diags[1].location.as_ref().unwrap().span.unwrap();
+        assert_eq!(span.line, 5);
+        assert!(span.column > "  a -> b when $x".len() as u32);
+
+        // Everything else is kept
+        assert_eq!(doc.workflows[0].nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_unbalanced_braces() {
+        let (_, diags) = parse_recovering("workflow w {\n  entry w run -> a\n");
+        assert_eq!(diags[0].code, "PARSE002");
+
+        let (_, diags) = parse_recovering("}\n");
+        assert_eq!(diags[0].code, "PARSE002");
+    }
+
+    #[test]
+    fn test_unknown_names() {
+        let src = "workflw w {\n}\nworkflow v {\n  entry v run -> a\n  node a transfrom opp=1\n}\n";
+        let (doc, diags) = parse_recovering(src);
+        assert_eq!(diags[0].code, "PARSE005");
+        assert_eq!(diags[0].hint.as_deref(), Some("Did you mean 'workflow'?"));
+        assert!(diags.iter().any(|d| d.code == "PARSE006" && d.hint.as_deref() == Some("Did you mean 'op'?")));
+        assert!(diags.iter().filter(|d| d.code != "PARSE004").all(|d| d.severity == Severity::Warn));
+        // The misspelled block is skipped without closing the next one early
+        assert_eq!(doc.workflows.len(), 1);
+
+        let (_, diags) = parse_with_options(src, &ParseOptions { strict: true, ..Default::default() });
+        assert!(diags.iter().all(|d| d.severity == Severity::Error));
+    }
+
+    #[test]
+    fn test_comments_and_strings() {
+        let src = "workflow w { # trailing\n  entry w run -> a\n  node a render message=\"# not a comment\"\n}\n";
+        let doc = parse(src).unwrap();
+        assert_eq!(doc.workflows[0].nodes[0].message.as_deref(), Some("# not a comment"));
+    }
+}
@@ -0,0 +1,214 @@
+//! Date and Duration Literals
+//!
+//! Predicate values may be points in time or lengths of time, written in
+//! ISO 8601:
+//!
+//! - a date, `2025-01-01` (midnight UTC), or a date-time with an explicit
+//!   offset, `2025-01-01T09:30:00Z` or `2025-01-01T09:30:00.250+02:00`
+//! - a duration, `P3D`, `PT12H`, `P1W` or `P1DT2H30M`, optionally negative
+//!   (`-P7D`); only weeks, days, hours, minutes and seconds, since months
+//!   and years have no fixed length
+//!
+//! Both compile to milliseconds: dates since the Unix epoch, durations as
+//! a signed length. Only `<before>`, `<after>`, `<within>` and `<compare>`
+//! read them (`Value::time_from_str` recognises the shapes); elsewhere
+//! `2025-01-01` or `P1` is a plain string. The compiler checks the values,
+//! so `2025-02-30` or `P1M` is a compile error rather than a string that
+//! silently never matches.
+
+use chrono::{DateTime, NaiveDate, NaiveTime};
+
+const MILLIS_PER_WEEK: i64 = 7 * MILLIS_PER_DAY;
+const MILLIS_PER_DAY: i64 = 24 * MILLIS_PER_HOUR;
+const MILLIS_PER_HOUR: i64 = 60 * MILLIS_PER_MINUTE;
+const MILLIS_PER_MINUTE: i64 = 60 * MILLIS_PER_SECOND;
+const MILLIS_PER_SECOND: i64 = 1000;
+
+/// Duration units in the order they must appear, with their length
+const UNITS: [(char, bool, i64); 5] = [
+    ('W', false, MILLIS_PER_WEEK),
+    ('D', false, MILLIS_PER_DAY),
+    ('H', true, MILLIS_PER_HOUR),
+    ('M', true, MILLIS_PER_MINUTE),
+    ('S', true, MILLIS_PER_SECOND),
+];
+
+/// True if `s` is shaped like a date: `YYYY-MM-DD`, optionally followed
+/// by `T` and a time
+pub fn is_time_literal(s: &str) -> bool {
+    let b = s.as_bytes();
+    let digits = |range: std::ops::Range<usize>| b[range].iter().all(u8::is_ascii_digit);
+    b.len() >= 10
+        && digits(0..4)
+        && b[4] == b'-'
+        && digits(5..7)
+        && b[7] == b'-'
+        && digits(8..10)
+        && (b.len() == 10 || b[10] == b'T')
+}
+
+/// True if `s` is shaped like a duration: `P` (or `-P`) followed by
+/// digits and unit letters
+pub fn is_duration_literal(s: &str) -> bool {
+    let s = s.strip_prefix('-').unwrap_or(s);
+    s.strip_prefix('P').is_some_and(|rest| {
+        rest.starts_with(|c: char| c.is_ascii_digit() || c == 'T')
+            && rest.chars().all(|c| c.is_ascii_digit() || "YMWDTHS.".contains(c))
+    })
+}
+
+/// Milliseconds since the Unix epoch of a date or date-time literal
+pub fn parse_time(s: &str) -> Result<i64, String> {
+    if s.len() == 10 {
+        let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map_err(|_| format!("Invalid date '{}'", s))?;
+        return Ok(date.and_time(NaiveTime::MIN).and_utc().timestamp_millis());
+    }
+
+    let time = DateTime::parse_from_rfc3339(s).map_err(|_| format!(
+        "Invalid date-time '{}': expected YYYY-MM-DDThh:mm:ss with Z or an offset such as +02:00",
+        s
+    ))?;
+    if time.timestamp_subsec_nanos() % 1_000_000 != 0 {
+        return Err(format!("Date-time '{}' loses precision: at most 3 fractional second digits", s));
+    }
+    Ok(time.timestamp_millis())
+}
+
+/// Signed length in milliseconds of a duration literal
+pub fn parse_duration(s: &str) -> Result<i64, String> {
+    let invalid = || format!("Invalid duration '{}': expected e.g. P3D, PT12H or -P1DT6H", s);
+    let (negative, rest) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut millis: i64 = 0;
+    let mut in_time = false;
+    let mut next_unit = 0;
+    let mut number = String::new();
+    for c in rest.chars() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        if c == 'T' && !in_time && number.is_empty() {
+            in_time = true;
+            continue;
+        }
+        if !in_time && matches!(c, 'Y' | 'M') {
+            return Err(format!(
+                "Duration '{}' uses years or months, which have no fixed length; use weeks or days",
+                s
+            ));
+        }
+
+        let Some(index) = UNITS.iter().position(|&(unit, time, _)| unit == c && time == in_time) else {
+            return Err(invalid());
+        };
+        if index < next_unit || number.is_empty() {
+            return Err(invalid());
+        }
+        next_unit = index + 1;
+
+        let length = UNITS[index].2;
+        let amount = match number.split_once('.') {
+            None => number.parse::<i64>().ok().and_then(|n| n.checked_mul(length)),
+            // Only seconds may have a fraction, of at most millisecond digits
+            Some((whole, fraction)) if c == 'S' && !whole.is_empty() && (1..=3).contains(&fraction.len()) => {
+                let fraction: i64 = format!("{:0<3}", fraction).parse().map_err(|_| invalid())?;
+                whole.parse::<i64>().ok()
+                    .and_then(|n| n.checked_mul(length))
+                    .and_then(|n| n.checked_add(fraction))
+            }
+            Some(_) => return Err(invalid()),
+        };
+        millis = amount
+            .and_then(|amount| millis.checked_add(amount))
+            .ok_or_else(|| format!("Duration '{}' is out of range", s))?;
+        number.clear();
+    }
+
+    if next_unit == 0 || !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(if negative { -millis } else { millis })
+}
+
+/// Write a length in milliseconds as a duration literal (`P1DT6H`)
+pub fn format_duration(millis: i64) -> String {
+    let mut out = String::from(if millis < 0 { "-P" } else { "P" });
+    let mut rest = millis.unsigned_abs();
+    for (unit, time, length) in UNITS.into_iter().skip(1) {
+        let length = length as u64;
+        let amount = if unit == 'S' { rest } else { rest / length * length };
+        if amount == 0 {
+            continue;
+        }
+        if time && !out.contains('T') {
+            out.push('T');
+        }
+        if unit == 'S' {
+            let (seconds, fraction) = (amount / length, amount % length);
+            out.push_str(&seconds.to_string());
+            if fraction > 0 {
+                out.push_str(format!(".{:03}", fraction).trim_end_matches('0'));
+            }
+        } else {
+            out.push_str(&(amount / length).to_string());
+        }
+        out.push(unit);
+        rest -= amount;
+    }
+    if rest == 0 && out.ends_with('P') {
+        out.push_str("0D");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time() {
+        assert!(is_time_literal("2025-01-01"));
+        assert!(is_time_literal("2025-01-01T09:30:00Z"));
+        assert!(!is_time_literal("2025-01-01 report"));
+        assert!(!is_time_literal("20250101"));
+
+        assert_eq!(parse_time("1970-01-02"), Ok(86_400_000));
+        assert_eq!(parse_time("2025-01-01T00:00:00.250+01:00"), Ok(1_735_686_000_250));
+        assert!(parse_time("2025-02-30").unwrap_err().starts_with("Invalid date"));
+        assert!(parse_time("2025-01-01T09:30:00").unwrap_err().contains("offset"));
+        assert!(parse_time("2025-01-01T09:30:00.0001Z").unwrap_err().contains("loses precision"));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert!(is_duration_literal("P3D"));
+        assert!(is_duration_literal("-PT0.5S"));
+        assert!(!is_duration_literal("PDF"));
+        assert!(!is_duration_literal("P"));
+
+        assert_eq!(parse_duration("P3D"), Ok(3 * MILLIS_PER_DAY));
+        assert_eq!(parse_duration("-P1W"), Ok(-MILLIS_PER_WEEK));
+        assert_eq!(parse_duration("P1DT2H30M"), Ok(MILLIS_PER_DAY + 2 * MILLIS_PER_HOUR + 30 * MILLIS_PER_MINUTE));
+        assert_eq!(parse_duration("PT1.5S"), Ok(1500));
+        for invalid in ["P", "PT", "P3", "P3H", "PT3D", "P1D2W", "P1.5D", "PT0.0001S"] {
+            assert!(parse_duration(invalid).unwrap_err().starts_with("Invalid duration"), "{}", invalid);
+        }
+        assert!(parse_duration("P1M").unwrap_err().contains("no fixed length"));
+        assert!(parse_duration("P99999999999999W").unwrap_err().contains("out of range"));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        for literal in ["P3D", "-P7D", "P1DT6H", "PT1H30M", "PT0.25S", "P0D"] {
+            assert_eq!(format_duration(parse_duration(literal).unwrap()), literal);
+        }
+        // Weeks are written as days
+        assert_eq!(format_duration(MILLIS_PER_WEEK), "P7D");
+    }
+}
@@ -0,0 +1,306 @@
+//! DSL Versions and Migrations
+//!
+//! `<omar version="...">` states which version of the DSL a document is
+//! written in. Parsers check it against the version this compiler reads:
+//!
+//! - same major version, not newer than `DSL_VERSION`: accepted
+//!   (`1.0` means `1.0.0`)
+//! - newer than `DSL_VERSION`: VER001, the document may use features this
+//!   compiler would misread
+//! - older major version: VER002, the document must be migrated first;
+//!   the hint suggests `pxyz migrate` only if `MIGRATIONS` leads from its
+//!   version, and otherwise an update by hand
+//! - not a version number: VER003
+//!
+//! A breaking DSL change bumps `DSL_VERSION` and adds a `Migration` from
+//! the previous version to `MIGRATIONS`. `migrate` applies the chain of
+//! migrations that leads from a document's version to the current one;
+//! `pxyz migrate` runs it on a file and writes canonical XML.
+
+use super::ast::OmarDocument;
+use super::parser::{report, ParseOptions};
+use super::{parse_source, writer, JSON_EXTENSION, TEXT_EXTENSION};
+use crate::{CompileError, Diagnostic, Location, Severity, Span};
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::Path;
+
+/// Version of the DSL this compiler reads and writes
+pub const DSL_VERSION: &str = "1.0.0";
+
+/// Known breaking changes, each upgrading a document by one version
+///
+/// 1.0.0 is the first versioned release of the DSL, so there is nothing
+/// to migrate from yet.
+pub const MIGRATIONS: &[Migration] = &[];
+
+/// An AST rewrite from one DSL version to the next
+#[derive(Debug)]
+pub struct Migration {
+    pub from: &'static str,
+    pub to: &'static str,
+    /// One-line summary, printed by `pxyz migrate`
+    pub description: &'static str,
+    pub apply: fn(&mut OmarDocument),
+}
+
+/// A `major.minor.patch` version; missing parts are zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.trim().split('.');
+        let mut next = || parts.next().map(|p| p.parse::<u32>().ok());
+        let major = next()??;
+        let minor = next().unwrap_or(Some(0))?;
+        let patch = next().unwrap_or(Some(0))?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self { major, minor, patch })
+    }
+
+    /// The version this compiler reads
+    pub fn current() -> Self {
+        Self::parse(DSL_VERSION).expect("DSL_VERSION is a valid version")
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Report a document version this compiler cannot read
+pub(crate) fn check_version(version: &str, span: Option<Span>, diags: &mut Vec<Diagnostic>) {
+    let Err((code, message, hint)) = supported(version) else {
+        return;
+    };
+    match span {
+        Some(span) => report(diags, Severity::Error, code, message, Some(hint), span),
+        None => diags.push(Diagnostic {
+            severity: Severity::Error,
+            code: code.into(),
+            message,
+            hint: Some(hint),
+            location: None,
+        }),
+    }
+}
+
+/// `Err((code, message, hint))` if `version` cannot be read as it is
+fn supported(version: &str) -> Result<(), (&'static str, String, String)> {
+    supported_with(version, MIGRATIONS)
+}
+
+/// `supported` with an explicit migration chain, which decides whether
+/// VER002 suggests `pxyz migrate`
+fn supported_with(version: &str, migrations: &[Migration]) -> Result<(), (&'static str, String, String)> {
+    let current = Version::current();
+    let Some(v) = Version::parse(version) else {
+        return Err((
+            "VER003",
+            format!("Invalid DSL version '{}'", version),
+            format!("Use a version number such as version=\"{}\"", DSL_VERSION),
+        ));
+    };
+
+    match (v.major.cmp(&current.major), v.cmp(&current)) {
+        (_, Ordering::Greater) => Err((
+            "VER001",
+            format!("Document version {} is newer than this compiler supports ({})", v, current),
+            "Upgrade pxyz to compile this document".into(),
+        )),
+        (Ordering::Less, _) => Err((
+            "VER002",
+            format!("Document version {} is no longer supported (current: {})", v, current),
+            match chain(v, migrations) {
+                Ok(_) => "Upgrade it with `pxyz migrate -i <file> -o <file>`".into(),
+                Err(_) if v.major == 0 => format!(
+                    "{} predates versioned DSL releases; update the document to {} by hand", v, current
+                ),
+                Err(from) => format!(
+                    "No migration leads from {}; update the document to {} by hand", from, current
+                ),
+            },
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Upgrade a document to `DSL_VERSION`, returning the migrations applied
+pub fn migrate(doc: OmarDocument) -> Result<(OmarDocument, Vec<&'static Migration>), CompileError> {
+    migrate_with(doc, MIGRATIONS)
+}
+
+/// `migrate` over an explicit migration chain
+pub fn migrate_with(
+    mut doc: OmarDocument,
+    migrations: &[Migration],
+) -> Result<(OmarDocument, Vec<&Migration>), CompileError> {
+    let fail = |code: &str, message: String| CompileError::Validation {
+        diagnostics: vec![Diagnostic {
+            severity: Severity::Error,
+            code: code.into(),
+            message,
+            hint: None,
+            location: None::<Location>,
+        }],
+    };
+
+    let current = Version::current();
+    let version = Version::parse(&doc.version)
+        .ok_or_else(|| fail("VER003", format!("Invalid DSL version '{}'", doc.version)))?;
+    if version > current {
+        return Err(fail("VER001", format!("Document version {} is newer than {}", version, current)));
+    }
+
+    let applied = chain(version, migrations)
+        .map_err(|from| fail("VER002", format!("No migration from DSL version {}", from)))?;
+    for migration in &applied {
+        (migration.apply)(&mut doc);
+    }
+
+    doc.version = current.to_string();
+    Ok((doc, applied))
+}
+
+/// The migrations leading from `from` to `DSL_VERSION`, in order, or the
+/// version no migration starts from
+fn chain(from: Version, migrations: &[Migration]) -> Result<Vec<&Migration>, Version> {
+    let current = Version::current();
+    let mut version = from;
+    let mut steps = Vec::new();
+    while version < current {
+        let migration = migrations.iter()
+            .find(|m| Version::parse(m.from) == Some(version))
+            .ok_or(version)?;
+        version = Version::parse(migration.to).expect("migration target is a valid version");
+        steps.push(migration);
+    }
+    Ok(steps)
+}
+
+/// Migrate a workflow file, returning formatted XML (as `pxyz fmt`
+/// writes it) and the migrations applied
+///
+/// The front-end is chosen by `path` as in `parse_source`. Like `pxyz fmt`,
+/// an XML document with comments is refused (FMT001) rather than rewritten
+/// without them.
+pub fn migrate_source(
+    path: &Path,
+    source: &str,
+) -> Result<(String, Vec<&'static Migration>), CompileError> {
+    let (doc, mut diagnostics) = parse_source(path, source, &ParseOptions::default());
+    // An old version is what migration is for
+    diagnostics.retain(|d| d.code != "VER002");
+    if !matches!(path.extension().and_then(|ext| ext.to_str()), Some(TEXT_EXTENSION | JSON_EXTENSION)) {
+        diagnostics.extend(writer::comments(source));
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return Err(CompileError::Validation { diagnostics });
+    }
+
+    let (doc, applied) = migrate(doc)?;
+    Ok((writer::to_formatted_xml(&doc), applied))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsl::parse_recovering;
+
+    fn codes(xml: &str) -> Vec<String> {
+        parse_recovering(xml).1.into_iter().map(|d| d.code).collect()
+    }
+
+    fn messages(err: CompileError) -> Vec<String> {
+        let CompileError::Validation { diagnostics } = err else {
+            panic!("expected diagnostics, got {}", err);
+        };
+        diagnostics.into_iter().map(|d| format!("{}: {}", d.code, d.message)).collect()
+    }
+
+    #[test]
+    fn test_version_parse() {
+        assert_eq!(Version::parse("1.0"), Version::parse("1.0.0"));
+        assert_eq!(Version::parse("2").unwrap().to_string(), "2.0.0");
+        assert!(Version::parse("1.x").is_none());
+        assert!(Version::parse("1.0.0.0").is_none());
+        assert!(Version::parse("").is_none());
+    }
+
+    #[test]
+    fn test_version_checked() {
+        assert!(codes(r#"<omar version="1.0"/>"#).is_empty());
+        assert!(codes(r#"<omar version="1.0.0"/>"#).is_empty());
+        assert_eq!(codes(r#"<omar version="1.1.0"/>"#), ["VER001"]);
+        assert_eq!(codes(r#"<omar version="2.0"/>"#), ["VER001"]);
+        assert_eq!(codes(r#"<omar version="0.9"/>"#), ["VER002"]);
+        assert_eq!(codes(r#"<omar version="one"/>"#), ["VER003"]);
+    }
+
+    #[test]
+    fn test_ver002_hint_follows_migrations() {
+        let hint = |version, migrations| supported_with(version, migrations).unwrap_err().2;
+        // No migrations are registered, so `pxyz migrate` cannot help
+        assert_eq!(hint("0.9", MIGRATIONS), format!("0.9.0 predates versioned DSL releases; update the document to {} by hand", DSL_VERSION));
+
+        fn noop(_: &mut OmarDocument) {}
+        let chain = [Migration { from: "0.9.0", to: "1.0.0", description: "noop", apply: noop }];
+        assert_eq!(hint("0.9", &chain), "Upgrade it with `pxyz migrate -i <file> -o <file>`");
+        assert!(hint("0.8", &chain).contains("by hand"));
+    }
+
+    #[test]
+    fn test_migration_chain() {
+        fn rename_workflows(doc: &mut OmarDocument) {
+            for workflow in &mut doc.workflows {
+                workflow.id = format!("legacy_{}", workflow.id);
+            }
+        }
+        fn noop(_: &mut OmarDocument) {}
+
+        let chain = [
+            Migration { from: "0.9.0", to: "1.0.0", description: "rename", apply: rename_workflows },
+            Migration { from: "0.8", to: "0.9", description: "noop", apply: noop },
+        ];
+        let (doc, _) = parse_recovering(r#"<omar version="0.8">
+            <workflow id="w"><entry p="a" x="b" node="n"/><nodes><node id="n"/></nodes></workflow>
+        </omar>"#);
+
+        let (migrated, applied) = migrate_with(doc.clone(), &chain).unwrap();
+        assert_eq!(migrated.version, DSL_VERSION);
+        assert_eq!(migrated.workflows[0].id, "legacy_w");
+        let steps: Vec<_> = applied.iter().map(|m| m.description).collect();
+        assert_eq!(steps, ["noop", "rename"]);
+
+        // No path from 0.8 without the first step
+        let err = migrate_with(doc, &chain[..1]).unwrap_err();
+        assert_eq!(messages(err), ["VER002: No migration from DSL version 0.8.0"]);
+    }
+
+    #[test]
+    fn test_migrate_source() {
+        let path = Path::new("w.xml");
+        let (xml, applied) = migrate_source(path, r#"<omar version="1.0"><templates/></omar>"#).unwrap();
+        assert!(applied.is_empty());
+        assert!(xml.contains(&format!(r#"<omar version="{}">"#, DSL_VERSION)), "{}", xml);
+
+        // No registered path from 0.x
+        let err = migrate_source(path, r#"<omar version="0.9"/>"#).unwrap_err();
+        assert_eq!(messages(err), ["VER002: No migration from DSL version 0.9.0"]);
+
+        let err = migrate_source(path, "<omar version=\"1.0\"><!-- note --></omar>").unwrap_err();
+        assert!(messages(err)[0].starts_with("FMT001"));
+
+        let err = migrate_source(path, r#"<omar version="3.0"/>"#).unwrap_err();
+        assert!(messages(err)[0].starts_with("VER001"));
+    }
+}
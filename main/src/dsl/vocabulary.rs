@@ -0,0 +1,220 @@
+//! DSL Vocabulary
+//!
+//! The closed set of elements the parser understands, where each may
+//! appear, and which attributes it accepts. Anything outside this table
+//! is ignored by the parser and reported as PARSE005/PARSE006.
+//!
+//! The table is also the grammar `pxyz schema --format xsd` publishes
+//! (see `schema`), so required attributes and text content are recorded
+//! here even though the parser checks them itself.
+
+/// Attribute list entry accepting any attribute name (fragment arguments)
+pub const ANY_ATTRIBUTE: &str = "*";
+
+/// An element as it may appear under one of `parents`
+///
+/// Some tags mean different things in different places (`<template>` is a
+/// definition under `<templates>` but a reference under `<node>`), so they
+/// have one entry per context.
+#[derive(Debug, Clone, Copy)]
+pub struct ElementSpec {
+    pub name: &'static str,
+    /// Tags this element may appear under ("" = document root)
+    pub parents: &'static [&'static str],
+    pub attributes: &'static [&'static str],
+    /// Attributes the parser reports as missing (PARSE003)
+    pub required: &'static [&'static str],
+    /// Whether the element's text content is read
+    pub text: bool,
+}
+
+impl ElementSpec {
+    const fn required(self, required: &'static [&'static str]) -> Self {
+        Self { required, ..self }
+    }
+
+    const fn text(self) -> Self {
+        Self { text: true, ..self }
+    }
+}
+
+/// Top-level sections, accepted under `<omar>` and (leniently) at the root
+const SECTION_PARENTS: &[&str] = &["", "omar"];
+
+/// Elements that hold a predicate expression
+const EXPR_PARENTS: &[&str] = &["predicate", "when", "and", "or", "not"];
+
+const COMPARISON: &[&str] = &["left", "right"];
+
+const fn el(
+    name: &'static str,
+    parents: &'static [&'static str],
+    attributes: &'static [&'static str],
+) -> ElementSpec {
+    ElementSpec { name, parents, attributes, required: &[], text: false }
+}
+
+pub const ELEMENTS: &[ElementSpec] = &[
+    el("omar", &[""], &["version"]),
+    el("import", SECTION_PARENTS, &["href", "as"]).required(&["href"]),
+
+    // Schemas
+    el("schemas", SECTION_PARENTS, &[]),
+    el("schema", &["schemas"], &["name"]).required(&["name"]),
+    el("field", &["schema"], &["name", "type", "required", "default", "pattern"]).required(&["name"]),
+
+    // Predicates
+    el("predicates", SECTION_PARENTS, &[]),
+    el("predicate", &["predicates"], &["id", "expr"]).required(&["id"]),
+    el("always", EXPR_PARENTS, &[]),
+    el("fail", EXPR_PARENTS, &[]),
+    el("eq", EXPR_PARENTS, COMPARISON),
+    el("neq", EXPR_PARENTS, COMPARISON),
+    el("gt", EXPR_PARENTS, COMPARISON),
+    el("gte", EXPR_PARENTS, COMPARISON),
+    el("lt", EXPR_PARENTS, COMPARISON),
+    el("lte", EXPR_PARENTS, COMPARISON),
+    el("before", EXPR_PARENTS, COMPARISON),
+    el("after", EXPR_PARENTS, COMPARISON),
+    el("within", EXPR_PARENTS, &["left", "duration"]),
+    el("contains", EXPR_PARENTS, COMPARISON),
+    el("matches", EXPR_PARENTS, &["left", "pattern"]),
+    el("startsWith", EXPR_PARENTS, &["left", "prefix"]),
+    el("starts_with", EXPR_PARENTS, &["left", "prefix"]),
+    el("endsWith", EXPR_PARENTS, &["left", "suffix"]),
+    el("ends_with", EXPR_PARENTS, &["left", "suffix"]),
+    el("in", EXPR_PARENTS, &["left", "values"]),
+    el("not_in", EXPR_PARENTS, &["left", "values"]),
+    el("notIn", EXPR_PARENTS, &["left", "values"]),
+    el("ref", EXPR_PARENTS, &["predicate"]),
+    el("fn", EXPR_PARENTS, &["name", "arg"]),
+    el("compare", EXPR_PARENTS, &["left", "cmp", "right"]),
+    el("and", EXPR_PARENTS, &[]),
+    el("or", EXPR_PARENTS, &[]),
+    el("not", EXPR_PARENTS, &[]),
+
+    // Workflows
+    el("workflow", SECTION_PARENTS, &["id", "description", "schema"]).required(&["id"]),
+    el("entry", &["workflow"], &["p", "x", "node"]).required(&["p", "x", "node"]),
+    el("nodes", &["workflow", "fragment"], &[]),
+    el("node", &["nodes"], &[
+        "id", "kind", "op", "template", "status", "actor", "confirmation", "async", "cacheable",
+        "workflow",
+    ]).required(&["id"]),
+    el("template", &["node"], &["ref"]),
+    el("schema", &["node"], &["ref"]),
+    el("require", &["node"], &["predicate"]),
+    el("selector", &["node"], &[]).text(),
+    el("message", &["node"], &[]).text(),
+    el("set", &["node"], &["key", "signal", "value"]).required(&["value"]),
+    el("edges", &["workflow", "fragment"], &[]),
+    el("edge", &["edges"], &["from", "to", "weight", "parallel", "fallback"]).required(&["from", "to"]),
+    el("when", &["edge"], &["expr"]),
+
+    // Fragments
+    el("fragment", SECTION_PARENTS, &["id", "params"]).required(&["id"]),
+    el("use", &["nodes"], &["fragment", "id", ANY_ATTRIBUTE]).required(&["fragment", "id"]),
+
+    // Templates
+    el("templates", SECTION_PARENTS, &[]),
+    el("template", &["templates"], &["id"]).required(&["id"]).text(),
+
+    // Merge policies
+    el("merge", SECTION_PARENTS, &[]),
+    el("merge_policies", SECTION_PARENTS, &[]),
+    el("entity", &["merge", "merge_policies"], &["name", "default", "pre", "post"]).required(&["name"]),
+    el("field", &["entity"], &["name", "policy", "validate", "prefer_origin"]).required(&["name"]),
+];
+
+/// Find the spec for `name` appearing under `parent`
+pub fn lookup(parent: &str, name: &str) -> Option<&'static ElementSpec> {
+    ELEMENTS.iter().find(|e| e.name == name && e.parents.contains(&parent))
+}
+
+/// Is `name` a known element anywhere in the vocabulary?
+pub fn is_known_element(name: &str) -> bool {
+    ELEMENTS.iter().any(|e| e.name == name)
+}
+
+/// Elements allowed under `parent`
+pub fn children_of(parent: &str) -> impl Iterator<Item = &'static str> + '_ {
+    ELEMENTS.iter()
+        .filter(move |e| e.parents.contains(&parent))
+        .map(|e| e.name)
+}
+
+/// Closest candidate to a misspelled name, if any is close enough
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).clamp(1, 3);
+    candidates.into_iter()
+        .map(|c| (edit_distance(&name.to_lowercase(), &c.to_lowercase()), c))
+        .filter(|&(d, _)| d <= max_distance)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, c)| c)
+}
+
+/// Edit distance counting insertions, deletions, substitutions and
+/// adjacent transpositions (so `form` → `from` is one edit)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_is_context_sensitive() {
+        assert_eq!(lookup("templates", "template").unwrap().attributes, &["id"]);
+        assert_eq!(lookup("node", "template").unwrap().attributes, &["ref"]);
+        assert!(lookup("edges", "node").is_none());
+        assert!(is_known_element("node"));
+    }
+
+    #[test]
+    fn test_required_attributes_are_attributes() {
+        for spec in ELEMENTS {
+            for attr in spec.required {
+                assert!(spec.attributes.contains(attr), "<{}> requires unknown '{}'", spec.name, attr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("form", "from"), 1);
+        assert_eq!(edit_distance("nod", "node"), 1);
+        assert_eq!(edit_distance("kind", "kind"), 0);
+        assert_eq!(edit_distance("abc", "xyz"), 3);
+    }
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(suggest("form", ["from", "to", "weight"]), Some("from"));
+        assert_eq!(suggest("nod", children_of("nodes")), Some("node"));
+        assert_eq!(suggest("banana", ["from", "to"]), None);
+    }
+}
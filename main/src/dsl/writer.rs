@@ -0,0 +1,599 @@
+//! Canonical XML Writer
+//!
+//! Renders an `OmarDocument` back to XML in one fixed layout: sections in
+//! AST order, two-space indentation, attributes in vocabulary order,
+//! defaults spelled out only where the parser needs them. Parsing the
+//! output gives back the same document (spans aside), so documents that
+//! differ only in formatting, or in which front-end read them, render to
+//! the same text. The binary's SOURCE_HASH is taken over this text.
+//!
+//! Predicate elements are written in one spelling (`startsWith`, not
+//! `starts_with`), flags only when set, and templates as CDATA.
+//!
+//! `format` is what `pxyz fmt` runs. It writes the canonical form except
+//! that conditions written as `expr` attributes keep that spelling (see
+//! `to_formatted_xml`), and refuses documents the canonical form would
+//! change in meaning or lose content from:
+//! - parse diagnostics of any severity (unknown elements and attributes
+//!   would be dropped)
+//! - FMT001: XML comments, which the AST does not keep
+
+use super::ast::*;
+use super::parser::{error, parse_recovering, LineIndex};
+use crate::{CompileError, Diagnostic};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::fmt::Write;
+
+/// Canonical form of an XML document
+///
+/// Formatting is idempotent: `format(&format(xml)?)` gives back its input.
+pub fn format(xml: &str) -> Result<String, CompileError> {
+    let (doc, mut diagnostics) = parse_recovering(xml);
+    diagnostics.extend(comments(xml));
+    if !diagnostics.is_empty() {
+        return Err(CompileError::Validation { diagnostics });
+    }
+    Ok(to_formatted_xml(&doc))
+}
+
+/// FMT001 for every comment in the document
+pub(super) fn comments(xml: &str) -> Vec<Diagnostic> {
+    let lines = LineIndex::new(xml);
+    let mut reader = Reader::from_str(xml);
+    let mut diags = Vec::new();
+    loop {
+        let start = reader.buffer_position();
+        match reader.read_event() {
+            Ok(Event::Comment(_)) => error(
+                &mut diags,
+                "FMT001",
+                "Comment would be lost by formatting".into(),
+                Some("Move the comment into a description or remove it".into()),
+                lines.span(start, reader.buffer_position()),
+            ),
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    diags
+}
+
+/// Render a document as canonical XML
+pub fn to_xml(doc: &OmarDocument) -> String {
+    render(doc, false)
+}
+
+/// Render a document as `pxyz fmt` writes it: canonical XML, except that
+/// a `<predicate>` or `<when>` parsed from an `expr` attribute is written
+/// with that attribute
+///
+/// The source is kept only while it still parses to the expression, so a
+/// condition rewritten after parsing is written as elements. SOURCE_HASH
+/// stays over `to_xml`, where both spellings are the same text.
+pub fn to_formatted_xml(doc: &OmarDocument) -> String {
+    render(doc, true)
+}
+
+fn render(doc: &OmarDocument, keep_expr: bool) -> String {
+    let mut w = Writer { keep_expr, ..Writer::default() };
+    w.line(0, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    w.open(0, "omar", &[("version", Some(doc.version.as_str()))]);
+
+    for import in &doc.imports {
+        w.empty(1, "import", &[("href", Some(import.href.as_str())), ("as", import.namespace.as_deref())]);
+    }
+
+    if !doc.schemas.is_empty() {
+        w.open(1, "schemas", &[]);
+        for schema in &doc.schemas {
+            w.open(2, "schema", &[("name", Some(schema.name.as_str()))]);
+            for field in &schema.fields {
+                w.empty(3, "field", &[
+                    ("name", Some(field.name.as_str())),
+                    ("type", Some(field.field_type.as_str())),
+                    ("required", flag(field.required)),
+                    ("default", field.default.as_deref()),
+                    ("pattern", field.pattern.as_deref()),
+                ]);
+            }
+            w.close(2, "schema");
+        }
+        w.close(1, "schemas");
+    }
+
+    if !doc.predicates.is_empty() {
+        w.open(1, "predicates", &[]);
+        for predicate in &doc.predicates {
+            let id = ("id", Some(predicate.id.as_str()));
+            if let Some(source) = w.infix(predicate.expr_source.as_deref(), &predicate.expr) {
+                w.empty(2, "predicate", &[id, ("expr", Some(source))]);
+                continue;
+            }
+            w.open(2, "predicate", &[id]);
+            w.expr(3, &predicate.expr);
+            w.close(2, "predicate");
+        }
+        w.close(1, "predicates");
+    }
+
+    for workflow in &doc.workflows {
+        w.open(1, "workflow", &[
+            ("id", Some(workflow.id.as_str())),
+            ("description", workflow.description.as_deref()),
+            ("schema", workflow.schema.as_deref()),
+        ]);
+        w.empty(2, "entry", &[
+            ("p", Some(workflow.entry.p.as_str())),
+            ("x", Some(workflow.entry.x.as_str())),
+            ("node", Some(workflow.entry.node.as_str())),
+        ]);
+        w.graph(2, &workflow.nodes, &workflow.edges, &workflow.uses);
+        w.close(1, "workflow");
+    }
+
+    if !doc.templates.is_empty() {
+        w.open(1, "templates", &[]);
+        for template in &doc.templates {
+            if template.content.is_empty() {
+                w.empty(2, "template", &[("id", Some(template.id.as_str()))]);
+            } else {
+                // CDATA keeps the content byte for byte (plain text is trimmed)
+                let cdata = template.content.replace("]]>", "]]]]><![CDATA[>");
+                let id = escape(&template.id);
+                w.line(2, &format!(r#"<template id="{}"><![CDATA[{}]]></template>"#, id, cdata));
+            }
+        }
+        w.close(1, "templates");
+    }
+
+    for fragment in &doc.fragments {
+        let params = fragment.params.join(",");
+        w.open(1, "fragment", &[
+            ("id", Some(fragment.id.as_str())),
+            ("params", (!params.is_empty()).then_some(params.as_str())),
+        ]);
+        w.graph(2, &fragment.nodes, &fragment.edges, &fragment.uses);
+        w.close(1, "fragment");
+    }
+
+    if !doc.merge_policies.is_empty() {
+        w.open(1, "merge", &[]);
+        for entity in &doc.merge_policies {
+            let attrs = [
+                ("name", Some(entity.entity.as_str())),
+                ("default", policy_name(&entity.default_policy)),
+                ("pre", entity.pre_condition.as_deref()),
+                ("post", entity.post_validate.as_deref()),
+            ];
+            if entity.fields.is_empty() {
+                w.empty(2, "entity", &attrs);
+                continue;
+            }
+            w.open(2, "entity", &attrs);
+            for field in &entity.fields {
+                let custom = match &field.policy {
+                    MergePolicy::Custom { predicate } => Some(format!("predicate:{}", predicate)),
+                    _ => None,
+                };
+                let origin = match &field.policy {
+                    MergePolicy::PreferOrigin { actor } => Some(actor.as_str()),
+                    _ => None,
+                };
+                w.empty(3, "field", &[
+                    ("name", Some(field.field.as_str())),
+                    ("policy", custom.as_deref().or(policy_name(&field.policy))),
+                    ("validate", field.validate.as_deref()),
+                    ("prefer_origin", origin),
+                ]);
+            }
+            w.close(2, "entity");
+        }
+        w.close(1, "merge");
+    }
+
+    w.close(0, "omar");
+    w.out
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    /// Write `expr` attributes from their source (`to_formatted_xml`)
+    keep_expr: bool,
+}
+
+type Attrs<'a> = [(&'a str, Option<&'a str>)];
+
+impl Writer {
+    fn line(&mut self, depth: usize, text: &str) {
+        let _ = writeln!(self.out, "{}{}", "  ".repeat(depth), text);
+    }
+
+    fn tag(name: &str, attrs: &Attrs) -> String {
+        let mut tag = format!("<{}", name);
+        for (key, value) in attrs {
+            if let Some(value) = value {
+                let _ = write!(tag, r#" {}="{}""#, key, escape(value));
+            }
+        }
+        tag
+    }
+
+    fn open(&mut self, depth: usize, name: &str, attrs: &Attrs) {
+        self.line(depth, &format!("{}>", Self::tag(name, attrs)));
+    }
+
+    fn empty(&mut self, depth: usize, name: &str, attrs: &Attrs) {
+        self.line(depth, &format!("{}/>", Self::tag(name, attrs)));
+    }
+
+    fn close(&mut self, depth: usize, name: &str) {
+        self.line(depth, &format!("</{}>", name));
+    }
+
+    fn text(&mut self, depth: usize, name: &str, text: &str) {
+        self.line(depth, &format!("<{}>{}</{}>", name, escape(text), name));
+    }
+
+    fn graph(&mut self, depth: usize, nodes: &[Node], edges: &[Edge], uses: &[FragmentUse]) {
+        self.open(depth, "nodes", &[]);
+        for node in nodes {
+            self.node(depth + 1, node);
+        }
+        for fragment_use in uses {
+            let mut attrs: Vec<(&str, Option<&str>)> = vec![
+                ("fragment", Some(fragment_use.fragment.as_str())),
+                ("id", Some(fragment_use.id.as_str())),
+            ];
+            attrs.extend(fragment_use.args.iter().map(|(k, v)| (k.as_str(), Some(v.as_str()))));
+            self.empty(depth + 1, "use", &attrs);
+        }
+        self.close(depth, "nodes");
+
+        self.open(depth, "edges", &[]);
+        for edge in edges {
+            self.edge(depth + 1, edge);
+        }
+        self.close(depth, "edges");
+    }
+
+    fn node(&mut self, depth: usize, node: &Node) {
+        let status = node.status.map(|s| s.to_string());
+        let kind = if node.call.is_some() { "call" } else { node.kind.as_str() };
+        let attrs = [
+            ("id", Some(node.id.as_str())),
+            ("kind", Some(kind)),
+            ("workflow", node.call.as_deref()),
+            ("op", node.op.as_deref()),
+            ("template", node.template.as_deref()),
+            ("status", status.as_deref()),
+            ("actor", node.actor.as_deref()),
+            ("confirmation", node.confirmation.as_deref()),
+            ("async", flag(node.async_node)),
+            ("cacheable", flag(node.cacheable)),
+        ];
+
+        let has_children = node.schema.is_some()
+            || node.predicate.is_some()
+            || node.selector.is_some()
+            || node.message.is_some()
+            || !node.data.is_empty()
+            || !node.signals.is_empty();
+        if !has_children {
+            self.empty(depth, "node", &attrs);
+            return;
+        }
+
+        self.open(depth, "node", &attrs);
+        if let Some(schema) = &node.schema {
+            self.empty(depth + 1, "schema", &[("ref", Some(schema))]);
+        }
+        if let Some(predicate) = &node.predicate {
+            self.empty(depth + 1, "require", &[("predicate", Some(predicate))]);
+        }
+        if let Some(selector) = &node.selector {
+            self.text(depth + 1, "selector", selector);
+        }
+        if let Some(message) = &node.message {
+            self.text(depth + 1, "message", message);
+        }
+        let mut data: Vec<_> = node.data.iter().collect();
+        data.sort();
+        for (key, value) in data {
+            self.empty(depth + 1, "set", &[("key", Some(key)), ("value", Some(value))]);
+        }
+        for (signal, value) in &node.signals {
+            self.empty(depth + 1, "set", &[("signal", Some(signal)), ("value", Some(value))]);
+        }
+        self.close(depth, "node");
+    }
+
+    fn edge(&mut self, depth: usize, edge: &Edge) {
+        let weight = edge.weight.map(|w| w.to_string());
+        let attrs = [
+            ("from", Some(edge.from.as_str())),
+            ("to", Some(edge.to.as_str())),
+            ("weight", weight.as_deref()),
+            ("parallel", flag(edge.parallel)),
+            ("fallback", flag(edge.fallback)),
+        ];
+
+        let reference = edge.predicate_ref.clone()
+            .map(|predicate| PredicateExpr::Ref { predicate });
+        match reference.as_ref().or(edge.predicate.as_ref()) {
+            None | Some(PredicateExpr::Always) => self.empty(depth, "edge", &attrs),
+            Some(expr) => {
+                self.open(depth, "edge", &attrs);
+                let source = reference.is_none().then_some(edge.predicate_source.as_deref()).flatten();
+                if let Some(source) = self.infix(source, expr) {
+                    self.empty(depth + 1, "when", &[("expr", Some(source))]);
+                } else {
+                    self.open(depth + 1, "when", &[]);
+                    self.expr(depth + 2, expr);
+                    self.close(depth + 1, "when");
+                }
+                self.close(depth, "edge");
+            }
+        }
+    }
+
+    /// The `expr` source to write for `expr`, if kept and it still parses
+    /// to `expr`
+    fn infix<'a>(&self, source: Option<&'a str>, expr: &PredicateExpr) -> Option<&'a str> {
+        source.filter(|source| {
+            self.keep_expr && super::expr::parse_expr(source).is_ok_and(|(parsed, _)| parsed == *expr)
+        })
+    }
+
+    fn expr(&mut self, depth: usize, expr: &PredicateExpr) {
+        let compare = |w: &mut Self, name: &str, left: &str, right: &Value| {
+            let right = value_text(right);
+            w.empty(depth, name, &[("left", Some(left)), ("right", Some(right.as_str()))]);
+        };
+        match expr {
+            PredicateExpr::Always => self.empty(depth, "always", &[]),
+            PredicateExpr::Fail => self.empty(depth, "fail", &[]),
+            PredicateExpr::Eq { left, right } => compare(self, "eq", left, right),
+            PredicateExpr::Neq { left, right } => compare(self, "neq", left, right),
+            PredicateExpr::Gt { left, right } => compare(self, "gt", left, right),
+            PredicateExpr::Gte { left, right } => compare(self, "gte", left, right),
+            PredicateExpr::Lt { left, right } => compare(self, "lt", left, right),
+            PredicateExpr::Lte { left, right } => compare(self, "lte", left, right),
+            PredicateExpr::Contains { left, right } => {
+                self.empty(depth, "contains", &[("left", Some(left)), ("right", Some(right))]);
+            }
+            PredicateExpr::Matches { left, pattern } => {
+                self.empty(depth, "matches", &[("left", Some(left)), ("pattern", Some(pattern))]);
+            }
+            PredicateExpr::StartsWith { left, prefix } => {
+                self.empty(depth, "startsWith", &[("left", Some(left)), ("prefix", Some(prefix))]);
+            }
+            PredicateExpr::EndsWith { left, suffix } => {
+                self.empty(depth, "endsWith", &[("left", Some(left)), ("suffix", Some(suffix))]);
+            }
+            PredicateExpr::In { left, values } | PredicateExpr::NotIn { left, values } => {
+                let name = if matches!(expr, PredicateExpr::In { .. }) { "in" } else { "not_in" };
+                let values: Vec<String> = values.iter().map(value_text).collect();
+                self.empty(depth, name, &[("left", Some(left)), ("values", Some(values.join(",").as_str()))]);
+            }
+            PredicateExpr::Before { left, right } => {
+                self.empty(depth, "before", &[("left", Some(left)), ("right", Some(right))]);
+            }
+            PredicateExpr::After { left, right } => {
+                self.empty(depth, "after", &[("left", Some(left)), ("right", Some(right))]);
+            }
+            PredicateExpr::Within { left, duration } => {
+                self.empty(depth, "within", &[("left", Some(left)), ("duration", Some(duration))]);
+            }
+            PredicateExpr::Ref { predicate } => {
+                self.empty(depth, "ref", &[("predicate", Some(predicate))]);
+            }
+            PredicateExpr::Fn { name, arg } => {
+                self.empty(depth, "fn", &[("name", Some(name)), ("arg", Some(arg))]);
+            }
+            PredicateExpr::Compare { cmp, left, right } => {
+                let (left, right) = (left.to_string(), right.to_string());
+                self.empty(depth, "compare", &[
+                    ("left", Some(left.as_str())),
+                    ("cmp", Some(cmp.name())),
+                    ("right", Some(right.as_str())),
+                ]);
+            }
+            PredicateExpr::And { conditions } | PredicateExpr::Or { conditions } => {
+                let name = if matches!(expr, PredicateExpr::And { .. }) { "and" } else { "or" };
+                self.open(depth, name, &[]);
+                for condition in conditions {
+                    self.expr(depth + 1, condition);
+                }
+                self.close(depth, name);
+            }
+            PredicateExpr::Not { condition } => {
+                self.open(depth, "not", &[]);
+                self.expr(depth + 1, condition);
+                self.close(depth, "not");
+            }
+        }
+    }
+}
+
+fn flag(set: bool) -> Option<&'static str> {
+    set.then_some("true")
+}
+
+/// Text that `Value::from_str_guess` (or `Value::time_from_str`, for
+/// times) reads back as the same value
+pub fn value_text(value: &Value) -> String {
+    match value {
+        Value::Int(i) => i.to_string(),
+        // `{:?}` keeps the decimal point, so 3.0 is not read back as Int(3)
+        Value::Float(f) => format!("{:?}", f),
+        Value::Bool(b) => b.to_string(),
+        Value::Str(s) | Value::Var(s) | Value::Time(s) | Value::Duration(s) => s.clone(),
+    }
+}
+
+/// Name of a built-in policy (`None` for policies with arguments)
+fn policy_name(policy: &MergePolicy) -> Option<&'static str> {
+    match policy {
+        MergePolicy::LWW => Some("lww"),
+        MergePolicy::FWW => Some("fww"),
+        MergePolicy::VClock => Some("vclock"),
+        MergePolicy::Max => Some("max"),
+        MergePolicy::Min => Some("min"),
+        MergePolicy::Union => Some("union"),
+        MergePolicy::Intersect => Some("intersect"),
+        MergePolicy::HumanReview => Some("human-review"),
+        MergePolicy::PreferOrigin { .. } | MergePolicy::Custom { .. } => None,
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' => out.push_str("&#10;"),
+            '\r' => out.push_str("&#13;"),
+            '\t' => out.push_str("&#9;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsl::parse;
+    use crate::Severity;
+
+    const DOC: &str = r#"
+        <omar version="1.0.0">
+            <import href="lib/auth.xml" as="auth"/>
+            <schemas>
+                <schema name="contact">
+                    <field name="email" type="string" required="true" pattern="^.+@.+$"/>
+                </schema>
+            </schemas>
+            <predicates>
+                <predicate id="is_admin">
+                    <and>
+                        <eq left="$token.role" right="admin"/>
+                        <not><lt left="$token.level" right="3.0"/></not>
+                        <startsWith left="$input.name" prefix="a &amp; b"/>
+                    </and>
+                </predicate>
+            </predicates>
+            <workflow id="flow" description="Demo">
+                <entry p="demo" x="run" node="start"/>
+                <nodes>
+                    <node id="start" kind="auth"><require predicate="is_admin"/></node>
+                    <node id="show" kind="render" template="card"><selector>#main</selector><set key="layout" value=""/></node>
+                    <node id="check" kind="call" workflow="other"/>
+                    <use fragment="f" id="u" a="1"/>
+                    <node id="done" kind="terminal" status="200" async="true"/>
+                </nodes>
+                <edges>
+                    <edge from="start" to="show" weight="2" fallback="true"/>
+                    <edge from="show" to="done"><when><or><ref predicate="is_admin"/><fn name="x" arg="y"/></or></when></edge>
+                </edges>
+            </workflow>
+            <templates>
+                <template id="card"><![CDATA[  <div>]]]]><![CDATA[></div>  ]]></template>
+            </templates>
+            <fragment id="f" params="a">
+                <nodes><node id="n" op="{a}"/></nodes>
+            </fragment>
+            <merge>
+                <entity name="contact" default="max" pre="is_admin">
+                    <field name="email" policy="predicate:is_admin"/>
+                    <field name="owner" prefer_origin="human"/>
+                </entity>
+            </merge>
+        </omar>
+    "#;
+
+    #[test]
+    fn test_round_trip() {
+        let doc = parse(DOC).unwrap();
+        let xml = to_xml(&doc);
+        let reparsed = parse(&xml).unwrap();
+
+        // Stable under re-rendering
+        assert_eq!(to_xml(&reparsed), xml);
+
+        assert_eq!(reparsed.templates[0].content, "  <div>]]></div>  ");
+        assert!(matches!(&reparsed.predicates[0].expr, PredicateExpr::And { conditions }
+            if matches!(&conditions[1], PredicateExpr::Not { condition }
+                if matches!(condition.as_ref(), PredicateExpr::Lt { right: Value::Float(f), .. } if *f == 3.0))));
+        assert_eq!(reparsed.workflows[0].nodes[1].data["layout"], "");
+        assert_eq!(reparsed.workflows[0].nodes[2].call.as_deref(), Some("other"));
+        assert!(matches!(&reparsed.merge_policies[0].fields[1].policy,
+            MergePolicy::PreferOrigin { actor } if actor == "human"));
+    }
+
+    #[test]
+    fn test_formatting_does_not_matter() {
+        let compact = r#"<omar version="1.0.0"><workflow id="w"><entry p="a" x="b" node="n"/>
+            <nodes><node kind="terminal" id="n"/></nodes><edges/></workflow></omar>"#;
+        let spaced = r#"
+            <omar   version="1.0.0">
+              <workflow id="w">
+                <entry node="n" p="a" x="b" />
+                <nodes>
+                  <node id="n"
+                        kind="terminal" />
+                </nodes>
+              </workflow>
+            </omar>"#;
+        assert_eq!(to_xml(&parse(compact).unwrap()), to_xml(&parse(spaced).unwrap()));
+    }
+
+    #[test]
+    fn test_format_is_idempotent() {
+        let xml = r#"<omar version="1.0.0"><workflow id="w"><entry p="a" x="b" node="n"/>
+            <nodes><node id="n" kind="terminal"/></nodes>
+            <edges><edge from="n" to="n"><when><starts_with left="$a" prefix="b"/></when></edge></edges>
+            </workflow></omar>"#;
+        let once = format(xml).unwrap();
+        assert!(once.contains("<startsWith "));
+        assert_eq!(format(&once).unwrap(), once);
+    }
+
+    #[test]
+    fn test_format_keeps_expr() {
+        let xml = r#"<omar version="1.0.0">
+            <predicates><predicate id="big" expr="$input.total &gt; 100"/></predicates>
+            <workflow id="w"><entry p="a" x="b" node="n"/>
+            <nodes><node id="n" kind="terminal"/></nodes>
+            <edges><edge from="n" to="n"><when expr="$token.role == 'admin' &amp;&amp; big"/></edge></edges>
+            </workflow></omar>"#;
+        let once = format(xml).unwrap();
+        assert!(once.contains(r#"<predicate id="big" expr="$input.total &gt; 100"/>"#), "{}", once);
+        assert!(once.contains(r#"<when expr="$token.role == 'admin' &amp;&amp; big"/>"#), "{}", once);
+        assert_eq!(format(&once).unwrap(), once);
+
+        // The canonical form, which SOURCE_HASH covers, is the element tree
+        let doc = parse(xml).unwrap();
+        assert!(to_xml(&doc).contains(r#"<eq left="$token.role" right="admin"/>"#));
+
+        // A condition rewritten after parsing no longer matches its source
+        let mut doc = doc;
+        doc.predicates[0].expr = PredicateExpr::Always;
+        assert!(!to_formatted_xml(&doc).contains(r#"expr="$input.total"#));
+    }
+
+    #[test]
+    fn test_format_refuses_lossy_documents() {
+        let Err(CompileError::Validation { diagnostics }) = format(r#"<omar><!-- note --><workflow id="w" colour="red"/></omar>"#) else {
+            panic!("expected diagnostics");
+        };
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code.as_str()).collect();
+        assert!(codes.contains(&"FMT001"), "{:?}", codes);
+        assert!(diagnostics.iter().any(|d| d.code == "PARSE006" && d.severity == Severity::Warn));
+    }
+}
@@ -1,6 +1,7 @@
 //! Audit JSON Generation
 
 use crate::compiler::ir::*;
+use crate::dsl::Profile;
 use crate::physics::{Energy, EnergyCosts, CacheAnalysis, MemoryTier};
 use crate::{Diagnostic, Severity};
 use serde::Serialize;
@@ -48,9 +49,13 @@ pub struct GraphAudit {
     pub compiled_at: String,
     pub source_hash: String,
     pub graph_hash: String,
+    /// Compile-time profile the source was compiled with, if any
+    pub profile: Option<Profile>,
     pub stats: AuditStats,
     pub checks: AuditChecks,
     pub entries: Vec<AuditEntry>,
+    /// Verified worst-case bounds of each predicate
+    pub predicates: Vec<AuditPredicate>,
     pub warnings: Vec<AuditWarning>,
 }
 
@@ -102,6 +107,20 @@ pub struct AuditEntry {
     pub node_name: String,
 }
 
+/// Predicate bounds as proven by the bytecode verifier
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditPredicate {
+    pub id: PredicateId,
+    pub name: String,
+    pub bytecode_size: usize,
+    /// Deepest VM stack, callees included
+    pub max_stack: usize,
+    /// Most instructions one evaluation can execute, callees included
+    pub max_steps: usize,
+    /// Nested evaluations through CALL_PRED, itself included
+    pub call_depth: usize,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct AuditWarning {
     pub code: String,
@@ -109,7 +128,13 @@ pub struct AuditWarning {
     pub hint: Option<String>,
 }
 
-pub fn generate(ir: &GraphIR, source_xml: &str, binary: &[u8], diagnostics: &[Diagnostic]) -> GraphAudit {
+pub fn generate(
+    ir: &GraphIR,
+    source_xml: &str,
+    binary: &[u8],
+    diagnostics: &[Diagnostic],
+    profile: &Profile,
+) -> GraphAudit {
     let now = chrono::Utc::now().to_rfc3339();
     
     // Source hash
@@ -161,6 +186,20 @@ pub fn generate(ir: &GraphIR, source_xml: &str, binary: &[u8], diagnostics: &[Di
         })
         .collect();
     
+    let predicates: Vec<_> = ir.predicates.iter()
+        .map(|p| {
+            let bounds = p.bounds.unwrap_or_default();
+            AuditPredicate {
+                id: p.id,
+                name: p.name.clone(),
+                bytecode_size: p.bytecode_len(),
+                max_stack: bounds.max_stack,
+                max_steps: bounds.max_steps,
+                call_depth: bounds.call_depth,
+            }
+        })
+        .collect();
+    
     // Compute energy estimation
     let cache_analysis = CacheAnalysis::analyze(binary);
     let energy_stats = calculate_energy_budget(ir, &cache_analysis);
@@ -170,6 +209,7 @@ pub fn generate(ir: &GraphIR, source_xml: &str, binary: &[u8], diagnostics: &[Di
         compiled_at: now,
         source_hash,
         graph_hash,
+        profile: (!profile.is_empty()).then(|| profile.clone()),
         stats: AuditStats {
             node_count: ir.nodes.len(),
             edge_count: ir.edges.len(),
@@ -197,6 +237,7 @@ pub fn generate(ir: &GraphIR, source_xml: &str, binary: &[u8], diagnostics: &[Di
             },
         },
         entries,
+        predicates,
         warnings,
     }
 }
\ No newline at end of file
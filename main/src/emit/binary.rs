@@ -22,8 +22,8 @@ pub fn emit(ir: &GraphIR, source_xml: &str) -> Result<Vec<u8>, CompileError> {
         buffer.extend_from_slice(&node.id.to_le_bytes());        // 4: id
         buffer.push(node.kind as u8);                            // 1: kind
         buffer.push(node.flags);                                 // 1: flags
-        buffer.extend_from_slice(&node.op_code.to_le_bytes());   // 2: op_code
-        buffer.extend_from_slice(&node.data_offset.to_le_bytes()); // 4: data
+        buffer.extend_from_slice(&node.op_code.to_le_bytes());   // 2: op_code (auth: predicate, render: template)
+        buffer.extend_from_slice(&node.data_offset.to_le_bytes()); // 4: node data record
         buffer.extend_from_slice(&node.edge_start.to_le_bytes()); // 2: edge_start
         buffer.extend_from_slice(&node.edge_count.to_le_bytes()); // 2: edge_count
     }
@@ -55,6 +55,18 @@ pub fn emit(ir: &GraphIR, source_xml: &str) -> Result<Vec<u8>, CompileError> {
         buffer.extend_from_slice(&entry.node_id.to_le_bytes()); // 4: node_id
     }
     
+    // Emit templates (8 bytes each: name, content string offsets)
+    let templates_offset = buffer.len() as u32;
+    for template in &ir.templates {
+        buffer.extend_from_slice(&template.name_offset.to_le_bytes());    // 4: name
+        buffer.extend_from_slice(&template.content_offset.to_le_bytes()); // 4: content
+    }
+    
+    // Emit node data section (records at GNode::data_offset)
+    let node_data_offset = buffer.len() as u32;
+    let node_data = ir.node_data.bytes();
+    buffer.extend_from_slice(node_data);
+    
     // Compute source hash
     let mut hasher = Sha256::new();
     hasher.update(source_xml.as_bytes());
@@ -70,12 +82,16 @@ pub fn emit(ir: &GraphIR, source_xml: &str) -> Result<Vec<u8>, CompileError> {
     h[header_offsets::PREDICATE_COUNT..header_offsets::PREDICATE_COUNT+4].copy_from_slice(&(ir.predicates.len() as u32).to_le_bytes());
     h[header_offsets::STRING_POOL_SIZE..header_offsets::STRING_POOL_SIZE+4].copy_from_slice(&(ir.strings.data.len() as u32).to_le_bytes());
     h[header_offsets::ENTRY_COUNT..header_offsets::ENTRY_COUNT+4].copy_from_slice(&(ir.entries.len() as u32).to_le_bytes());
+    h[header_offsets::TEMPLATE_COUNT..header_offsets::TEMPLATE_COUNT+4].copy_from_slice(&(ir.templates.len() as u32).to_le_bytes());
     h[header_offsets::SOURCE_HASH..header_offsets::SOURCE_HASH+32].copy_from_slice(&source_hash);
     h[header_offsets::NODES_OFFSET..header_offsets::NODES_OFFSET+4].copy_from_slice(&nodes_offset.to_le_bytes());
     h[header_offsets::EDGES_OFFSET..header_offsets::EDGES_OFFSET+4].copy_from_slice(&edges_offset.to_le_bytes());
     h[header_offsets::PREDICATES_OFFSET..header_offsets::PREDICATES_OFFSET+4].copy_from_slice(&predicates_offset.to_le_bytes());
     h[header_offsets::STRINGS_OFFSET..header_offsets::STRINGS_OFFSET+4].copy_from_slice(&strings_offset.to_le_bytes());
     h[header_offsets::ENTRIES_OFFSET..header_offsets::ENTRIES_OFFSET+4].copy_from_slice(&entries_offset.to_le_bytes());
+    h[header_offsets::TEMPLATES_OFFSET..header_offsets::TEMPLATES_OFFSET+4].copy_from_slice(&templates_offset.to_le_bytes());
+    h[header_offsets::NODE_DATA_OFFSET..header_offsets::NODE_DATA_OFFSET+4].copy_from_slice(&node_data_offset.to_le_bytes());
+    h[header_offsets::NODE_DATA_SIZE..header_offsets::NODE_DATA_SIZE+4].copy_from_slice(&(node_data.len() as u32).to_le_bytes());
     
     Ok(buffer)
 }
@@ -91,6 +107,9 @@ pub const EDGE_ENTRY_SIZE: usize = 12;
 /// Entry point size
 pub const ENTRY_SIZE: usize = 8;
 
+/// Template entry size
+pub const TEMPLATE_ENTRY_SIZE: usize = 8;
+
 /// Header field offsets
 pub mod header_offsets {
     pub const MAGIC: usize = 0x00;
@@ -101,14 +120,24 @@ pub mod header_offsets {
     pub const PREDICATE_COUNT: usize = 0x10;
     pub const STRING_POOL_SIZE: usize = 0x14;
     pub const ENTRY_COUNT: usize = 0x18;
-    pub const SCHEMA_COUNT: usize = 0x1C;
+    pub const TEMPLATE_COUNT: usize = 0x1C;
+    /// Reserved for a schema section that was never emitted; the word
+    /// now holds the template count
+    #[deprecated(note = "header word 0x1C holds the template count; use TEMPLATE_COUNT")]
+    pub const SCHEMA_COUNT: usize = TEMPLATE_COUNT;
     pub const SOURCE_HASH: usize = 0x20;
     pub const NODES_OFFSET: usize = 0x40;
     pub const EDGES_OFFSET: usize = 0x44;
     pub const PREDICATES_OFFSET: usize = 0x48;
     pub const STRINGS_OFFSET: usize = 0x4C;
     pub const ENTRIES_OFFSET: usize = 0x50;
-    pub const SCHEMAS_OFFSET: usize = 0x54;
+    pub const TEMPLATES_OFFSET: usize = 0x54;
+    /// Reserved for a schema section that was never emitted; the word
+    /// now holds the templates offset
+    #[deprecated(note = "header word 0x54 holds the templates offset; use TEMPLATES_OFFSET")]
+    pub const SCHEMAS_OFFSET: usize = TEMPLATES_OFFSET;
+    pub const NODE_DATA_OFFSET: usize = 0x58;
+    pub const NODE_DATA_SIZE: usize = 0x5C;
 }
 
 /// Node entry field offsets
@@ -130,6 +159,12 @@ pub mod edge_offsets {
     pub const FLAGS: usize = 0x0A;
 }
 
+/// Template entry field offsets (template `id` is entry `id - 1`)
+pub mod template_offsets {
+    pub const NAME: usize = 0x00;
+    pub const CONTENT: usize = 0x04;
+}
+
 /// Entry point field offsets
 pub mod entry_offsets {
     pub const PX_HASH: usize = 0x00;
@@ -138,7 +173,6 @@ pub mod entry_offsets {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
     use crate::hash_px;
 
     #[test]
@@ -150,5 +184,13 @@ mod tests {
         assert_eq!(h1, h2);
         assert_ne!(h1, h3);
     }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_schema_header_aliases() {
+        use super::header_offsets::*;
+        assert_eq!(SCHEMA_COUNT, TEMPLATE_COUNT);
+        assert_eq!(SCHEMAS_OFFSET, TEMPLATES_OFFSET);
+    }
 }
 
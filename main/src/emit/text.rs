@@ -32,7 +32,9 @@ pub fn format(ir: &GraphIR) -> String {
     out.push_str("──────\n");
     for node in &ir.nodes {
         out.push_str(&format!("  [{}] {} ({:?})\n", node.id, node.name, node.kind));
-        if node.op_code != 0 {
+        if let Some(ref template) = node.template {
+            out.push_str(&format!("       template: {}\n", template));
+        } else if node.op_code != 0 {
             out.push_str(&format!("       op: 0x{:04x}\n", node.op_code));
         }
         if node.edge_count > 0 {
@@ -85,6 +87,17 @@ pub fn format(ir: &GraphIR) -> String {
         out.push('\n');
     }
     
+    // Templates
+    if !ir.templates.is_empty() {
+        out.push_str("TEMPLATES:\n");
+        out.push_str("──────────\n");
+        for template in &ir.templates {
+            out.push_str(&format!("  [{}] {} ({} references)\n",
+                template.id, template.name, template.references));
+        }
+        out.push('\n');
+    }
+    
     // String pool
     if !ir.strings.data.is_empty() {
         out.push_str(&format!("STRING POOL ({} bytes, {} strings):\n",
@@ -148,7 +148,7 @@ impl CacheAlignedBuffer {
 
     /// Number of cache lines used
     pub fn cache_lines_used(&self) -> usize {
-        (self.len + CACHE_LINE_SIZE - 1) / CACHE_LINE_SIZE
+        self.len.div_ceil(CACHE_LINE_SIZE)
     }
 
     /// Check if a range fits in a single cache line
@@ -205,7 +205,7 @@ impl CacheAnalysis {
     /// Analyze a graph.bin buffer
     pub fn analyze(buffer: &[u8]) -> Self {
         let total_bytes = buffer.len();
-        let cache_lines = (total_bytes + CACHE_LINE_SIZE - 1) / CACHE_LINE_SIZE;
+        let cache_lines = total_bytes.div_ceil(CACHE_LINE_SIZE);
         let tier = MemoryTier::from_size(total_bytes);
 
         // Estimate hit rate based on tier
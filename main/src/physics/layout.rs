@@ -192,7 +192,7 @@ impl LayoutOptimizer {
 
             for (to, weight) in &node.edges {
                 let to_pos = position.get(to).copied().unwrap_or(0);
-                let distance = (to_pos as isize - from_pos as isize).abs() as usize;
+                let distance = (to_pos as isize - from_pos as isize).unsigned_abs();
 
                 total_weight += weight;
 
@@ -233,7 +233,7 @@ impl LayoutOptimizer {
 
             for (to, _) in &node.edges {
                 let to_pos = position.get(to).copied().unwrap_or(0);
-                let distance = (to_pos as isize - from_pos as isize).abs() as usize;
+                let distance = (to_pos as isize - from_pos as isize).unsigned_abs();
 
                 // Energy model: further = more cache misses
                 let edge_energy = if distance <= 1 {
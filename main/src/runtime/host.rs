@@ -164,7 +164,7 @@ mod tests {
     #[test]
     fn test_host_emit_event_json() {
         let shapes = ShapeRegistry::new();
-        let mut events = TestEventSink::new();
+        let events = TestEventSink::new();
         let mut host = HostEnv::new(shapes, events);
 
         let json = r#"{
@@ -23,15 +23,18 @@ fn test_lower_minimal_workflow() {
     // Construct AST
     let doc = OmarDocument {
         version: "1.0".into(),
+        imports: vec![],
         predicates: vec![],
         merge_policies: vec![],
         workflows: vec![Workflow {
             description: None,
+            schema: None,
             id: "test".into(),
             entry: EntryPoint {
                 p: "test".into(),
                 x: "run".into(),
                 node: "start".into(),
+                span: None,
             },
             nodes: vec![
                 Node {
@@ -52,8 +55,11 @@ fn test_lower_minimal_workflow() {
                 predicate: Some(PredicateExpr::Always),
                 ..Default::default()
             }],
+            uses: vec![],
+            span: None,
         }],
         templates: vec![],
+        fragments: vec![],
         schemas: vec![],
     };
 
@@ -73,15 +79,18 @@ fn test_lower_minimal_workflow() {
 fn test_lower_preserves_opcodes() {
     let doc = OmarDocument {
         version: "1.0".into(),
+        imports: vec![],
         predicates: vec![],
         merge_policies: vec![],
         workflows: vec![Workflow {
             description: None,
+            schema: None,
             id: "test".into(),
             entry: EntryPoint {
                 p: "test".into(),
                 x: "search".into(),
                 node: "search".into(),
+                span: None,
             },
             nodes: vec![
                 Node {
@@ -102,8 +111,11 @@ fn test_lower_preserves_opcodes() {
                 predicate: Some(PredicateExpr::Always),
                 ..Default::default()
             }],
+            uses: vec![],
+            span: None,
         }],
         templates: vec![],
+        fragments: vec![],
         schemas: vec![],
     };
 
@@ -121,21 +133,27 @@ fn test_lower_preserves_opcodes() {
 fn test_lower_auth_predicates() {
     let doc = OmarDocument {
         version: "1.0".into(),
+        imports: vec![],
         predicates: vec![PredicateDef {
             id: "is_admin".into(),
             expr: PredicateExpr::Eq {
                 left: "$token.role".into(),
                 right: Value::Str("admin".into()),
             },
+            span: None,
+            expr_spans: vec![],
+            expr_source: None,
         }],
         merge_policies: vec![],
         workflows: vec![Workflow {
             description: None,
+            schema: None,
             id: "test".into(),
             entry: EntryPoint {
                 p: "test".into(),
                 x: "run".into(),
                 node: "gate".into(),
+                span: None,
             },
             nodes: vec![
                 Node {
@@ -156,8 +174,11 @@ fn test_lower_auth_predicates() {
                 predicate: Some(PredicateExpr::Always),
                 ..Default::default()
             }],
+            uses: vec![],
+            span: None,
         }],
         templates: vec![],
+        fragments: vec![],
         schemas: vec![],
     };
 
@@ -180,15 +201,18 @@ fn test_lower_auth_predicates() {
 fn test_lower_error_edges() {
     let doc = OmarDocument {
         version: "1.0".into(),
+        imports: vec![],
         predicates: vec![],
         merge_policies: vec![],
         workflows: vec![Workflow {
             description: None,
+            schema: None,
             id: "test".into(),
             entry: EntryPoint {
                 p: "test".into(),
                 x: "run".into(),
                 node: "risky".into(),
+                span: None,
             },
             nodes: vec![
                 Node {
@@ -223,8 +247,11 @@ fn test_lower_error_edges() {
                     ..Default::default()
                 },
             ],
+            uses: vec![],
+            span: None,
         }],
         templates: vec![],
+        fragments: vec![],
         schemas: vec![],
     };
 
@@ -286,15 +313,18 @@ fn test_contract_invariants_enforced() {
 fn test_lowering_idempotent() {
     let doc = OmarDocument {
         version: "1.0".into(),
+        imports: vec![],
         predicates: vec![],
         merge_policies: vec![],
         workflows: vec![Workflow {
             description: None,
+            schema: None,
             id: "test".into(),
             entry: EntryPoint {
                 p: "test".into(),
                 x: "run".into(),
                 node: "a".into(),
+                span: None,
             },
             nodes: vec![
                 Node {
@@ -314,8 +344,11 @@ fn test_lowering_idempotent() {
                 predicate: Some(PredicateExpr::Always),
                 ..Default::default()
             }],
+            uses: vec![],
+            span: None,
         }],
         templates: vec![],
+        fragments: vec![],
         schemas: vec![],
     };
 
@@ -6,21 +6,132 @@
 //! Philosophy: "Tests that would fail if the implementation was naive."
 
 use pxyz::*;
-use pxyz::physics::{Energy, EnergyCosts};
+use pxyz::physics::EnergyCosts;
 use pxyz::dsl::parse;
 use pxyz::compiler::{lower, compile_and_validate};
 
 mod adversarial {
     use super::*;
 
+    /// ADVERSARIAL: A typo in an attribute name must not be silently dropped
+    ///
+    /// `<edge form=...>` would otherwise surface only as a confusing
+    /// missing-attribute error, or not at all for optional attributes.
+    #[test]
+    fn misspelled_attribute_rejected_in_strict_mode() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <workflow id="typo">
+            <entry p="test" x="typo" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="done" wieght="5"/>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        let lenient = compile(xml, &CompileOptions::default()).expect("lenient compile succeeds");
+        assert!(lenient.diagnostics.iter().any(|d| d.code == "PARSE006" && d.severity == Severity::Warn));
+
+        let strict = CompileOptions { strict: true, ..Default::default() };
+        match compile(xml, &strict) {
+            Err(CompileError::Validation { diagnostics }) => {
+                let diag = diagnostics.iter().find(|d| d.code == "PARSE006").expect("PARSE006");
+                assert_eq!(diag.severity, Severity::Error);
+                assert_eq!(diag.hint.as_deref(), Some("Did you mean 'weight'?"));
+            }
+            other => panic!("strict compile should fail, got {:?}", other.map(|r| r.diagnostics)),
+        }
+    }
+
+    /// ADVERSARIAL: A template reference with a typo must not compile
+    ///
+    /// SEM007 only asks for *some* template; the reference itself has to
+    /// name a defined template, and the misspelt one is reported unused.
+    #[test]
+    fn unknown_template_reference_rejected() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <workflow id="page">
+            <entry p="page" x="show" node="show"/>
+            <nodes>
+              <node id="show" kind="render"><template ref="cart_veiw"/></node>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="show" to="done"/>
+            </edges>
+          </workflow>
+          <templates>
+            <template id="cart_view"><![CDATA[<div id="cart"></div>]]></template>
+          </templates>
+        </omar>"#;
+
+        match compile(xml, &CompileOptions::default()) {
+            Err(CompileError::Validation { diagnostics }) => {
+                let codes: Vec<_> = diagnostics.iter()
+                    .filter(|d| d.code.starts_with("SEM"))
+                    .map(|d| (d.code.as_str(), d.severity))
+                    .collect();
+                assert_eq!(codes, [("SEM008", Severity::Error), ("SEM009", Severity::Warn)]);
+                assert_eq!(diagnostics[0].message, "Node 'show' references unknown template 'cart_veiw'");
+            }
+            other => panic!("unknown template should fail, got {:?}", other.map(|r| r.diagnostics)),
+        }
+    }
+
+    /// ADVERSARIAL: Predicates that reference each other must not compile
+    ///
+    /// The VM's call-depth guard would stop the recursion at runtime, but
+    /// only by failing the edge: composition is checked statically.
+    #[test]
+    fn recursive_predicate_references_rejected() {
+        let workflow = |predicates: &str| format!(r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <predicates>{}</predicates>
+          <workflow id="orders">
+            <entry p="orders" x="approve" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="done"><when><and><ref predicate="can_approve"/><gt left="$input.total" right="0"/></and></when></edge>
+            </edges>
+          </workflow>
+        </omar>"#, predicates);
+
+        let composed = workflow(r#"
+            <predicate id="is_admin"><eq left="$token.role" right="admin"/></predicate>
+            <predicate id="is_owner"><eq left="$entity.owner" right="$token.sub"/></predicate>
+            <predicate id="can_approve"><or><ref predicate="is_admin"/><not><ref predicate="is_owner"/></not></or></predicate>"#);
+        let ir = compile_pipeline(&composed, false).expect("composed predicates compile").0;
+        let calls = |name: &str| {
+            let pred = ir.predicates.iter().find(|p| p.name == name).unwrap();
+            pred.bytecode.iter().filter(|&&b| b == Opcode::CallPred as u8).count()
+        };
+        assert_eq!(calls("can_approve"), 2);
+        assert_eq!(calls("is_admin"), 0);
+
+        let recursive = workflow(r#"
+            <predicate id="can_approve"><and><ref predicate="is_admin"/><ref predicate="is_manager"/></and></predicate>
+            <predicate id="is_admin"><eq left="$token.role" right="admin"/></predicate>
+            <predicate id="is_manager"><or><ref predicate="is_admin"/><ref predicate="can_approve"/></or></predicate>"#);
+        let err = compile(&recursive, &CompileOptions::default()).unwrap_err();
+        assert!(
+            err.to_string().contains("Recursive predicate reference: can_approve → is_manager → can_approve"),
+            "{}", err
+        );
+    }
+
     /// ADVERSARIAL: Cyclic graph should be rejected at compile time
     ///
     /// A naive implementation might allow cycles and rely on MAX_VISITED runtime check.
     /// We reject cycles during semantic analysis.
-    ///
-    /// TODO: SEM004 cycle detection needs investigation
     #[test]
-    #[ignore]
     fn cyclic_graph_rejected_at_compile() {
         let xml = r#"<?xml version="1.0"?>
         <omar version="1.0.0">
@@ -54,10 +165,7 @@ mod adversarial {
     /// ADVERSARIAL: LLM → Irreversible without gate should fail PRAG001
     ///
     /// A naive implementation might not check this dangerous pattern.
-    ///
-    /// TODO: PRAG001 validation needs review
     #[test]
-    #[ignore]
     fn llm_to_irreversible_rejected() {
         let xml = r#"<?xml version="1.0"?>
         <omar version="1.0.0">
@@ -90,10 +198,7 @@ mod adversarial {
     /// ADVERSARIAL: Terminal node with outgoing edges is suspicious
     ///
     /// A naive implementation might not catch this logical error.
-    ///
-    /// TODO: SEM003 warning needs investigation
     #[test]
-    #[ignore]
     fn terminal_with_outgoing_edges_warned() {
         let xml = r#"<?xml version="1.0"?>
         <omar version="1.0.0">
@@ -122,6 +227,240 @@ mod adversarial {
         assert!(has_terminal_warning,
             "Terminal with outgoing edges should trigger SEM003");
     }
+
+    /// ADVERSARIAL: Two imported libraries defining the same predicate id
+    /// must not collide, and each node must bind to the one it names
+    #[test]
+    fn imported_predicates_do_not_collide() {
+        use pxyz::dsl::MemoryResolver;
+        use std::path::Path;
+
+        let library = |role: &str| format!(r#"
+        <omar version="1.0.0">
+          <predicates>
+            <predicate id="is_admin"><eq left="$token.role" right="{}"/></predicate>
+          </predicates>
+        </omar>"#, role);
+        let resolver = MemoryResolver::new()
+            .with_file("lib/crm.xml", library("crm_admin"))
+            .with_file("lib/billing.xml", library("billing_admin"));
+
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <import href="lib/crm.xml"/>
+          <import href="lib/billing.xml"/>
+          <workflow id="both">
+            <entry p="test" x="both" node="start"/>
+            <nodes>
+              <node id="start" kind="auth"><require predicate="crm.is_admin"/></node>
+              <node id="billing" kind="auth"><require predicate="billing.is_admin"/></node>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="billing"/>
+              <edge from="billing" to="done"/>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        let resolved = pxyz::dsl::parse_with_imports(xml, Path::new("main.xml"), &resolver, &Default::default());
+        let ir = lower(&resolved.document).expect("no duplicate predicate ids");
+        let crm = ir.predicates.iter().find(|p| p.name == "crm.is_admin").unwrap().id;
+        let billing = ir.predicates.iter().find(|p| p.name == "billing.is_admin").unwrap().id;
+        assert_ne!(crm, billing);
+        assert_eq!(ir.nodes[0].auth_predicate, Some(crm));
+        assert_eq!(ir.nodes[1].auth_predicate, Some(billing));
+
+        let result = compile_with_resolver(xml, Path::new("main.xml"), &resolver, &CompileOptions::default())
+            .expect("namespaced imports compile");
+        assert!(!result.binary.is_empty());
+
+        let diags = validate_with_resolver(xml, Path::new("main.xml"), &resolver, &CompileOptions::default());
+        assert!(diags.iter().all(|d| d.severity != Severity::Error), "{:?}", diags);
+    }
+
+    /// ADVERSARIAL: A document written for a newer DSL must be rejected,
+    /// not compiled with whatever this compiler happens to understand,
+    /// even when it arrives through an import or as JSON
+    #[test]
+    fn newer_dsl_version_rejected() {
+        use pxyz::dsl::MemoryResolver;
+        use std::path::Path;
+
+        let resolver = MemoryResolver::new()
+            .with_file("lib/future.xml", r#"<omar version="1.1.0"><predicates/></omar>"#.to_string());
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0">
+          <import href="lib/future.xml"/>
+          <workflow id="w">
+            <entry p="test" x="w" node="done"/>
+            <nodes><node id="done" kind="terminal" status="200"/></nodes>
+          </workflow>
+        </omar>"#;
+
+        match compile_with_resolver(xml, Path::new("main.xml"), &resolver, &CompileOptions::default()) {
+            Err(CompileError::Validation { diagnostics }) => {
+                let diag = diagnostics.iter().find(|d| d.code == "VER001").expect("VER001");
+                let file = diag.location.as_ref().and_then(|l| l.file.as_deref());
+                assert_eq!(file, Some("lib/future.xml"));
+            }
+            other => panic!("newer import should fail, got {:?}", other.map(|r| r.diagnostics)),
+        }
+
+        let mut doc = parse(&xml.replace(r#"<import href="lib/future.xml"/>"#, "")).unwrap();
+        assert!(compile_document(&doc, &CompileOptions::default()).is_ok());
+        doc.version = "2.0".into();
+        match compile_document(&doc, &CompileOptions::default()) {
+            Err(CompileError::Validation { diagnostics }) => assert_eq!(diagnostics[0].code, "VER001"),
+            other => panic!("newer document should fail, got {:?}", other.map(|r| r.diagnostics)),
+        }
+    }
+
+    /// ADVERSARIAL: Hiding an irreversible action behind a sub-workflow
+    /// call must not bypass the LLM → irreversible check
+    #[test]
+    fn llm_cannot_reach_irreversible_through_call() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <workflow id="notify">
+            <entry p="notify" x="send" node="send"/>
+            <nodes>
+              <node id="send" kind="external" op="0x0340"/>
+              <node id="sent" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="send" to="sent"/>
+            </edges>
+          </workflow>
+          <workflow id="agent">
+            <entry p="agent" x="run" node="think"/>
+            <nodes>
+              <node id="think" kind="external" op="0x0800"/>
+              <node id="notify" kind="call" workflow="notify"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="think" to="notify"/>
+              <edge from="notify" to="done"/>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        let diags = validate(xml);
+        let prag001: Vec<_> = diags.iter().filter(|d| d.code == "PRAG001").collect();
+        assert_eq!(prag001.len(), 1, "{:?}", diags);
+        assert!(prag001[0].message.contains("'notify.send'"), "{}", prag001[0].message);
+    }
+
+    /// ADVERSARIAL: A misspelt field or a string compared as a number
+    /// must not compile when the node declares its schema
+    ///
+    /// Without the schema the VM would resolve the typo to nothing and
+    /// the guard would silently never pass.
+    #[test]
+    fn schema_bound_predicates_are_type_checked() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <schemas>
+            <schema name="deal">
+              <field name="amount" type="number"/>
+              <field name="stage" type="string"/>
+            </schema>
+          </schemas>
+          <predicates>
+            <predicate id="large"><gt left="$entity.amout" right="5"/></predicate>
+          </predicates>
+          <workflow id="deals" schema="deal">
+            <entry p="deal" x="close" node="check"/>
+            <nodes>
+              <node id="check" kind="transform"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="check" to="done"><when><ref predicate="large"/></when></edge>
+              <edge from="check" to="done"><when><gte left="$entity.stage" right="3"/></when></edge>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        match compile(xml, &CompileOptions::default()) {
+            Err(CompileError::Validation { diagnostics }) => {
+                let codes: Vec<_> = diagnostics.iter().map(|d| d.code.as_str()).collect();
+                assert_eq!(codes, ["SEM010", "SEM011"], "{:?}", diagnostics);
+                assert_eq!(diagnostics[0].hint.as_deref(), Some("Did you mean 'amount'?"));
+            }
+            other => panic!("typed predicates should fail, got {:?}", other.map(|r| r.diagnostics)),
+        }
+
+        let fixed = xml.replace("amout", "amount").replace(r#"right="3""#, r#"right="won""#).replace("gte", "eq");
+        compile(&fixed, &CompileOptions::default()).expect("well-typed predicates compile");
+    }
+
+    /// ADVERSARIAL: Arithmetic written without spaces must not compile
+    /// to a read of a variable that does not exist
+    ///
+    /// `$end-$start` would otherwise load the path `end-$start`, which is
+    /// never set, so the guard would silently never pass.
+    #[test]
+    fn unspaced_arithmetic_rejected() {
+        let xml = |when: &str| format!(r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <workflow id="window">
+            <entry p="window" x="check" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges><edge from="start" to="done"><when>{}</when></edge></edges>
+          </workflow>
+        </omar>"#, when);
+
+        for when in [
+            r#"<compare left="$end-$start" cmp="lt" right="86400"/>"#,
+            r#"<lt left="$end-$start" right="86400"/>"#,
+            r#"<gt left="$a*2" right="1"/>"#,
+        ] {
+            let err = compile(&xml(when), &CompileOptions::default()).unwrap_err();
+            assert!(format!("{:?}", err).contains("put spaces around arithmetic operators"), "{}: {:?}", when, err);
+        }
+
+        let spaced = xml(r#"<compare left="$end - $start" cmp="lt" right="86400"/>"#);
+        compile(&spaced, &CompileOptions::default()).expect("spaced arithmetic compiles");
+    }
+
+    /// ADVERSARIAL: A graph.bin whose predicate bytecode was tampered
+    /// with after compiling is rejected by the binary verifier
+    #[test]
+    fn tampered_predicate_bytecode_rejected() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <predicates>
+            <predicate id="is_admin"><eq left="$token.role" right="admin"/></predicate>
+          </predicates>
+          <workflow id="admin">
+            <entry p="admin" x="run" node="start"/>
+            <nodes>
+              <node id="start" kind="auth"><require predicate="is_admin"/></node>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges><edge from="start" to="done"/></edges>
+          </workflow>
+        </omar>"#;
+
+        let mut binary = compile(xml, &CompileOptions::default()).expect("compiles").binary;
+        inspect(&binary).expect("compiled binary verifies");
+
+        // Same length, but AND pops two values from an empty stack
+        let at = u32::from_le_bytes(binary[0x48..0x4C].try_into().unwrap()) as usize;
+        let len = u16::from_le_bytes([binary[at], binary[at + 1]]) as usize;
+        let code = &mut binary[at + 2..at + 2 + len];
+        code.fill(Opcode::Noop as u8);
+        code[len - 2] = Opcode::And as u8;
+        code[len - 1] = Opcode::Ret as u8;
+
+        let err = inspect(&binary).unwrap_err();
+        assert!(err.contains("Predicate 1") && err.contains("underflow"), "{}", err);
+    }
 }
 
 mod invariants {
@@ -177,10 +516,7 @@ mod invariants {
     /// INVARIANT: Every entry point must be reachable from some node
     ///
     /// Entry points can't reference non-existent nodes.
-    ///
-    /// TODO: Fix XML test case - edge parsing issue
     #[test]
-    #[ignore]
     fn entry_points_reference_valid_nodes() {
         let xml = r#"<?xml version="1.0"?>
         <omar version="1.0.0">
@@ -232,6 +568,505 @@ mod invariants {
                 curr_name, curr_cost, prev_name, prev_cost);
         }
     }
+
+    /// INVARIANT: The XML and text front-ends compile the same workflow to
+    /// byte-identical graph.bin, source hash included
+    #[test]
+    fn text_and_xml_compile_identically() {
+        use pxyz::dsl::MemoryResolver;
+        use std::path::Path;
+
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <predicates>
+            <predicate id="is_admin"><eq left="$token.role" right="admin"/></predicate>
+          </predicates>
+          <workflow id="orders">
+            <entry p="orders" x="create" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="check" kind="auth"><require predicate="is_admin"/></node>
+              <node id="save" kind="external" op="0x0900"/>
+              <node id="done" kind="terminal" status="200"/>
+              <node id="denied" kind="error" status="403"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="check"/>
+              <edge from="check" to="save">
+                <when><and><eq left="$token.role" right="admin"/><gt left="$input.total" right="0"/></and></when>
+              </edge>
+              <edge from="check" to="denied" fallback="true"/>
+              <edge from="save" to="done"/>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        let text = r#"
+        omar 1.0.0
+        predicate is_admin = $token.role == "admin"
+
+        workflow orders {
+          entry orders create -> start
+          node start
+          node check auth require=is_admin
+          node save external op=0x0900
+          node done terminal status=200
+          node denied error status=403
+          start -> check
+          check -> save when $token.role == "admin" and $input.total > 0
+          check -> denied fallback
+          save -> done
+        }
+        "#;
+
+        let resolver = MemoryResolver::new();
+        let options = CompileOptions::default();
+        let from_xml = compile_with_resolver(xml, Path::new("orders.xml"), &resolver, &options)
+            .expect("XML compiles");
+        let from_text = compile_with_resolver(text, Path::new("orders.pxyz"), &resolver, &options)
+            .expect("text compiles");
+        assert_eq!(from_xml.binary, from_text.binary);
+
+        // The front-end follows the extension of each file, imports included
+        let resolver = MemoryResolver::new().with_file("orders.pxyz", text);
+        let importer = r#"<omar version="1.0.0"><import href="orders.pxyz"/></omar>"#;
+        let imported = compile_with_resolver(importer, Path::new("main.xml"), &resolver, &options)
+            .expect("text import compiles");
+        let (imported, direct) = (inspect(&imported.binary).unwrap(), inspect(&from_xml.binary).unwrap());
+        assert_eq!(imported.node_count, direct.node_count);
+        assert_eq!(imported.edge_count, direct.edge_count);
+    }
+
+    /// INVARIANT: `expr` attributes are only a spelling; they compile to
+    /// the same graph.bin as the element tree they stand for
+    #[test]
+    fn expr_attribute_compiles_like_element_tree() {
+        let workflow = |predicate: &str, when: &str| format!(r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <predicates>{}</predicates>
+          <workflow id="cart">
+            <entry p="cart" x="checkout" node="start"/>
+            <nodes>
+              <node id="start" kind="auth"><require predicate="is_admin"/></node>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges><edge from="start" to="done">{}</edge></edges>
+          </workflow>
+        </omar>"#, predicate, when);
+
+        let trees = workflow(
+            r#"<predicate id="is_admin"><or><eq left="$token.role" right="admin"/><not><fn name="null" arg="$token.sub"/></not></or></predicate>"#,
+            r#"<when><and><eq left="$token.role" right="admin"/><gt left="len($input.items)" right="0"/></and></when>"#,
+        );
+        let exprs = workflow(
+            r#"<predicate id="is_admin" expr="$token.role == 'admin' || !null($token.sub)"/>"#,
+            r#"<when expr="$token.role == 'admin' &amp;&amp; len($input.items) &gt; 0"/>"#,
+        );
+
+        let options = CompileOptions::default();
+        let from_trees = compile(&trees, &options).expect("element trees compile");
+        let from_exprs = compile(&exprs, &options).expect("expr attributes compile");
+        assert_eq!(from_trees.binary, from_exprs.binary);
+
+        // An unknown function is reported at its position in the attribute
+        let typo = exprs.replace("len($input", "lenn($input");
+        let diags = validate(&typo);
+        let diag = diags.iter().find(|d| d.message.contains("Unknown function: lenn")).expect("unknown function");
+        let span = diag.location.as_ref().and_then(|l| l.span).expect("span");
+        assert!(typo[span.start..span.end].starts_with("lenn($input.items)"), "{}", &typo[span.start..span.end]);
+    }
+
+    /// INVARIANT: Compiling with a profile gives the same graph.bin as the
+    /// document with the values written in, and the audit records the
+    /// profile; an undefined variable fails compilation
+    #[test]
+    fn profile_variables_substitute_and_are_audited() {
+        use pxyz::dsl::Profile;
+
+        let workflow = |op: &str, threshold: &str| format!(r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <workflow id="orders">
+            <entry p="orders" x="create" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="lookup" kind="external" op="{}"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="lookup"><when><gt left="$input.total" right="{}"/></when></edge>
+              <edge from="start" to="done" fallback="true"/>
+              <edge from="lookup" to="done"/>
+            </edges>
+          </workflow>
+        </omar>"#, op, threshold);
+        let source = workflow("${lookup_op}", "${threshold}");
+
+        let compile_with = |name: &str, profile: &str| {
+            let options = CompileOptions {
+                emit_audit: true,
+                profile: Profile::parse(name, profile).unwrap(),
+                ..Default::default()
+            };
+            compile(&source, &options).expect("profile compiles")
+        };
+        let staging = compile_with("staging", "lookup_op = 0x0101\nthreshold = 10\n");
+        let production = compile_with("production", "lookup_op = 0x0400\nthreshold = 5000\n");
+        assert_ne!(staging.binary, production.binary);
+
+        let forked = compile(&workflow("0x0400", "5000"), &CompileOptions::default()).unwrap();
+        assert_eq!(production.binary, forked.binary);
+
+        let audit = production.audit.expect("audit");
+        let profile = audit.profile.expect("profile recorded");
+        assert_eq!(profile.name.as_deref(), Some("production"));
+        assert_eq!(profile.variables["threshold"], "5000");
+        assert!(forked.audit.is_none());
+
+        match compile(&source, &CompileOptions::default()) {
+            Err(CompileError::Validation { diagnostics }) => {
+                let undefined: Vec<_> = diagnostics.iter()
+                    .filter(|d| d.code == "VAR001")
+                    .map(|d| d.message.as_str())
+                    .collect();
+                assert_eq!(undefined, ["Undefined variable 'lookup_op'", "Undefined variable 'threshold'"]);
+            }
+            other => panic!("undefined variables should fail, got {:?}", other.map(|r| r.diagnostics)),
+        }
+    }
+
+    /// INVARIANT: `<set>` data and signals reach graph.bin
+    ///
+    /// Every node's data_offset points at a record in the node data
+    /// section whose pairs resolve through the string pool, so the
+    /// runtime can hand them to the host with io.call.
+    #[test]
+    fn node_data_and_signals_are_emitted() {
+        use pxyz::compiler::ir::decode_node_data;
+        use pxyz::emit::binary::{header_offsets, node_offsets, NODE_ENTRY_SIZE};
+
+        let xml = r##"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <workflow id="cart">
+            <entry p="cart" x="add" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="update" kind="signal">
+                <set signal="cart.count" value="3"/>
+                <set signal="cart.busy" value="false"/>
+                <set key="target" value="#cart"/>
+              </node>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="update"/>
+              <edge from="update" to="done"/>
+            </edges>
+          </workflow>
+        </omar>"##;
+        let binary = compile(xml, &CompileOptions::default()).expect("compiles").binary;
+
+        let u32_at = |at: usize| u32::from_le_bytes(binary[at..at + 4].try_into().unwrap()) as usize;
+        let string_at = |offset: u32| {
+            let start = u32_at(header_offsets::STRINGS_OFFSET) + offset as usize;
+            let end = binary[start..].iter().position(|&b| b == 0).unwrap() + start;
+            std::str::from_utf8(&binary[start..end]).unwrap().to_string()
+        };
+        let section_start = u32_at(header_offsets::NODE_DATA_OFFSET);
+        let section = &binary[section_start..section_start + u32_at(header_offsets::NODE_DATA_SIZE)];
+
+        let records: Vec<_> = (0..u32_at(header_offsets::NODE_COUNT))
+            .map(|i| {
+                let node = u32_at(header_offsets::NODES_OFFSET) + i * NODE_ENTRY_SIZE;
+                let offset = u32_at(node + node_offsets::DATA_OFFSET) as u32;
+                decode_node_data(section, offset).expect("data_offset points at a record")
+            })
+            .collect();
+
+        let resolve = |pairs: &[(u32, u32)]| -> Vec<(String, String)> {
+            pairs.iter().map(|&(k, v)| (string_at(k), string_at(v))).collect()
+        };
+        let with_data: Vec<_> = records.iter().filter(|r| !r.data.is_empty() || !r.signals.is_empty()).collect();
+        assert_eq!(with_data.len(), 1, "only the signal node carries data");
+        assert_eq!(resolve(&with_data[0].data), [("target".to_string(), "#cart".to_string())]);
+        assert_eq!(
+            resolve(&with_data[0].signals),
+            [("cart.count".to_string(), "3".to_string()), ("cart.busy".to_string(), "false".to_string())]
+        );
+    }
+
+    /// INVARIANT: A render node's op code is the id of its template, and
+    /// the templates section resolves that id to the template content
+    #[test]
+    fn render_nodes_resolve_to_emitted_templates() {
+        use pxyz::emit::binary::{header_offsets, node_offsets, template_offsets, NODE_ENTRY_SIZE, TEMPLATE_ENTRY_SIZE};
+
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <workflow id="page">
+            <entry p="page" x="show" node="header"/>
+            <nodes>
+              <node id="header" kind="render"><template ref="header"/></node>
+              <node id="body" kind="render"><template ref="body"/></node>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="header" to="body"/>
+              <edge from="body" to="done"/>
+            </edges>
+          </workflow>
+          <templates>
+            <template id="body"><![CDATA[<main>{content}</main>]]></template>
+            <template id="header"><![CDATA[<h1>{title}</h1>]]></template>
+            <template id="unused">-</template>
+          </templates>
+        </omar>"#;
+        let result = compile(xml, &CompileOptions::default()).expect("compiles");
+        assert!(result.diagnostics.iter().any(|d| d.code == "SEM009" && d.message.contains("'unused'")));
+        let binary = result.binary;
+
+        let u16_at = |at: usize| u16::from_le_bytes(binary[at..at + 2].try_into().unwrap()) as usize;
+        let u32_at = |at: usize| u32::from_le_bytes(binary[at..at + 4].try_into().unwrap()) as usize;
+        let string_at = |offset: usize| {
+            let start = u32_at(header_offsets::STRINGS_OFFSET) + offset;
+            let end = binary[start..].iter().position(|&b| b == 0).unwrap() + start;
+            std::str::from_utf8(&binary[start..end]).unwrap().to_string()
+        };
+        assert_eq!(u32_at(header_offsets::TEMPLATE_COUNT), 3);
+
+        let rendered: Vec<_> = (0..2)
+            .map(|i| {
+                let node = u32_at(header_offsets::NODES_OFFSET) + i * NODE_ENTRY_SIZE;
+                let template_id = u16_at(node + node_offsets::OP_CODE);
+                assert_ne!(template_id, 0, "0 means no template");
+                let entry = u32_at(header_offsets::TEMPLATES_OFFSET) + (template_id - 1) * TEMPLATE_ENTRY_SIZE;
+                (
+                    string_at(u32_at(entry + template_offsets::NAME)),
+                    string_at(u32_at(entry + template_offsets::CONTENT)),
+                )
+            })
+            .collect();
+        assert_eq!(rendered, [
+            ("header".to_string(), "<h1>{title}</h1>".to_string()),
+            ("body".to_string(), "<main>{content}</main>".to_string()),
+        ]);
+    }
+
+    /// INVARIANT: The workflow fixtures are in canonical form (so
+    /// `pxyz fmt --check` passes on them), formatting them is a no-op,
+    /// and they compile
+    #[test]
+    fn fixtures_are_formatted_and_compile() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workflows");
+        let mut count = 0;
+        for entry in std::fs::read_dir(&dir).expect("fixtures directory") {
+            let path = entry.unwrap().path();
+            let xml = std::fs::read_to_string(&path).unwrap();
+
+            let formatted = pxyz::dsl::writer::format(&xml)
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            assert_eq!(formatted, xml, "{} is not formatted", path.display());
+
+            compile(&xml, &CompileOptions::default())
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            count += 1;
+        }
+        assert!(count > 0, "no fixtures in {}", dir.display());
+    }
+
+    /// INVARIANT: A document compiled from JSON, or handed over directly,
+    /// gives the same graph.bin as the XML it was serialized from
+    #[test]
+    fn json_and_documents_compile_identically() {
+        use pxyz::dsl::MemoryResolver;
+        use std::path::Path;
+
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <workflow id="orders">
+            <entry p="orders" x="create" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="done"><when><gte left="$input.total" right="10"/></when></edge>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        let options = CompileOptions::default();
+        let from_xml = compile(xml, &options).expect("XML compiles");
+
+        let doc = parse(xml).unwrap();
+        let from_doc = compile_document(&doc, &options).expect("document compiles");
+        assert_eq!(from_doc.binary, from_xml.binary);
+
+        let json = pxyz::dsl::json::to_json(&doc);
+        let from_json = compile_with_resolver(&json, Path::new("orders.json"), &MemoryResolver::new(), &options)
+            .expect("JSON compiles");
+        assert_eq!(from_json.binary, from_xml.binary);
+
+        // Imports must be resolved before compile_document
+        let with_import = parse(r#"<omar version="1.0.0"><import href="lib.xml"/></omar>"#).unwrap();
+        match compile_document(&with_import, &options) {
+            Err(CompileError::Validation { diagnostics }) => assert_eq!(diagnostics[0].code, "IMP001"),
+            other => panic!("expected IMP001, got {:?}", other.map(|r| r.diagnostics)),
+        }
+    }
+
+    /// INVARIANT: Deduplicating predicates keeps every reference in
+    /// graph.bin pointing at the bytecode it pointed at unoptimized; the
+    /// runtime finds predicate n at table entry n - 1
+    fn u32_at(binary: &[u8], at: usize) -> usize {
+        u32::from_le_bytes(binary[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    /// Bytecode of each predicate table entry; entry n - 1 is predicate n
+    fn predicate_table(binary: &[u8]) -> Vec<Vec<u8>> {
+        let mut at = u32_at(binary, 0x48);
+        (0..u32_at(binary, 0x10)).map(|_| {
+            let len = u16::from_le_bytes([binary[at], binary[at + 1]]) as usize;
+            at += 2 + len;
+            binary[at - len..at].to_vec()
+        }).collect()
+    }
+
+    #[test]
+    fn deduplicated_predicates_keep_their_references() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <predicates>
+            <predicate id="is_admin"><eq left="$token.role" right="admin"/></predicate>
+            <predicate id="is_admin_too"><eq left="$token.role" right="admin"/></predicate>
+            <predicate id="has_total"><gt left="$input.total" right="0"/></predicate>
+          </predicates>
+          <workflow id="order">
+            <entry p="order" x="create" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="check" kind="auth"><require predicate="is_admin_too"/></node>
+              <node id="done" kind="terminal" status="200"/>
+              <node id="empty" kind="error" status="400"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="check"><when><ref predicate="has_total"/></when></edge>
+              <edge from="start" to="empty" fallback="true"/>
+              <edge from="check" to="done"/>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        // Bytecode of every edge guard and auth predicate, in table order
+        fn referenced(binary: &[u8]) -> Vec<Vec<u8>> {
+            let table = predicate_table(binary);
+            let lookup = |id: usize| table.get(id - 1).cloned().expect("id within the predicate table");
+            let edges = (0..u32_at(binary, 0x0C))
+                .map(|i| u32_at(binary, u32_at(binary, 0x44) + i * 12 + 4))
+                .filter(|&id| id != 0);
+            let auth = (0..u32_at(binary, 0x08))
+                .map(|i| u32_at(binary, 0x40) + i * 16)
+                .filter(|&at| binary[at + 4] == NodeKind::Auth as u8)
+                .map(|at| u16::from_le_bytes([binary[at + 6], binary[at + 7]]) as usize);
+            let mut code: Vec<_> = edges.chain(auth).map(lookup).collect();
+            code.sort();
+            code
+        }
+
+        let plain = compile(xml, &CompileOptions::default()).expect("compiles");
+        let optimized = compile(xml, &CompileOptions { optimize: true, ..Default::default() })
+            .expect("compiles optimized");
+
+        assert_eq!(predicate_table(&plain.binary).len(), 3);
+        assert_eq!(predicate_table(&optimized.binary).len(), 2, "duplicate removed");
+        assert_eq!(referenced(&optimized.binary), referenced(&plain.binary));
+    }
+
+    /// INVARIANT: A CallPred past a removed duplicate still targets its
+    /// callee's table entry, and the binary verifier accepts it
+    #[test]
+    fn calls_past_removed_duplicates_verify() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <predicates>
+            <predicate id="is_admin"><eq left="$token.role" right="admin"/></predicate>
+            <predicate id="is_admin_too"><eq left="$token.role" right="admin"/></predicate>
+            <predicate id="has_total"><gt left="$input.total" right="0"/></predicate>
+            <predicate id="can_order">
+              <and><ref predicate="has_total"/><ref predicate="is_admin_too"/></and>
+            </predicate>
+          </predicates>
+          <workflow id="order">
+            <entry p="order" x="create" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges><edge from="start" to="done"><when><ref predicate="can_order"/></when></edge></edges>
+          </workflow>
+        </omar>"#;
+
+        let optimized = compile(xml, &CompileOptions { optimize: true, ..Default::default() })
+            .expect("compiles optimized");
+        let info = inspect(&optimized.binary).expect("optimized binary verifies");
+        assert_eq!(info.predicate_bounds.len(), 3, "duplicate removed");
+
+        // can_order calls has_total first, now predicate 2
+        let table = predicate_table(&optimized.binary);
+        let caller = table.iter().find(|code| code[0] == Opcode::CallPred as u8).expect("caller");
+        let callee = u16::from_le_bytes([caller[1], caller[2]]) as usize;
+        assert_eq!(callee, 2);
+        let plain = compile(xml, &CompileOptions::default()).expect("compiles");
+        assert_eq!(table[callee - 1], predicate_table(&plain.binary)[2], "has_total's bytecode");
+    }
+
+    /// INVARIANT: The bounds in the audit are the bounds the binary
+    /// verifier proves again from graph.bin, and are within the VM limits
+    #[test]
+    fn audited_predicate_bounds_match_binary() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <predicates>
+            <predicate id="is_admin"><eq left="$token.role" right="admin"/></predicate>
+            <predicate id="can_ship">
+              <and><ref predicate="is_admin"/><gt left="$input.total" right="0"/></and>
+            </predicate>
+          </predicates>
+          <workflow id="ship">
+            <entry p="order" x="ship" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="done" kind="terminal" status="200"/>
+              <node id="denied" kind="error" status="403"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="done">
+                <when><or><ref predicate="can_ship"/><eq left="$input.force" right="true"/></or></when>
+              </edge>
+              <edge from="start" to="denied" fallback="true"/>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        let options = CompileOptions { emit_audit: true, ..Default::default() };
+        let result = compile(xml, &options).expect("compiles");
+        let audit = result.audit.expect("audit");
+        let info = inspect(&result.binary).expect("binary verifies");
+
+        assert_eq!(audit.predicates.len(), info.predicate_bounds.len());
+        for (audited, proven) in audit.predicates.iter().zip(&info.predicate_bounds) {
+            assert_eq!(
+                (audited.max_stack, audited.max_steps, audited.call_depth),
+                (proven.max_stack, proven.max_steps, proven.call_depth),
+                "{}", audited.name
+            );
+            assert!(audited.max_stack <= limits::MAX_STACK_DEPTH);
+            assert!(audited.max_steps <= limits::MAX_PREDICATE_STEPS);
+        }
+
+        // The inline condition runs can_ship, which runs is_admin
+        let inline = audit.predicates.iter().find(|p| p.name.starts_with("_inline_")).expect("inline predicate");
+        assert_eq!(inline.call_depth, 3);
+    }
 }
 
 mod mutation_resistant {
@@ -297,6 +1132,113 @@ mod mutation_resistant {
         assert!(has_prag004_bad, "MUST have PRAG004 when no human in path (even if target is confirmed)");
     }
 
+    /// MUTATION-RESISTANT: Decimal literals keep their fraction
+    ///
+    /// Truncating `0.97` to an integer compiles cleanly and turns an LTV
+    /// ceiling into `ltv <= 0`; rounding a literal with more digits than
+    /// the VM holds would move the threshold just as silently.
+    #[test]
+    fn decimal_literals_are_not_truncated() {
+        let workflow = |ceiling: &str| format!(r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <predicates>
+            <predicate id="ltv_ok"><lte left="$loan.ltv" right="{}"/></predicate>
+          </predicates>
+          <workflow id="loans">
+            <entry p="loans" x="approve" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="done"><when><ref predicate="ltv_ok"/></when></edge>
+            </edges>
+          </workflow>
+        </omar>"#, ceiling);
+
+        let ir = compile_pipeline(&workflow("0.97"), false).expect("decimal literal compiles").0;
+        let pred = ir.predicates.iter().find(|p| p.name == "ltv_ok").unwrap();
+        let push = pred.bytecode.iter().position(|&b| b == Opcode::PushDec as u8)
+            .expect("0.97 must compile to PushDec, not PushInt");
+        assert_eq!(pred.bytecode[push + 1..push + 9], 970_000i64.to_le_bytes());
+
+        let err = compile(&workflow("0.9712345"), &CompileOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("loses precision"), "{}", err);
+    }
+
+    /// MUTATION-RESISTANT: arithmetic keeps operand order and precedence
+    ///
+    /// `end - start < 86400` with the operands swapped, or with `*` bound
+    /// looser than `-`, still compiles and still looks plausible in a
+    /// disassembly; only the instruction order shows the difference.
+    #[test]
+    fn arithmetic_keeps_operand_order() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <predicates>
+            <predicate id="short_stay">
+              <compare left="$input.end - $input.start * 2" cmp="lt" right="86400"/>
+            </predicate>
+          </predicates>
+          <workflow id="bookings">
+            <entry p="bookings" x="create" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="done"><when><ref predicate="short_stay"/></when></edge>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        let ir = compile_pipeline(xml, false).expect("arithmetic compiles").0;
+        let pred = ir.predicates.iter().find(|p| p.name == "short_stay").unwrap();
+        let ops: Vec<u8> = [0usize, 5, 10, 15, 16, 17, 22, 23].iter().map(|&at| pred.bytecode[at]).collect();
+        assert_eq!(ops, [
+            Opcode::LoadVar as u8,  // $input.end
+            Opcode::LoadVar as u8,  // $input.start
+            Opcode::PushInt as u8,  // 2
+            Opcode::Mul as u8,      // binds tighter than -
+            Opcode::Sub as u8,      // end - (start * 2), not (start * 2) - end
+            Opcode::PushInt as u8,  // 86400
+            Opcode::Lt as u8,
+            Opcode::Ret as u8,
+        ]);
+    }
+
+    /// MUTATION-RESISTANT: Optimizing keeps operands that may fault
+    ///
+    /// Dividing by zero fails the whole evaluation. Folding
+    /// `<or>(x / 0 > 1, <always/>)` to `<always/>` would pass a guard the
+    /// unoptimized build fails, so both builds must run the same bytecode.
+    #[test]
+    fn optimizing_keeps_faulting_operands() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <workflow id="ratios">
+            <entry p="ratios" x="check" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="done">
+                <when><or><compare left="$input.total / 0" cmp="gt" right="1"/><always/></or></when>
+              </edge>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        let bytecode = |optimize: bool| {
+            let ir = compile_pipeline(xml, optimize).expect("compiles").0;
+            ir.predicates.iter().map(|p| p.bytecode.clone()).collect::<Vec<_>>()
+        };
+        let plain = bytecode(false);
+        assert_eq!(bytecode(true), plain);
+        assert!(plain[0].contains(&(Opcode::Div as u8)), "the division still runs");
+    }
+
     /// MUTATION-RESISTANT: MAX_VISITED is not arbitrary
     ///
     /// A naive implementation might use 10 or 1,000,000. We use 1000 for a reason.
@@ -333,12 +1275,79 @@ mod boundary {
         if let Ok(ast) = result {
             let ir_result = lower(&ast);
             assert!(ir_result.is_err() || {
-                let diags = compiler::validate(&ir_result.as_ref().unwrap());
+                let diags = compiler::validate(ir_result.as_ref().unwrap());
                 diags.iter().any(|d| d.severity == Severity::Error)
             }, "Empty workflow should be rejected");
         }
     }
 
+    /// BOUNDARY: A broken document reports parse errors and downstream
+    /// validation errors in a single pass
+    #[test]
+    fn broken_document_reports_downstream_errors() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <workflow id="broken">
+            <entry p="test" x="broken" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <node id="done" kind="terminal" status="two hundred"/>
+              <node id="orphan" kind="transform">
+            </nodes>
+            <edges>
+              <edge from="start" to="done"/>
+              <edge from="done" to="start"/>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        let diags = validate(xml);
+        let codes: Vec<_> = diags.iter().map(|d| d.code.as_str()).collect();
+
+        assert!(codes.contains(&"PARSE004"), "bad status should be reported: {:?}", codes);
+        assert!(codes.contains(&"PARSE002"), "unclosed node should be reported: {:?}", codes);
+        assert!(codes.contains(&"SEM004"), "cycle should still be reported: {:?}", codes);
+    }
+
+    /// BOUNDARY: The same fragment used twice yields two independent,
+    /// fully checked copies
+    #[test]
+    fn fragment_uses_expand_to_distinct_nodes() {
+        let xml = r#"<?xml version="1.0"?>
+        <omar version="1.0.0">
+          <fragment id="guarded_write" params="op,next">
+            <nodes>
+              <node id="write" kind="external" op="{op}"/>
+              <node id="failed" kind="error" status="500"/>
+            </nodes>
+            <edges>
+              <edge from="write" to="{next}"/>
+              <edge from="write" to="failed" fallback="true"/>
+            </edges>
+          </fragment>
+          <workflow id="save">
+            <entry p="test" x="save" node="start"/>
+            <nodes>
+              <node id="start" kind="transform"/>
+              <use fragment="guarded_write" id="store" op="0x0901" next="log.write"/>
+              <use fragment="guarded_write" id="log" op="0x0910" next="done"/>
+              <node id="done" kind="terminal" status="200"/>
+            </nodes>
+            <edges>
+              <edge from="start" to="store.write"/>
+            </edges>
+          </workflow>
+        </omar>"#;
+
+        let (ir, _, diags) = compile_pipeline(xml, false).expect("fragments expand and compile");
+        assert!(!diags.iter().any(|d| d.code == "PRAG002"), "{:?}", diags);
+        assert!(diags.iter().all(|d| d.severity != Severity::Error), "{:?}", diags);
+
+        for name in ["store.write", "store.failed", "log.write", "log.failed"] {
+            assert!(ir.get_node_by_name(name).is_some(), "missing {}", name);
+        }
+    }
+
     /// BOUNDARY: Single node workflow (no edges) should work
     #[test]
     fn single_node_workflow_valid() {
@@ -371,7 +1380,40 @@ mod boundary {
 
         // This is enforced during compilation - we can't test here without a predicate compiler,
         // but we document the invariant
-        assert!(MAX_PREDICATE_BYTECODE > 0, "Must have non-zero bytecode limit");
-        assert!(MAX_PREDICATE_BYTECODE <= 1024, "Bytecode limit should be reasonable");
+        const { assert!(MAX_PREDICATE_BYTECODE > 0, "Must have non-zero bytecode limit") };
+        const { assert!(MAX_PREDICATE_BYTECODE <= 1024, "Bytecode limit should be reasonable") };
+    }
+
+    /// BOUNDARY: Operands held on the stack at once may reach
+    /// MAX_STACK_DEPTH but not exceed it
+    #[test]
+    fn predicate_stack_depth_limit_enforced() {
+        let workflow = |operands: usize| {
+            // Right-nested sums keep every operand on the stack until the end
+            let sum: Vec<_> = (0..operands).map(|i| format!("($input.a{}", i)).collect();
+            format!(r#"<?xml version="1.0"?>
+            <omar version="1.0.0">
+              <predicates>
+                <predicate id="deep"><compare left="{}{}" cmp="gt" right="0"/></predicate>
+              </predicates>
+              <workflow id="deep">
+                <entry p="test" x="deep" node="start"/>
+                <nodes>
+                  <node id="start" kind="auth"><require predicate="deep"/></node>
+                  <node id="done" kind="terminal" status="200"/>
+                </nodes>
+                <edges><edge from="start" to="done"/></edges>
+              </workflow>
+            </omar>"#, sum.join(" + "), ")".repeat(operands))
+        };
+
+        let at_limit = compile(&workflow(limits::MAX_STACK_DEPTH), &CompileOptions::default())
+            .expect("stack at the limit compiles");
+        assert_eq!(inspect(&at_limit.binary).unwrap().predicate_bounds[0].max_stack, limits::MAX_STACK_DEPTH);
+
+        match compile(&workflow(limits::MAX_STACK_DEPTH + 1), &CompileOptions::default()) {
+            Err(e) => assert!(e.to_string().contains("exceeds MAX_STACK_DEPTH"), "{}", e),
+            Ok(_) => panic!("stack over the limit should fail"),
+        }
     }
 }
@@ -0,0 +1,361 @@
+//! Published Grammar Tests
+//!
+//! Every workflow fixture must validate against the schemas `pxyz schema`
+//! prints. No schema validator is a dependency of the crate, so this file
+//! interprets the subset of XSD and JSON Schema the generator emits; when
+//! `xmllint` is installed the XSD is also checked with it.
+
+use pxyz::dsl::grammar::{json_schema, xsd};
+use pxyz::dsl::json::to_json;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workflows");
+    let mut paths: Vec<_> = std::fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "xml"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    paths
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// XSD
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[derive(Default)]
+struct ComplexType {
+    mixed: bool,
+    children: HashMap<String, String>,
+    attributes: HashMap<String, bool>,
+    any_attribute: bool,
+}
+
+struct Xsd {
+    roots: HashMap<String, String>,
+    types: HashMap<String, ComplexType>,
+}
+
+fn attr(e: &BytesStart, name: &str) -> Option<String> {
+    e.attributes().flatten()
+        .find(|a| a.key.as_ref() == name.as_bytes())
+        .map(|a| a.unescape_value().unwrap().into_owned())
+}
+
+impl Xsd {
+    fn parse(src: &str) -> Self {
+        let mut xsd = Xsd { roots: HashMap::new(), types: HashMap::new() };
+        let mut current: Option<String> = None;
+        let mut reader = Reader::from_str(src);
+
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(e) | Event::Empty(e) => {
+                    let name = attr(&e, "name");
+                    match (e.name().as_ref(), &current) {
+                        (b"xs:complexType", _) => {
+                            let name = name.unwrap();
+                            let mixed = attr(&e, "mixed").as_deref() == Some("true");
+                            xsd.types.insert(name.clone(), ComplexType { mixed, ..Default::default() });
+                            current = Some(name);
+                        }
+                        (b"xs:element", None) => {
+                            xsd.roots.insert(name.unwrap(), attr(&e, "type").unwrap());
+                        }
+                        (b"xs:element", Some(ty)) => {
+                            xsd.types.get_mut(ty).unwrap().children.insert(name.unwrap(), attr(&e, "type").unwrap());
+                        }
+                        (b"xs:attribute", Some(ty)) => {
+                            let required = attr(&e, "use").as_deref() == Some("required");
+                            xsd.types.get_mut(ty).unwrap().attributes.insert(name.unwrap(), required);
+                        }
+                        (b"xs:anyAttribute", Some(ty)) => xsd.types.get_mut(ty).unwrap().any_attribute = true,
+                        (b"xs:schema" | b"xs:choice", _) => {}
+                        (other, _) => panic!("generator emitted unexpected {}", String::from_utf8_lossy(other)),
+                    }
+                }
+                Event::End(e) if e.name().as_ref() == b"xs:complexType" => current = None,
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        xsd
+    }
+
+    /// Whether the innermost open element may contain text
+    fn mixed(&self, stack: &[&str]) -> bool {
+        stack.last().is_some_and(|ty| self.types[*ty].mixed)
+    }
+
+    /// Validation errors for `xml`, empty if it is valid
+    fn validate(&self, xml: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut stack: Vec<&str> = Vec::new();
+        let mut reader = Reader::from_str(xml);
+
+        loop {
+            let event = reader.read_event().unwrap();
+            match &event {
+                Event::Start(e) | Event::Empty(e) => {
+                    let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                    let declared = match stack.last() {
+                        Some(parent) => self.types[*parent].children.get(&name),
+                        None => self.roots.get(&name),
+                    };
+                    let Some(ty) = declared else {
+                        errors.push(format!("<{}> not allowed under {:?}", name, stack.last()));
+                        break;
+                    };
+                    let spec = &self.types[ty];
+                    let present: Vec<String> = e.attributes().flatten()
+                        .map(|a| String::from_utf8_lossy(a.key.as_ref()).into_owned())
+                        .collect();
+                    for a in &present {
+                        if !spec.attributes.contains_key(a) && !spec.any_attribute {
+                            errors.push(format!("<{}> has undeclared attribute '{}'", name, a));
+                        }
+                    }
+                    for (a, required) in &spec.attributes {
+                        if *required && !present.contains(a) {
+                            errors.push(format!("<{}> is missing required '{}'", name, a));
+                        }
+                    }
+                    if matches!(event, Event::Start(_)) {
+                        stack.push(ty);
+                    }
+                }
+                Event::End(_) => {
+                    stack.pop();
+                }
+                Event::Text(t) if !t.unescape().unwrap().trim().is_empty() && !self.mixed(&stack) => {
+                    errors.push(format!("text not allowed in {:?}", stack.last()));
+                }
+                Event::CData(_) if !self.mixed(&stack) => {
+                    errors.push(format!("CDATA not allowed in {:?}", stack.last()));
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        errors
+    }
+}
+
+#[test]
+fn fixtures_validate_against_xsd() {
+    let xsd = Xsd::parse(&xsd());
+    for path in fixtures() {
+        let xml = std::fs::read_to_string(&path).unwrap();
+        let errors = xsd.validate(&xml);
+        assert!(errors.is_empty(), "{}: {:?}", path.display(), errors);
+    }
+}
+
+#[test]
+fn xsd_rejects_what_the_parser_rejects() {
+    let xsd = Xsd::parse(&xsd());
+    let errors = xsd.validate(r#"<omar version="1.0">
+      <workflow id="w">
+        <entry p="a" x="b"/>
+        <nodes><node id="n" kind="terminal" wieght="1"><bogus/></node></nodes>
+      </workflow>
+    </omar>"#);
+    assert_eq!(errors, [
+        "<entry> is missing required 'node'",
+        "<node> has undeclared attribute 'wieght'",
+        "<bogus> not allowed under Some(\"node\")",
+    ]);
+
+    // Context matters: <template ref> belongs under <node>, not <templates>
+    let errors = xsd.validate(r#"<omar><templates><template ref="x"/></templates></omar>"#);
+    assert_eq!(errors, [
+        "<template> has undeclared attribute 'ref'",
+        "<template> is missing required 'id'",
+    ]);
+}
+
+/// The generated XSD is a valid schema for a real validator too
+#[test]
+fn fixtures_validate_with_xmllint() {
+    if std::process::Command::new("xmllint").arg("--version").output().is_err() {
+        eprintln!("xmllint not installed, skipping");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("pxyz-schema-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let schema = dir.join("pxyz.xsd");
+    std::fs::write(&schema, xsd()).unwrap();
+
+    let output = std::process::Command::new("xmllint")
+        .arg("--noout")
+        .arg("--schema")
+        .arg(&schema)
+        .args(fixtures())
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// JSON SCHEMA
+// ═══════════════════════════════════════════════════════════════════════════
+
+/// Validation errors for `value` against `schema`, resolving `$ref`s in
+/// `root`
+fn json_errors(root: &Value, schema: &Value, value: &Value, path: &str) -> Vec<String> {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference.strip_prefix("#/$defs/").expect("local ref");
+        return json_errors(root, &root["$defs"][name], value, path);
+    }
+    if let Some(options) = schema.get("oneOf").and_then(Value::as_array) {
+        let matching = options.iter().filter(|s| json_errors(root, s, value, path).is_empty()).count();
+        return match matching {
+            1 => Vec::new(),
+            n => vec![format!("{}: matches {} of oneOf", path, n)],
+        };
+    }
+
+    let mut errors = Vec::new();
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::Array(list) => list.iter().filter_map(Value::as_str).collect(),
+            other => vec![other.as_str().unwrap()],
+        };
+        let fits = |ty: &str| match ty {
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            other => panic!("unknown type {}", other),
+        };
+        if !types.iter().any(|ty| fits(ty)) {
+            return vec![format!("{}: expected {:?}, got {}", path, types, value)];
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            errors.push(format!("{}: expected {}", path, expected));
+        }
+    }
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            errors.push(format!("{}: {} not in enum", path, value));
+        }
+    }
+    if let (Some(min), Some(n)) = (schema.get("minimum").and_then(Value::as_i64), value.as_i64()) {
+        if n < min {
+            errors.push(format!("{}: below minimum", path));
+        }
+    }
+    if let (Some(max), Some(n)) = (schema.get("maximum").and_then(Value::as_i64), value.as_i64()) {
+        if n > max {
+            errors.push(format!("{}: above maximum", path));
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        let len = items.len() as u64;
+        if schema.get("minItems").and_then(Value::as_u64).is_some_and(|min| len < min)
+            || schema.get("maxItems").and_then(Value::as_u64).is_some_and(|max| len > max)
+        {
+            errors.push(format!("{}: wrong length {}", path, len));
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                errors.extend(json_errors(root, item_schema, item, &format!("{}[{}]", path, i)));
+            }
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for required in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+            if !object.contains_key(required.as_str().unwrap()) {
+                errors.push(format!("{}: missing {}", path, required));
+            }
+        }
+        for (key, item) in object {
+            let item_path = format!("{}.{}", path, key);
+            match (properties.and_then(|p| p.get(key)), schema.get("additionalProperties")) {
+                (Some(item_schema), _) => errors.extend(json_errors(root, item_schema, item, &item_path)),
+                (None, Some(Value::Bool(false))) => errors.push(format!("{}: not allowed", item_path)),
+                (None, Some(extra)) if extra.is_object() => errors.extend(json_errors(root, extra, item, &item_path)),
+                (None, _) => {}
+            }
+        }
+    }
+    errors
+}
+
+#[test]
+fn fixtures_validate_against_json_schema() {
+    let schema = json_schema();
+    for path in fixtures() {
+        let doc = pxyz::dsl::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let json: Value = serde_json::from_str(&to_json(&doc)).unwrap();
+        let errors = json_errors(&schema, &schema, &json, "$");
+        assert!(errors.is_empty(), "{}: {:?}", path.display(), errors);
+    }
+}
+
+/// Every AST field appears in the schema: a fully populated document
+/// exercises all of them, and the schema's closed objects reject any the
+/// schema does not list
+#[test]
+fn json_schema_covers_the_ast() {
+    let xml = r#"<omar version="1.0.0">
+      <import href="lib.xml" as="lib"/>
+      <schemas><schema name="S"><field name="f" type="number" required="true" default="1" pattern="x"/></schema></schemas>
+      <predicates>
+        <predicate id="p"><and>
+          <eq left="$a" right="1"/><neq left="$a" right="$b"/><gt left="$a" right="1.5"/><gte left="$a" right="true"/>
+          <lt left="$a" right="x"/><lte left="$a" right="2"/><contains left="$a" right="x"/><matches left="$a" pattern="x"/>
+          <startsWith left="$a" prefix="x"/><endsWith left="$a" suffix="x"/><fn name="defined" arg="$a"/>
+          <or><always/><fail/></or><not><ref predicate="q"/></not>
+        </and></predicate>
+      </predicates>
+      <workflow id="w" description="d">
+        <entry p="a" x="b" node="n"/>
+        <nodes>
+          <node id="n" kind="render" op="0x0100" status="200" actor="human" confirmation="c" async="true" cacheable="true">
+            <template ref="t"/><schema ref="S"/><require predicate="p"/><selector>s</selector><message>m</message>
+            <set signal="k" value="v"/>
+          </node>
+          <node id="c" kind="call" workflow="w2"/>
+          <use fragment="f" id="u" arg="1"/>
+        </nodes>
+        <edges>
+          <edge from="n" to="c" weight="2" parallel="true" fallback="true"><when><eq left="$a" right="1"/></when></edge>
+        </edges>
+      </workflow>
+      <fragment id="f" params="arg"><nodes><node id="x"/></nodes></fragment>
+      <templates><template id="t">hi</template></templates>
+      <merge>
+        <entity name="E" default="max" pre="p" post="p">
+          <field name="a" policy="union" validate="p"/><field name="b" prefer_origin="admin"/>
+        </entity>
+      </merge>
+    </omar>"#;
+    let (mut doc, _) = pxyz::dsl::parse_recovering(xml);
+    doc.workflows[0].nodes[0].data.insert("key".into(), "value".into());
+    doc.workflows[0].edges[0].predicate_ref = Some("p".into());
+    doc.merge_policies[0].fields[0].policy = pxyz::dsl::MergePolicy::Custom { predicate: "p".into() };
+
+    let schema = json_schema();
+    let json: Value = serde_json::from_str(&to_json(&doc)).unwrap();
+    let errors = json_errors(&schema, &schema, &json, "$");
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let mut unknown = json.clone();
+    unknown["workflows"][0]["nodes"][0]["wieght"] = 1.into();
+    assert_eq!(json_errors(&schema, &schema, &unknown, "$"), ["$.workflows[0].nodes[0].wieght: not allowed"]);
+}
@@ -27,15 +27,24 @@
   
   (import "io" "call" (func $io_call (param i32 i32 i32) (result i32)))
   ;; io_call(op_code, payload_ptr, payload_len) -> result_ptr
+  ;; payload: the node's <set> data and signals (see NODE DATA PAYLOAD)
   
-  (import "io" "resolve_var" (func $io_resolve_var (param i32 i32) (result i64)))
-  ;; resolve_var(path_ptr, path_len) -> i64 (type << 32 | value)
+  (import "io" "resolve_var" (func $io_resolve_var (param i32 i32 i32) (result i64)))
+  ;; resolve_var(path_ptr, path_len, type_ptr) -> i64 value
+  ;; host writes the value's type (i32, VAL_INT/VAL_STR/VAL_DEC) at type_ptr
   
   (import "io" "str_contains" (func $io_str_contains (param i32 i32 i32 i32) (result i32)))
   (import "io" "str_matches" (func $io_str_matches (param i32 i32 i32 i32) (result i32)))
   (import "io" "str_starts_with" (func $io_str_starts_with (param i32 i32 i32 i32) (result i32)))
   (import "io" "str_ends_with" (func $io_str_ends_with (param i32 i32 i32 i32) (result i32)))
   
+  (import "io" "now" (func $io_now (result i64)))
+  ;; now() -> current time in Unix epoch milliseconds (for NOW / WITHIN)
+  
+  (import "io" "in_list" (func $io_in_list (param i64 i32 i32 i32) (result i32)))
+  ;; in_list(value, type, list_ptr, list_len) -> 0 or 1
+  ;; list: comma-separated member literals from the String Pool (see OP_IN)
+  
   (import "io" "is_confirmed" (func $io_is_confirmed (param i32) (result i32)))
   ;; is_confirmed(entity_id) -> 0 or 1
   
@@ -91,6 +100,9 @@
   (global $pred_sp (mut i32) (i32.const 0))        ;; stack pointer
   (global $pred_steps (mut i32) (i32.const 0))     ;; step counter
   (global $pred_call_depth (mut i32) (i32.const 0)) ;; nested predicate calls
+  (global $pred_fault (mut i32) (i32.const 0))     ;; arithmetic fault, fails the evaluation
+  (global $pred_now (mut i64) (i64.const 0))       ;; io_now() for this evaluation
+  (global $pred_now_set (mut i32) (i32.const 0))   ;; whether $pred_now was read
 
   ;; ─── ENERGY TRACKING (Physics Integration) ───
   ;; Based on Horowitz 2014: DRAM = 6400× register operation
@@ -106,7 +118,10 @@
   (global $GRAPH_BASE i32 (i32.const 0x000000))
   (global $VISITED_BASE i32 (i32.const 0x010000))
   (global $IO_BASE i32 (i32.const 0x020000))
-  (global $STACK_BASE i32 (i32.const 0x030000))
+  (global $STACK_BASE i32 (i32.const 0x030000))  ;; 16 × i64 stack cells
+  (global $TAG_BASE i32 (i32.const 0x030080))    ;; 16 × i32 cell types
+  (global $VAR_TYPE_PTR i32 (i32.const 0x0300C0)) ;; resolve_var type out-param
+  (global $IO_SIZE i32 (i32.const 0x010000))
   
   ;; Safety limits
   (global $MAX_VISITED i32 (i32.const 1000))
@@ -123,11 +138,15 @@
   (global $HDR_PRED_COUNT i32 (i32.const 0x10))
   (global $HDR_STRING_SIZE i32 (i32.const 0x14))
   (global $HDR_ENTRY_COUNT i32 (i32.const 0x18))
+  (global $HDR_TEMPLATE_COUNT i32 (i32.const 0x1C))
   (global $HDR_NODES_OFF i32 (i32.const 0x40))
   (global $HDR_EDGES_OFF i32 (i32.const 0x44))
   (global $HDR_PREDS_OFF i32 (i32.const 0x48))
   (global $HDR_STRINGS_OFF i32 (i32.const 0x4C))
   (global $HDR_ENTRIES_OFF i32 (i32.const 0x50))
+  (global $HDR_TEMPLATES_OFF i32 (i32.const 0x54))
+  (global $HDR_NODE_DATA_OFF i32 (i32.const 0x58))
+  (global $HDR_NODE_DATA_SIZE i32 (i32.const 0x5C))
   
   ;; Node kinds
   (global $KIND_TRANSFORM i32 (i32.const 0))
@@ -151,12 +170,23 @@
   (global $EDGE_FALLBACK i32 (i32.const 0x0002))
   (global $EDGE_ERROR i32 (i32.const 0x0004))
   
+  ;; Predicate value types (stack cell tags)
+  (global $VAL_INT i32 (i32.const 0))
+  (global $VAL_STR i32 (i32.const 1))
+  (global $VAL_DEC i32 (i32.const 2))            ;; i64 count of 10^-6
+  (global $DEC_ONE i64 (i64.const 1000000))      ;; 10^DECIMAL_PLACES
+  (global $DEC_INT_MAX i64 (i64.const 9223372036854)) ;; i64::MAX / DEC_ONE
+  (global $I64_MIN i64 (i64.const -9223372036854775808))
+  (global $I64_MAX i64 (i64.const 9223372036854775807))
+
   ;; Predicate opcodes
   (global $OP_NOOP i32 (i32.const 0x00))
   (global $OP_PUSH_INT i32 (i32.const 0x01))
   (global $OP_PUSH_STR i32 (i32.const 0x02))
   (global $OP_LOAD_VAR i32 (i32.const 0x03))
   (global $OP_LOAD_FIELD i32 (i32.const 0x04))
+  (global $OP_PUSH_DEC i32 (i32.const 0x05))
+  (global $OP_PUSH_I64 i32 (i32.const 0x06))
   (global $OP_EQ i32 (i32.const 0x10))
   (global $OP_NEQ i32 (i32.const 0x11))
   (global $OP_GT i32 (i32.const 0x12))
@@ -170,11 +200,20 @@
   (global $OP_MATCHES i32 (i32.const 0x31))
   (global $OP_STARTS_WITH i32 (i32.const 0x32))
   (global $OP_ENDS_WITH i32 (i32.const 0x33))
+  (global $OP_IN i32 (i32.const 0x34))           ;; + 4 bytes list offset
   (global $OP_LEN i32 (i32.const 0x40))
   (global $OP_GET i32 (i32.const 0x41))
   (global $OP_IS_NULL i32 (i32.const 0x42))
   (global $OP_IS_DEFINED i32 (i32.const 0x43))
   (global $OP_IS_CONFIRMED i32 (i32.const 0x44))
+  (global $OP_ADD i32 (i32.const 0x60))
+  (global $OP_SUB i32 (i32.const 0x61))
+  (global $OP_MUL i32 (i32.const 0x62))
+  (global $OP_DIV i32 (i32.const 0x63))
+  (global $OP_MOD i32 (i32.const 0x64))
+  (global $OP_NEG i32 (i32.const 0x65))
+  (global $OP_NOW i32 (i32.const 0x70))          ;; + 8 bytes offset (ms)
+  (global $OP_WITHIN i32 (i32.const 0x71))       ;; + 8 bytes duration (ms)
 
   ;; Merge/CRDT opcodes (Y-constraint operations for conflict resolution)
   (global $OP_TIMESTAMP i32 (i32.const 0x50))    ;; pop value ref, push i64 timestamp
@@ -184,6 +223,8 @@
   (global $OP_MERGE_FIELD i32 (i32.const 0x54))  ;; + 1 byte selector, load merge context field
 
   (global $OP_CALL_PRED i32 (i32.const 0xF0))
+  (global $OP_JUMP_IF_FALSE i32 (i32.const 0xF1))
+  (global $OP_JUMP_IF_TRUE i32 (i32.const 0xF2))
   (global $OP_RET i32 (i32.const 0xFF))
   
   ;; Error codes
@@ -197,6 +238,8 @@
   (global $ERR_STACK_UNDERFLOW i32 (i32.const -7))
   (global $ERR_INVALID_OPCODE i32 (i32.const -8))
   (global $ERR_CYCLE_DETECTED i32 (i32.const -9))
+  (global $ERR_DIV_BY_ZERO i32 (i32.const -10))
+  (global $ERR_TYPE_MISMATCH i32 (i32.const -11))
   (global $ERR_INVALID_MAGIC i32 (i32.const -100))
   (global $ERR_VERSION_MISMATCH i32 (i32.const -101))
   (global $ERR_AUTH_FAILED i32 (i32.const -403))
@@ -449,6 +492,91 @@
   
   (func $get_node_edge_count (param $node_ptr i32) (result i32)
     (i32.load16_u (i32.add (local.get $node_ptr) (i32.const 14))))
+  
+  (func $get_node_data_offset (param $node_ptr i32) (result i32)
+    (i32.load (i32.add (local.get $node_ptr) (i32.const 8))))
+
+  ;; ═══════════════════════════════════════════════════════════════════════════
+  ;; NODE DATA PAYLOAD
+  ;; ═══════════════════════════════════════════════════════════════════════════
+  
+  ;; Node data record (at NODE_DATA_OFF + data_offset):
+  ;; [0:2] data_count, [2:2] signal_count,
+  ;; then data_count + signal_count × [key:4][value:4] string pool offsets
+  ;;
+  ;; io_call payload written at IO_BASE:
+  ;; [0:4] node_id, [4:2] data_count, [6:2] signal_count,
+  ;; then per pair (data first, then signals, 16 bytes each):
+  ;; [key_ptr:4][key_len:4][value_ptr:4][value_len:4]
+  ;; Pointers are absolute addresses of UTF-8 bytes in the string pool.
+  ;; The compiler bounds pairs so the payload fits IO_SIZE.
+  
+  ;; Length of a null-terminated string
+  (func $strlen (param $ptr i32) (result i32)
+    (local $len i32)
+    (block $break
+      (loop $loop
+        (br_if $break (i32.eqz (i32.load8_u (i32.add (local.get $ptr) (local.get $len)))))
+        (local.set $len (i32.add (local.get $len) (i32.const 1)))
+        (br $loop)))
+    (local.get $len))
+  
+  ;; Write [ptr:4][len:4] for the string at pool offset $str_off
+  (func $write_string_ref (param $out i32) (param $str_off i32)
+    (local $ptr i32)
+    (local.set $ptr
+      (i32.add (global.get $GRAPH_BASE)
+        (i32.add (i32.load (i32.add (global.get $GRAPH_BASE) (global.get $HDR_STRINGS_OFF)))
+          (local.get $str_off))))
+    (i32.store (local.get $out) (local.get $ptr))
+    (i32.store (i32.add (local.get $out) (i32.const 4)) (call $strlen (local.get $ptr))))
+  
+  ;; Write a node's payload to IO_BASE, returning its length
+  (func $write_node_payload (param $node_id i32) (param $node_ptr i32) (result i32)
+    (local $data_off i32)
+    (local $record i32)
+    (local $pairs i32)
+    (local $i i32)
+    (local $src i32)
+    (local $out i32)
+    
+    (i32.store (global.get $IO_BASE) (local.get $node_id))
+    (i32.store (i32.add (global.get $IO_BASE) (i32.const 4)) (i32.const 0))
+    (local.set $out (i32.add (global.get $IO_BASE) (i32.const 8)))
+    
+    ;; Record must lie inside the section (absent in graphs without one)
+    (local.set $data_off (call $get_node_data_offset (local.get $node_ptr)))
+    (if (i32.gt_u
+          (i32.add (local.get $data_off) (i32.const 4))
+          (i32.load (i32.add (global.get $GRAPH_BASE) (global.get $HDR_NODE_DATA_SIZE))))
+      (then (return (i32.const 8))))
+    
+    (local.set $record
+      (i32.add (global.get $GRAPH_BASE)
+        (i32.add (i32.load (i32.add (global.get $GRAPH_BASE) (global.get $HDR_NODE_DATA_OFF)))
+          (local.get $data_off))))
+    (local.set $pairs
+      (i32.add (i32.load16_u (local.get $record))
+        (i32.load16_u (i32.add (local.get $record) (i32.const 2)))))
+    
+    ;; Both counts as stored
+    (i32.store (i32.add (global.get $IO_BASE) (i32.const 4)) (i32.load (local.get $record)))
+    
+    (local.set $src (i32.add (local.get $record) (i32.const 4)))
+    (local.set $i (i32.const 0))
+    (block $break
+      (loop $loop
+        (br_if $break (i32.ge_u (local.get $i) (local.get $pairs)))
+        (call $write_string_ref (local.get $out) (i32.load (local.get $src)))
+        (call $write_string_ref
+          (i32.add (local.get $out) (i32.const 8))
+          (i32.load (i32.add (local.get $src) (i32.const 4))))
+        (local.set $src (i32.add (local.get $src) (i32.const 8)))
+        (local.set $out (i32.add (local.get $out) (i32.const 16)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $loop)))
+    
+    (i32.sub (local.get $out) (global.get $IO_BASE)))
 
   ;; ═══════════════════════════════════════════════════════════════════════════
   ;; EDGE ACCESS
@@ -531,37 +659,276 @@
   ;; PREDICATE VM - Stack Operations
   ;; ═══════════════════════════════════════════════════════════════════════════
   
+  ;; Cells are i64 values with an i32 type tag; $stack_push/$stack_pop
+  ;; work on int cells, the *_value forms carry the full value and tag
+  
   (func $stack_push (param $val i32) (result i32)
+    (call $stack_push_value (i64.extend_i32_s (local.get $val)) (global.get $VAL_INT)))
+  
+  (func $stack_push_value (param $val i64) (param $tag i32) (result i32)
     (if (i32.ge_u (global.get $pred_sp) (global.get $MAX_STACK))
       (then (return (global.get $ERR_STACK_OVERFLOW))))
     
-    (i32.store 
-      (i32.add (global.get $STACK_BASE) (i32.mul (global.get $pred_sp) (i32.const 4)))
+    (i64.store 
+      (i32.add (global.get $STACK_BASE) (i32.mul (global.get $pred_sp) (i32.const 8)))
       (local.get $val))
+    (i32.store 
+      (i32.add (global.get $TAG_BASE) (i32.mul (global.get $pred_sp) (i32.const 4)))
+      (local.get $tag))
     (global.set $pred_sp (i32.add (global.get $pred_sp) (i32.const 1)))
     (i32.const 0))
   
   (func $stack_pop (result i32)
+    (i32.wrap_i64 (call $stack_pop_value)))
+  
+  (func $stack_pop_value (result i64)
     (if (i32.le_s (global.get $pred_sp) (i32.const 0))
-      (then (return (i32.const 0)))) ;; underflow returns 0
+      (then (return (i64.const 0)))) ;; underflow returns 0
     
     (global.set $pred_sp (i32.sub (global.get $pred_sp) (i32.const 1)))
-    (i32.load 
-      (i32.add (global.get $STACK_BASE) (i32.mul (global.get $pred_sp) (i32.const 4)))))
+    (i64.load 
+      (i32.add (global.get $STACK_BASE) (i32.mul (global.get $pred_sp) (i32.const 8)))))
   
   (func $stack_peek (result i32)
     (if (i32.le_s (global.get $pred_sp) (i32.const 0))
       (then (return (i32.const 0))))
     
-    (i32.load 
+    (i32.wrap_i64 (i64.load 
       (i32.add (global.get $STACK_BASE) 
+        (i32.mul (i32.sub (global.get $pred_sp) (i32.const 1)) (i32.const 8))))))
+  
+  ;; Type tag of the top cell (VAL_INT when empty)
+  (func $stack_peek_tag (result i32)
+    (if (i32.le_s (global.get $pred_sp) (i32.const 0))
+      (then (return (global.get $VAL_INT))))
+    
+    (i32.load 
+      (i32.add (global.get $TAG_BASE) 
         (i32.mul (i32.sub (global.get $pred_sp) (i32.const 1)) (i32.const 4)))))
+  
+  ;; Scale an int to a decimal, saturating outside the decimal range
+  (func $int_to_dec (param $val i64) (result i64)
+    (if (i64.gt_s (local.get $val) (global.get $DEC_INT_MAX))
+      (then (return (i64.const 9223372036854775807))))
+    (if (i64.lt_s (local.get $val) (i64.sub (i64.const 0) (global.get $DEC_INT_MAX)))
+      (then (return (i64.const -9223372036854775808))))
+    (i64.mul (local.get $val) (global.get $DEC_ONE)))
+  
+  ;; Pop b then a and compare numerically: -1, 0 or 1 for a <, ==, > b
+  ;; An int compared with a decimal is scaled to a decimal first
+  (func $stack_pop_compare (result i32)
+    (local $a i64)
+    (local $b i64)
+    (local $a_tag i32)
+    (local $b_tag i32)
+    (local.set $b_tag (call $stack_peek_tag))
+    (local.set $b (call $stack_pop_value))
+    (local.set $a_tag (call $stack_peek_tag))
+    (local.set $a (call $stack_pop_value))
+    
+    (if (i32.and
+          (i32.eq (local.get $a_tag) (global.get $VAL_DEC))
+          (i32.eq (local.get $b_tag) (global.get $VAL_INT)))
+      (then (local.set $b (call $int_to_dec (local.get $b)))))
+    (if (i32.and
+          (i32.eq (local.get $a_tag) (global.get $VAL_INT))
+          (i32.eq (local.get $b_tag) (global.get $VAL_DEC)))
+      (then (local.set $a (call $int_to_dec (local.get $a)))))
+    
+    (i32.sub
+      (i64.gt_s (local.get $a) (local.get $b))
+      (i64.lt_s (local.get $a) (local.get $b))))
+
+  ;; ═══════════════════════════════════════════════════════════════════════════
+  ;; PREDICATE VM - Arithmetic
+  ;; ═══════════════════════════════════════════════════════════════════════════
+  
+  ;; 64-bit arithmetic saturating at I64_MIN / I64_MAX instead of wrapping
+  
+  (func $add_sat (param $a i64) (param $b i64) (result i64)
+    (local $r i64)
+    (local.set $r (i64.add (local.get $a) (local.get $b)))
+    ;; Overflow iff both operands have the sign the result lacks
+    (if (i64.lt_s
+          (i64.and
+            (i64.xor (local.get $a) (local.get $r))
+            (i64.xor (local.get $b) (local.get $r)))
+          (i64.const 0))
+      (then (return (select (global.get $I64_MIN) (global.get $I64_MAX)
+                      (i64.lt_s (local.get $a) (i64.const 0))))))
+    (local.get $r))
+  
+  (func $sub_sat (param $a i64) (param $b i64) (result i64)
+    (local $r i64)
+    (local.set $r (i64.sub (local.get $a) (local.get $b)))
+    ;; Overflow iff the operands differ in sign and the result lost a's
+    (if (i64.lt_s
+          (i64.and
+            (i64.xor (local.get $a) (local.get $b))
+            (i64.xor (local.get $a) (local.get $r)))
+          (i64.const 0))
+      (then (return (select (global.get $I64_MIN) (global.get $I64_MAX)
+                      (i64.lt_s (local.get $a) (i64.const 0))))))
+    (local.get $r))
+  
+  (func $mul_sat (param $a i64) (param $b i64) (result i64)
+    (local $r i64)
+    (if (i64.eqz (local.get $a))
+      (then (return (i64.const 0))))
+    (if (i64.eq (local.get $a) (i64.const -1))
+      (then (return (call $sub_sat (i64.const 0) (local.get $b)))))
+    (local.set $r (i64.mul (local.get $a) (local.get $b)))
+    (if (i64.ne (i64.div_s (local.get $r) (local.get $a)) (local.get $b))
+      (then (return (select (global.get $I64_MIN) (global.get $I64_MAX)
+                      (i32.ne (i64.lt_s (local.get $a) (i64.const 0))
+                              (i64.lt_s (local.get $b) (i64.const 0)))))))
+    (local.get $r))
+  
+  ;; Integer division truncating toward zero; b must not be 0
+  (func $div_sat (param $a i64) (param $b i64) (result i64)
+    ;; I64_MIN / -1 is the one quotient that does not fit
+    (if (i64.eq (local.get $b) (i64.const -1))
+      (then (return (call $sub_sat (i64.const 0) (local.get $a)))))
+    (i64.div_s (local.get $a) (local.get $b)))
+  
+  ;; Decimal a * b / DEC_ONE without a 128-bit intermediate: split a into
+  ;; whole and fractional parts, and b likewise for the fractional product
+  (func $dec_mul (param $a i64) (param $b i64) (result i64)
+    (local $af i64)
+    (local.set $af (i64.rem_s (local.get $a) (global.get $DEC_ONE)))
+    (call $add_sat
+      (call $mul_sat (i64.div_s (local.get $a) (global.get $DEC_ONE)) (local.get $b))
+      (call $add_sat
+        (i64.mul (local.get $af) (i64.div_s (local.get $b) (global.get $DEC_ONE)))
+        (i64.div_s
+          (i64.mul (local.get $af) (i64.rem_s (local.get $b) (global.get $DEC_ONE)))
+          (global.get $DEC_ONE)))))
+  
+  ;; Decimal a * DEC_ONE / b; b must not be 0
+  (func $dec_div (param $a i64) (param $b i64) (result i64)
+    ;; |b| <= DEC_INT_MAX: quotient, plus the remainder scaled (fits)
+    (if (i32.and
+          (i64.le_s (local.get $b) (global.get $DEC_INT_MAX))
+          (i64.ge_s (local.get $b) (i64.sub (i64.const 0) (global.get $DEC_INT_MAX))))
+      (then
+        (return (call $add_sat
+          (call $mul_sat (call $div_sat (local.get $a) (local.get $b)) (global.get $DEC_ONE))
+          (i64.div_s
+            (i64.mul (i64.rem_s (local.get $a) (local.get $b)) (global.get $DEC_ONE))
+            (local.get $b))))))
+    ;; Larger divisors: drop their last DECIMAL_PLACES digits instead
+    (call $div_sat (local.get $a) (i64.div_s (local.get $b) (global.get $DEC_ONE))))
+  
+  ;; Stop the evaluation: it returns false with last_error set to $err
+  (func $pred_fail (param $err i32)
+    (global.set $last_error (local.get $err))
+    (global.set $pred_fault (i32.const 1)))
+  
+  ;; ADD, SUB, MUL, DIV, MOD, NEG on the top of the stack
+  ;; An int with a decimal is scaled to a decimal first; string operands
+  ;; and division by zero fault
+  (func $arith (param $op i32)
+    (local $a i64)
+    (local $b i64)
+    (local $a_tag i32)
+    (local $b_tag i32)
+    (local $dec i32)
+    (local $r i64)
+    
+    (if (i32.eq (local.get $op) (global.get $OP_NEG))
+      (then
+        (local.set $a_tag (call $stack_peek_tag))
+        (local.set $a (call $stack_pop_value))
+        (if (i32.eq (local.get $a_tag) (global.get $VAL_STR))
+          (then
+            (call $pred_fail (global.get $ERR_TYPE_MISMATCH))
+            (return)))
+        (drop (call $stack_push_value
+          (call $sub_sat (i64.const 0) (local.get $a)) (local.get $a_tag)))
+        (return)))
+    
+    (local.set $b_tag (call $stack_peek_tag))
+    (local.set $b (call $stack_pop_value))
+    (local.set $a_tag (call $stack_peek_tag))
+    (local.set $a (call $stack_pop_value))
+    (if (i32.or
+          (i32.eq (local.get $a_tag) (global.get $VAL_STR))
+          (i32.eq (local.get $b_tag) (global.get $VAL_STR)))
+      (then
+        (call $pred_fail (global.get $ERR_TYPE_MISMATCH))
+        (return)))
+    
+    (local.set $dec (i32.or
+      (i32.eq (local.get $a_tag) (global.get $VAL_DEC))
+      (i32.eq (local.get $b_tag) (global.get $VAL_DEC))))
+    (if (local.get $dec)
+      (then
+        (if (i32.eq (local.get $a_tag) (global.get $VAL_INT))
+          (then (local.set $a (call $int_to_dec (local.get $a)))))
+        (if (i32.eq (local.get $b_tag) (global.get $VAL_INT))
+          (then (local.set $b (call $int_to_dec (local.get $b)))))))
+    
+    (if (i32.and
+          (i64.eqz (local.get $b))
+          (i32.or
+            (i32.eq (local.get $op) (global.get $OP_DIV))
+            (i32.eq (local.get $op) (global.get $OP_MOD))))
+      (then
+        (call $pred_fail (global.get $ERR_DIV_BY_ZERO))
+        (return)))
+    
+    (if (i32.eq (local.get $op) (global.get $OP_ADD))
+      (then (local.set $r (call $add_sat (local.get $a) (local.get $b)))))
+    (if (i32.eq (local.get $op) (global.get $OP_SUB))
+      (then (local.set $r (call $sub_sat (local.get $a) (local.get $b)))))
+    (if (i32.eq (local.get $op) (global.get $OP_MUL))
+      (then (local.set $r (if (result i64) (local.get $dec)
+        (then (call $dec_mul (local.get $a) (local.get $b)))
+        (else (call $mul_sat (local.get $a) (local.get $b)))))))
+    (if (i32.eq (local.get $op) (global.get $OP_DIV))
+      (then (local.set $r (if (result i64) (local.get $dec)
+        (then (call $dec_div (local.get $a) (local.get $b)))
+        (else (call $div_sat (local.get $a) (local.get $b)))))))
+    ;; Scaled remainders are already scaled; wasm defines I64_MIN % -1 = 0
+    (if (i32.eq (local.get $op) (global.get $OP_MOD))
+      (then (local.set $r (i64.rem_s (local.get $a) (local.get $b)))))
+    
+    (drop (call $stack_push_value (local.get $r)
+      (select (global.get $VAL_DEC) (global.get $VAL_INT) (local.get $dec)))))
+
+  ;; ═══════════════════════════════════════════════════════════════════════════
+  ;; PREDICATE VM - Time
+  ;; ═══════════════════════════════════════════════════════════════════════════
+  
+  ;; The host's current time, read once per top-level evaluation so that
+  ;; every NOW / WITHIN in it sees the same instant
+  (func $now (result i64)
+    (if (i32.eqz (global.get $pred_now_set))
+      (then
+        (global.set $pred_now (call $io_now))
+        (global.set $pred_now_set (i32.const 1))))
+    (global.get $pred_now))
+  
+  ;; 1 if $t lies between now and now + $duration (either order), inclusive
+  (func $within (param $t i64) (param $duration i64) (result i32)
+    (local $lo i64)
+    (local $hi i64)
+    (local.set $lo (call $now))
+    (local.set $hi (call $add_sat (local.get $lo) (local.get $duration)))
+    (if (i64.lt_s (local.get $duration) (i64.const 0))
+      (then
+        (local.set $hi (local.get $lo))
+        (local.set $lo (call $add_sat (local.get $hi) (local.get $duration)))))
+    (i32.and
+      (i64.ge_s (local.get $t) (local.get $lo))
+      (i64.le_s (local.get $t) (local.get $hi))))
 
   ;; ═══════════════════════════════════════════════════════════════════════════
   ;; PREDICATE VM - Evaluation
   ;; ═══════════════════════════════════════════════════════════════════════════
   
   ;; Get predicate bytecode pointer
+  ;; Predicate ID n is entry n - 1 (ID 0 = always true has no entry)
   (func $get_predicate_ptr (param $pred_id i32) (result i32)
     (local $preds_off i32)
     (local $i i32)
@@ -576,7 +943,7 @@
     (local.set $i (i32.const 0))
     (block $break
       (loop $loop
-        (br_if $break (i32.ge_u (local.get $i) (local.get $pred_id)))
+        (br_if $break (i32.ge_u (local.get $i) (i32.sub (local.get $pred_id) (i32.const 1))))
         (local.set $len (i32.load16_u (local.get $ptr)))
         (local.set $ptr (i32.add (local.get $ptr) (i32.add (i32.const 2) (local.get $len))))
         (local.set $i (i32.add (local.get $i) (i32.const 1)))
@@ -593,27 +960,37 @@
     (local $a i32)
     (local $b i32)
     (local $str_off i32)
+    (local $val i64)
+    (local $code i32)
     
     ;; Predicate ID 0 = always true
     (if (i32.eqz (local.get $pred_id))
       (then (return (i32.const 1))))
     
-    ;; Check call depth
+    ;; Check call depth. Past the limit the evaluation faults rather than
+    ;; pushing false, which NOT in the caller would turn into true
     (if (i32.ge_u (global.get $pred_call_depth) (global.get $MAX_PRED_DEPTH))
       (then
-        (global.set $last_error (global.get $ERR_PRED_DEPTH_EXCEEDED))
+        (call $pred_fail (global.get $ERR_PRED_DEPTH_EXCEEDED))
         (return (i32.const 0))))
     
-    (global.set $pred_call_depth (i32.add (global.get $pred_call_depth) (i32.const 1)))
+    ;; Reset stack and step counter for a top-level evaluation; a nested
+    ;; call (CALL_PRED) shares them with its caller, whose operands are
+    ;; still on the stack
+    (if (i32.eqz (global.get $pred_call_depth))
+      (then
+        (global.set $pred_sp (i32.const 0))
+        (global.set $pred_steps (i32.const 0))
+        (global.set $pred_fault (i32.const 0))
+        (global.set $pred_now_set (i32.const 0))))
     
-    ;; Reset stack and step counter for this evaluation
-    (global.set $pred_sp (i32.const 0))
-    (global.set $pred_steps (i32.const 0))
+    (global.set $pred_call_depth (i32.add (global.get $pred_call_depth) (i32.const 1)))
     
     ;; Get bytecode
     (local.set $ptr (call $get_predicate_ptr (local.get $pred_id)))
     (local.set $len (i32.load16_u (local.get $ptr)))
     (local.set $ptr (i32.add (local.get $ptr) (i32.const 2))) ;; skip length
+    (local.set $code (local.get $ptr)) ;; jump targets are relative to this
     (local.set $end (i32.add (local.get $ptr) (local.get $len)))
     
     ;; Execute bytecode
@@ -621,11 +998,19 @@
       (loop $exec
         (br_if $done (i32.ge_u (local.get $ptr) (local.get $end)))
         
-        ;; Check step limit
+        ;; An arithmetic fault, here or in a called predicate, fails the
+        ;; whole evaluation
+        (if (global.get $pred_fault)
+          (then
+            (global.set $pred_call_depth (i32.sub (global.get $pred_call_depth) (i32.const 1)))
+            (return (i32.const 0))))
+        
+        ;; Check step limit. Like the depth limit this faults, so a caller
+        ;; never sees the exhausted call as an ordinary false
         (global.set $pred_steps (i32.add (global.get $pred_steps) (i32.const 1)))
         (if (i32.gt_u (global.get $pred_steps) (global.get $MAX_PRED_STEPS))
           (then
-            (global.set $last_error (global.get $ERR_PRED_STEPS_EXCEEDED))
+            (call $pred_fail (global.get $ERR_PRED_STEPS_EXCEEDED))
             (global.set $pred_call_depth (i32.sub (global.get $pred_call_depth) (i32.const 1)))
             (return (i32.const 0))))
         
@@ -643,66 +1028,72 @@
             (local.set $ptr (i32.add (local.get $ptr) (i32.const 4)))
             (br $exec)))
         
+        ;; PUSH_DEC: push next 8 bytes as an i64 decimal (10^-6 units)
+        (if (i32.eq (local.get $op) (global.get $OP_PUSH_DEC))
+          (then
+            (drop (call $stack_push_value (i64.load (local.get $ptr)) (global.get $VAL_DEC)))
+            (local.set $ptr (i32.add (local.get $ptr) (i32.const 8)))
+            (br $exec)))
+        
+        ;; PUSH_I64: push next 8 bytes as an i64 int
+        (if (i32.eq (local.get $op) (global.get $OP_PUSH_I64))
+          (then
+            (drop (call $stack_push_value (i64.load (local.get $ptr)) (global.get $VAL_INT)))
+            (local.set $ptr (i32.add (local.get $ptr) (i32.const 8)))
+            (br $exec)))
+        
         ;; LOAD_VAR: resolve variable from Y-context
         (if (i32.eq (local.get $op) (global.get $OP_LOAD_VAR))
           (then
             (local.set $str_off (i32.load (local.get $ptr)))
             (local.set $ptr (i32.add (local.get $ptr) (i32.const 4)))
-            ;; Call host to resolve, push low 32 bits
-            (drop (call $stack_push 
-              (i32.wrap_i64 (call $io_resolve_var 
-                (i32.add (global.get $GRAPH_BASE) 
-                  (i32.add (i32.load (i32.add (global.get $GRAPH_BASE) (global.get $HDR_STRINGS_OFF)))
-                    (local.get $str_off)))
-                (i32.const 64))))) ;; TODO: actual string length
+            (local.set $str_off
+              (i32.add (global.get $GRAPH_BASE) 
+                (i32.add (i32.load (i32.add (global.get $GRAPH_BASE) (global.get $HDR_STRINGS_OFF)))
+                  (local.get $str_off))))
+            ;; Call host to resolve; a host that writes no type yields an int
+            (i32.store (global.get $VAR_TYPE_PTR) (global.get $VAL_INT))
+            (local.set $val (call $io_resolve_var 
+              (local.get $str_off)
+              (call $strlen (local.get $str_off))
+              (global.get $VAR_TYPE_PTR)))
+            (drop (call $stack_push_value (local.get $val) (i32.load (global.get $VAR_TYPE_PTR))))
             (br $exec)))
         
-        ;; EQ: pop two, push (a == b)
+        ;; EQ: pop two, push (a == b); comparisons are numeric, see $stack_pop_compare
         (if (i32.eq (local.get $op) (global.get $OP_EQ))
           (then
-            (local.set $b (call $stack_pop))
-            (local.set $a (call $stack_pop))
-            (drop (call $stack_push (i32.eq (local.get $a) (local.get $b))))
+            (drop (call $stack_push (i32.eqz (call $stack_pop_compare))))
             (br $exec)))
         
         ;; NEQ
         (if (i32.eq (local.get $op) (global.get $OP_NEQ))
           (then
-            (local.set $b (call $stack_pop))
-            (local.set $a (call $stack_pop))
-            (drop (call $stack_push (i32.ne (local.get $a) (local.get $b))))
+            (drop (call $stack_push (i32.ne (call $stack_pop_compare) (i32.const 0))))
             (br $exec)))
         
         ;; GT
         (if (i32.eq (local.get $op) (global.get $OP_GT))
           (then
-            (local.set $b (call $stack_pop))
-            (local.set $a (call $stack_pop))
-            (drop (call $stack_push (i32.gt_s (local.get $a) (local.get $b))))
+            (drop (call $stack_push (i32.gt_s (call $stack_pop_compare) (i32.const 0))))
             (br $exec)))
         
         ;; GTE
         (if (i32.eq (local.get $op) (global.get $OP_GTE))
           (then
-            (local.set $b (call $stack_pop))
-            (local.set $a (call $stack_pop))
-            (drop (call $stack_push (i32.ge_s (local.get $a) (local.get $b))))
+            (drop (call $stack_push (i32.ge_s (call $stack_pop_compare) (i32.const 0))))
             (br $exec)))
         
         ;; LT
         (if (i32.eq (local.get $op) (global.get $OP_LT))
           (then
-            (local.set $b (call $stack_pop))
-            (local.set $a (call $stack_pop))
-            (drop (call $stack_push (i32.lt_s (local.get $a) (local.get $b))))
+            (drop (call $stack_push (i32.lt_s (call $stack_pop_compare) (i32.const 0))))
             (br $exec)))
         
         ;; LTE
         (if (i32.eq (local.get $op) (global.get $OP_LTE))
           (then
-            (local.set $b (call $stack_pop))
-            (local.set $a (call $stack_pop))
-            (drop (call $stack_push (i32.le_s (local.get $a) (local.get $b))))
+            (drop (call $stack_push (i32.le_s (call $stack_pop_compare) (i32.const 0))))
             (br $exec)))
         
         ;; AND
@@ -755,8 +1146,8 @@
         (if (i32.eq (local.get $op) (global.get $OP_TIMESTAMP))
           (then
             (local.set $a (call $stack_pop))
-            ;; Push low 32 bits of timestamp (seconds since epoch fits in i32)
-            (drop (call $stack_push (i32.wrap_i64 (call $io_get_timestamp (local.get $a)))))
+            ;; Push the full 64-bit timestamp (epoch milliseconds fit)
+            (drop (call $stack_push_value (call $io_get_timestamp (local.get $a)) (global.get $VAL_INT)))
             (br $exec)))
 
         ;; IS_FLAGGED - check if value is flagged for human review
@@ -811,6 +1202,48 @@
             (drop (call $stack_push (i32.const 0))) ;; placeholder
             (br $exec)))
         
+        ;; ADD..NEG: arithmetic, see $arith
+        (if (i32.and
+              (i32.ge_u (local.get $op) (global.get $OP_ADD))
+              (i32.le_u (local.get $op) (global.get $OP_NEG)))
+          (then
+            (call $arith (local.get $op))
+            (br $exec)))
+        
+        ;; NOW: push the evaluation's current time plus an offset
+        (if (i32.eq (local.get $op) (global.get $OP_NOW))
+          (then
+            (drop (call $stack_push_value
+              (call $add_sat (call $now) (i64.load (local.get $ptr)))
+              (global.get $VAL_INT)))
+            (local.set $ptr (i32.add (local.get $ptr) (i32.const 8)))
+            (br $exec)))
+        
+        ;; WITHIN: pop a time, push 1 if it is within the duration from now
+        (if (i32.eq (local.get $op) (global.get $OP_WITHIN))
+          (then
+            (drop (call $stack_push
+              (call $within (call $stack_pop_value) (i64.load (local.get $ptr)))))
+            (local.set $ptr (i32.add (local.get $ptr) (i32.const 8)))
+            (br $exec)))
+        
+        ;; IN: is the top value one of a constant list - delegate to host
+        (if (i32.eq (local.get $op) (global.get $OP_IN))
+          (then
+            (local.set $str_off
+              (i32.add (global.get $GRAPH_BASE) 
+                (i32.add (i32.load (i32.add (global.get $GRAPH_BASE) (global.get $HDR_STRINGS_OFF)))
+                  (i32.load (local.get $ptr)))))
+            (local.set $ptr (i32.add (local.get $ptr) (i32.const 4)))
+            (local.set $b (call $stack_peek_tag))
+            (local.set $val (call $stack_pop_value))
+            (drop (call $stack_push (call $io_in_list
+              (local.get $val)
+              (local.get $b)
+              (local.get $str_off)
+              (call $strlen (local.get $str_off)))))
+            (br $exec)))
+        
         ;; CALL_PRED: nested predicate call
         (if (i32.eq (local.get $op) (global.get $OP_CALL_PRED))
           (then
@@ -819,6 +1252,22 @@
             (drop (call $stack_push (call $eval_predicate (local.get $a))))
             (br $exec)))
         
+        ;; JUMP_IF_FALSE / JUMP_IF_TRUE: short-circuit AND / OR
+        ;; The operand that decides the result stays on the stack as the
+        ;; result; otherwise it is popped. Targets only point forward
+        ;; (checked by the compiler), so the step bound still holds.
+        (if (i32.or
+              (i32.eq (local.get $op) (global.get $OP_JUMP_IF_FALSE))
+              (i32.eq (local.get $op) (global.get $OP_JUMP_IF_TRUE)))
+          (then
+            (local.set $a (i32.load16_u (local.get $ptr)))
+            (local.set $ptr (i32.add (local.get $ptr) (i32.const 2)))
+            (local.set $b (i32.ne (call $stack_peek) (i32.const 0)))
+            (if (i32.eq (local.get $b) (i32.eq (local.get $op) (global.get $OP_JUMP_IF_TRUE)))
+              (then (local.set $ptr (i32.add (local.get $code) (local.get $a))))
+              (else (drop (call $stack_pop))))
+            (br $exec)))
+        
         ;; RET: return top of stack
         (if (i32.eq (local.get $op) (global.get $OP_RET))
           (then
@@ -879,21 +1328,24 @@
         (call $spend_energy (global.get $ENERGY_IO_CALL))
         (if (global.get $trace_mode)
           (then (call $io_emit_event (global.get $EVT_IO_CALL) (local.get $node_id) (local.get $op_code))))
-        (local.set $result (call $io_call (local.get $op_code) (global.get $IO_BASE) (i32.const 0)))))
+        (local.set $result (call $io_call (local.get $op_code) (global.get $IO_BASE)
+          (call $write_node_payload (local.get $node_id) (local.get $node_ptr))))))
     
     (if (i32.eq (local.get $kind) (global.get $KIND_RENDER))
       (then
-        ;; Render: call host with template data
-        (local.set $result (call $io_call (i32.const 0xF000) (global.get $IO_BASE) (i32.const 0)))))
+        ;; Render: call host; op_code is the template id (templates section)
+        (local.set $result (call $io_call (i32.const 0xF000) (global.get $IO_BASE)
+          (call $write_node_payload (local.get $node_id) (local.get $node_ptr))))))
     
     (if (i32.eq (local.get $kind) (global.get $KIND_SIGNAL))
       (then
-        ;; Signal: update Datastar signals via host
-        (local.set $result (call $io_call (i32.const 0xF001) (global.get $IO_BASE) (i32.const 0)))))
+        ;; Signal: update Datastar signals via host (signals in the payload)
+        (local.set $result (call $io_call (i32.const 0xF001) (global.get $IO_BASE)
+          (call $write_node_payload (local.get $node_id) (local.get $node_ptr))))))
     
     (if (i32.eq (local.get $kind) (global.get $KIND_AUTH))
       (then
-        ;; Auth: evaluate predicate (stored in data_offset as predicate ID)
+        ;; Auth: evaluate predicate (stored in op_code as predicate ID)
         ;; The predicate result IS the auth result
         (local.set $result (i32.const 0)))) ;; predicate checked on edge
     